@@ -0,0 +1,8 @@
+fn main() {
+    // The gRPC stubs (and `protoc`) are only needed with `--features grpc`.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/sentinel.proto");
+        tonic_build::compile_protos("proto/sentinel.proto").expect("compiling proto/sentinel.proto");
+    }
+}
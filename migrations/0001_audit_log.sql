@@ -0,0 +1,19 @@
+-- Audit log entries, one row per id. `entry` holds the full snapshot as the
+-- JSONL file would; the other columns are copies for indexing.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id         BIGINT PRIMARY KEY,
+    ts         BIGINT NOT NULL,
+    session_id TEXT   NOT NULL,
+    tenant     TEXT,
+    detector   TEXT   NOT NULL,
+    entry      JSONB  NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_ts ON audit_log (ts);
+CREATE INDEX IF NOT EXISTS audit_log_session ON audit_log (session_id);
+
+-- Small counters, e.g. the archive watermark.
+CREATE TABLE IF NOT EXISTS sentinel_meta (
+    key   TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
@@ -0,0 +1,28 @@
+-- Live state each instance saves on every session sweep and loads on
+-- startup, keyed by `SENTINEL_INSTANCE_ID`. An instance only replaces its
+-- own rows.
+CREATE TABLE IF NOT EXISTS sentinel_sessions (
+    instance   TEXT   NOT NULL,
+    id         TEXT   NOT NULL,
+    state      JSONB  NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (instance, id)
+);
+
+-- Spend per tenant (`kind = 'tenant'`) and per budget pool (`'pool'`).
+CREATE TABLE IF NOT EXISTS sentinel_spend (
+    instance  TEXT             NOT NULL,
+    kind      TEXT             NOT NULL,
+    name      TEXT             NOT NULL,
+    spent_usd DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (instance, kind, name)
+);
+
+-- Model prices shared by all instances, maintained by operators. Matched in
+-- `position` order ahead of `SENTINEL_PRICING` and the built-in table.
+CREATE TABLE IF NOT EXISTS sentinel_pricing (
+    pattern         TEXT             PRIMARY KEY,
+    input_per_mtok  DOUBLE PRECISION NOT NULL,
+    output_per_mtok DOUBLE PRECISION NOT NULL,
+    position        INTEGER          NOT NULL DEFAULT 0
+);
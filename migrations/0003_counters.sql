@@ -0,0 +1,9 @@
+-- Aggregate counters, one row per instance and counter. Instances only ever
+-- add to their own rows; cluster totals are the sum over instances.
+CREATE TABLE IF NOT EXISTS sentinel_counters (
+    instance   TEXT   NOT NULL,
+    name       TEXT   NOT NULL,
+    value      BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (instance, name)
+);
@@ -0,0 +1,8 @@
+-- Which provider key is in use and how each one last checked out; keys are
+-- stored by their last four characters only.
+CREATE TABLE IF NOT EXISTS sentinel_provider_keys (
+    instance TEXT  NOT NULL,
+    provider TEXT  NOT NULL,
+    ring     JSONB NOT NULL,
+    PRIMARY KEY (instance, provider)
+);
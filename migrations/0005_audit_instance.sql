@@ -0,0 +1,8 @@
+-- Audit ids are counted per process, so two instances can write the same
+-- id: rows are keyed by the instance that wrote them too. The key is only
+-- for uniqueness; every instance reads and erases all rows. Rows written
+-- before this migration go to the default instance id, `sentinel`.
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS instance TEXT NOT NULL DEFAULT '';
+UPDATE audit_log SET instance = 'sentinel' WHERE instance = '';
+ALTER TABLE audit_log ALTER COLUMN instance DROP DEFAULT;
+ALTER TABLE audit_log DROP CONSTRAINT audit_log_pkey, ADD PRIMARY KEY (instance, id);
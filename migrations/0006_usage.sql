@@ -0,0 +1,7 @@
+-- Each instance's usage ledger behind /api/reports and the delivery period
+-- it last saw open, saved with its sessions.
+CREATE TABLE IF NOT EXISTS sentinel_usage (
+    instance   TEXT   PRIMARY KEY,
+    ledger     JSONB  NOT NULL,
+    updated_at BIGINT NOT NULL
+);
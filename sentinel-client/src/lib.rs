@@ -0,0 +1,444 @@
+//! Typed client for a Sentinel deployment: the admin API behind the
+//! dashboard, the live intervention stream and, with the `openai` feature, a
+//! `SentinelConfig` that points `async-openai` at the proxy and tags every
+//! request with a session.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), sentinel_client::Error> {
+//! use futures_util::StreamExt;
+//! use sentinel_client::{Client, Event, LogQuery};
+//!
+//! let sentinel = Client::new("http://127.0.0.1:3000");
+//! println!("saved so far: ${:.2}", sentinel.stats().await?.total_saved_usd);
+//!
+//! let mut events = sentinel.interventions(&LogQuery { detector: Some("leak".into()), ..Default::default() }).await?;
+//! while let Some(Event::Intervention(log)) = events.next().await.transpose()? {
+//!     sentinel.block_session(&log.session_id, Some("leaked a secret")).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+use std::pin::Pin;
+
+#[cfg(feature = "openai")]
+mod openai;
+#[cfg(feature = "openai")]
+pub use openai::SentinelConfig;
+
+/// Header the proxy keys loop and budget tracking on.
+pub const SESSION_HEADER: &str = "x-sentinel-session";
+
+// --- ERRORS ---
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    /// Sentinel answered with a non-success status; `message` is its `error` field when there is one.
+    Status { status: u16, message: String },
+    Decode(serde_json::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request to Sentinel failed: {}", e),
+            Error::Status { status, message } => write!(f, "Sentinel returned {}: {}", status, message),
+            Error::Decode(e) => write!(f, "unexpected response from Sentinel: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// --- TYPES ---
+// Mirrors of the server's JSON. Fields the client doesn't model are kept in
+// `extra` so nothing is lost when the server grows.
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LogQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector: Option<String>,
+    /// Case-insensitive substring of `reason`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Unix-second bounds, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<u64>,
+    /// Full-text search over `content_snippet`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Cursor: only entries older than this id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Correct,
+    FalsePositive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterventionLog {
+    pub id: u64,
+    pub timestamp: u64,
+    pub session_id: String,
+    pub detector: String,
+    pub reason: String,
+    pub content_snippet: String,
+    pub savings_est: f64,
+    #[serde(default)]
+    pub feedback: Option<Verdict>,
+    #[serde(default)]
+    pub bypassed: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Audit chain link, see `/api/logs/verify`.
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+/// One page of `GET /api/logs`, newest last.
+#[derive(Debug, Clone)]
+pub struct LogPage {
+    pub total: usize,
+    /// Pass as `LogQuery::before` for the next, older page.
+    pub next_cursor: Option<u64>,
+    pub logs: Vec<InterventionLog>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Stats {
+    pub active_sessions: usize,
+    pub total_saved_usd: f64,
+    pub interventions: u64,
+    pub status: String,
+    #[serde(default)]
+    pub startup_problems: Vec<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub cumulative_cost: f64,
+    pub created_at: u64,
+    pub last_activity: u64,
+    pub interventions: u32,
+    pub history_len: usize,
+    pub blocked: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionPage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub sessions: Vec<SessionSummary>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockEntry {
+    pub reason: String,
+    pub blocked_at: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionDetail {
+    pub id: String,
+    pub created_at: u64,
+    pub last_activity: u64,
+    pub cumulative_cost: f64,
+    pub interventions: u32,
+    #[serde(default)]
+    pub interventions_by_reason: HashMap<String, u32>,
+    #[serde(default)]
+    pub recent_prompts: Vec<String>,
+    pub blocked: Option<BlockEntry>,
+    #[serde(default)]
+    pub intervention_log: Vec<InterventionLog>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantineStatus {
+    Pending,
+    Approved,
+    Denied,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: u64,
+    pub created_at: u64,
+    pub resolved_at: Option<u64>,
+    pub detector: String,
+    pub reason: String,
+    pub status: QuarantineStatus,
+    pub upstream_status: Option<u16>,
+    pub result: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// An item of the live stream.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Intervention(Box<InterventionLog>),
+    /// The server dropped this many interventions because the client fell behind.
+    Lagged(u64),
+}
+
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<Event>> + Send>>;
+
+// --- CLIENT ---
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base: String,
+    token: Option<String>,
+}
+
+impl Client {
+    /// `base_url` is the proxy's root, e.g. `http://127.0.0.1:3000`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http(reqwest::Client::new(), base_url)
+    }
+
+    pub fn with_http(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        let base = base_url.into().trim_end_matches('/').to_string();
+        Self { http, base, token: None }
+    }
+
+    /// Bearer token sent with every admin call.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        Err(Error::Status { status, message: error_message(&body) })
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let bytes = self.send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// `GET /api/stats`
+    pub async fn stats(&self) -> Result<Stats> {
+        self.json(self.request(reqwest::Method::GET, "/api/stats")).await
+    }
+
+    /// `GET /api/logs`
+    pub async fn logs(&self, query: &LogQuery) -> Result<LogPage> {
+        let response = self.send(self.request(reqwest::Method::GET, "/api/logs").query(query)).await?;
+        let header = |name: &str| -> Option<u64> { response.headers().get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok()) };
+        let total = header("x-total-count").unwrap_or_default() as usize;
+        let next_cursor = header("x-next-cursor");
+        let logs: Vec<InterventionLog> = serde_json::from_slice(&response.bytes().await?)?;
+        Ok(LogPage { total, next_cursor, logs })
+    }
+
+    /// `GET /api/logs/stream`: interventions as they happen. `limit` and
+    /// `before` are ignored by the server.
+    pub async fn interventions(&self, query: &LogQuery) -> Result<EventStream> {
+        let request = self.request(reqwest::Method::GET, "/api/logs/stream")
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .query(query);
+        let body = Box::pin(self.send(request).await?.bytes_stream());
+        Ok(Box::pin(futures_util::stream::unfold((body, Vec::<u8>::new()), |(mut body, mut buf)| async move {
+            loop {
+                if let Some(end) = buf.windows(2).position(|w| w == b"\n\n") {
+                    let block: Vec<u8> = buf.drain(..end + 2).collect();
+                    match parse_event(&String::from_utf8_lossy(&block)) {
+                        Ok(Some(event)) => return Some((Ok(event), (body, buf))),
+                        Ok(None) => continue,
+                        Err(e) => return Some((Err(e), (body, buf))),
+                    }
+                }
+                match body.next().await? {
+                    Ok(chunk) => buf.extend(chunk.iter().filter(|b| **b != b'\r')),
+                    Err(e) => return Some((Err(e.into()), (body, buf))),
+                }
+            }
+        })))
+    }
+
+    /// `GET /api/sessions`
+    pub async fn sessions(&self, offset: usize, limit: usize) -> Result<SessionPage> {
+        let request = self.request(reqwest::Method::GET, "/api/sessions").query(&[("offset", offset), ("limit", limit)]);
+        self.json(request).await
+    }
+
+    /// `GET /api/sessions/{id}`
+    pub async fn session(&self, id: &str) -> Result<SessionDetail> {
+        self.json(self.request(reqwest::Method::GET, &format!("/api/sessions/{}", path_segment(id)))).await
+    }
+
+    /// `DELETE /api/sessions/{id}`: forget the session's history and spend.
+    pub async fn reset_session(&self, id: &str) -> Result<()> {
+        self.send(self.request(reqwest::Method::DELETE, &format!("/api/sessions/{}", path_segment(id)))).await?;
+        Ok(())
+    }
+
+    /// `POST /api/sessions/{id}/block`
+    pub async fn block_session(&self, id: &str, reason: Option<&str>) -> Result<()> {
+        let request = self.request(reqwest::Method::POST, &format!("/api/sessions/{}/block", path_segment(id)))
+            .json(&serde_json::json!({ "reason": reason }));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// `POST /api/sessions/{id}/unblock`
+    pub async fn unblock_session(&self, id: &str) -> Result<()> {
+        self.send(self.request(reqwest::Method::POST, &format!("/api/sessions/{}/unblock", path_segment(id)))).await?;
+        Ok(())
+    }
+
+    /// `POST /api/interventions/{id}/feedback`
+    pub async fn feedback(&self, intervention_id: u64, verdict: Verdict) -> Result<()> {
+        let request = self.request(reqwest::Method::POST, &format!("/api/interventions/{}/feedback", intervention_id))
+            .json(&serde_json::json!({ "verdict": verdict }));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// `GET /api/quarantine`, optionally filtered by status.
+    pub async fn quarantine(&self, status: Option<QuarantineStatus>) -> Result<Vec<QuarantineEntry>> {
+        let mut request = self.request(reqwest::Method::GET, "/api/quarantine");
+        if let Some(status) = status {
+            request = request.query(&[("status", status)]);
+        }
+        self.json(request).await
+    }
+
+    /// `POST /api/quarantine/{id}/approve`: forwards the held request and
+    /// returns the entry with the upstream result.
+    pub async fn approve(&self, id: u64) -> Result<QuarantineEntry> {
+        self.json(self.request(reqwest::Method::POST, &format!("/api/quarantine/{}/approve", id))).await
+    }
+
+    /// `POST /api/quarantine/{id}/deny`
+    pub async fn deny(&self, id: u64) -> Result<QuarantineEntry> {
+        self.json(self.request(reqwest::Method::POST, &format!("/api/quarantine/{}/deny", id))).await
+    }
+
+    /// `GET /api/openapi.json`
+    pub async fn openapi(&self) -> Result<serde_json::Value> {
+        self.json(self.request(reqwest::Method::GET, "/api/openapi.json")).await
+    }
+}
+
+/// Sentinel's error bodies are `{"error": "..."}` on the admin API and
+/// OpenAI-style `{"error": {"message": ...}}` on the proxy.
+fn error_message(body: &str) -> String {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(body) else { return body.to_string() };
+    json["error"].as_str()
+        .or_else(|| json["error"]["message"].as_str())
+        .map_or_else(|| body.to_string(), str::to_string)
+}
+
+/// Percent-encodes the characters that would change a path's meaning.
+fn path_segment(id: &str) -> String {
+    id.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+/// One server-sent event block; `None` for keep-alives and unknown events.
+fn parse_event(block: &str) -> Result<Option<Event>> {
+    let mut name = "message";
+    let mut data = String::new();
+    for line in block.lines() {
+        if let Some(v) = line.strip_prefix("event:") {
+            name = v.trim();
+        } else if let Some(v) = line.strip_prefix("data:") {
+            if !data.is_empty() { data.push('\n'); }
+            data.push_str(v.strip_prefix(' ').unwrap_or(v));
+        }
+    }
+    Ok(match name {
+        "intervention" => Some(Event::Intervention(Box::new(serde_json::from_str(&data)?))),
+        "lagged" => Some(Event::Lagged(data.trim().parse().unwrap_or_default())),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_stream_events_and_errors() {
+        let block = "event: intervention\nid: 7\ndata: {\"id\":7,\"timestamp\":1,\"session_id\":\"a/b\",\"detector\":\"leak\",\"reason\":\"Secret\",\"content_snippet\":\"\",\"savings_est\":0.0}\n\n";
+        match parse_event(block).unwrap() {
+            Some(Event::Intervention(log)) => assert_eq!((log.id, log.detector.as_str()), (7, "leak")),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse_event("event: lagged\ndata: 12\n\n").unwrap(), Some(Event::Lagged(12))));
+        assert!(parse_event(":\n\n").unwrap().is_none());
+
+        assert_eq!(error_message(r#"{"error":"Session not found"}"#), "Session not found");
+        assert_eq!(error_message(r#"{"error":{"message":"Loop Detected","type":"sentinel_blocked"}}"#), "Loop Detected");
+        assert_eq!(path_segment("team/a b"), "team%2Fa%20b");
+    }
+}
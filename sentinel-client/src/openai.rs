@@ -0,0 +1,73 @@
+use async_openai::config::{Config, OpenAIConfig};
+use reqwest::header::{HeaderMap, HeaderValue};
+use secrecy::SecretString;
+
+use crate::SESSION_HEADER;
+
+/// `async-openai` configuration that sends requests through Sentinel and
+/// tags each one with `x-sentinel-session`, so loop and budget tracking work
+/// without touching call sites:
+///
+/// ```no_run
+/// let config = sentinel_client::SentinelConfig::new("http://127.0.0.1:3000", "agent-42");
+/// let openai = async_openai::Client::with_config(config);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SentinelConfig {
+    inner: OpenAIConfig,
+    session: String,
+}
+
+impl SentinelConfig {
+    /// The provider key is read from `OPENAI_API_KEY`, as `OpenAIConfig` does.
+    pub fn new(sentinel_url: &str, session: impl Into<String>) -> Self {
+        let base = format!("{}/v1", sentinel_url.trim_end_matches('/'));
+        Self { inner: OpenAIConfig::new().with_api_base(base), session: session.into() }
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.inner = self.inner.with_api_key(api_key);
+        self
+    }
+
+    pub fn with_org_id(mut self, org_id: impl Into<String>) -> Self {
+        self.inner = self.inner.with_org_id(org_id);
+        self
+    }
+
+    /// Same settings, another session: one per agent run.
+    pub fn with_session(&self, session: impl Into<String>) -> Self {
+        Self { inner: self.inner.clone(), session: session.into() }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+}
+
+impl Config for SentinelConfig {
+    fn headers(&self) -> HeaderMap {
+        let mut headers = self.inner.headers();
+        // Ids that aren't valid header values fall back to the body's `user`.
+        if let Ok(value) = HeaderValue::from_str(&self.session) {
+            headers.insert(SESSION_HEADER, value);
+        }
+        headers
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.inner.url(path)
+    }
+
+    fn query(&self) -> Vec<(&str, &str)> {
+        self.inner.query()
+    }
+
+    fn api_base(&self) -> &str {
+        self.inner.api_base()
+    }
+
+    fn api_key(&self) -> &SecretString {
+        self.inner.api_key()
+    }
+}
@@ -0,0 +1,66 @@
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::config::AlertPolicy;
+
+// --- BUDGET ALERTS ---
+// Warnings only: alerts never block traffic, the economic throttle does that.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetAlert {
+    pub timestamp: u64,
+    /// What the budget belongs to: "session" or "pool".
+    pub scope: &'static str,
+    pub id: String,
+    /// Fraction of the budget that was crossed (e.g. 0.8).
+    pub level: f64,
+    pub spent_usd: f64,
+    pub budget_usd: f64,
+}
+
+/// Returns the alert levels crossed when spend moves from `before` to `after`.
+pub fn crossed_levels(before: f64, after: f64, budget: f64, levels: &[f64]) -> Vec<f64> {
+    if budget <= 0.0 {
+        return Vec::new();
+    }
+    levels
+        .iter()
+        .copied()
+        .filter(|level| before < level * budget && after >= level * budget)
+        .collect()
+}
+
+/// Logs the alert and, if configured, POSTs it to the webhook in the background.
+pub fn dispatch(client: &Client, policy: &AlertPolicy, alert: BudgetAlert) {
+    tracing::warn!(
+        "💸 Budget alert: {} '{}' crossed {:.0}% (${:.4} of ${:.2})",
+        alert.scope, alert.id, alert.level * 100.0, alert.spent_usd, alert.budget_usd
+    );
+
+    if let Some(url) = policy.webhook_url.clone() {
+        let client = client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.post(&url).json(&alert).send().await {
+                tracing::error!("Budget alert webhook failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossed_levels_single_step() {
+        let levels = [0.5, 0.8, 1.0];
+        assert_eq!(crossed_levels(4.0, 6.0, 10.0, &levels), vec![0.5]);
+        assert!(crossed_levels(6.0, 7.0, 10.0, &levels).is_empty());
+    }
+
+    #[test]
+    fn test_crossed_levels_jump() {
+        let levels = [0.5, 0.8, 1.0];
+        assert_eq!(crossed_levels(1.0, 12.0, 10.0, &levels), vec![0.5, 0.8, 1.0]);
+    }
+}
@@ -0,0 +1,1053 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::{AuditPolicy, Config, SnippetMode, StorageKind};
+use crate::embedded::EmbeddedStore;
+use crate::{AppState, upstream};
+
+// --- AUDIT LOGS ---
+// Every intervention is appended to a JSONL file (one snapshot per line; a
+// later line with the same id supersedes an earlier one, which is how
+// feedback updates are persisted). The in-memory ring only holds the newest
+// entries as a hot cache for the dashboard.
+
+pub const MAX_AUDIT_LOGS: usize = 50;
+
+/// `tracing` target of the per-intervention event, so log output can route it
+/// to its own file.
+pub const AUDIT_TARGET: &str = "sentinel::audit";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterventionLog {
+    pub id: u64,
+    pub timestamp: u64,
+    pub session_id: String,
+    /// Stable detector key ("semantic_loop", "leak", ...) used for precision stats.
+    pub detector: String,
+    pub reason: String,
+    pub content_snippet: String,
+    pub savings_est: f64,
+    #[serde(default)]
+    pub feedback: Option<Verdict>,
+    /// The detector fired but an exemption let the request through.
+    #[serde(default)]
+    pub bypassed: bool,
+    /// The detector fired in `log` or `warn` mode, so nothing was blocked.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Token usage of the upstream call, when one was made.
+    #[serde(default)]
+    pub prompt_tokens: Option<u64>,
+    #[serde(default)]
+    pub completion_tokens: Option<u64>,
+    /// Hash of the entry before this one in the chain (see `chain_hash`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// HMAC of `hash` on checkpoint entries, when a signing key is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// SHA-256 of `content_snippet`, kept when the snippet was erased so the
+    /// chain still verifies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet_digest: Option<String>,
+    /// Original request, kept for replay. Not exposed through the logs API.
+    #[serde(skip)]
+    pub request: Option<upstream::StoredRequest>,
+}
+
+impl InterventionLog {
+    /// Whether Sentinel actually stepped in, as opposed to only noting a hit.
+    pub fn enforced(&self) -> bool {
+        !self.bypassed && !self.dry_run
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Correct,
+    FalsePositive,
+}
+
+/// Feedback counts per detector. Kept outside the log ring buffer so
+/// precision survives log eviction.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FeedbackTally {
+    pub correct: u64,
+    pub false_positive: u64,
+}
+
+impl FeedbackTally {
+    pub fn add(&mut self, verdict: Verdict, delta: i64) {
+        let slot = match verdict {
+            Verdict::Correct => &mut self.correct,
+            Verdict::FalsePositive => &mut self.false_positive,
+        };
+        *slot = slot.saturating_add_signed(delta);
+    }
+
+    pub fn precision(&self) -> Option<f64> {
+        let total = self.correct + self.false_positive;
+        (total > 0).then(|| self.correct as f64 / total as f64)
+    }
+}
+
+// --- HASH CHAIN ---
+// Each new entry carries the SHA-256 of the previous entry's hash and its
+// own content, so editing, deleting or reordering a line breaks the chain
+// from there on. Feedback is an annotation added later and is not covered;
+// the snippet is covered by its digest, so a data-subject erasure can blank
+// it without breaking the chain.
+// With `SENTINEL_AUDIT_SIGNING_KEY`, every `SENTINEL_AUDIT_CHECKPOINT_EVERY`-th
+// entry also carries an HMAC of its hash, so rewriting the whole chain needs
+// the key. Retention drops the oldest entries, so verification anchors on
+// the oldest one retained.
+
+/// `prev_hash` of the very first entry.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The hash `log` must carry when it follows `prev`.
+pub fn chain_hash(prev: &str, log: &InterventionLog) -> String {
+    let snippet = log.snippet_digest.clone().unwrap_or_else(|| sha256(&log.content_snippet));
+    let content = serde_json::json!([
+        log.id, log.timestamp, log.session_id, log.detector, log.reason, snippet,
+        log.savings_est, log.bypassed, log.dry_run, log.model, log.provider, log.tenant,
+        log.prompt_tokens, log.completion_tokens,
+    ]);
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(content.to_string().as_bytes());
+    hex(&hasher.finalize())
+}
+
+pub(crate) fn sha256(text: &str) -> String {
+    hex(&Sha256::digest(text.as_bytes()))
+}
+
+fn checkpoint_mac(key: &str, hash: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(hash.as_bytes());
+    mac
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Result of `GET /api/logs/verify`.
+#[derive(Debug, Default, Serialize)]
+pub struct ChainReport {
+    pub ok: bool,
+    /// Chained entries checked.
+    pub verified: usize,
+    /// Older entries written before the chain existed.
+    pub unchained: usize,
+    pub checkpoints: usize,
+    /// Newest entry covered by a valid signature; anything after it could
+    /// have been rewritten by whoever can write the file.
+    pub signed_through: Option<u64>,
+    /// Hash of the newest entry; keep a copy to detect truncation later.
+    pub head: Option<String>,
+    pub broken_at: Option<u64>,
+    pub problem: Option<String>,
+}
+
+/// Walks `logs` (oldest first) and reports the first entry that does not
+/// hash to its recorded value or does not follow its predecessor.
+/// Checkpoint signatures are only checked when the signing key is set.
+pub fn verify_chain(logs: &[InterventionLog], policy: &AuditPolicy) -> ChainReport {
+    let mut report = ChainReport::default();
+    let mut prev: Option<&str> = None;
+    for log in logs {
+        let broken = |problem: String| ChainReport { ok: false, broken_at: Some(log.id), problem: Some(problem), ..Default::default() };
+        let Some(hash) = log.hash.as_deref() else {
+            if prev.is_some() {
+                return broken(format!("entry {} has no hash", log.id));
+            }
+            report.unchained += 1;
+            continue;
+        };
+        let recorded_prev = log.prev_hash.as_deref().unwrap_or_default();
+        if prev.is_some_and(|p| p != recorded_prev) {
+            return broken(format!("entry {} does not follow the entry before it (removed or reordered)", log.id));
+        }
+        if chain_hash(recorded_prev, log) != hash {
+            return broken(format!("entry {} does not match its hash (modified)", log.id));
+        }
+        if let Some(key) = &policy.signing_key {
+            match &log.signature {
+                Some(signature) if hex(&checkpoint_mac(key, hash).finalize().into_bytes()) == *signature => {
+                    report.checkpoints += 1;
+                    report.signed_through = Some(log.id);
+                }
+                Some(_) => return broken(format!("checkpoint {} has an invalid signature", log.id)),
+                // Once signing started, a checkpoint can't just lose its signature.
+                None if report.signed_through.is_some() && log.id % policy.checkpoint_every.max(1) == 0 => {
+                    return broken(format!("checkpoint {} is not signed", log.id));
+                }
+                None => {}
+            }
+        }
+        report.verified += 1;
+        prev = Some(hash);
+    }
+    ChainReport { ok: true, head: prev.map(str::to_string), ..report }
+}
+
+// --- DURABLE STORE ---
+// The JSONL file by default; with `SENTINEL_STORAGE_KIND=embedded` or
+// `postgres` the same operations go to that store (`embedded.rs`,
+// `postgres.rs`) and the file is not used.
+
+pub struct AuditStore {
+    path: Option<PathBuf>,
+    policy: AuditPolicy,
+    /// Append handle; also serializes appends against compaction.
+    file: std::sync::Mutex<Option<std::fs::File>>,
+    /// Hash of the newest chained entry.
+    head: std::sync::Mutex<String>,
+    embedded: Option<Arc<EmbeddedStore>>,
+    #[cfg(feature = "postgres")]
+    postgres: Option<crate::postgres::PgStore>,
+}
+
+impl AuditStore {
+    /// Opens (creating if needed) the log file. A store without a path keeps
+    /// nothing beyond the in-memory ring.
+    pub fn open(policy: &AuditPolicy) -> Self {
+        let path = policy.path.as_ref().map(PathBuf::from);
+        let file = path.as_ref().and_then(|p| {
+            std::fs::OpenOptions::new().create(true).append(true).open(p)
+                .inspect_err(|e| tracing::error!("Cannot open audit log {}: {}", p.display(), e))
+                .ok()
+        });
+        Self {
+            path,
+            policy: policy.clone(),
+            file: std::sync::Mutex::new(file),
+            head: std::sync::Mutex::new(GENESIS_HASH.to_string()),
+            embedded: None,
+            #[cfg(feature = "postgres")]
+            postgres: None,
+        }
+    }
+
+    /// The store `storage.kind` selects; `embedded` is the already opened
+    /// embedded store. Exits when Postgres is selected but unusable, rather
+    /// than silently dropping the audit trail.
+    pub fn for_config(config: &Config, embedded: Option<Arc<EmbeddedStore>>) -> Self {
+        let without_file = AuditPolicy { path: None, ..config.audit.clone() };
+        match config.storage.kind {
+            StorageKind::File => return Self::open(&config.audit),
+            StorageKind::Embedded => return Self { embedded, ..Self::open(&without_file) },
+            StorageKind::Postgres => {}
+        }
+        #[cfg(feature = "postgres")]
+        let connected = crate::postgres::PgStore::connect(&config.storage).map(|pg| Self {
+            postgres: Some(pg),
+            ..Self::open(&without_file)
+        });
+        #[cfg(not(feature = "postgres"))]
+        let connected: Result<Self, String> = Err("built without the `postgres` feature".to_string());
+        match connected {
+            Ok(store) => {
+                tracing::info!("Audit log stored in Postgres");
+                store
+            }
+            Err(e) => {
+                tracing::error!("SENTINEL_STORAGE_KIND=postgres: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn is_durable(&self) -> bool {
+        if self.embedded.is_some() {
+            return true;
+        }
+        #[cfg(feature = "postgres")]
+        if self.postgres.is_some() {
+            return true;
+        }
+        self.path.is_some()
+    }
+
+    /// Whether the backing store can be used right now, for `/readyz`.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(embedded) = &self.embedded {
+            return embedded.check().map_err(|e| e.to_string());
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            return pg.ping().map_err(|e| e.to_string());
+        }
+        match &self.path {
+            Some(path) if self.file.lock().unwrap().is_none() => Err(format!("cannot open audit log {}", path.display())),
+            _ => Ok(()),
+        }
+    }
+
+    /// The Postgres store, which also keeps sessions, spend, prices and the
+    /// shared counters (`counters.rs`).
+    #[cfg(feature = "postgres")]
+    pub fn postgres(&self) -> Option<&crate::postgres::PgStore> {
+        self.postgres.as_ref()
+    }
+
+    /// Writes a snapshot of an entry that is already chained.
+    pub fn append(&self, log: &InterventionLog) {
+        let mut guard = self.file.lock().unwrap();
+        self.persist(&mut guard, log);
+    }
+
+    /// Gives a new entry the next id from `ids`, links it to the chain and
+    /// writes it. Ids are taken under the append lock so the chain runs in
+    /// id order.
+    pub fn chain(&self, log: &mut InterventionLog, ids: &AtomicU64) {
+        let mut guard = self.file.lock().unwrap();
+        let mut head = self.head.lock().unwrap();
+        log.id = ids.fetch_add(1, Ordering::Relaxed);
+        match self.policy.snippets {
+            SnippetMode::Raw => {}
+            SnippetMode::Hash => erase_snippet(log),
+            SnippetMode::None => log.content_snippet.clear(),
+        }
+        let hash = chain_hash(&head, log);
+        if let Some(key) = &self.policy.signing_key
+            && log.id.is_multiple_of(self.policy.checkpoint_every.max(1)) {
+            log.signature = Some(hex(&checkpoint_mac(key, &hash).finalize().into_bytes()));
+        }
+        log.prev_hash = Some(std::mem::replace(&mut *head, hash.clone()));
+        log.hash = Some(hash);
+        self.persist(&mut guard, log);
+    }
+
+    /// Blanks the snippets of entries `erase` selects, in every retained
+    /// snapshot, and rewrites the file so the old lines are gone. Returns
+    /// how many entries were erased.
+    pub fn erase(&self, erase: impl Fn(&InterventionLog) -> bool) -> std::io::Result<usize> {
+        let mut erased = 0;
+        self.rewrite(|_| true, |log| {
+            if erase(log) && log.snippet_digest.is_none() {
+                erase_snippet(log);
+                erased += 1;
+            }
+        })?;
+        Ok(erased)
+    }
+
+    /// Continues the chain after `hash` (the newest entry restored at startup).
+    pub fn resume_chain(&self, hash: &str) {
+        *self.head.lock().unwrap() = hash.to_string();
+    }
+
+    fn persist(&self, file: &mut Option<std::fs::File>, log: &InterventionLog) {
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            pg.upsert(log);
+            return;
+        }
+        if let Some(store) = &self.embedded {
+            if let Err(e) = store.upsert(log) {
+                tracing::error!("Audit log write failed: {}", e);
+            }
+            return;
+        }
+        Self::write(file, log);
+    }
+
+    fn write(file: &mut Option<std::fs::File>, log: &InterventionLog) {
+        let Some(file) = file.as_mut() else { return };
+        let line = serde_json::to_string(log).unwrap_or_default();
+        if let Err(e) = writeln!(file, "{}", line) {
+            tracing::error!("Audit log write failed: {}", e);
+        }
+    }
+
+    /// All retained entries, oldest first, with superseded snapshots folded.
+    pub fn load(&self) -> Vec<InterventionLog> {
+        let _guard = self.file.lock().unwrap();
+        self.read_retained(crate::now_secs())
+    }
+
+    fn read_retained(&self, now: u64) -> Vec<InterventionLog> {
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            return pg.load(&self.policy, now)
+                .inspect_err(|e| tracing::error!("Reading the audit log from Postgres failed: {}", e))
+                .unwrap_or_default();
+        }
+        if let Some(store) = &self.embedded {
+            return store.load(&self.policy, now)
+                .inspect_err(|e| tracing::error!("Reading the embedded audit log failed: {}", e))
+                .unwrap_or_default();
+        }
+        let Some(path) = &self.path else { return Vec::new() };
+        let Ok(file) = std::fs::File::open(path) else { return Vec::new() };
+
+        let mut by_id: BTreeMap<u64, InterventionLog> = BTreeMap::new();
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            if let Ok(log) = serde_json::from_str::<InterventionLog>(&line) {
+                by_id.insert(log.id, log);
+            }
+        }
+        let mut logs: Vec<InterventionLog> = by_id.into_values()
+            .filter(|l| self.policy.retention_secs == 0 || now.saturating_sub(l.timestamp) <= self.policy.retention_secs)
+            .collect();
+        if self.policy.max_entries > 0 && logs.len() > self.policy.max_entries {
+            logs.drain(..logs.len() - self.policy.max_entries);
+        }
+        logs
+    }
+
+    /// Rewrites the file with only retained, deduplicated entries, applying
+    /// the snippet policy to older ones. Returns how many lines were dropped.
+    pub fn compact(&self) -> std::io::Result<usize> {
+        let now = crate::now_secs();
+        self.rewrite(|_| true, |log| { scrub(&self.policy, log, now); })
+    }
+
+    /// Highest id the archiver (`archive.rs`) has uploaded; 0 before the first upload.
+    pub fn archived_through(&self) -> u64 {
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            return pg.archived_through();
+        }
+        if let Some(store) = &self.embedded {
+            return store.archived_through()
+                .inspect_err(|e| tracing::error!("Reading the archive watermark failed: {}", e))
+                .unwrap_or(0);
+        }
+        self.watermark_path()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Records that entries up to `id` are archived, then drops those older
+    /// than `keep_secs` from the file. Returns how many lines were dropped.
+    pub fn mark_archived(&self, id: u64, keep_secs: u64) -> std::io::Result<usize> {
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            pg.set_archived_through(id).map_err(std::io::Error::other)?;
+            return self.trim_archived(id, keep_secs);
+        }
+        if let Some(store) = &self.embedded {
+            store.set_archived_through(id).map_err(std::io::Error::other)?;
+            return self.trim_archived(id, keep_secs);
+        }
+        let Some(watermark) = self.watermark_path() else { return Ok(0) };
+        let tmp = watermark.with_extension("archived.tmp");
+        std::fs::write(&tmp, id.to_string())?;
+        std::fs::rename(&tmp, &watermark)?;
+        self.trim_archived(id, keep_secs)
+    }
+
+    fn trim_archived(&self, id: u64, keep_secs: u64) -> std::io::Result<usize> {
+        let now = crate::now_secs();
+        self.rewrite(|log| log.id > id || now.saturating_sub(log.timestamp) <= keep_secs, |_| {})
+    }
+
+    fn watermark_path(&self) -> Option<PathBuf> {
+        self.path.as_ref().map(|p| p.with_extension("archived"))
+    }
+
+    fn rewrite(&self, keep: impl Fn(&InterventionLog) -> bool, mut edit: impl FnMut(&mut InterventionLog)) -> std::io::Result<usize> {
+        #[cfg(feature = "postgres")]
+        if let Some(pg) = &self.postgres {
+            let _guard = self.file.lock().unwrap();
+            return pg.rewrite(&self.policy, keep, edit).map_err(std::io::Error::other);
+        }
+        if let Some(store) = &self.embedded {
+            let _guard = self.file.lock().unwrap();
+            return store.rewrite(&self.policy, keep, edit).map_err(std::io::Error::other);
+        }
+        let Some(path) = &self.path else { return Ok(0) };
+        let mut guard = self.file.lock().unwrap();
+
+        let before = std::fs::read_to_string(path).map(|s| s.lines().count()).unwrap_or(0);
+        let mut logs = self.read_retained(crate::now_secs());
+        logs.retain(keep);
+        logs.iter_mut().for_each(&mut edit);
+
+        let tmp = path.with_extension("jsonl.tmp");
+        {
+            let mut out = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+            for log in &logs {
+                writeln!(out, "{}", serde_json::to_string(log).unwrap_or_default())?;
+            }
+            out.flush()?;
+        }
+        std::fs::rename(&tmp, path)?;
+        *guard = Some(std::fs::OpenOptions::new().append(true).open(path)?);
+        Ok(before.saturating_sub(logs.len()))
+    }
+}
+
+/// Reduces a raw snippet to its digest once the snippet policy no longer
+/// allows it: the mode was switched away from `raw`, or the snippet is older
+/// than `snippet_retention_secs`. Returns whether it did.
+pub fn scrub(policy: &AuditPolicy, log: &mut InterventionLog, now: u64) -> bool {
+    let expired = policy.snippet_retention_secs > 0 && now.saturating_sub(log.timestamp) > policy.snippet_retention_secs;
+    let raw = !log.content_snippet.is_empty() || log.request.is_some();
+    if raw && (policy.snippets != SnippetMode::Raw || expired) {
+        erase_snippet(log);
+        return true;
+    }
+    false
+}
+
+/// Replaces an entry's snippet with its digest and drops the stored request.
+pub fn erase_snippet(log: &mut InterventionLog) {
+    if log.snippet_digest.is_none() {
+        log.snippet_digest = Some(sha256(&log.content_snippet));
+    }
+    log.content_snippet.clear();
+    log.request = None;
+}
+
+/// Compacts on startup and then every `compact_interval_secs`.
+pub fn spawn_compactor(state: AppState) {
+    if !state.audit.is_durable() { return; }
+    tokio::spawn(async move {
+        let every = std::time::Duration::from_secs(state.config.audit.compact_interval_secs.max(1));
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            let store = state.audit.clone();
+            match tokio::task::spawn_blocking(move || store.compact()).await {
+                Ok(Ok(dropped)) if dropped > 0 => tracing::info!("Audit log compacted, {} stale lines dropped", dropped),
+                Ok(Err(e)) => tracing::error!("Audit log compaction failed: {}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+fn to_micros(usd: f64) -> u64 {
+    (usd.max(0.0) * 1_000_000.0).round() as u64
+}
+
+/// Seeds the hot cache, id counter, savings total and feedback tallies from
+/// the durable log.
+pub fn restore(state: &AppState) {
+    let logs = state.audit.load();
+    if let Some(hash) = logs.last().and_then(|l| l.hash.as_deref()) {
+        state.audit.resume_chain(hash);
+    }
+    if logs.is_empty() { return; }
+
+    let next_id = logs.iter().map(|l| l.id).max().unwrap_or(0) + 1;
+    state.next_log_id.fetch_max(next_id, Ordering::Relaxed);
+    let saved: u64 = logs.iter().filter(|l| l.enforced()).map(|l| to_micros(l.savings_est)).sum();
+    state.saved_micro_usd.fetch_add(saved, Ordering::Relaxed);
+    for log in &logs {
+        if let Some(verdict) = log.feedback {
+            state.feedback.entry(log.detector.clone()).or_default().add(verdict, 1);
+        }
+    }
+    let hot: VecDeque<InterventionLog> = logs[logs.len().saturating_sub(MAX_AUDIT_LOGS)..].iter().cloned().collect();
+    tracing::info!("Restored {} audit entries ({} cached)", logs.len(), hot.len());
+    if let Ok(mut ring) = state.audit_logs.try_lock() {
+        *ring = hot;
+    }
+}
+
+// --- RECORDING ---
+
+/// Request metadata stamped onto every entry logged for it.
+#[derive(Debug, Clone, Default)]
+pub struct LogContext {
+    pub session_id: String,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub tenant: Option<String>,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+}
+
+impl LogContext {
+    pub fn new(session_id: &str, model: &str) -> Self {
+        Self { session_id: session_id.to_string(), model: Some(model.to_string()), ..Default::default() }
+    }
+
+    pub fn provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
+    }
+
+    pub fn tenant(mut self, tenant: Option<&str>) -> Self {
+        self.tenant = tenant.map(str::to_string);
+        self
+    }
+
+    /// Copies token counts from a completion body's `usage` object.
+    pub fn with_usage(&self, body: &serde_json::Value) -> Self {
+        let mut ctx = self.clone();
+        ctx.prompt_tokens = body["usage"]["prompt_tokens"].as_u64();
+        ctx.completion_tokens = body["usage"]["completion_tokens"].as_u64();
+        ctx
+    }
+}
+
+/// Appends an intervention to the audit log and returns its id.
+pub async fn record_intervention(
+    state: &AppState,
+    ctx: &LogContext,
+    detector: &str,
+    reason: &str,
+    content_snippet: String,
+    savings_est: f64,
+) -> u64 {
+    push_log(state, ctx, detector, reason, content_snippet, savings_est, Outcome::Enforced).await
+}
+
+/// Records that `detector` fired on an exempt session, for traceability.
+pub async fn record_bypass(state: &AppState, ctx: &LogContext, detector: &str, reason: &str) -> u64 {
+    push_log(state, ctx, detector, &format!("Exempted: {}", reason), String::new(), 0.0, Outcome::Bypassed).await
+}
+
+/// Records a hit by a detector in `log` / `warn` mode. Not counted as an
+/// intervention and saves nothing.
+pub async fn record_dry_run(state: &AppState, ctx: &LogContext, detector: &str, reason: &str, content_snippet: String) -> u64 {
+    push_log(state, ctx, detector, &format!("Dry run: {}", reason), content_snippet, 0.0, Outcome::DryRun).await
+}
+
+/// Records a change made to a request before it was forwarded. Like a dry
+/// run, not counted as an intervention.
+pub async fn record_rewrite(state: &AppState, ctx: &LogContext, detector: &str, change: &str) -> u64 {
+    push_log(state, ctx, detector, &format!("Rewritten: {}", change), String::new(), 0.0, Outcome::DryRun).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Enforced,
+    Bypassed,
+    DryRun,
+}
+
+async fn push_log(
+    state: &AppState,
+    ctx: &LogContext,
+    detector: &str,
+    reason: &str,
+    content_snippet: String,
+    savings_est: f64,
+    outcome: Outcome,
+) -> u64 {
+    let mut log = InterventionLog {
+        id: 0,
+        timestamp: crate::now_secs(),
+        session_id: ctx.session_id.clone(),
+        detector: detector.to_string(),
+        reason: reason.to_string(),
+        content_snippet,
+        savings_est,
+        feedback: None,
+        bypassed: outcome == Outcome::Bypassed,
+        dry_run: outcome == Outcome::DryRun,
+        model: ctx.model.clone(),
+        provider: ctx.provider.clone(),
+        tenant: ctx.tenant.clone(),
+        prompt_tokens: ctx.prompt_tokens,
+        completion_tokens: ctx.completion_tokens,
+        prev_hash: None,
+        hash: None,
+        signature: None,
+        snippet_digest: None,
+        request: None,
+    };
+    state.audit.chain(&mut log, &state.next_log_id);
+    let id = log.id;
+    state.detectors.record_trigger(detector);
+    tracing::info!(
+        target: AUDIT_TARGET,
+        id,
+        session_id = %log.session_id,
+        detector = %log.detector,
+        bypassed = log.bypassed,
+        dry_run = log.dry_run,
+        savings_est,
+        "{}", log.reason
+    );
+    if log.enforced() {
+        if let Some(mut sess) = state.sessions.get_mut(&ctx.session_id) {
+            sess.record_intervention(detector);
+        }
+        state.timeseries.record_intervention(log.timestamp, detector);
+        state.timeseries.record_savings(log.timestamp, savings_est);
+        state.usage.record_intervention(log.timestamp, log.tenant.as_deref(), &log.session_id, detector, savings_est);
+        state.saved_micro_usd.fetch_add(to_micros(savings_est), Ordering::Relaxed);
+    }
+    // No subscribers is the common case, not an error.
+    let _ = state.live_logs.send(log.clone());
+    crate::notify::raise(state, crate::notify::Notice::intervention(&log, &state.config.notify));
+    crate::events::emit(state, crate::events::Event::Intervention(Box::new(log.clone())));
+
+    let mut logs = state.audit_logs.lock().await;
+    logs.push_back(log);
+    if logs.len() > MAX_AUDIT_LOGS { logs.pop_front(); }
+    id
+}
+
+/// Keeps the request behind an intervention so it can be replayed later.
+/// Only with raw snippets: the request carries the full prompt.
+pub async fn attach_request(state: &AppState, log_id: u64, request: upstream::StoredRequest) {
+    if state.config.audit.snippets != SnippetMode::Raw {
+        return;
+    }
+    let mut logs = state.audit_logs.lock().await;
+    if let Some(entry) = logs.iter_mut().find(|l| l.id == log_id) {
+        entry.request = Some(request);
+    }
+}
+
+// --- QUERYING ---
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogQuery {
+    pub session_id: Option<String>,
+    pub tenant: Option<String>,
+    pub detector: Option<String>,
+    /// Case-insensitive substring of `reason`.
+    pub reason: Option<String>,
+    /// Unix-second bounds, inclusive.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Case-insensitive full-text search over `content_snippet`.
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+    /// Cursor: only entries with an id lower than this (older).
+    pub before: Option<u64>,
+}
+
+impl LogQuery {
+    pub fn matches(&self, log: &InterventionLog) -> bool {
+        let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        self.session_id.as_ref().is_none_or(|s| &log.session_id == s)
+            && self.tenant.as_ref().is_none_or(|t| log.tenant.as_ref() == Some(t))
+            && self.detector.as_ref().is_none_or(|d| &log.detector == d)
+            && self.reason.as_ref().is_none_or(|r| contains(&log.reason, r))
+            && self.from.is_none_or(|from| log.timestamp >= from)
+            && self.to.is_none_or(|to| log.timestamp <= to)
+            && self.q.as_ref().is_none_or(|q| contains(&log.content_snippet, q))
+            && self.before.is_none_or(|before| log.id < before)
+    }
+
+    /// Whether the query can be answered from the hot cache alone.
+    pub fn is_plain(&self) -> bool {
+        self.session_id.is_none() && self.detector.is_none() && self.reason.is_none()
+            && self.from.is_none() && self.to.is_none() && self.q.is_none() && self.before.is_none()
+            && self.tenant.is_none()
+    }
+}
+
+/// Returns the newest `limit` matches in chronological order, plus the total
+/// number of matches ignoring `limit`.
+pub fn query_logs<'a>(logs: impl DoubleEndedIterator<Item = &'a InterventionLog>, query: &LogQuery) -> (usize, Vec<InterventionLog>) {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let mut total = 0;
+    let mut page: Vec<InterventionLog> = Vec::new();
+    for log in logs.rev().filter(|l| query.matches(l)) {
+        total += 1;
+        if page.len() < limit { page.push(log.clone()); }
+    }
+    page.reverse();
+    (total, page)
+}
+
+// --- EXPORT ---
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+pub const CSV_HEADER: &str = "id,timestamp,session_id,detector,reason,content_snippet,savings_est,feedback,bypassed,model,provider,prompt_tokens,completion_tokens,dry_run,tenant\n";
+
+/// One export record, newline-terminated.
+pub fn export_line(log: &InterventionLog, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Jsonl => serde_json::to_string(log).unwrap_or_default() + "\n",
+        ExportFormat::Csv => {
+            let feedback = match log.feedback {
+                Some(Verdict::Correct) => "correct",
+                Some(Verdict::FalsePositive) => "false_positive",
+                None => "",
+            };
+            let opt = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_default();
+            let fields = [
+                log.id.to_string(),
+                log.timestamp.to_string(),
+                csv_escape(&log.session_id),
+                csv_escape(&log.detector),
+                csv_escape(&log.reason),
+                csv_escape(&log.content_snippet),
+                log.savings_est.to_string(),
+                feedback.to_string(),
+                log.bypassed.to_string(),
+                csv_escape(log.model.as_deref().unwrap_or_default()),
+                csv_escape(log.provider.as_deref().unwrap_or_default()),
+                opt(log.prompt_tokens),
+                opt(log.completion_tokens),
+                log.dry_run.to_string(),
+                csv_escape(log.tenant.as_deref().unwrap_or_default()),
+            ];
+            fields.join(",") + "\n"
+        }
+    }
+}
+
+/// RFC 4180 quoting. Leading formula characters are neutralised so the file
+/// is safe to open in a spreadsheet.
+fn csv_escape(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) { format!("'{}", value) } else { value.to_string() };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: u64, session_id: &str, detector: &str, snippet: &str) -> InterventionLog {
+        InterventionLog {
+            id,
+            timestamp: 1_000 + id,
+            session_id: session_id.to_string(),
+            detector: detector.to_string(),
+            reason: format!("{} fired", detector),
+            content_snippet: snippet.to_string(),
+            savings_est: 0.5,
+            feedback: None,
+            bypassed: false,
+            dry_run: false,
+            model: Some("gpt-4o".to_string()),
+            provider: None,
+            tenant: None,
+            prompt_tokens: Some(10),
+            completion_tokens: None,
+            prev_hash: None,
+            hash: None,
+            signature: None,
+            snippet_digest: None,
+            request: None,
+        }
+    }
+
+    #[test]
+    fn test_feedback_tally_precision() {
+        let mut tally = FeedbackTally::default();
+        assert_eq!(tally.precision(), None);
+        tally.add(Verdict::Correct, 1);
+        tally.add(Verdict::Correct, 1);
+        tally.add(Verdict::FalsePositive, 1);
+        tally.add(Verdict::Correct, -1);
+        assert_eq!(tally.precision(), Some(0.5));
+    }
+
+    #[test]
+    fn test_query_logs_filters_and_paginates() {
+        let logs = [
+            log(1, "a", "fuzzy_loop", "Analyzing system logs"),
+            log(2, "b", "leak", "[REDACTED]"),
+            log(3, "a", "fuzzy_loop", "analyzing again"),
+            log(4, "a", "semantic_loop", "something else"),
+        ];
+        let query = LogQuery { session_id: Some("a".into()), q: Some("ANALYZING".into()), ..Default::default() };
+        let (total, page) = query_logs(logs.iter(), &query);
+        assert_eq!(total, 2);
+        assert_eq!(page.iter().map(|l| l.id).collect::<Vec<_>>(), vec![1, 3]);
+
+        let query = LogQuery { limit: Some(2), ..Default::default() };
+        let (total, page) = query_logs(logs.iter(), &query);
+        assert_eq!(total, 4);
+        assert_eq!(page.iter().map(|l| l.id).collect::<Vec<_>>(), vec![3, 4]);
+
+        let query = LogQuery { limit: Some(2), before: Some(3), ..Default::default() };
+        let (_, page) = query_logs(logs.iter(), &query);
+        assert_eq!(page.iter().map(|l| l.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        let query = LogQuery { from: Some(1_002), to: Some(1_003), ..Default::default() };
+        assert_eq!(query_logs(logs.iter(), &query).0, 2);
+    }
+
+    #[test]
+    fn test_csv_export_escapes_fields() {
+        let mut entry = log(7, "s,1", "leak", "said \"hi\"\nthen =SUM(A1)");
+        entry.reason = "=cmd".to_string();
+        entry.tenant = Some("acme".to_string());
+        let line = export_line(&entry, ExportFormat::Csv);
+        assert_eq!(line, "7,1007,\"s,1\",leak,'=cmd,\"said \"\"hi\"\"\nthen =SUM(A1)\",0.5,,false,gpt-4o,,10,,false,acme\n");
+        assert_eq!(CSV_HEADER.matches(',').count(), 14);
+        entry.tenant = None;
+        assert!(export_line(&entry, ExportFormat::Csv).ends_with(",false,\n"));
+
+        let json: InterventionLog = serde_json::from_str(&export_line(&entry, ExportFormat::Jsonl)).unwrap();
+        assert_eq!(json.model.as_deref(), Some("gpt-4o"));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_interventions_reach_live_subscribers() {
+        let state = AppState::for_tests(crate::config::Config::default());
+        let mut rx = state.live_logs.subscribe();
+        let ctx = LogContext::new("agent", "gpt-4o-mini").provider("openai");
+        let id = record_intervention(&state, &ctx, "leak", "Leak", String::new(), 0.1).await;
+
+        let live = rx.try_recv().unwrap();
+        assert_eq!(live.id, id);
+        assert_eq!(live.provider.as_deref(), Some("openai"));
+    }
+
+    #[test]
+    fn test_store_supersedes_and_compacts() {
+        let path = std::env::temp_dir().join(format!("sentinel-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let policy = AuditPolicy {
+            path: Some(path.to_string_lossy().into_owned()),
+            retention_secs: 0,
+            max_entries: 2,
+            compact_interval_secs: 3600,
+            ..Default::default()
+        };
+        let store = AuditStore::open(&policy);
+        store.append(&log(1, "a", "leak", ""));
+        store.append(&log(2, "a", "leak", ""));
+        let mut updated = log(2, "a", "leak", "");
+        updated.feedback = Some(Verdict::FalsePositive);
+        store.append(&updated);
+        store.append(&log(3, "a", "leak", ""));
+
+        let loaded = store.load();
+        assert_eq!(loaded.iter().map(|l| l.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(loaded[0].feedback, Some(Verdict::FalsePositive));
+
+        assert_eq!(store.compact().unwrap(), 2);
+        store.append(&log(4, "a", "leak", ""));
+        assert_eq!(store.load().iter().map(|l| l.id).collect::<Vec<_>>(), vec![3, 4]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_queries_reach_past_the_hot_cache() {
+        use axum::extract::{Query, State};
+        use axum::response::IntoResponse;
+
+        let path = std::env::temp_dir().join(format!("sentinel-audit-tenant-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit = AuditPolicy { path: Some(path.to_string_lossy().into_owned()), retention_secs: 0, ..Default::default() };
+        let state = AppState::for_tests(crate::config::Config { audit, ..Default::default() });
+        {
+            let mut cache = state.audit_logs.lock().await;
+            for id in 1..=MAX_AUDIT_LOGS as u64 + 10 {
+                let mut entry = log(id, "s", "leak", "");
+                entry.tenant = (id <= 5).then(|| "acme".to_string());
+                state.audit.append(&entry);
+                cache.push_back(entry);
+                if cache.len() > MAX_AUDIT_LOGS {
+                    cache.pop_front();
+                }
+            }
+        }
+
+        // acme's entries have all left the cache but not the durable log.
+        let query = LogQuery { tenant: Some("acme".into()), ..Default::default() };
+        let response = crate::get_logs(State(state), Query(query)).await.into_response();
+        assert_eq!(response.headers()["x-total-count"], "5");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_chain_detects_edits_and_gaps() {
+        let policy = AuditPolicy { signing_key: Some("k".to_string()), checkpoint_every: 2, ..Default::default() };
+        let store = AuditStore::open(&policy);
+        let ids = AtomicU64::new(1);
+        let mut logs: Vec<InterventionLog> = (0..4).map(|_| {
+            let mut entry = log(0, "a", "leak", "secret");
+            store.chain(&mut entry, &ids);
+            entry
+        }).collect();
+        assert_eq!(logs[0].prev_hash.as_deref(), Some(GENESIS_HASH));
+        let report = verify_chain(&logs, &policy);
+        assert!(report.ok);
+        assert_eq!((report.verified, report.checkpoints, report.signed_through), (4, 2, Some(4)));
+        assert_eq!(report.head, logs[3].hash);
+        assert!(!verify_chain(&logs, &AuditPolicy { signing_key: Some("other".to_string()), ..policy.clone() }).ok);
+
+        // Retention dropping the oldest entries is not tampering.
+        assert!(verify_chain(&logs[2..], &policy).ok);
+
+        let mut gap = logs.clone();
+        gap.remove(1);
+        assert_eq!(verify_chain(&gap, &policy).broken_at, Some(3));
+
+        let mut stripped = logs.clone();
+        stripped[3].signature = None;
+        assert_eq!(verify_chain(&stripped, &policy).broken_at, Some(4));
+
+        logs[2].feedback = Some(Verdict::FalsePositive);
+        erase_snippet(&mut logs[1]);
+        assert!(logs[1].content_snippet.is_empty());
+        assert!(verify_chain(&logs, &policy).ok);
+        logs[2].content_snippet = "redacted".to_string();
+        let report = verify_chain(&logs, &policy);
+        assert_eq!((report.ok, report.broken_at), (false, Some(3)));
+    }
+
+    #[test]
+    fn test_snippet_modes_and_retention() {
+        let ids = AtomicU64::new(1);
+        let hashed = AuditStore::open(&AuditPolicy { snippets: SnippetMode::Hash, ..Default::default() });
+        let mut entry = log(0, "a", "leak", "sk-live-123");
+        hashed.chain(&mut entry, &ids);
+        assert_eq!((entry.content_snippet.as_str(), entry.snippet_digest.as_deref()), ("", Some(sha256("sk-live-123").as_str())));
+
+        let dropped = AuditStore::open(&AuditPolicy { snippets: SnippetMode::None, ..Default::default() });
+        let mut entry = log(0, "a", "leak", "sk-live-123");
+        dropped.chain(&mut entry, &ids);
+        assert_eq!((entry.content_snippet.as_str(), entry.snippet_digest.as_deref()), ("", None));
+        assert!(verify_chain(&[entry], &AuditPolicy::default()).ok);
+
+        let policy = AuditPolicy { snippet_retention_secs: 100, ..Default::default() };
+        let mut entry = log(1, "a", "leak", "sk-live-123");
+        assert!(!scrub(&policy, &mut entry, 1_050));
+        assert!(scrub(&policy, &mut entry, 1_200));
+        assert!(entry.content_snippet.is_empty() && !scrub(&policy, &mut entry, 1_300));
+    }
+}
@@ -0,0 +1,191 @@
+use axum::{
+    Json,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use crate::AppState;
+use crate::client_ip::ClientIp;
+use crate::config::IpLimitPolicy;
+
+// --- IP RATE LIMITS AND BANS ---
+// An exposed Sentinel spends our upstream credits for whoever reaches it.
+// Each client IP (after `client_ip`'s proxy handling) may send
+// `SENTINEL_IP_RATE_LIMIT` proxy requests per `SENTINEL_IP_RATE_WINDOW_SECS`;
+// going over bans it for `SENTINEL_IP_BAN_SECS`. Operators can also ban and
+// unban addresses through `/api/bans`. Like session blocks, bans are kept in
+// memory and do not survive a restart.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    pub ip: IpAddr,
+    pub reason: String,
+    pub banned_at: u64,
+    /// `None` until an operator lifts it.
+    pub expires_at: Option<u64>,
+    /// Placed through the API rather than by the rate limit.
+    pub manual: bool,
+}
+
+impl Ban {
+    fn active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|t| now < t)
+    }
+}
+
+struct Window {
+    started_at: u64,
+    requests: u64,
+}
+
+#[derive(Default)]
+pub struct IpGuard {
+    windows: DashMap<IpAddr, Window>,
+    bans: DashMap<IpAddr, Ban>,
+}
+
+impl IpGuard {
+    /// Counts a request from `ip` and returns the ban it runs into, if any.
+    pub fn admit(&self, ip: IpAddr, policy: &IpLimitPolicy, now: u64) -> Option<Ban> {
+        if let Some(ban) = self.bans.get(&ip).filter(|b| b.active(now)) {
+            return Some(ban.clone());
+        }
+        self.bans.remove_if(&ip, |_, b| !b.active(now));
+        if policy.max_requests == 0 {
+            return None;
+        }
+        let mut window = self.windows.entry(ip).or_insert(Window { started_at: now, requests: 0 });
+        if now.saturating_sub(window.started_at) >= policy.window_secs {
+            *window = Window { started_at: now, requests: 0 };
+        }
+        window.requests += 1;
+        if window.requests <= policy.max_requests {
+            return None;
+        }
+        let reason = format!("More than {} requests in {}s", policy.max_requests, policy.window_secs);
+        if policy.ban_secs == 0 {
+            // Throttle only: refuse until the window rolls over.
+            return Some(Ban { ip, reason, banned_at: now, expires_at: Some(window.started_at + policy.window_secs), manual: false });
+        }
+        drop(window);
+        self.windows.remove(&ip);
+        tracing::warn!("🚫 {} banned for {}s: {}", ip, policy.ban_secs, reason);
+        let ban = Ban { ip, reason, banned_at: now, expires_at: Some(now + policy.ban_secs), manual: false };
+        self.bans.insert(ip, ban.clone());
+        Some(ban)
+    }
+
+    pub fn ban(&self, ip: IpAddr, reason: String, secs: Option<u64>, now: u64) -> Ban {
+        let ban = Ban { ip, reason, banned_at: now, expires_at: secs.map(|s| now + s), manual: true };
+        self.bans.insert(ip, ban.clone());
+        ban
+    }
+
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.windows.remove(&ip);
+        self.bans.remove(&ip).is_some()
+    }
+
+    /// Bans in force, newest first.
+    pub fn list(&self, now: u64) -> Vec<Ban> {
+        let mut bans: Vec<Ban> = self.bans.iter().filter(|b| b.active(now)).map(|b| b.clone()).collect();
+        bans.sort_by_key(|b| std::cmp::Reverse(b.banned_at));
+        bans
+    }
+
+    /// Forgets finished windows and expired bans.
+    pub fn sweep(&self, policy: &IpLimitPolicy, now: u64) {
+        self.windows.retain(|_, w| now.saturating_sub(w.started_at) < policy.window_secs);
+        self.bans.retain(|_, b| b.active(now));
+    }
+}
+
+/// Middleware on the proxy routes: 429 for the rate limit, 403 for an
+/// operator ban. Requests without a known client address pass.
+pub async fn guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(&ClientIp(ip)) = request.extensions().get::<ClientIp>() else {
+        return next.run(request).await;
+    };
+    let now = crate::now_secs();
+    let Some(ban) = state.ip_guard.admit(ip, &state.config.ip_limits, now) else {
+        return next.run(request).await;
+    };
+    let (status, code) = if ban.manual {
+        (StatusCode::FORBIDDEN, "ip_banned")
+    } else {
+        (StatusCode::TOO_MANY_REQUESTS, "rate_limited")
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(expires_at) = ban.expires_at {
+        headers.insert("retry-after", HeaderValue::from(expires_at.saturating_sub(now).max(1)));
+    }
+    (status, headers, Json(serde_json::json!({
+        "error": {
+            "message": format!("Sentinel refused requests from {}: {}", ip, ban.reason),
+            "type": "sentinel_rate_limited",
+            "param": null,
+            "code": code
+        }
+    }))).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    ip: IpAddr,
+    reason: Option<String>,
+    /// Omit for a ban that lasts until lifted.
+    secs: Option<u64>,
+}
+
+/// `GET /api/bans`
+pub async fn list(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.ip_guard.list(crate::now_secs()))
+}
+
+/// `POST /api/bans`
+pub async fn ban(State(state): State<AppState>, Json(req): Json<BanRequest>) -> impl IntoResponse {
+    let reason = req.reason.unwrap_or_else(|| "Banned by operator".to_string());
+    tracing::warn!("🚫 {} banned: {}", req.ip, reason);
+    Json(state.ip_guard.ban(req.ip, reason, req.secs, crate::now_secs()))
+}
+
+/// `DELETE /api/bans/{ip}`
+pub async fn unban(State(state): State<AppState>, Path(ip): Path<IpAddr>) -> impl IntoResponse {
+    if state.ip_guard.unban(ip) {
+        tracing::info!("{} unbanned", ip);
+        Json(serde_json::json!({"ip": ip, "banned": false})).into_response()
+    } else {
+        (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Address is not banned"}))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_bans_then_expires() {
+        let guard = IpGuard::default();
+        let policy = IpLimitPolicy { max_requests: 2, window_secs: 60, ban_secs: 300 };
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(guard.admit(ip, &policy, 1000).is_none());
+        assert!(guard.admit(ip, &policy, 1001).is_none());
+        let ban = guard.admit(ip, &policy, 1002).unwrap();
+        assert_eq!((ban.expires_at, ban.manual), (Some(1302), false));
+        assert!(guard.admit(ip, &policy, 1200).is_some());
+        assert!(guard.admit(ip, &policy, 1302).is_none());
+
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+        guard.ban(other, "abuse".to_string(), None, 1000);
+        let off = IpLimitPolicy { max_requests: 0, ..policy };
+        assert!(guard.admit(other, &off, 99_999).is_some_and(|b| b.manual));
+        assert_eq!(guard.list(99_999).len(), 1);
+        assert!(guard.unban(other));
+        assert!(guard.admit(other, &off, 99_999).is_none());
+    }
+}
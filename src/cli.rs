@@ -0,0 +1,263 @@
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use sentinel_client::{Event, LogQuery};
+use std::path::PathBuf;
+
+use crate::config::{self, Config};
+
+// --- CLI ---
+// `sentinel serve` runs the proxy (and is what a bare `sentinel` does); the
+// other subcommands talk to a running instance over the admin API via
+// `sentinel-client`, or check a configuration without starting anything.
+
+#[derive(Debug, Parser)]
+#[command(name = "sentinel", version, about = "AI performance firewall: loop, leak and spend protection for LLM traffic")]
+pub struct Cli {
+    /// Running instance for the admin subcommands.
+    #[arg(long, global = true, env = "SENTINEL_URL", default_value = "http://127.0.0.1:3000")]
+    pub url: String,
+    /// Bearer token for the admin API.
+    #[arg(long, global = true, env = "SENTINEL_ADMIN_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the proxy.
+    Serve {
+        /// `.toml` or `.env` file; environment variables take precedence.
+        #[arg(long, short)]
+        config: Option<PathBuf>,
+        /// Listen address, repeatable [default: `SENTINEL_ADDR`, or
+        /// `SENTINEL_HOST`:`SENTINEL_PORT`, or 127.0.0.1:3000]
+        #[arg(long)]
+        addr: Vec<String>,
+    },
+    /// Inspect and control sessions.
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Read the intervention log.
+    #[command(subcommand)]
+    Logs(LogsCommand),
+    /// Load the configuration and report problems without serving.
+    ValidateConfig {
+        #[arg(long, short)]
+        config: Option<PathBuf>,
+        /// Skip the provider reachability probes.
+        #[arg(long)]
+        offline: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// Most recently active first.
+    List {
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
+    Show { id: String },
+    /// Forget a session's history and spend.
+    Reset { id: String },
+    Block {
+        id: String,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    Unblock { id: String },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct LogFilter {
+    #[arg(long)]
+    pub session: Option<String>,
+    #[arg(long)]
+    pub detector: Option<String>,
+    /// Substring of the reason.
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+impl LogFilter {
+    fn query(self) -> LogQuery {
+        LogQuery { session_id: self.session, detector: self.detector, reason: self.reason, ..Default::default() }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogsCommand {
+    /// The latest interventions.
+    List {
+        #[command(flatten)]
+        filter: LogFilter,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Follow interventions as they happen.
+    Tail {
+        #[command(flatten)]
+        filter: LogFilter,
+    },
+}
+
+pub fn run(cli: Cli) -> Result<(), String> {
+    let runtime = || tokio::runtime::Runtime::new().map_err(|e| e.to_string());
+    match cli.command.unwrap_or(Command::Serve { config: None, addr: Vec::new() }) {
+        Command::Serve { config, addr } => {
+            load_config(config.as_deref())?;
+            runtime()?.block_on(crate::serve(addr));
+            Ok(())
+        }
+        Command::ValidateConfig { config, offline } => {
+            load_config(config.as_deref())?;
+            runtime()?.block_on(validate(offline))
+        }
+        Command::Sessions(command) => runtime()?.block_on(sessions(&client(&cli.url, cli.token), command)),
+        Command::Logs(command) => runtime()?.block_on(logs(&client(&cli.url, cli.token), command)),
+    }
+}
+
+fn load_config(path: Option<&std::path::Path>) -> Result<(), String> {
+    if let Some(path) = path {
+        config::load_file(path)?;
+    }
+    Ok(())
+}
+
+fn client(url: &str, token: Option<String>) -> sentinel_client::Client {
+    let client = sentinel_client::Client::new(url);
+    match token {
+        Some(token) => client.with_token(token),
+        None => client,
+    }
+}
+
+async fn validate(offline: bool) -> Result<(), String> {
+    let config = Config::from_env();
+    let problems = if offline {
+        crate::selfcheck::static_problems(&config)
+    } else {
+        crate::selfcheck::run(&reqwest::Client::new(), &config).await
+    };
+    println!("providers: {}", sorted(config.providers.keys().map(String::as_str)).join(", "));
+    println!("routing rules: {}, model profiles: {}, exemptions: {}", config.routing_rules.len(), config.model_profiles.len(), config.exemptions.len());
+    if problems.is_empty() {
+        println!("configuration OK");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("problem: {}", problem);
+    }
+    Err(format!("{} problem(s) found", problems.len()))
+}
+
+async fn sessions(client: &sentinel_client::Client, command: SessionsCommand) -> Result<(), String> {
+    let e = |e: sentinel_client::Error| e.to_string();
+    match command {
+        SessionsCommand::List { limit, offset } => {
+            let page = client.sessions(offset, limit).await.map_err(e)?;
+            println!("{:<36} {:>10} {:>13} {:>8}  LAST ACTIVE", "SESSION", "COST", "INTERVENTIONS", "TURNS");
+            for s in &page.sessions {
+                println!(
+                    "{:<36} {:>10.4} {:>13} {:>8}  {}{}",
+                    s.id, s.cumulative_cost, s.interventions, s.history_len, ago(s.last_activity),
+                    if s.blocked { "  [blocked]" } else { "" },
+                );
+            }
+            println!("{} of {} session(s)", page.sessions.len(), page.total);
+        }
+        SessionsCommand::Show { id } => {
+            let s = client.session(&id).await.map_err(e)?;
+            println!("session        {}", s.id);
+            println!("cost           ${:.4}", s.cumulative_cost);
+            println!("interventions  {}", s.interventions);
+            for (reason, count) in sorted(s.interventions_by_reason.iter()) {
+                println!("  {:>4}  {}", count, reason);
+            }
+            if let Some(block) = &s.blocked {
+                println!("blocked        {} ({})", block.reason, ago(block.blocked_at));
+            }
+            println!("recent prompts");
+            for prompt in &s.recent_prompts {
+                println!("  - {}", prompt.replace('\n', " "));
+            }
+        }
+        SessionsCommand::Reset { id } => {
+            client.reset_session(&id).await.map_err(e)?;
+            println!("session '{}' reset", id);
+        }
+        SessionsCommand::Block { id, reason } => {
+            client.block_session(&id, reason.as_deref()).await.map_err(e)?;
+            println!("session '{}' blocked", id);
+        }
+        SessionsCommand::Unblock { id } => {
+            client.unblock_session(&id).await.map_err(e)?;
+            println!("session '{}' unblocked", id);
+        }
+    }
+    Ok(())
+}
+
+async fn logs(client: &sentinel_client::Client, command: LogsCommand) -> Result<(), String> {
+    let e = |e: sentinel_client::Error| e.to_string();
+    match command {
+        LogsCommand::List { filter, limit } => {
+            let page = client.logs(&LogQuery { limit: Some(limit), ..filter.query() }).await.map_err(e)?;
+            for log in &page.logs {
+                print_log(log);
+            }
+            println!("{} of {} intervention(s)", page.logs.len(), page.total);
+        }
+        LogsCommand::Tail { filter } => {
+            let mut events = client.interventions(&filter.query()).await.map_err(e)?;
+            while let Some(event) = events.next().await {
+                match event.map_err(e)? {
+                    Event::Intervention(log) => print_log(&log),
+                    Event::Lagged(missed) => eprintln!("... {} intervention(s) missed", missed),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_log(log: &sentinel_client::InterventionLog) {
+    let mode = if log.bypassed { " (bypassed)" } else if log.dry_run { " (dry run)" } else { "" };
+    println!("#{:<6} {:>8}  {:<24} {:<18} {}{}", log.id, ago(log.timestamp), log.session_id, log.detector, log.reason, mode);
+}
+
+fn ago(timestamp: u64) -> String {
+    let secs = crate::now_secs().saturating_sub(timestamp);
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn sorted<T: Ord>(items: impl Iterator<Item = T>) -> Vec<T> {
+    let mut items: Vec<T> = items.collect();
+    items.sort();
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_subcommands() {
+        let cli = Cli::try_parse_from(["sentinel", "serve", "--config", "sentinel.toml", "--addr", "0.0.0.0:8080", "--addr", "[::1]:8080"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve { config: Some(_), ref addr }) if addr == &["0.0.0.0:8080", "[::1]:8080"]));
+        let cli = Cli::try_parse_from(["sentinel", "--url", "http://sentinel:3000", "logs", "tail", "--detector", "leak"]).unwrap();
+        assert_eq!(cli.url, "http://sentinel:3000");
+        assert!(matches!(cli.command, Some(Command::Logs(LogsCommand::Tail { ref filter })) if filter.detector.as_deref() == Some("leak")));
+        assert!(Cli::try_parse_from(["sentinel"]).unwrap().command.is_none());
+        assert!(Cli::try_parse_from(["sentinel", "sessions", "block"]).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, header::USER_AGENT},
+    middleware::Next,
+    response::Response,
+};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use crate::AppState;
+
+// --- CLIENT SESSIONS ---
+// Requests with neither `x-sentinel-session` nor a `user` used to share the
+// `default` session, so unrelated agents tripped each other's loop detector.
+// They now fall back to a session per client IP and user agent. Behind a
+// load balancer the peer is the proxy, so `Forwarded` / `X-Forwarded-For` are
+// honoured, but only when the peer is in `SENTINEL_TRUSTED_PROXIES`;
+// otherwise any client could pick its own session by sending the header.
+
+/// Set by `identify` on every request (a client-sent value is discarded) and
+/// read by `session_id` as the last resort before `default`.
+pub const CLIENT_HEADER: &str = "x-sentinel-client";
+
+/// The originating client, as a request extension for later middleware.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// An IP network, `10.0.0.0/8` or `2001:db8::/32`; a bare address is a /32 or /128.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr.parse().map_err(|_| format!("invalid address `{}`", addr))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| format!("invalid prefix length in `{}`", s))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Dual-stack listeners report IPv4 peers as `::ffff:a.b.c.d`.
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => (u32::from(net) as u128, u32::from(ip) as u128, 32),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => (u128::from(net), u128::from(ip), 128),
+            _ => return false,
+        };
+        (network ^ ip).checked_shr(bits - self.prefix as u32).unwrap_or(0) == 0
+    }
+}
+
+/// Middleware resolving the `ClientIp` and recording the fallback session
+/// key in `CLIENT_HEADER`.
+pub async fn identify(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    request.headers_mut().remove(CLIENT_HEADER);
+    let policy = &state.config.client_sessions;
+    let Some(peer) = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip()) else {
+        return next.run(request).await;
+    };
+    let ip = client_ip(peer, request.headers(), &policy.trusted_proxies);
+    request.extensions_mut().insert(ClientIp(ip));
+    if policy.enabled {
+        let user_agent = request.headers().get(USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+        if let Ok(value) = HeaderValue::from_str(&fallback_session(ip, user_agent)) {
+            request.headers_mut().insert(CLIENT_HEADER, value);
+        }
+    }
+    next.run(request).await
+}
+
+/// The originating client: the peer itself unless it is a trusted proxy,
+/// else the nearest forwarded address not added by a trusted proxy.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|c| c.contains(*ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+    let chain = forwarded_chain(headers);
+    chain.iter().rev().find(|ip| !is_trusted(ip)).or(chain.first()).copied().unwrap_or(peer)
+}
+
+/// Addresses the proxies recorded, client first: `Forwarded: for=` when
+/// present, else `X-Forwarded-For`.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    let entries = |name: &str| -> Vec<String> {
+        headers.get_all(name).iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|e| e.trim().to_string())
+            .collect()
+    };
+    let forwarded: Vec<IpAddr> = entries("forwarded").iter()
+        .filter_map(|element| element.split(';').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            key.trim().eq_ignore_ascii_case("for").then(|| parse_node(value)).flatten()
+        }))
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    entries("x-forwarded-for").iter().filter_map(|e| parse_node(e)).collect()
+}
+
+/// `203.0.113.7`, `198.51.100.2:8080` or `"[2001:db8::1]:4711"`; `unknown`
+/// and obfuscated identifiers give `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.parse::<SocketAddr>().ok().map(|a| a.ip())
+}
+
+/// `client-<ip>-<hash of the user agent>`, so two agents behind one NAT stay
+/// apart when they identify differently.
+pub fn fallback_session(ip: IpAddr, user_agent: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    user_agent.hash(&mut hasher);
+    format!("client-{}-{:08x}", ip, hasher.finish() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip_trusts_only_configured_proxies() {
+        let trusted: Vec<Cidr> = ["10.0.0.0/8", "2001:db8::/32"].iter().map(|c| c.parse().unwrap()).collect();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "198.51.100.9, 203.0.113.7, 10.1.2.3".parse().unwrap());
+
+        assert_eq!(client_ip(ip("192.0.2.1"), &headers, &trusted), ip("192.0.2.1"));
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &trusted), ip("203.0.113.7"));
+        assert_eq!(client_ip(ip("::ffff:10.0.0.1"), &headers, &trusted), ip("203.0.113.7"));
+
+        headers.insert("forwarded", "for=\"[2001:db8::5]:4711\", for=192.0.2.60;proto=https".parse().unwrap());
+        assert_eq!(client_ip(ip("2001:db8::1"), &headers, &trusted), ip("192.0.2.60"));
+        assert_eq!(client_ip(ip("10.0.0.1"), &HeaderMap::new(), &trusted), ip("10.0.0.1"));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+        assert_ne!(fallback_session(ip("192.0.2.1"), "agent/1"), fallback_session(ip("192.0.2.1"), "agent/2"));
+    }
+}
@@ -0,0 +1,141 @@
+use axum::{
+    Json,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::AppState;
+use crate::audit::{LogContext, record_intervention};
+use crate::pricing::Pricing;
+use crate::savings::estimate_prompt_tokens;
+
+// --- PER-REQUEST COST CEILINGS ---
+// The calling application may know better than any configured budget what
+// one call is worth. `x-sentinel-max-cost` sets a ceiling in USD for this
+// request only; `x-sentinel-budget-remaining` says what the caller has left
+// overall, and acts as a ceiling too. The lower of the two applies, on top
+// of session, pool and tenant budgets.
+//
+// Before forwarding, the prompt is priced from its estimate (about four
+// characters per token) and the completion budget is lowered so the whole
+// call fits. When the prompt alone doesn't leave room for a single output
+// token, the request is refused with a 429 (`request_budget_exceeded`) and
+// logged under `cost_spike`. Non-streaming responses carry the actual cost
+// in `x-sentinel-cost`, the ceiling back in `x-sentinel-max-cost` and, when
+// it was sent, `x-sentinel-budget-remaining` less that cost. Streams echo
+// the ceiling only: their cost is known after the headers are gone.
+
+pub const MAX_COST: &str = "x-sentinel-max-cost";
+pub const BUDGET_REMAINING: &str = "x-sentinel-budget-remaining";
+pub const COST: &str = "x-sentinel-cost";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CostHints {
+    pub max_cost: Option<f64>,
+    pub remaining: Option<f64>,
+}
+
+impl CostHints {
+    /// Reads both headers; an unparseable or negative value is an error.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, String> {
+        let read = |name: &str| -> Result<Option<f64>, String> {
+            let Some(value) = headers.get(name) else { return Ok(None) };
+            let text = value.to_str().unwrap_or_default().trim().trim_start_matches('$');
+            match text.parse::<f64>() {
+                Ok(usd) if usd.is_finite() && usd >= 0.0 => Ok(Some(usd)),
+                _ => Err(format!("Invalid {}: expected a USD amount, got `{}`", name, text)),
+            }
+        };
+        Ok(Self { max_cost: read(MAX_COST)?, remaining: read(BUDGET_REMAINING)? })
+    }
+
+    /// The ceiling that applies, if either header was sent.
+    pub fn ceiling(&self) -> Option<f64> {
+        match (self.max_cost, self.remaining) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Response headers echoing the hints, with `cost` once it is known.
+    pub fn response_headers(&self, cost: Option<f64>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let usd = |v: f64| HeaderValue::from_str(&format!("{:.6}", v)).unwrap_or(HeaderValue::from_static("0"));
+        if let Some(ceiling) = self.ceiling() {
+            headers.insert(MAX_COST, usd(ceiling));
+        }
+        if let Some(cost) = cost {
+            headers.insert(COST, usd(cost));
+            if let Some(remaining) = self.remaining {
+                headers.insert(BUDGET_REMAINING, usd((remaining - cost).max(0.0)));
+            }
+        }
+        headers
+    }
+}
+
+/// Lowers the completion budget of `body` so its worst-case cost stays
+/// under `ceiling`. Returns the estimated prompt cost when nothing fits.
+pub fn fit(pricing: &Pricing, ceiling: f64, model: &str, body: &mut Value) -> Result<(), f64> {
+    let prompt_cost = pricing.cost(model, estimate_prompt_tokens(body), 0);
+    let per_token = pricing.cost(model, 0, 1);
+    let room = ceiling - prompt_cost;
+    if room <= 0.0 || room < per_token {
+        return Err(prompt_cost);
+    }
+    if per_token > 0.0 {
+        crate::limits::cap_output((room / per_token).floor().min(u64::MAX as f64) as u64, model, body);
+    }
+    Ok(())
+}
+
+/// Logs the refusal and answers 429 with an OpenAI-style error.
+pub async fn reject(state: &AppState, headers: &HeaderMap, ctx: &LogContext, ceiling: f64, prompt_cost: f64) -> Response {
+    let reason = "Request Cost Ceiling Exceeded";
+    tracing::warn!(session_id = %ctx.session_id, "💸 Prompt alone costs ~${:.4}, over the ${:.4} ceiling", prompt_cost, ceiling);
+    record_intervention(state, ctx, "cost_spike", reason, format!("Ceiling: ${:.4}", ceiling), prompt_cost).await;
+    let messages = &state.config.messages;
+    let budget = format!("{:.4}", ceiling);
+    let error_body = serde_json::json!({
+        "error": {
+            "message": messages.render(&messages.locale_for(headers), "request_budget", &[("budget", &budget), ("session", &ctx.session_id)]),
+            "type": "sentinel_budget",
+            "param": null,
+            "code": "request_budget_exceeded"
+        }
+    });
+    let marks = crate::intervention_headers("blocked", "cost_spike", reason);
+    (StatusCode::TOO_MANY_REQUESTS, marks, Json(error_body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hints_cap_the_completion_and_echo_the_cost() {
+        let mut headers = HeaderMap::new();
+        headers.insert(MAX_COST, "$0.05".parse().unwrap());
+        headers.insert(BUDGET_REMAINING, "0.02".parse().unwrap());
+        let hints = CostHints::from_headers(&headers).unwrap();
+        assert_eq!(hints.ceiling(), Some(0.02));
+        assert_eq!(CostHints::from_headers(&HeaderMap::new()).unwrap().ceiling(), None);
+        headers.insert(MAX_COST, "lots".parse().unwrap());
+        assert!(CostHints::from_headers(&headers).is_err());
+
+        let pricing = Pricing::default();
+        let mut body = serde_json::json!({"messages": [{"role": "user", "content": "hi"}], "max_tokens": 1_000_000});
+        assert_eq!(fit(&pricing, 0.02, "gpt-4o", &mut body), Ok(()));
+        let capped = body["max_tokens"].as_u64().unwrap();
+        assert!(capped < 1_000_000);
+        assert!(pricing.cost("gpt-4o", estimate_prompt_tokens(&body), capped) <= 0.02);
+        assert!(fit(&pricing, 0.0, "gpt-4o", &mut body).is_err());
+
+        let echoed = hints.response_headers(Some(0.005));
+        assert_eq!(echoed[COST], "0.005000");
+        assert_eq!(echoed[BUDGET_REMAINING], "0.015000");
+        assert_eq!(echoed[MAX_COST], "0.020000");
+        assert!(!hints.response_headers(None).contains_key(COST));
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+use crate::AppState;
+
+// --- CLUSTER COUNTERS ---
+// Savings, budget alerts and evictions are counted per process. With the
+// Postgres backend every instance adds what it counted since its last sync
+// (`SENTINEL_COUNTER_SYNC_SECS`) to its own rows in `sentinel_counters`, in
+// one transaction, and reads back every instance's rows: `/api/stats` then
+// shows the local values next to `cluster` totals and a per-instance
+// breakdown (`SENTINEL_INSTANCE_ID`, by default the host name). A failed
+// sync is retried with the accumulated deltas next time, so none are lost.
+
+/// Shared counter names, in the order `local` returns them.
+#[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+const COUNTERS: [&str; 4] = ["saved_micro_usd", "budget_alerts", "sessions_expired", "sessions_lru_evicted"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub saved_usd: f64,
+    pub budget_alerts: u64,
+    pub sessions_expired: u64,
+    pub sessions_lru_evicted: u64,
+}
+
+impl Totals {
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    fn add(&mut self, name: &str, value: u64) {
+        match name {
+            "saved_micro_usd" => self.saved_usd += value as f64 / 1_000_000.0,
+            "budget_alerts" => self.budget_alerts += value,
+            "sessions_expired" => self.sessions_expired += value,
+            "sessions_lru_evicted" => self.sessions_lru_evicted += value,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClusterTotals {
+    pub synced_at: u64,
+    pub total: Totals,
+    pub instances: BTreeMap<String, Totals>,
+}
+
+impl ClusterTotals {
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    fn from_rows(rows: &[(String, String, i64)], now: u64) -> Self {
+        let mut totals = Self { synced_at: now, ..Default::default() };
+        for (instance, name, value) in rows {
+            let value = (*value).max(0) as u64;
+            totals.total.add(name, value);
+            totals.instances.entry(instance.clone()).or_default().add(name, value);
+        }
+        totals
+    }
+}
+
+#[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+fn local(state: &AppState) -> [u64; 4] {
+    [
+        state.saved_micro_usd.load(Ordering::Relaxed),
+        state.budget_alerts.load(Ordering::Relaxed),
+        state.sessions_expired.load(Ordering::Relaxed),
+        state.sessions_lru_evicted.load(Ordering::Relaxed),
+    ]
+}
+
+/// What each counter gained since `synced`, skipping unchanged ones.
+#[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+fn deltas(current: [u64; 4], synced: [u64; 4]) -> Vec<(&'static str, u64)> {
+    COUNTERS.iter().zip(current.iter().zip(synced))
+        .map(|(name, (now, then))| (*name, now.saturating_sub(then)))
+        .filter(|(_, delta)| *delta > 0)
+        .collect()
+}
+
+/// Starts syncing when the audit log is in Postgres; a no-op otherwise.
+pub fn spawn_sync(state: AppState) {
+    #[cfg(feature = "postgres")]
+    if state.audit.postgres().is_some() {
+        tokio::spawn(async move {
+            // Savings restored from the audit log at startup are already in it.
+            let mut synced = local(&state);
+            let policy = state.config.storage.clone();
+            let mut tick = tokio::time::interval(std::time::Duration::from_secs(policy.counter_sync_secs));
+            loop {
+                tick.tick().await;
+                let current = local(&state);
+                let (store, instance) = (state.audit.clone(), policy.instance_id.clone());
+                let result = tokio::task::spawn_blocking(move || {
+                    let pg = store.postgres().expect("checked before spawning");
+                    pg.add_counters(&instance, &deltas(current, synced))?;
+                    pg.counters()
+                }).await;
+                match result {
+                    Ok(Ok(rows)) => {
+                        synced = current;
+                        *state.cluster.write().unwrap() = Some(ClusterTotals::from_rows(&rows, crate::now_secs()));
+                    }
+                    Ok(Err(e)) => tracing::warn!("Syncing shared counters failed: {}", e),
+                    Err(e) => tracing::warn!("Syncing shared counters failed: {}", e),
+                }
+            }
+        });
+    }
+    #[cfg(not(feature = "postgres"))]
+    let _ = state;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deltas_and_cluster_totals() {
+        assert_eq!(deltas([1_500_000, 2, 0, 7], [1_000_000, 2, 0, 3]), vec![("saved_micro_usd", 500_000), ("sessions_lru_evicted", 4)]);
+        assert!(deltas([5, 5, 5, 5], [5, 5, 5, 5]).is_empty());
+
+        let rows = [
+            ("a".to_string(), "saved_micro_usd".to_string(), 250_000),
+            ("b".to_string(), "saved_micro_usd".to_string(), 1_000_001),
+            ("b".to_string(), "budget_alerts".to_string(), 3),
+        ];
+        let totals = ClusterTotals::from_rows(&rows, 42);
+        assert!((totals.total.saved_usd - 1.250001).abs() < 1e-9);
+        assert_eq!(totals.total.budget_alerts, 3);
+        assert_eq!(totals.instances["a"], Totals { saved_usd: 0.25, ..Default::default() });
+        assert_eq!(totals.instances.len(), 2);
+    }
+}
@@ -0,0 +1,222 @@
+use dashmap::DashMap;
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::SessionState;
+use crate::audit::InterventionLog;
+use crate::config::{AuditPolicy, StorageKind, StoragePolicy};
+use crate::reports::SavedUsage;
+
+// --- EMBEDDED STORAGE ---
+// With `SENTINEL_STORAGE_KIND=embedded` everything durable lives in one redb
+// file, `<SENTINEL_DATA_DIR>/sentinel.redb`: the audit log (instead of the
+// JSONL file), a snapshot of the live sessions and the usage ledger behind
+// `/api/reports`, so spend, baselines and history survive a restart without
+// an external database. Sessions and usage are
+// written on every sweep (`SENTINEL_SESSION_SWEEP_SECS`) and after an
+// erasure, so a crash loses at most one sweep of session changes; audit
+// entries are committed as they are written.
+
+const AUDIT: TableDefinition<u64, &str> = TableDefinition::new("audit_log");
+const SESSIONS: TableDefinition<&str, &str> = TableDefinition::new("sessions");
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+const USAGE: TableDefinition<&str, &str> = TableDefinition::new("usage");
+
+/// redb's error as text; callers only report it, and the enum is too
+/// large to return by value.
+fn fail(e: impl Into<redb::Error>) -> String {
+    e.into().to_string()
+}
+
+pub struct EmbeddedStore {
+    db: Database,
+}
+
+impl EmbeddedStore {
+    /// Opens the store when `storage.kind` selects it. Exits when it can't
+    /// be opened, rather than running without the persistence asked for.
+    pub fn for_config(policy: &StoragePolicy) -> Option<Arc<Self>> {
+        if policy.kind != StorageKind::Embedded {
+            return None;
+        }
+        match Self::open(Path::new(&policy.data_dir)) {
+            Ok(store) => {
+                tracing::info!("Sessions and audit log stored in {}", policy.data_dir);
+                Some(Arc::new(store))
+            }
+            Err(e) => {
+                tracing::error!("SENTINEL_STORAGE_KIND=embedded: cannot open {}: {}", policy.data_dir, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let db = Database::create(dir.join("sentinel.redb")).map_err(fail)?;
+        let tx = db.begin_write().map_err(fail)?;
+        tx.open_table(AUDIT).map_err(fail)?;
+        tx.open_table(SESSIONS).map_err(fail)?;
+        tx.open_table(META).map_err(fail)?;
+        tx.open_table(USAGE).map_err(fail)?;
+        tx.commit().map_err(fail)?;
+        Ok(Self { db })
+    }
+
+    /// Opens a read transaction, to see the database is usable.
+    pub fn check(&self) -> Result<(), String> {
+        self.db.begin_read().map_err(fail)?.open_table(META).map_err(fail)?;
+        Ok(())
+    }
+
+    pub fn upsert(&self, log: &InterventionLog) -> Result<(), String> {
+        let tx = self.db.begin_write().map_err(fail)?;
+        tx.open_table(AUDIT).map_err(fail)?.insert(log.id, serde_json::to_string(log).unwrap_or_default().as_str()).map_err(fail)?;
+        tx.commit().map_err(fail)?;
+        Ok(())
+    }
+
+    /// All retained entries, oldest first.
+    pub fn load(&self, policy: &AuditPolicy, now: u64) -> Result<Vec<InterventionLog>, String> {
+        let tx = self.db.begin_read().map_err(fail)?;
+        let table = tx.open_table(AUDIT).map_err(fail)?;
+        let mut logs = Vec::new();
+        for entry in table.iter().map_err(fail)?.rev() {
+            if policy.max_entries > 0 && logs.len() >= policy.max_entries {
+                break;
+            }
+            let (_, value) = entry.map_err(fail)?;
+            let Ok(log) = serde_json::from_str::<InterventionLog>(value.value()) else { continue };
+            if policy.retention_secs == 0 || now.saturating_sub(log.timestamp) <= policy.retention_secs {
+                logs.push(log);
+            }
+        }
+        logs.reverse();
+        Ok(logs)
+    }
+
+    /// Keeps only retained entries `keep` selects, applying `edit` to them.
+    /// Returns how many entries were deleted.
+    pub fn rewrite(
+        &self,
+        policy: &AuditPolicy,
+        keep: impl Fn(&InterventionLog) -> bool,
+        mut edit: impl FnMut(&mut InterventionLog),
+    ) -> Result<usize, String> {
+        let mut logs = self.load(policy, crate::now_secs())?;
+        logs.retain(keep);
+        let kept: std::collections::HashSet<u64> = logs.iter().map(|l| l.id).collect();
+
+        let tx = self.db.begin_write().map_err(fail)?;
+        let deleted = {
+            let mut table = tx.open_table(AUDIT).map_err(fail)?;
+            let before = table.len().map_err(fail)?;
+            table.retain(|id, _| kept.contains(&id)).map_err(fail)?;
+            for log in &mut logs {
+                let unchanged = serde_json::to_string(log).unwrap_or_default();
+                edit(log);
+                let line = serde_json::to_string(log).unwrap_or_default();
+                if line != unchanged {
+                    table.insert(log.id, line.as_str()).map_err(fail)?;
+                }
+            }
+            before - table.len().map_err(fail)?
+        };
+        tx.commit().map_err(fail)?;
+        Ok(deleted as usize)
+    }
+
+    pub fn archived_through(&self) -> Result<u64, String> {
+        let tx = self.db.begin_read().map_err(fail)?;
+        let value = tx.open_table(META).map_err(fail)?.get("archived_through").map_err(fail)?.map(|v| v.value());
+        Ok(value.unwrap_or(0))
+    }
+
+    pub fn set_archived_through(&self, id: u64) -> Result<(), String> {
+        let tx = self.db.begin_write().map_err(fail)?;
+        tx.open_table(META).map_err(fail)?.insert("archived_through", id).map_err(fail)?;
+        tx.commit().map_err(fail)?;
+        Ok(())
+    }
+
+    /// Replaces the stored sessions and usage with the current ones.
+    pub fn save_sessions(&self, sessions: &DashMap<String, SessionState>, usage: &SavedUsage) -> Result<usize, String> {
+        let snapshot: Vec<(String, String)> = sessions.iter()
+            .map(|s| (s.key().clone(), serde_json::to_string(s.value()).unwrap_or_default()))
+            .collect();
+        let usage = serde_json::to_string(usage).unwrap_or_default();
+        let tx = self.db.begin_write().map_err(fail)?;
+        tx.open_table(USAGE).map_err(fail)?.insert("ledger", usage.as_str()).map_err(fail)?;
+        {
+            let mut table = tx.open_table(SESSIONS).map_err(fail)?;
+            table.retain(|_, _| false).map_err(fail)?;
+            for (id, session) in &snapshot {
+                table.insert(id.as_str(), session.as_str()).map_err(fail)?;
+            }
+        }
+        tx.commit().map_err(fail)?;
+        Ok(snapshot.len())
+    }
+
+    pub fn load_sessions(&self) -> Result<Vec<(String, SessionState)>, String> {
+        let tx = self.db.begin_read().map_err(fail)?;
+        let table = tx.open_table(SESSIONS).map_err(fail)?;
+        let mut sessions = Vec::new();
+        for entry in table.iter().map_err(fail)? {
+            let (id, value) = entry.map_err(fail)?;
+            match serde_json::from_str(value.value()) {
+                Ok(session) => sessions.push((id.value().to_string(), session)),
+                Err(e) => tracing::warn!("Skipping stored session {}: {}", id.value(), e),
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub fn load_usage(&self) -> Result<SavedUsage, String> {
+        let tx = self.db.begin_read().map_err(fail)?;
+        let table = tx.open_table(USAGE).map_err(fail)?;
+        let Some(value) = table.get("ledger").map_err(fail)? else { return Ok(SavedUsage::default()) };
+        serde_json::from_str(value.value()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_and_sessions_survive_reopen() {
+        let dir = std::env::temp_dir().join(format!("sentinel-embedded-{}", std::process::id()));
+        let policy = AuditPolicy::default();
+        let now = crate::now_secs();
+        {
+            let store = EmbeddedStore::open(&dir).unwrap();
+            for id in 1..=3 {
+                let log: InterventionLog = serde_json::from_value(serde_json::json!({
+                    "id": id, "timestamp": now, "session_id": "s", "detector": "leak",
+                    "reason": "r", "content_snippet": "secret", "savings_est": 0.0,
+                })).unwrap();
+                store.upsert(&log).unwrap();
+            }
+            let sessions = DashMap::new();
+            sessions.insert("s".to_string(), SessionState { cumulative_cost: 1.25, ..SessionState::new() });
+            let usage = crate::reports::UsageLedger::default();
+            usage.record_cost(now, Some("acme"), "s", 1.25);
+            store.save_sessions(&sessions, &usage.saved()).unwrap();
+            assert_eq!(store.rewrite(&policy, |l| l.id > 1, |l| l.content_snippet.clear()).unwrap(), 1);
+            store.set_archived_through(2).unwrap();
+        }
+        let store = EmbeddedStore::open(&dir).unwrap();
+        let logs = store.load(&policy, now).unwrap();
+        assert_eq!(logs.iter().map(|l| l.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(logs.iter().all(|l| l.content_snippet.is_empty()));
+        assert_eq!(store.archived_through().unwrap(), 2);
+        let sessions = store.load_sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].1.cumulative_cost, 1.25);
+        assert_eq!(store.load_usage().unwrap().days[0].sessions["s"].cost_usd, 1.25);
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
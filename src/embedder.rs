@@ -0,0 +1,160 @@
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::EmbeddingBatchPolicy;
+
+// --- EMBEDDING BATCHER ---
+// Loop detection needs one embedding per chat request. Rather than one HTTP
+// call each, lookups arriving within `window_ms` of each other are coalesced
+// into a single array request (identical texts are sent once). A window of 0
+// calls the API directly.
+
+type Reply = oneshot::Sender<Result<Vec<f32>, String>>;
+
+/// The error when the embeddings API doesn't answer within the timeout.
+pub const TIMED_OUT: &str = "Embedding request timed out";
+
+pub struct Embedder {
+    client: Client,
+    api_key: String,
+    policy: EmbeddingBatchPolicy,
+    timeout: Option<Duration>,
+    /// Started on first use, so constructing the state needs no runtime.
+    queue: OnceLock<mpsc::Sender<(String, Reply)>>,
+    /// Every text asked for, so tests can see what would have left.
+    #[cfg(test)]
+    pub seen: std::sync::Mutex<Vec<String>>,
+}
+
+impl Embedder {
+    pub fn new(client: Client, api_key: String, policy: EmbeddingBatchPolicy, timeout: Option<Duration>) -> Self {
+        Self {
+            client, api_key, policy, timeout, queue: OnceLock::new(),
+            #[cfg(test)]
+            seen: Default::default(),
+        }
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        #[cfg(test)]
+        self.seen.lock().unwrap().push(text.to_string());
+        if !crate::has_embedding_key(&self.api_key) {
+            return Err("No Key".to_string());
+        }
+        if self.policy.window_ms == 0 {
+            return fetch(&self.client, &self.api_key, self.timeout, &[text.to_string()]).await?
+                .pop()
+                .ok_or_else(|| "No embedding".to_string());
+        }
+        let queue = self.queue.get_or_init(|| {
+            let (tx, rx) = mpsc::channel(1024);
+            tokio::spawn(run(self.client.clone(), self.api_key.clone(), self.policy.clone(), self.timeout, rx));
+            tx
+        });
+        let (reply, answer) = oneshot::channel();
+        queue.send((text.to_string(), reply)).await.map_err(|_| "Embedder stopped".to_string())?;
+        answer.await.map_err(|_| "Embedder dropped the request".to_string())?
+    }
+}
+
+/// Collects a batch (the first lookup opens the window) and answers it.
+async fn run(client: Client, api_key: String, policy: EmbeddingBatchPolicy, timeout: Option<Duration>, mut rx: mpsc::Receiver<(String, Reply)>) {
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(policy.window_ms);
+        while batch.len() < policy.max_batch {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(item)) => batch.push(item),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        let client = client.clone();
+        let api_key = api_key.clone();
+        // Don't hold the next window hostage to this call.
+        tokio::spawn(async move { answer(&client, &api_key, timeout, batch).await });
+    }
+}
+
+async fn answer(client: &Client, api_key: &str, timeout: Option<Duration>, batch: Vec<(String, Reply)>) {
+    let (texts, slots) = dedupe(batch.iter().map(|(text, _)| text.as_str()));
+    match fetch(client, api_key, timeout, &texts).await {
+        Ok(embeddings) if embeddings.len() == texts.len() => {
+            for ((_, reply), slot) in batch.into_iter().zip(slots) {
+                let _ = reply.send(Ok(embeddings[slot].clone()));
+            }
+        }
+        Ok(embeddings) => {
+            let e = format!("Expected {} embeddings, got {}", texts.len(), embeddings.len());
+            for (_, reply) in batch {
+                let _ = reply.send(Err(e.clone()));
+            }
+        }
+        Err(e) => {
+            for (_, reply) in batch {
+                let _ = reply.send(Err(e.clone()));
+            }
+        }
+    }
+}
+
+/// Unique texts in first-seen order, and for each input its index among them.
+fn dedupe<'a>(texts: impl Iterator<Item = &'a str>) -> (Vec<String>, Vec<usize>) {
+    let mut unique: Vec<String> = Vec::new();
+    let mut seen: HashMap<&'a str, usize> = HashMap::new();
+    let slots = texts
+        .map(|text| *seen.entry(text).or_insert_with(|| {
+            unique.push(text.to_string());
+            unique.len() - 1
+        }))
+        .collect();
+    (unique, slots)
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    #[serde(default)]
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// One embeddings API call; results come back in input order. `timeout`
+/// covers the whole exchange, body included.
+async fn fetch(client: &Client, api_key: &str, timeout: Option<Duration>, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let mut req = client.post("https://api.openai.com/v1/embeddings")
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({"input": texts, "model": "text-embedding-3-small"}));
+    if let Some(timeout) = timeout {
+        req = req.timeout(timeout);
+    }
+    let error = |e: reqwest::Error| if e.is_timeout() { TIMED_OUT.to_string() } else { e.to_string() };
+    let res = req.send().await.map_err(error)?;
+
+    let mut data: EmbeddingResponse = res.json().await.map_err(error)?;
+    if data.data.is_empty() {
+        return Err("No embedding".to_string());
+    }
+    data.data.sort_by_key(|d| d.index);
+    Ok(data.data.into_iter().map(|d| d.embedding).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedupe_maps_repeats_to_one_slot() {
+        let (texts, slots) = dedupe(["a", "b", "a", "c", "b"].into_iter());
+        assert_eq!(texts, vec!["a", "b", "c"]);
+        assert_eq!(slots, vec![0, 1, 0, 2, 1]);
+    }
+}
@@ -0,0 +1,165 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::AppState;
+use crate::audit::erase_snippet;
+
+// --- DATA-SUBJECT ERASURE ---
+// `DELETE /api/data/{subject}` removes what Sentinel keeps about a user or
+// session: live session state (prompt texts and their embeddings), the
+// user's cross-session fingerprints, cached embeddings it paid for,
+// quarantined requests, responses kept for idempotency keys, each session's
+// share of the usage ledger (the day totals stay), shadow comparisons, and
+// the prompt snippets and stored requests in the audit log, in memory and on
+// disk. The audit entries themselves stay, with their snippet replaced by
+// its digest, so the hash chain still verifies.
+// A subject is a session id or user, qualified as `<tenant>/<subject>` for a
+// tenant's (tenant-bound callers may only name their own); a user's sessions
+// in that tenant, or outside any tenant when unqualified, are erased too.
+// Diagnostic log files (`SENTINEL_LOG_PATH`) are outside this and rotate away
+// on their own.
+
+/// What was erased, returned to the caller as proof of the deletion.
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub subject: String,
+    pub erased_at: u64,
+    /// Live sessions dropped.
+    pub sessions: usize,
+    pub prompts: usize,
+    pub embeddings: usize,
+    pub fingerprints: usize,
+    pub quarantined_requests: usize,
+    pub idempotent_responses: usize,
+    /// Per-day usage entries of the erased sessions.
+    pub usage_entries: usize,
+    pub shadow_records: usize,
+    /// Output schemas registered for the erased sessions.
+    pub schemas: usize,
+    pub audit_snippets: usize,
+    /// SHA-256 over the fields above, to quote in the requester's records.
+    pub receipt_id: String,
+}
+
+/// Whether `session_id` belongs to the tenant `subject` is qualified with,
+/// or to no tenant for an unqualified subject.
+fn in_scope(session_id: &str, subject: &str) -> bool {
+    match (session_id.split_once('/'), subject.split_once('/')) {
+        (Some((tenant, _)), Some((subject_tenant, _))) => tenant == subject_tenant,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+pub async fn erase_subject(state: &AppState, subject: &str) -> std::io::Result<Receipt> {
+    let user = subject.split_once('/').map_or(subject, |(_, user)| user);
+    let (mut sessions, user_fingerprints) = state.user_fingerprints.forget_user(user, |session| in_scope(session, subject));
+    sessions.insert(subject.to_string());
+    let matches = |id: &str| sessions.contains(id);
+    let fingerprints = user_fingerprints + state.user_fingerprints.forget_sessions(matches);
+
+    let (mut live, mut prompts, mut embeddings) = (0, 0, 0);
+    state.sessions.retain(|id, sess| {
+        if !matches(id.as_str()) {
+            return true;
+        }
+        live += 1;
+        prompts += sess.history_text.len();
+        embeddings += sess.history.len();
+        false
+    });
+    embeddings += state.embedding_cache.forget_sessions(matches);
+    let usage_entries = state.usage.forget_sessions(matches);
+    crate::sessions::persist(state).await;
+
+    let before = state.quarantine.len();
+    state.quarantine.retain(|_, entry| !matches(entry.request.session_id.as_str()));
+    let quarantined_requests = before - state.quarantine.len();
+    let idempotent_responses = state.idempotency.forget_sessions(matches);
+    let shadow_records = state.shadow.forget_sessions(matches);
+    let schemas = crate::structured::forget_sessions(state, matches);
+
+    let mut audit_snippets = 0;
+    for log in state.audit_logs.lock().await.iter_mut().filter(|l| matches(l.session_id.as_str())) {
+        if log.snippet_digest.is_none() {
+            audit_snippets += 1;
+        }
+        erase_snippet(log);
+    }
+    if state.audit.is_durable() {
+        let (store, sessions) = (state.audit.clone(), sessions.clone());
+        audit_snippets = tokio::task::spawn_blocking(move || {
+            store.erase(|l| sessions.contains(&l.session_id))
+        }).await.map_err(std::io::Error::other)??;
+    }
+
+    let mut receipt = Receipt {
+        subject: subject.to_string(),
+        erased_at: crate::now_secs(),
+        sessions: live,
+        prompts,
+        embeddings,
+        fingerprints,
+        quarantined_requests,
+        idempotent_responses,
+        usage_entries,
+        shadow_records,
+        schemas,
+        audit_snippets,
+        receipt_id: String::new(),
+    };
+    let digest = Sha256::digest(serde_json::to_vec(&receipt).unwrap_or_default());
+    receipt.receipt_id = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    tracing::info!("🧹 Erased data subject '{}': receipt {}", subject, receipt.receipt_id);
+    Ok(receipt)
+}
+
+/// `DELETE /api/data/{subject}`: erases a user or session and returns the
+/// receipt. Erasing a subject Sentinel knows nothing about is not an error.
+pub async fn erase(State(state): State<AppState>, Path(subject): Path<String>) -> Response {
+    match erase_subject(&state, &subject).await {
+        Ok(receipt) => Json(receipt).into_response(),
+        Err(e) => {
+            tracing::error!("Erasing '{}' failed: {}", subject, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": format!("Erasure incomplete: {}", e)}))).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{LogContext, record_intervention, verify_chain};
+    use crate::config::{AuditPolicy, Config};
+
+    #[tokio::test]
+    async fn test_erases_subject_and_keeps_chain_valid() {
+        let state = AppState::for_tests(Config::default());
+        let mut sess = crate::SessionState::new();
+        sess.history_text.push("my card is 4111 1111 1111 1111".to_string());
+        state.sessions.insert("acme/u1".to_string(), sess);
+        state.sessions.insert("u10".to_string(), crate::SessionState::new());
+        record_intervention(&state, &LogContext::new("acme/u1", "gpt-4o"), "leak", "Leak", "4111 1111".to_string(), 0.1).await;
+        record_intervention(&state, &LogContext::new("u10", "gpt-4o"), "leak", "Leak", "keep me".to_string(), 0.1).await;
+
+        // Another tenant's `u1` and the untenanted one are not this subject.
+        state.sessions.insert("globex/u1".to_string(), crate::SessionState::new());
+        assert_eq!(erase_subject(&state, "u1").await.unwrap().sessions, 0);
+
+        let receipt = erase_subject(&state, "acme/u1").await.unwrap();
+        assert_eq!((receipt.sessions, receipt.prompts, receipt.usage_entries, receipt.audit_snippets), (1, 1, 1, 1));
+        assert_eq!(receipt.receipt_id.len(), 64);
+        assert!(state.sessions.contains_key("u10") && state.sessions.contains_key("globex/u1") && !state.sessions.contains_key("acme/u1"));
+
+        let logs: Vec<_> = state.audit_logs.lock().await.iter().cloned().collect();
+        assert_eq!(logs.iter().map(|l| l.content_snippet.as_str()).collect::<Vec<_>>(), vec!["", "keep me"]);
+        assert!(verify_chain(&logs, &AuditPolicy::default()).ok);
+        assert_eq!(erase_subject(&state, "acme/u1").await.unwrap().audit_snippets, 0);
+    }
+}
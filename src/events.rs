@@ -0,0 +1,239 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+use crate::AppState;
+use crate::audit::InterventionLog;
+use crate::config::EventPolicy;
+
+// --- EVENT STREAMING ---
+// Every upstream call Sentinel makes (a `request` event: session, tenant,
+// model, tokens, cost) and every audit entry (an `intervention` event, shaped
+// like `/api/logs`) can be published to a Kafka topic (`--features kafka`,
+// `SENTINEL_KAFKA_BROKERS`) and/or NATS subjects (`--features nats`,
+// `SENTINEL_NATS_URL`), for data platforms that would otherwise poll the
+// REST API. Kafka messages are keyed by session id, so one session's events
+// stay ordered; NATS subjects end in the event type.
+//
+// Publishing is best effort: events wait in a bounded queue
+// (`SENTINEL_EVENT_BUFFER`) and are dropped, and counted in
+// `sentinel_events_dropped_total`, when the broker cannot keep up. The
+// audit log stays the record of truth.
+
+/// Summary of one upstream call.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestEvent {
+    pub timestamp: u64,
+    pub session_id: String,
+    pub tenant: Option<String>,
+    pub provider: String,
+    /// Upstream path, e.g. `chat/completions`.
+    pub endpoint: String,
+    pub model: String,
+    pub status: u16,
+    pub streamed: bool,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub cost_usd: f64,
+    /// Time from sending upstream to the last byte, when measured.
+    pub latency_ms: Option<u64>,
+}
+
+impl RequestEvent {
+    pub fn new(session_id: &str, tenant: Option<&str>, provider: &str, endpoint: &str, model: &str) -> Self {
+        Self {
+            timestamp: crate::now_secs(),
+            session_id: session_id.to_string(),
+            tenant: tenant.map(str::to_string),
+            provider: provider.to_string(),
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            status: 200,
+            streamed: false,
+            prompt_tokens: None,
+            completion_tokens: None,
+            cost_usd: 0.0,
+            latency_ms: None,
+        }
+    }
+
+    /// Copies token counts from a response body's `usage` object.
+    pub fn with_usage(mut self, body: &serde_json::Value) -> Self {
+        self.prompt_tokens = body["usage"]["prompt_tokens"].as_u64();
+        self.completion_tokens = body["usage"]["completion_tokens"].as_u64();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Request(RequestEvent),
+    Intervention(Box<InterventionLog>),
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::Request(_) => "request",
+            Event::Intervention(_) => "intervention",
+        }
+    }
+
+    #[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+    fn session_id(&self) -> &str {
+        match self {
+            Event::Request(e) => &e.session_id,
+            Event::Intervention(log) => &log.session_id,
+        }
+    }
+}
+
+pub struct EventBus {
+    tx: mpsc::Sender<Event>,
+    /// Taken by the publisher when it starts.
+    rx: Mutex<Option<mpsc::Receiver<Event>>>,
+    pub dropped: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new(buffer: usize) -> Self {
+        let (tx, rx) = mpsc::channel(buffer);
+        Self { tx, rx: Mutex::new(Some(rx)), dropped: AtomicU64::new(0) }
+    }
+
+    /// Queues `event` for the publisher without waiting; a no-op when no
+    /// broker is configured.
+    pub fn publish(&self, policy: &EventPolicy, event: Event) {
+        if policy.enabled() && self.tx.try_send(event).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn emit(state: &AppState, event: Event) {
+    state.events.publish(&state.config.events, event);
+}
+
+/// Connects to the configured brokers and publishes queued events until the
+/// process exits.
+pub fn spawn_publisher(state: AppState) {
+    let policy = state.config.events.clone();
+    let Some(mut rx) = state.events.rx.lock().unwrap_or_else(|p| p.into_inner()).take() else {
+        return;
+    };
+    if !policy.enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let sinks = Sinks::connect(&policy).await;
+        if sinks.is_empty() {
+            // Dropping `rx` turns further events into counted drops.
+            return;
+        }
+        while let Some(event) = rx.recv().await {
+            match serde_json::to_vec(&event) {
+                Ok(payload) => sinks.send(&policy, &event, payload).await,
+                Err(e) => tracing::error!("Could not encode {} event: {}", event.kind(), e),
+            }
+        }
+    });
+}
+
+/// Broker connections; which exist depends on the build features.
+struct Sinks {
+    #[cfg(feature = "kafka")]
+    kafka: Option<rdkafka::producer::FutureProducer>,
+    #[cfg(feature = "nats")]
+    nats: Option<async_nats::Client>,
+}
+
+impl Sinks {
+    async fn connect(policy: &EventPolicy) -> Self {
+        #[cfg(not(feature = "kafka"))]
+        if let Some(brokers) = &policy.kafka_brokers {
+            tracing::warn!("SENTINEL_KAFKA_BROKERS={} ignored: built without the `kafka` feature", brokers);
+        }
+        #[cfg(not(feature = "nats"))]
+        if let Some(url) = &policy.nats_url {
+            tracing::warn!("SENTINEL_NATS_URL={} ignored: built without the `nats` feature", url);
+        }
+        Self {
+            #[cfg(feature = "kafka")]
+            kafka: policy.kafka_brokers.as_deref().and_then(|brokers| {
+                let producer = rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", brokers)
+                    .set("message.timeout.ms", "30000")
+                    .create()
+                    .inspect_err(|e| tracing::error!("Kafka producer for {} failed: {}", brokers, e))
+                    .ok()?;
+                tracing::info!("📤 Publishing events to Kafka topic {}", policy.kafka_topic);
+                Some(producer)
+            }),
+            #[cfg(feature = "nats")]
+            nats: match &policy.nats_url {
+                Some(url) => async_nats::ConnectOptions::new()
+                    .retry_on_initial_connect()
+                    .connect(url.as_str())
+                    .await
+                    .inspect(|_| tracing::info!("📤 Publishing events to NATS {}.*", policy.nats_subject))
+                    .inspect_err(|e| tracing::error!("NATS connection to {} failed: {}", url, e))
+                    .ok(),
+                None => None,
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut connected = false;
+        #[cfg(feature = "kafka")]
+        {
+            connected |= self.kafka.is_some();
+        }
+        #[cfg(feature = "nats")]
+        {
+            connected |= self.nats.is_some();
+        }
+        !connected
+    }
+
+    #[cfg_attr(not(any(feature = "kafka", feature = "nats")), allow(unused_variables))]
+    async fn send(&self, policy: &EventPolicy, event: &Event, payload: Vec<u8>) {
+        #[cfg(feature = "kafka")]
+        if let Some(producer) = &self.kafka {
+            let record = rdkafka::producer::FutureRecord::to(&policy.kafka_topic).key(event.session_id()).payload(&payload);
+            // The delivery report is not awaited: the queue would back up
+            // behind every broker round trip.
+            if let Err((e, _)) = producer.send_result(record) {
+                tracing::warn!("Kafka publish failed: {}", e);
+            }
+        }
+        #[cfg(feature = "nats")]
+        if let Some(client) = &self.nats
+            && let Err(e) = client.publish(format!("{}.{}", policy.nats_subject, event.kind()), payload.into()).await {
+            tracing::warn!("NATS publish failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_tagged_and_bounded() {
+        let bus = EventBus::new(1);
+        let event = || Event::Request(RequestEvent { cost_usd: 0.002, ..RequestEvent::new("acme/u1", Some("acme"), "openai", "chat/completions", "gpt-4o") });
+        bus.publish(&EventPolicy::default(), event());
+        let policy = EventPolicy { nats_url: Some("nats://localhost:4222".to_string()), ..Default::default() };
+        bus.publish(&policy, event());
+        bus.publish(&policy, event());
+        assert_eq!(bus.dropped.load(Ordering::Relaxed), 1);
+
+        let queued = bus.rx.lock().unwrap().as_mut().unwrap().try_recv().unwrap();
+        let json = serde_json::to_value(&queued).unwrap();
+        assert_eq!((json["type"].as_str(), json["session_id"].as_str(), json["cost_usd"].as_f64()), (Some("request"), Some("acme/u1"), Some(0.002)));
+    }
+}
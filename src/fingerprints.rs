@@ -0,0 +1,114 @@
+use dashmap::DashMap;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::config::UserLoopPolicy;
+
+// --- CROSS-SESSION LOOPS ---
+// An agent that crashes and restarts comes back under a new session (or as
+// `default`) and its loop history starts over. With `SENTINEL_USER_LOOPS` on,
+// each prompt is also fingerprinted per user (the request's `user`, else the
+// client API key); the same prompt turning up in `min_sessions` different
+// sessions within `window_secs` is reported as a `cross_session_loop`.
+
+struct Sighting {
+    fingerprint: u64,
+    session: String,
+    at: u64,
+}
+
+#[derive(Default)]
+pub struct UserFingerprints {
+    users: DashMap<String, VecDeque<Sighting>>,
+}
+
+impl UserFingerprints {
+    /// Records `prompt` for `user` and returns how many distinct sessions
+    /// sent it within the window, this one included.
+    pub fn observe(&self, user: &str, session: &str, prompt: &str, now: u64, policy: &UserLoopPolicy) -> usize {
+        let fingerprint = fingerprint(prompt);
+        let mut sightings = self.users.entry(user.to_string()).or_default();
+        while sightings.front().is_some_and(|s| now.saturating_sub(s.at) > policy.window_secs) {
+            sightings.pop_front();
+        }
+        sightings.push_back(Sighting { fingerprint, session: session.to_string(), at: now });
+        if sightings.len() > policy.max_per_user {
+            sightings.pop_front();
+        }
+        sightings.iter()
+            .filter(|s| s.fingerprint == fingerprint)
+            .map(|s| s.session.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Forgets sightings older than the window and users left without any.
+    pub fn sweep(&self, now: u64, policy: &UserLoopPolicy) {
+        self.users.retain(|_, sightings| {
+            sightings.retain(|s| now.saturating_sub(s.at) <= policy.window_secs);
+            !sightings.is_empty()
+        });
+    }
+
+    /// Forgets `user`'s sightings in sessions `scope` selects (users aren't
+    /// tenant-qualified); returns those sessions and how many sightings
+    /// were dropped.
+    pub fn forget_user(&self, user: &str, scope: impl Fn(&str) -> bool) -> (HashSet<String>, usize) {
+        let Some(mut sightings) = self.users.get_mut(user) else { return (HashSet::new(), 0) };
+        let (erased, kept): (VecDeque<Sighting>, VecDeque<Sighting>) = sightings.drain(..).partition(|s| scope(&s.session));
+        *sightings = kept;
+        let empty = sightings.is_empty();
+        drop(sightings);
+        if empty {
+            self.users.remove_if(user, |_, sightings| sightings.is_empty());
+        }
+        (erased.iter().map(|s| s.session.clone()).collect(), erased.len())
+    }
+
+    /// Forgets every sighting from sessions `erase` selects, whoever the
+    /// user; returns how many.
+    pub fn forget_sessions(&self, erase: impl Fn(&str) -> bool) -> usize {
+        let mut dropped = 0;
+        self.users.retain(|_, sightings| {
+            let before = sightings.len();
+            sightings.retain(|s| !erase(&s.session));
+            dropped += before - sightings.len();
+            !sightings.is_empty()
+        });
+        dropped
+    }
+
+    /// Users with sightings still held, for `/api/stats`.
+    pub fn users(&self) -> usize {
+        self.users.len()
+    }
+}
+
+/// Case- and whitespace-insensitive hash of a prompt.
+fn fingerprint(prompt: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in prompt.split_whitespace() {
+        word.to_lowercase().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_distinct_sessions_within_window() {
+        let store = UserFingerprints::default();
+        let policy = UserLoopPolicy { enabled: true, window_secs: 600, ..UserLoopPolicy::default() };
+        assert_eq!(store.observe("agent", "s1", "Fix the build", 1_000, &policy), 1);
+        assert_eq!(store.observe("agent", "s1", "fix  the BUILD", 1_010, &policy), 1);
+        assert_eq!(store.observe("agent", "s2", "Fix the build", 1_020, &policy), 2);
+        assert_eq!(store.observe("other", "s3", "Fix the build", 1_030, &policy), 1);
+        // The first two sightings have aged out.
+        assert_eq!(store.observe("agent", "s3", "Fix the build", 1_615, &policy), 2);
+
+        store.sweep(5_000, &policy);
+        assert_eq!(store.users(), 0);
+    }
+}
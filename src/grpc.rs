@@ -0,0 +1,190 @@
+use futures_util::stream::Stream;
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+
+use crate::AppState;
+use crate::audit::{InterventionLog, LogQuery, query_logs};
+use crate::rbac::{self, Role};
+
+pub mod pb {
+    tonic::include_proto!("sentinel.v1");
+}
+
+use pb::sentinel_admin_server::{SentinelAdmin, SentinelAdminServer};
+
+// --- gRPC ADMIN API ---
+// `proto/sentinel.proto`, served on `SENTINEL_GRPC_ADDR` when built with
+// `--features grpc`. Each RPC answers from the same state as its REST twin
+// and needs the same role (`rbac.rs`), sent as `authorization: Bearer <token>`
+// metadata.
+
+pub struct Admin {
+    state: AppState,
+}
+
+/// Serves the admin service until the process exits.
+pub fn spawn(state: AppState, addr: std::net::SocketAddr) {
+    tokio::spawn(async move {
+        tracing::info!("🛡️ gRPC admin API on {}", addr);
+        let service = SentinelAdminServer::new(Admin { state });
+        if let Err(e) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+            tracing::error!("gRPC admin API stopped: {}", e);
+        }
+    });
+}
+
+impl From<&InterventionLog> for pb::Intervention {
+    fn from(log: &InterventionLog) -> Self {
+        Self {
+            id: log.id,
+            timestamp: log.timestamp,
+            session_id: log.session_id.clone(),
+            detector: log.detector.clone(),
+            reason: log.reason.clone(),
+            content_snippet: log.content_snippet.clone(),
+            savings_est: log.savings_est,
+            bypassed: log.bypassed,
+            dry_run: log.dry_run,
+            model: log.model.clone(),
+            provider: log.provider.clone(),
+        }
+    }
+}
+
+impl From<pb::InterventionQuery> for LogQuery {
+    fn from(q: pb::InterventionQuery) -> Self {
+        Self {
+            session_id: q.session_id,
+            tenant: None,
+            detector: q.detector,
+            reason: q.reason,
+            from: q.from,
+            to: q.to,
+            q: q.q,
+            limit: q.limit.map(|l| l as usize),
+            before: q.before,
+        }
+    }
+}
+
+impl Admin {
+    /// Same rules as the REST API; tenant-bound callers are limited to it.
+    async fn authorize(&self, token: Option<String>, required: Role) -> Result<(), Status> {
+        let state = self.state.with_current_config();
+        if rbac::is_open(&state.config) {
+            return Ok(());
+        }
+        let principal = rbac::identify(&state, token.as_deref()).await.map_err(Status::unauthenticated)?;
+        if principal.role < required {
+            return Err(Status::permission_denied(format!("needs the {} role, the caller has {}", required.as_str(), principal.role.as_str())));
+        }
+        if principal.tenant.is_some() {
+            return Err(Status::permission_denied("tenant-bound callers can only use the REST admin API"));
+        }
+        Ok(())
+    }
+}
+
+/// The bearer token in a call's `authorization` metadata.
+fn bearer<T>(request: &Request<T>) -> Option<String> {
+    request.metadata().get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string())
+}
+
+type InterventionStream = Pin<Box<dyn Stream<Item = Result<pb::Intervention, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl SentinelAdmin for Admin {
+    async fn get_stats(&self, request: Request<pb::GetStatsRequest>) -> Result<Response<pb::Stats>, Status> {
+        self.authorize(bearer(&request), Role::Viewer).await?;
+        let state = &self.state;
+        Ok(Response::new(pb::Stats {
+            active_sessions: state.sessions.len() as u64,
+            total_saved_usd: state.total_saved_usd(),
+            interventions: state.sessions.iter().map(|s| s.interventions as u64).sum(),
+            blocked_sessions: state.blocked.len() as u64,
+            budget_alerts: state.budget_alerts.load(std::sync::atomic::Ordering::Relaxed),
+            status: if state.startup_problems.is_empty() { "Healthy" } else { "Degraded" }.to_string(),
+            startup_problems: state.startup_problems.to_vec(),
+        }))
+    }
+
+    async fn list_interventions(&self, request: Request<pb::InterventionQuery>) -> Result<Response<pb::InterventionPage>, Status> {
+        self.authorize(bearer(&request), Role::Viewer).await?;
+        let query = LogQuery::from(request.into_inner());
+        let history = crate::audit_history(&self.state).await;
+        let (total, page) = query_logs(history.iter(), &query);
+        let next_cursor = (total > page.len()).then(|| page.first().map(|l| l.id)).flatten();
+        Ok(Response::new(pb::InterventionPage {
+            total: total as u64,
+            interventions: page.iter().map(pb::Intervention::from).collect(),
+            next_cursor,
+        }))
+    }
+
+    type StreamInterventionsStream = InterventionStream;
+
+    async fn stream_interventions(&self, request: Request<pb::InterventionQuery>) -> Result<Response<InterventionStream>, Status> {
+        self.authorize(bearer(&request), Role::Viewer).await?;
+        let query = LogQuery { limit: None, before: None, ..LogQuery::from(request.into_inner()) };
+        let rx = self.state.live_logs.subscribe();
+        let events = futures_util::stream::unfold((rx, query), |(mut rx, query)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(log) if query.matches(&log) => return Some((Ok(pb::Intervention::from(&log)), (rx, query))),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        return Some((Err(Status::data_loss(format!("missed {} interventions", missed))), (rx, query)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn get_session(&self, request: Request<pb::SessionRef>) -> Result<Response<pb::Session>, Status> {
+        self.authorize(bearer(&request), Role::Viewer).await?;
+        let id = request.into_inner().id;
+        let sess = self.state.sessions.get(&id).ok_or_else(|| Status::not_found("Session not found"))?;
+        Ok(Response::new(pb::Session {
+            id: id.clone(),
+            created_at: sess.created_at,
+            last_activity: sess.last_activity,
+            cumulative_cost: sess.cumulative_cost,
+            budget_usd: sess.budget(&self.state.current_config().cost),
+            interventions: sess.interventions,
+            interventions_by_reason: sess.interventions_by_reason.clone().into_iter().collect(),
+            recent_prompts: sess.history_text.clone(),
+            blocked_reason: self.state.blocked.get(&id).map(|b| b.reason.clone()),
+        }))
+    }
+
+    async fn reset_session(&self, request: Request<pb::SessionRef>) -> Result<Response<pb::Ack>, Status> {
+        self.authorize(bearer(&request), Role::Operator).await?;
+        let id = request.into_inner().id;
+        self.state.sessions.remove(&id).ok_or_else(|| Status::not_found("Session not found"))?;
+        tracing::info!("Session '{}' reset over gRPC", id);
+        Ok(Response::new(pb::Ack { ok: true }))
+    }
+
+    async fn block_session(&self, request: Request<pb::BlockSessionRequest>) -> Result<Response<pb::Ack>, Status> {
+        self.authorize(bearer(&request), Role::Operator).await?;
+        let request = request.into_inner();
+        let reason = request.reason.unwrap_or_else(|| "Blocked by operator".to_string());
+        tracing::warn!("⛔ Session '{}' blocked over gRPC: {}", request.id, reason);
+        self.state.blocked.insert(request.id, crate::sessions::BlockEntry { reason, blocked_at: crate::now_secs() });
+        Ok(Response::new(pb::Ack { ok: true }))
+    }
+
+    async fn unblock_session(&self, request: Request<pb::SessionRef>) -> Result<Response<pb::Ack>, Status> {
+        self.authorize(bearer(&request), Role::Operator).await?;
+        let id = request.into_inner().id;
+        self.state.blocked.remove(&id).ok_or_else(|| Status::not_found("Session is not blocked"))?;
+        tracing::info!("Session '{}' unblocked over gRPC", id);
+        Ok(Response::new(pb::Ack { ok: true }))
+    }
+}
@@ -0,0 +1,169 @@
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::AppState;
+use crate::config::Config;
+
+// --- HEALTH AND READINESS ---
+// `/healthz` answers as long as the process serves requests (liveness);
+// `/health` stays as its older alias. `/readyz` is what an orchestrator
+// should route on: it is 503 when the audit store can't be used (the JSONL
+// file didn't open, the embedded database or Postgres doesn't answer) or,
+// with `SENTINEL_READY_PROBE_PROVIDERS=true`, when a provider rejects its
+// key or can't be reached. Probes list the provider's models and their
+// results are reused for `SENTINEL_READY_PROBE_TTL_SECS`, so frequent
+// readiness polls don't hammer the providers. `SENTINEL_READY_PROVIDERS`
+// limits probing to some providers; by default every one with a key.
+// `/readyz` is unauthenticated, so it only says `ok` or `fail` per check; the
+// errors behind a failure and the configuration warnings of the startup
+// self-check (which don't make the instance unready) are in
+// `GET /api/readiness`, behind the admin API.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Unix seconds the result was obtained.
+    pub checked_at: u64,
+}
+
+impl Check {
+    fn of(result: Result<(), String>, now: u64) -> Self {
+        Self { ok: result.is_ok(), error: result.err(), checked_at: now }
+    }
+}
+
+/// Provider probe results, by provider name.
+#[derive(Debug, Default)]
+pub struct ProbeCache {
+    results: DashMap<String, Check>,
+}
+
+/// `GET /healthz`.
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Providers `/readyz` probes: those named, or every one with a key.
+fn probed(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = if config.readiness.providers.is_empty() {
+        config.providers.iter()
+            .filter(|(name, p)| name.as_str() != crate::mock::PROVIDER && p.api_key != "none" && !p.api_key.contains("xxxx"))
+            .map(|(name, _)| name.clone())
+            .collect()
+    } else {
+        config.readiness.providers.clone()
+    };
+    names.sort();
+    names
+}
+
+async fn probe_all(state: &AppState, now: u64) -> BTreeMap<String, Check> {
+    let ttl = state.config.readiness.probe_ttl_secs;
+    let mut checks = BTreeMap::new();
+    let mut probes = tokio::task::JoinSet::new();
+    for name in probed(&state.config) {
+        if let Some(cached) = state.probes.results.get(&name).filter(|c| now.saturating_sub(c.checked_at) < ttl) {
+            checks.insert(name, cached.clone());
+            continue;
+        }
+        let Some(provider) = state.config.providers.get(&name).cloned() else {
+            checks.insert(name, Check::of(Err("not configured".to_string()), now));
+            continue;
+        };
+        let client = state.client.clone();
+        probes.spawn(async move {
+            let result = crate::selfcheck::probe_provider(&client, &provider).await;
+            (name, Check::of(result, now))
+        });
+    }
+    while let Some(Ok((name, check))) = probes.join_next().await {
+        state.probes.results.insert(name.clone(), check.clone());
+        checks.insert(name, check);
+    }
+    checks
+}
+
+/// The storage check and the provider probes.
+async fn checks(state: &AppState) -> (Check, BTreeMap<String, Check>) {
+    let now = crate::now_secs();
+    let audit = state.audit.clone();
+    let storage = tokio::task::spawn_blocking(move || audit.check()).await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    let providers = if state.config.readiness.probe_providers { probe_all(state, now).await } else { BTreeMap::new() };
+    (Check::of(storage, now), providers)
+}
+
+fn verdict(check: &Check) -> &'static str {
+    if check.ok { "ok" } else { "fail" }
+}
+
+/// `GET /readyz`: 200 when ready, 503 otherwise; `ok` or `fail` per check.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let (storage, providers) = checks(&state).await;
+    let ready = storage.ok && providers.values().all(|c| c.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let providers: BTreeMap<&str, &str> = providers.iter().map(|(name, check)| (name.as_str(), verdict(check))).collect();
+    (status, Json(serde_json::json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "storage": verdict(&storage),
+        "providers": providers,
+    })))
+}
+
+/// `GET /api/readiness`: the checks of `/readyz` with their errors, and the
+/// configuration warnings.
+pub async fn report(State(state): State<AppState>) -> impl IntoResponse {
+    let (storage, providers) = checks(&state).await;
+    let ready = storage.ok && providers.values().all(|c| c.ok);
+    Json(serde_json::json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "config": { "warnings": crate::selfcheck::static_problems(&state.config) },
+        "storage": storage,
+        "providers": providers,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProviderConfig;
+
+    #[test]
+    fn test_probes_providers_with_keys() {
+        let mut config = Config::default();
+        config.providers.insert("azure".to_string(), ProviderConfig::new("https://a", "sk-live"));
+        config.providers.insert("groq".to_string(), ProviderConfig::new("https://g", "none"));
+        assert_eq!(probed(&config), vec!["azure".to_string()]);
+        config.readiness.providers = vec!["groq".to_string()];
+        assert_eq!(probed(&config), vec!["groq".to_string()]);
+        assert!(!Check::of(Err("down".to_string()), 1).ok);
+    }
+
+    #[tokio::test]
+    async fn test_readyz_only_says_ok_or_fail() {
+        let mut config = Config::default();
+        config.readiness.probe_providers = true;
+        config.readiness.providers = vec!["nowhere".to_string()];
+        let state = AppState::for_tests(config);
+        let response = readyz(State(state.clone())).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, serde_json::json!({"status": "not_ready", "storage": "ok", "providers": {"nowhere": "fail"}}));
+
+        let response = report(State(state)).await.into_response();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["providers"]["nowhere"]["error"], "not configured");
+        assert!(body["config"]["warnings"].is_array());
+    }
+}
@@ -0,0 +1,300 @@
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use sha2::{Digest, Sha256};
+
+use crate::AppState;
+use crate::config::IdempotencyPolicy;
+
+// --- IDEMPOTENCY KEYS ---
+// Agents retry after client-side timeouts, and every retry is paid for
+// again. A proxy request with an `Idempotency-Key` header has its response
+// kept for `SENTINEL_IDEMPOTENCY_TTL_SECS`; a retry with the same key (from
+// the same API key, tenant and session) gets that response back, marked
+// `idempotent-replayed: true`, without reaching the detectors or the
+// provider. A retry that arrives while the first request is still running
+// gets a 409, and reusing a key for a different request a 422.
+//
+// Only successful, non-streamed completions are kept: blocks, quarantines
+// (202), errors and streams run for real when retried, and the key is
+// released as soon as they finish. Erasing a session drops its responses.
+
+pub const HEADER: &str = "idempotency-key";
+pub const REPLAYED: &str = "idempotent-replayed";
+
+#[derive(Debug, Clone)]
+pub struct Cached {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    created_at: u64,
+    /// The session the key was used in, for erasure.
+    session: String,
+    fingerprint: [u8; 32],
+    /// `None` while the first request is in flight.
+    response: Option<Cached>,
+}
+
+/// What to do with a request carrying a key.
+#[derive(Debug)]
+enum Begin {
+    Run,
+    Replay(Cached),
+    InProgress,
+    Mismatch,
+}
+
+#[derive(Debug, Default)]
+pub struct IdempotencyCache {
+    slots: DashMap<String, Slot>,
+}
+
+impl IdempotencyCache {
+    fn begin(&self, key: &str, session: &str, fingerprint: [u8; 32], now: u64, policy: &IdempotencyPolicy) -> Begin {
+        let fresh = |slot: &Slot| now.saturating_sub(slot.created_at) <= policy.ttl_secs;
+        if !self.slots.contains_key(key) && self.slots.len() >= policy.max_entries {
+            self.slots.retain(|_, slot| fresh(slot));
+            if self.slots.len() >= policy.max_entries
+                && let Some(oldest) = self.slots.iter().min_by_key(|s| s.created_at).map(|s| s.key().clone()) {
+                self.slots.remove(&oldest);
+            }
+        }
+        let slot = Slot { created_at: now, session: session.to_string(), fingerprint, response: None };
+        match self.slots.entry(key.to_string()) {
+            Entry::Occupied(entry) if fresh(entry.get()) => match &entry.get().response {
+                _ if entry.get().fingerprint != fingerprint => Begin::Mismatch,
+                Some(cached) => Begin::Replay(cached.clone()),
+                None => Begin::InProgress,
+            },
+            Entry::Occupied(mut entry) => {
+                entry.insert(slot);
+                Begin::Run
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(slot);
+                Begin::Run
+            }
+        }
+    }
+
+    /// Drops the keys used in sessions `matches` selects; how many were dropped.
+    pub fn forget_sessions(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let before = self.slots.len();
+        self.slots.retain(|_, slot| !matches(&slot.session));
+        before - self.slots.len()
+    }
+
+    /// Keeps `response` for `key`, or releases the key when there is none.
+    fn complete(&self, key: &str, response: Option<Cached>) {
+        match response {
+            Some(cached) => {
+                if let Some(mut slot) = self.slots.get_mut(key) {
+                    slot.response = Some(cached);
+                }
+            }
+            None => {
+                self.slots.remove(key);
+            }
+        }
+    }
+}
+
+/// A key whose first request is in flight. Dropping it before `complete`
+/// (the client went away and the request future with it) releases the key,
+/// so retries run instead of getting 409s until it expires.
+struct Pending<'a> {
+    cache: &'a IdempotencyCache,
+    key: String,
+    done: bool,
+}
+
+impl<'a> Pending<'a> {
+    fn new(cache: &'a IdempotencyCache, key: String) -> Self {
+        Self { cache, key, done: false }
+    }
+
+    fn complete(mut self, response: Option<Cached>) {
+        self.cache.complete(&self.key, response);
+        self.done = true;
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.cache.complete(&self.key, None);
+        }
+    }
+}
+
+fn keep(response: &Response) -> bool {
+    let status = response.status();
+    let stream = response.headers().get(CONTENT_TYPE).is_some_and(|t| t.as_bytes().starts_with(b"text/event-stream"));
+    // Blocked completions can be well-formed 200s.
+    let stopped = response.headers().get("x-sentinel-intervention").is_some_and(|a| a == "blocked" || a == "quarantined");
+    status.is_success() && status != StatusCode::ACCEPTED && !stream && !stopped
+}
+
+fn rejection(status: StatusCode, code: &str, message: &str) -> Response {
+    (status, Json(serde_json::json!({
+        "error": {
+            "message": format!("Sentinel: {}", message),
+            "type": "sentinel_idempotency",
+            "param": null,
+            "code": code
+        }
+    }))).into_response()
+}
+
+/// Middleware on the proxy routes: replays kept responses for retried keys.
+pub async fn replay(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let policy = &state.config.idempotency;
+    let key = request.headers().get(HEADER).and_then(|v| v.to_str().ok()).map(str::trim).filter(|k| !k.is_empty());
+    let Some(key) = key.filter(|_| policy.enabled) else {
+        return next.run(request).await;
+    };
+    let key = key.to_string();
+
+    let (parts, body) = request.into_parts();
+    // `limits::body_size` already bounded the body.
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return rejection(StatusCode::BAD_REQUEST, "unreadable_body", "could not read the request body");
+    };
+    // Keys are per caller: the same key from another API key, tenant or
+    // session (which already names the tenant) is another request.
+    let user = serde_json::from_slice::<serde_json::Value>(&bytes).ok()
+        .and_then(|body| body.get("user").and_then(|u| u.as_str()).map(str::to_string));
+    let session = crate::session_id(&parts.headers, user.as_deref());
+    let key = hex(&digest(&[crate::client_api_key(&parts.headers).unwrap_or_default().as_bytes(), session.as_bytes(), key.as_bytes()]));
+    let fingerprint = digest(&[parts.uri.path().as_bytes(), &bytes]);
+
+    match state.idempotency.begin(&key, &session, fingerprint, crate::now_secs(), policy) {
+        Begin::Replay(cached) => {
+            let mut response = (cached.status, cached.headers, cached.body).into_response();
+            response.headers_mut().insert(REPLAYED, HeaderValue::from_static("true"));
+            response
+        }
+        Begin::InProgress => rejection(StatusCode::CONFLICT, "idempotency_key_in_use", "a request with this Idempotency-Key is still in progress"),
+        Begin::Mismatch => rejection(StatusCode::UNPROCESSABLE_ENTITY, "idempotency_key_reused", "this Idempotency-Key was used for a different request"),
+        Begin::Run => {
+            let pending = Pending::new(&state.idempotency, key);
+            run_first(pending, next.run(Request::from_parts(parts, Body::from(bytes)))).await
+        }
+    }
+}
+
+/// Awaits the first request for a key and keeps its response if it can be
+/// replayed; otherwise, or if this is dropped first, the key is released.
+async fn run_first(pending: Pending<'_>, response: impl Future<Output = Response>) -> Response {
+    let response = response.await;
+    if !keep(&response) {
+        pending.complete(None);
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => {
+            let cached = Cached { status: parts.status, headers: parts.headers.clone(), body: body.clone() };
+            pending.complete(Some(cached));
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => {
+            pending.complete(None);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+    }
+}
+
+/// SHA-256 over `parts`, each prefixed with its length so they can't run together.
+fn digest(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_replay_conflict_and_expire() {
+        let cache = IdempotencyCache::default();
+        let policy = IdempotencyPolicy { enabled: true, ttl_secs: 60, max_entries: 2 };
+        assert!(matches!(cache.begin("a", "s", [1; 32], 100, &policy), Begin::Run));
+        assert!(matches!(cache.begin("a", "s", [1; 32], 101, &policy), Begin::InProgress));
+        let cached = Cached { status: StatusCode::OK, headers: HeaderMap::new(), body: Bytes::from_static(b"{}") };
+        cache.complete("a", Some(cached));
+        assert!(matches!(cache.begin("a", "s", [1; 32], 102, &policy), Begin::Replay(c) if c.body == "{}"));
+        assert!(matches!(cache.begin("a", "s", [2; 32], 102, &policy), Begin::Mismatch));
+        // Expired: runs again.
+        assert!(matches!(cache.begin("a", "s", [2; 32], 200, &policy), Begin::Run));
+
+        // Released keys run again; a full cache drops the oldest slot.
+        cache.complete("a", None);
+        assert!(matches!(cache.begin("b", "s", [1; 32], 300, &policy), Begin::Run));
+        assert!(matches!(cache.begin("c", "s", [1; 32], 301, &policy), Begin::Run));
+        assert!(matches!(cache.begin("d", "s", [1; 32], 302, &policy), Begin::Run));
+        assert_eq!(cache.slots.len(), 2);
+        assert!(!cache.slots.contains_key("b"));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_requests_release_their_key() {
+        let cache = IdempotencyCache::default();
+        let policy = IdempotencyPolicy { enabled: true, ttl_secs: 60, max_entries: 10 };
+        assert!(matches!(cache.begin("a", "s", [1; 32], 100, &policy), Begin::Run));
+        let upstream = std::future::pending::<Response>();
+        // The client disconnects before the provider answers.
+        let first = tokio::time::timeout(std::time::Duration::from_millis(10), run_first(Pending::new(&cache, "a".to_string()), upstream));
+        assert!(first.await.is_err());
+        assert!(matches!(cache.begin("a", "s", [1; 32], 101, &policy), Begin::Run));
+
+        let ok = async { (StatusCode::OK, "{}").into_response() };
+        assert_eq!(run_first(Pending::new(&cache, "a".to_string()), ok).await.status(), StatusCode::OK);
+        assert!(matches!(cache.begin("a", "s", [1; 32], 102, &policy), Begin::Replay(c) if c.body == "{}"));
+    }
+
+    #[test]
+    fn test_only_successful_completions_are_kept() {
+        let response = |status: StatusCode, action: &'static str| {
+            let mut response = (status, "{}").into_response();
+            response.headers_mut().insert("x-sentinel-intervention", HeaderValue::from_static(action));
+            response
+        };
+        assert!(keep(&response(StatusCode::OK, "none")));
+        assert!(keep(&response(StatusCode::OK, "warned")));
+        assert!(!keep(&response(StatusCode::OK, "blocked")));
+        assert!(!keep(&response(StatusCode::FORBIDDEN, "blocked")));
+        assert!(!keep(&response(StatusCode::ACCEPTED, "quarantined")));
+        assert!(!keep(&response(StatusCode::BAD_REQUEST, "none")));
+    }
+
+    #[test]
+    fn test_erased_sessions_lose_their_keys() {
+        let cache = IdempotencyCache::default();
+        let policy = IdempotencyPolicy { enabled: true, ttl_secs: 60, max_entries: 10 };
+        cache.begin("a", "acme/alice", [1; 32], 100, &policy);
+        cache.begin("b", "acme/bob", [1; 32], 100, &policy);
+        assert_eq!(cache.forget_sessions(|s| s == "acme/alice"), 1);
+        assert!(matches!(cache.begin("a", "acme/alice", [1; 32], 101, &policy), Begin::Run));
+        assert!(matches!(cache.begin("b", "acme/bob", [1; 32], 101, &policy), Begin::InProgress));
+    }
+}
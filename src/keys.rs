@@ -0,0 +1,212 @@
+use dashmap::DashMap;
+use dashmap::mapref::one::RefMut;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::config::{Config, ProviderConfig};
+
+// --- PROVIDER KEYS ---
+// An expired or revoked key used to show up only as every request to its
+// provider failing. Every key of every provider is validated with the same
+// `GET /models` as the startup self-check, at startup and then every
+// `SENTINEL_KEY_CHECK_SECS` (900); failures are logged, and `/api/stats`
+// lists each key (by its last four characters) under `provider_keys`.
+//
+// A key variable may hold several keys, comma-separated
+// (`OPENAI_API_KEY=sk-a,sk-b`). One is in use at a time: when it fails a
+// check, or the provider answers a request with 401, the next key not known
+// to be bad takes over. With `SENTINEL_KEY_ROTATE_SECS` the keys also take
+// turns on that schedule, so one can be replaced upstream while another
+// carries the traffic. Tenant keys are sent as configured.
+
+/// How often the checker wakes to see whether a check or rotation is due.
+const TICK_SECS: u64 = 15;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyHealth {
+    /// The key's last four characters.
+    pub key: String,
+    /// `None` until the key is first checked.
+    pub ok: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub checked_at: Option<u64>,
+}
+
+/// The keys of one provider and which of them is in use.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ring {
+    /// Index into `keys`.
+    pub active: usize,
+    pub rotated_at: u64,
+    pub keys: Vec<KeyHealth>,
+}
+
+impl Ring {
+    /// Moves to the next key not known to be bad; false when there is none.
+    fn advance(&mut self, now: u64) -> bool {
+        let n = self.keys.len();
+        let Some(next) = (1..n).map(|step| (self.active + step) % n).find(|&i| self.keys[i].ok != Some(false)) else {
+            return false;
+        };
+        self.active = next;
+        self.rotated_at = now;
+        true
+    }
+}
+
+fn masked(key: &str) -> String {
+    let tail: Vec<char> = key.chars().rev().take(4).collect();
+    format!("…{}", tail.into_iter().rev().collect::<String>())
+}
+
+#[derive(Debug, Default)]
+pub struct KeyRing {
+    rings: DashMap<String, Ring>,
+}
+
+impl KeyRing {
+    /// `name`'s ring, started over when its configured keys changed.
+    fn ring(&self, name: &str, provider: &ProviderConfig, now: u64) -> RefMut<'_, String, Ring> {
+        let keys = provider.keys();
+        let mut ring = self.rings.entry(name.to_string()).or_default();
+        if ring.keys.len() != keys.len() || ring.keys.iter().zip(&keys).any(|(health, key)| health.key != masked(key)) {
+            *ring = Ring {
+                active: 0,
+                rotated_at: now,
+                keys: keys.iter().map(|key| KeyHealth { key: masked(key), ..KeyHealth::default() }).collect(),
+            };
+        }
+        ring
+    }
+
+    /// The key to send to provider `name`.
+    pub fn active(&self, name: &str, provider: &ProviderConfig) -> String {
+        if provider.spare_keys.is_empty() {
+            return provider.api_key.clone();
+        }
+        let index = self.ring(name, provider, crate::now_secs()).active;
+        provider.keys().get(index).map_or_else(|| provider.api_key.clone(), |key| key.to_string())
+    }
+
+    /// Records a check of key `index`; a failed key in use hands over.
+    pub fn record(&self, name: &str, provider: &ProviderConfig, index: usize, result: Result<(), String>, now: u64) {
+        let mut ring = self.ring(name, provider, now);
+        let failed = result.is_err();
+        let Some(health) = ring.keys.get_mut(index) else { return };
+        health.ok = Some(!failed);
+        health.error = result.err();
+        health.checked_at = Some(now);
+        if failed && ring.active == index && ring.advance(now) {
+            tracing::warn!("Provider '{}' switched to key {}", name, ring.keys[ring.active].key);
+        }
+    }
+
+    /// Hands over to the next key once the one in use has had `every` seconds.
+    fn rotate(&self, name: &str, provider: &ProviderConfig, every: u64, now: u64) -> bool {
+        let mut ring = self.ring(name, provider, now);
+        every > 0 && now.saturating_sub(ring.rotated_at) >= every && ring.advance(now)
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, Ring> {
+        self.rings.iter().map(|r| (r.key().clone(), r.value().clone())).collect()
+    }
+
+    /// Puts back a saved ring; one whose keys no longer match the
+    /// configuration starts over on first use.
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub fn restore(&self, name: String, ring: Ring) {
+        self.rings.insert(name, ring);
+    }
+}
+
+/// Providers whose keys are checked: all with a key, except the mock.
+fn checked(config: &Config) -> Vec<(String, ProviderConfig)> {
+    config.providers.iter()
+        .filter(|(name, p)| name.as_str() != crate::mock::PROVIDER && crate::has_embedding_key(&p.api_key))
+        .map(|(name, p)| (name.clone(), p.clone()))
+        .collect()
+}
+
+/// Provider `name` answered 401 to `key`.
+pub fn rejected(state: &AppState, name: &str, key: &str) {
+    let name = if state.config.providers.contains_key(name) { name } else { "openai" };
+    let Some(provider) = state.config.providers.get(name) else { return };
+    if let Some(index) = provider.keys().iter().position(|k| *k == key) {
+        tracing::warn!("Provider '{}' rejected key {}", name, masked(key));
+        state.keys.record(name, provider, index, Err("rejected by the provider (401)".to_string()), crate::now_secs());
+    }
+}
+
+async fn check_all(state: &AppState, config: &Config, now: u64) {
+    let mut probes = tokio::task::JoinSet::new();
+    for (name, provider) in checked(config) {
+        for (index, key) in provider.keys().into_iter().enumerate() {
+            let probe = ProviderConfig::new(&provider.base_url, key);
+            let (client, name, provider) = (state.client.clone(), name.clone(), provider.clone());
+            probes.spawn(async move {
+                let result = crate::selfcheck::probe_provider(&client, &probe).await;
+                (name, provider, index, result)
+            });
+        }
+    }
+    while let Some(Ok((name, provider, index, result))) = probes.join_next().await {
+        if let Err(e) = &result {
+            tracing::warn!("Provider '{}' key {} {}", name, masked(provider.keys()[index]), e);
+        }
+        state.keys.record(&name, &provider, index, result, now);
+    }
+}
+
+/// Validates keys at startup and every `check_secs`, and rotates them.
+pub fn spawn_checker(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(TICK_SECS));
+        let mut checked_at: Option<u64> = None;
+        loop {
+            tick.tick().await;
+            let config = state.current_config();
+            let now = crate::now_secs();
+            let every = config.keys.check_secs;
+            if checked_at.is_none_or(|at| every > 0 && now.saturating_sub(at) >= every) {
+                check_all(&state, &config, now).await;
+                checked_at = Some(now);
+            }
+            for (name, provider) in checked(&config).into_iter().filter(|(_, p)| !p.spare_keys.is_empty()) {
+                if state.keys.rotate(&name, &provider, config.keys.rotate_secs, now) {
+                    tracing::info!("Rotated provider '{}' to its next key", name);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_keys_hand_over_and_keys_rotate() {
+        let ring = KeyRing::default();
+        let provider = ProviderConfig::new("https://api", "sk-aaaa, sk-bbbb,sk-cccc");
+        assert_eq!(provider.keys(), vec!["sk-aaaa", "sk-bbbb", "sk-cccc"]);
+        assert_eq!(ring.active("openai", &provider), "sk-aaaa");
+
+        ring.record("openai", &provider, 1, Err("rejected".to_string()), 10);
+        ring.record("openai", &provider, 0, Err("rejected".to_string()), 10);
+        // Key 1 is known bad, so key 2 takes over.
+        assert_eq!(ring.active("openai", &provider), "sk-cccc");
+        assert_eq!(ring.snapshot()["openai"].keys[0].key, "…aaaa");
+
+        ring.record("openai", &provider, 0, Ok(()), 20);
+        assert!(!ring.rotate("openai", &provider, 60, 30));
+        assert!(ring.rotate("openai", &provider, 60, 70));
+        assert_eq!(ring.active("openai", &provider), "sk-aaaa");
+        assert!(!ring.rotate("openai", &provider, 0, 1_000));
+
+        let single = ProviderConfig::new("https://api", "sk-only");
+        assert_eq!(ring.active("groq", &single), "sk-only");
+    }
+}
@@ -0,0 +1,153 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode, header::CONTENT_LENGTH},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::AppState;
+use crate::audit::{LogContext, record_intervention};
+use crate::config::LimitPolicy;
+
+// --- REQUEST LIMITS ---
+// Agents that stuff megabytes of context into every call cost money and
+// memory before any detector runs. The body size is enforced as middleware
+// on the proxy routes (replacing axum's fixed 2 MB default), the message
+// count and prompt length once the pipeline has the parsed request. Each
+// rejection is a 413 and an intervention under the `limits` detector.
+//
+// The completion budget is capped rather than rejected: with
+// `SENTINEL_MAX_OUTPUT_TOKENS` (or `max_output` in a model profile or
+// tenant), a larger `max_tokens` / `max_completion_tokens` is lowered to the
+// cap, and a request without either gets the cap set.
+
+pub const DETECTOR: &str = "limits";
+
+/// Rejects proxy requests whose body is larger than `max_body_bytes`, by
+/// `Content-Length` when declared and by reading at most the limit otherwise.
+pub async fn body_size(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let max = state.config.limits.max_body_bytes;
+    if max == 0 {
+        return next.run(request).await;
+    }
+    let session_id = crate::session_id(request.headers(), None);
+    let ctx = LogContext { session_id, ..Default::default() };
+    let reason = format!("request body exceeds {} bytes", max);
+    let declared = request.headers().get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > max) {
+        return reject(&state, request.headers(), &ctx, None, reason).await;
+    }
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, max).await {
+        Ok(bytes) => next.run(Request::from_parts(parts, Body::from(bytes))).await,
+        Err(_) => reject(&state, &parts.headers, &ctx, None, reason).await,
+    }
+}
+
+/// The first message-count or prompt-length limit `body` breaks.
+pub fn check(policy: &LimitPolicy, body: &Value) -> Option<String> {
+    let messages = body["messages"].as_array().map_or(&[][..], Vec::as_slice);
+    if policy.max_messages > 0 && messages.len() > policy.max_messages {
+        return Some(format!("{} messages, the limit is {}", messages.len(), policy.max_messages));
+    }
+    let chars = messages.iter().map(|m| text_chars(&m["content"])).sum::<usize>() + text_chars(&body["prompt"]);
+    if policy.max_prompt_chars > 0 && chars > policy.max_prompt_chars {
+        return Some(format!("{} prompt characters, the limit is {}", chars, policy.max_prompt_chars));
+    }
+    None
+}
+
+/// Applies the completion-token cap to `body`; returns whether it changed.
+pub fn cap_output(cap: u64, model: &str, body: &mut Value) -> bool {
+    let Some(request) = body.as_object_mut().filter(|_| cap > 0) else { return false };
+    let mut asked = false;
+    let mut changed = false;
+    for key in ["max_completion_tokens", "max_tokens"] {
+        if let Some(tokens) = request.get(key).and_then(Value::as_u64) {
+            asked = true;
+            if tokens > cap {
+                request.insert(key.to_string(), cap.into());
+                changed = true;
+            }
+        }
+    }
+    if !asked {
+        // Reasoning models take only `max_completion_tokens` on chat.
+        let reasoning = ["o1", "o3", "o4"].iter().any(|p| model.starts_with(p));
+        let key = if reasoning && request.contains_key("messages") { "max_completion_tokens" } else { "max_tokens" };
+        request.insert(key.to_string(), cap.into());
+        changed = true;
+    }
+    changed
+}
+
+/// Characters of text in a message `content` or completions `prompt`:
+/// plain strings, arrays of them, and `{ "type": "text", "text": .. }` parts.
+fn text_chars(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(items) => items.iter().map(text_chars).sum(),
+        Value::Object(part) => part.get("text").map_or(0, text_chars),
+        _ => 0,
+    }
+}
+
+/// Logs the rejection and answers 413 with an OpenAI-style error.
+pub async fn reject(state: &AppState, headers: &HeaderMap, ctx: &LogContext, request: Option<&Value>, reason: String) -> Response {
+    tracing::warn!(session_id = %ctx.session_id, "📦 Request over limits: {}", reason);
+    let model = ctx.model.as_deref().unwrap_or_default();
+    let avoided = crate::savings::avoided(&state.config, DETECTOR, model, request);
+    record_intervention(state, ctx, DETECTOR, "Request Too Large", reason.clone(), avoided).await;
+    let messages = &state.config.messages;
+    let error_body = serde_json::json!({
+        "error": {
+            "message": messages.render(&messages.locale_for(headers), "too_large", &[("reason", &reason), ("session", &ctx.session_id)]),
+            "type": "sentinel_limit",
+            "param": null,
+            "code": "request_too_large"
+        }
+    });
+    let headers = crate::intervention_headers("blocked", DETECTOR, &reason);
+    (StatusCode::PAYLOAD_TOO_LARGE, headers, Json(error_body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_counts_messages_and_prompt_chars() {
+        let policy = LimitPolicy { max_body_bytes: 0, max_messages: 2, max_prompt_chars: 10 };
+        let chat = |messages: Value| serde_json::json!({"model": "gpt-4o", "messages": messages});
+        assert_eq!(check(&policy, &chat(serde_json::json!([{"role": "user", "content": "hello"}]))), None);
+        let three = chat(serde_json::json!([{"content": "a"}, {"content": "b"}, {"content": "c"}]));
+        assert_eq!(check(&policy, &three).as_deref(), Some("3 messages, the limit is 2"));
+        let parts = chat(serde_json::json!([{"content": [{"type": "text", "text": "hello"}, {"type": "text", "text": "world!"}]}]));
+        assert_eq!(check(&policy, &parts).as_deref(), Some("11 prompt characters, the limit is 10"));
+        let prompt = serde_json::json!({"model": "gpt-3.5-turbo-instruct", "prompt": ["hello", "world!"]});
+        assert!(check(&policy, &prompt).is_some());
+        assert_eq!(check(&LimitPolicy { max_body_bytes: 0, max_messages: 0, max_prompt_chars: 0 }, &three), None);
+    }
+
+    #[test]
+    fn test_cap_output_lowers_or_injects() {
+        let mut greedy = serde_json::json!({"messages": [], "max_tokens": 16_000});
+        assert!(cap_output(4_096, "gpt-4o", &mut greedy));
+        assert_eq!(greedy["max_tokens"], 4_096);
+        let mut modest = serde_json::json!({"messages": [], "max_completion_tokens": 500});
+        assert!(!cap_output(4_096, "gpt-4o", &mut modest));
+        assert_eq!(modest["max_completion_tokens"], 500);
+        let mut unset = serde_json::json!({"messages": []});
+        assert!(cap_output(4_096, "o3-mini", &mut unset));
+        assert_eq!(unset["max_completion_tokens"], 4_096);
+        let mut prompt = serde_json::json!({"prompt": "hi"});
+        assert!(cap_output(256, "gpt-3.5-turbo-instruct", &mut prompt));
+        assert_eq!(prompt["max_tokens"], 256);
+        assert!(!cap_output(0, "gpt-4o", &mut serde_json::json!({"messages": []})));
+    }
+}
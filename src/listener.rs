@@ -0,0 +1,84 @@
+use axum::{
+    Json, Router,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::{SocketAddr, ToSocketAddrs};
+use tokio::net::{TcpListener, TcpSocket};
+
+use crate::AppState;
+use crate::config::ServerPolicy;
+
+// --- LISTENERS ---
+// Sockets are bound up front, one per `SENTINEL_ADDR` entry, so a bad
+// address stops startup instead of surfacing after the first one is live.
+// The request timeout is middleware that reads the live config, so it
+// follows reloads; bind-time options need a restart.
+
+/// Binds every address, failing on the first one that can't be bound.
+pub fn bind_all(addrs: &[String], policy: &ServerPolicy) -> std::io::Result<Vec<TcpListener>> {
+    addrs.iter().map(|addr| bind(addr, policy)).collect()
+}
+
+fn bind(addr: &str, policy: &ServerPolicy) -> std::io::Result<TcpListener> {
+    let socket_addr = addr.to_socket_addrs()?.next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} did not resolve", addr)))?;
+    let socket = if socket_addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_reuseaddr(true)?;
+    // Accepted connections inherit it from the listening socket.
+    socket.set_keepalive(policy.tcp_keepalive)?;
+    socket.bind(socket_addr)?;
+    socket.listen(policy.backlog)
+}
+
+/// Serves `app` on every listener until one of them fails.
+pub async fn serve(listeners: Vec<TcpListener>, app: Router) -> std::io::Result<()> {
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tracing::info!("🛡️ Sentinel SaaS active on {}", listener.local_addr()?);
+        servers.spawn(axum::serve(listener, app.clone().into_make_service_with_connect_info::<SocketAddr>()).into_future());
+    }
+    while let Some(result) = servers.join_next().await {
+        result.map_err(std::io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// Answers 504 with an OpenAI-style error once a request runs past
+/// `request_timeout_secs` without producing response headers.
+pub async fn request_timeout(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let secs = state.config.server.request_timeout_secs;
+    if secs == 0 {
+        return next.run(request).await;
+    }
+    match tokio::time::timeout(std::time::Duration::from_secs(secs), next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("Request timed out after {}s", secs);
+            (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!({
+                "error": {
+                    "message": format!("Sentinel gave up after {}s waiting for a response", secs),
+                    "type": "sentinel_timeout",
+                    "param": null,
+                    "code": "request_timeout"
+                }
+            }))).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_binds_every_address_or_none() {
+        let policy = ServerPolicy::default();
+        let listeners = bind_all(&["127.0.0.1:0".to_string(), "127.0.0.1:0".to_string()], &policy).unwrap();
+        assert_eq!(listeners.len(), 2);
+        assert_ne!(listeners[0].local_addr().unwrap(), listeners[1].local_addr().unwrap());
+        assert!(bind_all(&["127.0.0.1:0".to_string(), "not an address".to_string()], &policy).is_err());
+    }
+}
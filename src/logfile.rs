@@ -0,0 +1,115 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::config::LogFilePolicy;
+
+// --- ROTATING LOG FILES ---
+// A plain append-only file that rolls over to `<path>.1` when it grows past
+// `max_bytes` or a new `rotate_secs` period starts (periods are aligned to the
+// Unix epoch, so `daily` rolls at midnight UTC). Older files shift up one
+// number and anything beyond `keep` is deleted.
+
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: LogFilePolicy,
+    file: Option<File>,
+    size: u64,
+    period: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, policy: &LogFilePolicy) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let meta = file.metadata()?;
+        // Use the existing file's age so a restart in a new period still rolls it.
+        let modified = meta.modified().ok()
+            .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or_else(crate::now_secs, |d| d.as_secs());
+        let mut rotating = Self { path, policy: policy.clone(), file: Some(file), size: meta.len(), period: 0 };
+        rotating.period = rotating.period_of(modified);
+        Ok(rotating)
+    }
+
+    fn period_of(&self, secs: u64) -> u64 {
+        secs.checked_div(self.policy.rotate_secs).unwrap_or(0)
+    }
+
+    fn needs_rotation(&self, incoming: usize, now: u64) -> bool {
+        let too_big = self.policy.max_bytes > 0 && self.size > 0 && self.size + incoming as u64 > self.policy.max_bytes;
+        too_big || self.period_of(now) != self.period
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self, now: u64) -> io::Result<()> {
+        self.file = None;
+        if self.policy.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.numbered(self.policy.keep));
+            for n in (1..self.policy.keep).rev() {
+                let _ = std::fs::rename(self.numbered(n), self.numbered(n + 1));
+            }
+            std::fs::rename(&self.path, self.numbered(1))?;
+        }
+        self.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        self.size = 0;
+        self.period = self.period_of(now);
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = crate::now_secs();
+        if self.needs_rotation(buf.len(), now)
+            && let Err(e) = self.rotate(now) {
+            // Keep logging to whatever we have rather than losing lines.
+            eprintln!("Log rotation of {} failed: {}", self.path.display(), e);
+            if self.file.is_none() {
+                self.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+            }
+        }
+        let file = self.file.as_mut().ok_or_else(|| io::Error::other("log file is closed"))?;
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().map_or(Ok(()), |f| f.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotates_by_size_and_keeps_n() {
+        let dir = std::env::temp_dir().join(format!("sentinel-logs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("app.log");
+        let policy = LogFilePolicy { max_bytes: 10, rotate_secs: 0, keep: 2, ..LogFilePolicy::default() };
+
+        let mut file = RotatingFile::open(path.to_str().unwrap(), &policy).unwrap();
+        for line in ["first-line\n", "second-line\n", "third-line\n", "fourth-line\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        let read = |p: PathBuf| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), "fourth-line\n");
+        assert_eq!(read(file.numbered(1)), "third-line\n");
+        assert_eq!(read(file.numbered(2)), "second-line\n");
+        assert!(!file.numbered(3).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
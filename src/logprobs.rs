@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+use crate::config::StallPolicy;
+
+// --- LOGPROB STALLS ---
+// When a client asks for `logprobs`, the completion carries how sure the
+// model was of every token. A long run of near-certain tokens (entropy close
+// to zero) is what a model stuck in a groove looks like, so the
+// `logprob_stall` detector flags `window` consecutive tokens whose mean
+// entropy stays below `max_entropy`. It works on the completion alone, next to
+// the embedding-based loop check across turns.
+
+/// Sliding window over per-token entropies (nats).
+#[derive(Debug, Default)]
+pub struct StallTracker {
+    window: VecDeque<f32>,
+    sum: f32,
+    /// Tokens seen, for the report.
+    pub tokens: usize,
+}
+
+impl StallTracker {
+    /// Adds tokens; true once the last `window` tokens form a stall.
+    pub fn push(&mut self, entropies: &[f32], policy: &StallPolicy) -> bool {
+        let mut stalled = false;
+        for &entropy in entropies {
+            self.window.push_back(entropy);
+            self.sum += entropy;
+            self.tokens += 1;
+            if self.window.len() > policy.window {
+                self.sum -= self.window.pop_front().unwrap_or_default();
+            }
+            stalled |= self.window.len() == policy.window && self.mean() < policy.max_entropy;
+        }
+        stalled
+    }
+
+    pub fn mean(&self) -> f32 {
+        if self.window.is_empty() { return 0.0; }
+        (self.sum / self.window.len() as f32).max(0.0)
+    }
+}
+
+/// Per-token entropy from a choice's `logprobs`: chat's `content` list or
+/// the legacy `token_logprobs` / `top_logprobs` arrays. With alternatives it
+/// is their entropy (the unlisted mass as one extra outcome); without, the
+/// token's own surprisal stands in.
+pub fn token_entropies(logprobs: &serde_json::Value) -> Vec<f32> {
+    if let Some(tokens) = logprobs["content"].as_array() {
+        return tokens.iter().filter_map(|t| {
+            let top: Vec<f64> = t["top_logprobs"].as_array()
+                .map(|alts| alts.iter().filter_map(|a| a["logprob"].as_f64()).collect())
+                .unwrap_or_default();
+            entropy(t["logprob"].as_f64()?, &top)
+        }).collect();
+    }
+    let Some(chosen) = logprobs["token_logprobs"].as_array() else { return Vec::new() };
+    chosen.iter().enumerate().filter_map(|(i, lp)| {
+        let top: Vec<f64> = logprobs["top_logprobs"][i].as_object()
+            .map(|alts| alts.values().filter_map(|v| v.as_f64()).collect())
+            .unwrap_or_default();
+        entropy(lp.as_f64()?, &top)
+    }).collect()
+}
+
+fn entropy(logprob: f64, top: &[f64]) -> Option<f32> {
+    if top.is_empty() {
+        return Some((-logprob).max(0.0) as f32);
+    }
+    let probs: Vec<f64> = top.iter().map(|lp| lp.exp()).collect();
+    let rest = (1.0 - probs.iter().sum::<f64>()).max(0.0);
+    let h: f64 = probs.iter().chain(std::iter::once(&rest))
+        .filter(|p| **p > 0.0)
+        .map(|p| -p * p.ln())
+        .sum();
+    Some(h as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_long_certain_runs_only() {
+        let policy = StallPolicy { enabled: true, window: 4, max_entropy: 0.05 };
+        let certain = serde_json::json!({ "content": [
+            { "token": "a", "logprob": -0.001, "top_logprobs": [{ "token": "a", "logprob": -0.001 }, { "token": "b", "logprob": -9.0 }] },
+        ] });
+        let unsure = serde_json::json!({ "token_logprobs": [null, -1.2], "top_logprobs": [null, { "x": -1.2, "y": -0.5 }] });
+
+        let h = token_entropies(&certain);
+        assert!(h[0] < 0.01);
+        let h_unsure = token_entropies(&unsure);
+        assert_eq!(h_unsure.len(), 1);
+        assert!(h_unsure[0] > 0.5);
+
+        let mut tracker = StallTracker::default();
+        assert!(!tracker.push(&[h[0]; 3], &policy));
+        assert!(tracker.push(&h, &policy));
+        assert!(!StallTracker::default().push(&[h[0], h[0], h_unsure[0], h[0]], &policy));
+    }
+}
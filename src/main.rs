@@ -1,34 +1,185 @@
 use axum::{
-    routing::{post, get},
+    routing::{delete, get, post, put},
     Router,
     Json,
-    response::{IntoResponse, Response},
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::Response,
 };
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::Instrument;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use dashmap::DashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use tokio::sync::Mutex;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 
+mod alerts;
+mod archive;
+mod audit;
+mod bans;
+mod cli;
+mod client_ip;
+mod config;
+mod cost_hints;
+mod counters;
+mod embedded;
+mod embedder;
+mod erasure;
+mod events;
+mod fingerprints;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod idempotency;
+mod keys;
+mod limits;
+mod listener;
+mod logfile;
+mod logprobs;
+mod mcp;
+mod mcp_proxy;
+mod messages;
+mod metrics;
+mod mock;
+mod ner;
+mod notify;
+mod oidc;
+mod openapi;
+mod paging;
+mod passthrough;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod plugins;
+mod pricing;
+mod prompt_leak;
+mod quarantine;
+mod rbac;
+mod reload;
+mod repetition;
+mod reports;
+mod rewrite;
+mod routing;
+mod savings;
+mod scripts;
+mod selfcheck;
+mod sessions;
+mod shadow;
+mod streaming;
+mod structured;
+mod system_prompt;
+mod telemetry;
+mod tenancy;
+mod timeseries;
+mod tls;
+mod toxicity;
+mod trimming;
+mod upstream;
+mod vcr;
+
+use config::{BlockStyle, Config, CostPolicy, DetectorMode, EmbeddingStorage, LoopComparison, LoopPolicy, PinMode, SimilarityMetric, SystemPromptAction, ToxicityAction};
+use metrics::{DetectorMetrics, LatencyMetrics};
+use audit::{
+    AuditStore, FeedbackTally, InterventionLog, LogQuery, MAX_AUDIT_LOGS, Verdict,
+    attach_request, query_logs, record_bypass, record_dry_run, record_intervention, record_rewrite, ExportFormat, LogContext,
+};
+
 // --- SEMANTIC SCORER & SECURITY ---
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Embedding(pub Vec<f32>);
 
+/// A history embedding as kept in memory. `Int8` stores each component as a
+/// multiple of `scale` (the largest magnitude / 127) and is dequantized for
+/// comparison. Untagged, so sessions saved as plain arrays still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredEmbedding {
+    F32(Vec<f32>),
+    Int8 { scale: f32, values: Vec<i8> },
+}
+
+impl StoredEmbedding {
+    pub fn store(embedding: Embedding, storage: EmbeddingStorage) -> Self {
+        match storage {
+            EmbeddingStorage::F32 => StoredEmbedding::F32(embedding.0),
+            EmbeddingStorage::Int8 => {
+                let max = embedding.0.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                let values = embedding.0.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect();
+                StoredEmbedding::Int8 { scale, values }
+            }
+        }
+    }
+
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self {
+            StoredEmbedding::F32(values) => values.clone(),
+            StoredEmbedding::Int8 { scale, values } => values.iter().map(|v| *v as f32 * scale).collect(),
+        }
+    }
+
+    /// Heap bytes held by the components.
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            StoredEmbedding::F32(values) => values.len() * std::mem::size_of::<f32>(),
+            StoredEmbedding::Int8 { values, .. } => values.len(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionState {
-    pub history: Vec<Embedding>,
+    pub history: Vec<StoredEmbedding>,
     pub history_text: Vec<String>,
     pub cumulative_cost: f64,
     pub last_cost: f64,
+    /// Enforced interventions (exempted detections are not counted).
     pub interventions: u32,
+    /// The same count split by detector key.
+    #[serde(default)]
+    pub interventions_by_reason: BTreeMap<String, u32>,
+    /// Rolling (EWMA) baseline of per-call cost used by the economic throttle.
+    pub cost_mean: f64,
+    pub cost_var: f64,
+    pub cost_samples: u32,
+    /// Feedback-driven multiplier on the loop thresholds (1.0 = as configured).
+    pub loop_sensitivity: f32,
+    /// EWMA of consecutive-turn similarity, i.e. how repetitive this session
+    /// normally is. Used to keep thresholds above its everyday chatter.
+    pub semantic_baseline: f32,
+    pub fuzzy_baseline: f32,
+    pub semantic_samples: u32,
+    pub fuzzy_samples: u32,
+    /// Most recent per-call costs, oldest first (for inspection only).
+    #[serde(default)]
+    pub cost_history: VecDeque<f64>,
+    /// Operator override of the policy's session budget.
+    #[serde(default)]
+    pub budget_usd: Option<f64>,
+    /// Fingerprints of recent proxied MCP tool calls, oldest first.
+    #[serde(default)]
+    pub tool_calls: VecDeque<u64>,
+    /// Upstream held fixed by `SENTINEL_SESSION_PIN`.
+    #[serde(default)]
+    pub pinned_provider: Option<String>,
+    #[serde(default)]
+    pub pinned_model: Option<String>,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub last_activity: u64,
+}
+
+const COST_HISTORY_LEN: usize = 20;
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SessionState {
@@ -39,56 +190,228 @@ impl SessionState {
             cumulative_cost: 0.0,
             last_cost: 0.0,
             interventions: 0,
+            interventions_by_reason: BTreeMap::new(),
+            cost_mean: 0.0,
+            cost_var: 0.0,
+            cost_samples: 0,
+            loop_sensitivity: 1.0,
+            semantic_baseline: 0.0,
+            fuzzy_baseline: 0.0,
+            semantic_samples: 0,
+            fuzzy_samples: 0,
+            cost_history: VecDeque::with_capacity(COST_HISTORY_LEN),
+            budget_usd: None,
+            tool_calls: VecDeque::new(),
+            pinned_provider: None,
+            pinned_model: None,
+            created_at: now_secs(),
+            last_activity: now_secs(),
         }
     }
 
-    pub fn check_loop(&mut self, embedding: Embedding, threshold: f32, turns: usize) -> bool {
-        self.history.push(embedding);
-        if self.history.len() > 5 { self.history.remove(0); }
-        if self.history.len() < turns { return false; }
+    pub fn touch(&mut self) {
+        self.last_activity = now_secs();
+    }
 
-        let last_n = &self.history[self.history.len() - turns..];
-        let mut loop_detected = true;
-        for i in 0..last_n.len() - 1 {
-            let similarity = dot_product(&last_n[i].0, &last_n[i+1].0);
-            if similarity < (1.0 - threshold) {
-                loop_detected = false;
-                break;
+    /// The provider and model this request goes to under `mode`: the first
+    /// ones the session used, or this request's when `reset` re-pins it.
+    pub fn pin(&mut self, mode: PinMode, provider: &str, model: &str, reset: bool) -> (String, String) {
+        if mode == PinMode::Off {
+            return (provider.to_string(), model.to_string());
+        }
+        if reset || self.pinned_provider.is_none() {
+            self.pinned_provider = Some(provider.to_string());
+            self.pinned_model = (mode == PinMode::Model).then(|| model.to_string());
+        }
+        let provider = self.pinned_provider.clone().unwrap_or_else(|| provider.to_string());
+        let model = match mode {
+            PinMode::Model => self.pinned_model.get_or_insert_with(|| model.to_string()).clone(),
+            _ => model.to_string(),
+        };
+        (provider, model)
+    }
+
+    pub fn record_intervention(&mut self, detector: &str) {
+        self.interventions += 1;
+        *self.interventions_by_reason.entry(detector.to_string()).or_default() += 1;
+    }
+
+    /// Threshold to use for this session, adapted from `base` when enabled.
+    pub fn effective_threshold(&self, base: f32, semantic: bool, policy: &LoopPolicy) -> f32 {
+        if !policy.adaptive { return base; }
+        let (baseline, samples) = if semantic {
+            (self.semantic_baseline, self.semantic_samples)
+        } else {
+            (self.fuzzy_baseline, self.fuzzy_samples)
+        };
+        let mut threshold = base * self.loop_sensitivity;
+        if samples >= policy.warmup_turns {
+            threshold = threshold.min(1.0 - baseline - policy.margin);
+        }
+        threshold.clamp(base * policy.min_factor, base * policy.max_factor)
+    }
+
+    /// Nudges loop sensitivity after an operator verdict on a loop block.
+    pub fn apply_loop_feedback(&mut self, false_positive: bool, policy: &LoopPolicy) {
+        self.loop_sensitivity = if false_positive {
+            self.loop_sensitivity * (1.0 - policy.feedback_step)
+        } else {
+            (self.loop_sensitivity * (1.0 + policy.feedback_step)).min(1.0)
+        };
+        self.loop_sensitivity = self.loop_sensitivity.max(policy.min_factor);
+    }
+
+    /// Adds `embedding` to the history and reports whether it closes a loop
+    /// (see `repeats`). An embedding that can't be compared with the history
+    /// restarts it and is returned as an error.
+    pub fn check_loop(&mut self, embedding: Embedding, threshold: f32, loops: &LoopPolicy) -> Result<bool, String> {
+        let metric = loops.metric;
+        let stored = StoredEmbedding::store(embedding, loops.storage);
+        if let Some(prev) = self.history.last() {
+            match similarity(metric, &prev.to_f32(), &stored.to_f32()) {
+                Ok(similarity) => {
+                    self.semantic_baseline = ewma(self.semantic_baseline, similarity, self.semantic_samples);
+                    self.semantic_samples += 1;
+                }
+                Err(e) => {
+                    self.history = vec![stored];
+                    return Err(e);
+                }
             }
         }
-        loop_detected
+        self.history.push(stored);
+        let excess = self.history.len().saturating_sub(loops.window());
+        self.history.drain(..excess);
+
+        let history: Vec<Vec<f32>> = self.history.iter().map(StoredEmbedding::to_f32).collect();
+        repeats(history.len(), threshold, loops, |a, b| similarity(metric, &history[a], &history[b]))
+    }
+
+    /// Heap bytes held by this session's embedding history.
+    pub fn embedding_bytes(&self) -> usize {
+        self.history.iter().map(StoredEmbedding::size_bytes).sum()
     }
 
-    pub fn check_basic_loop(&mut self, text: String, threshold: f32, turns: usize) -> bool {
+    pub fn check_basic_loop(&mut self, text: String, threshold: f32, loops: &LoopPolicy) -> bool {
+        if let Some(prev) = self.history_text.last() {
+            let similarity = word_overlap_similarity(prev, &text);
+            self.fuzzy_baseline = ewma(self.fuzzy_baseline, similarity, self.fuzzy_samples);
+            self.fuzzy_samples += 1;
+        }
         self.history_text.push(text);
-        if self.history_text.len() > 5 { self.history_text.remove(0); }
-        if self.history_text.len() < turns { return false; }
-
-        let last_n = &self.history_text[self.history_text.len() - turns..];
-        let mut loop_detected = true;
-        for i in 0..last_n.len() - 1 {
-            let similarity = word_overlap_similarity(&last_n[i], &last_n[i+1]);
-            if similarity < (1.0 - threshold) { 
-                loop_detected = false;
-                break;
-            }
+        let excess = self.history_text.len().saturating_sub(loops.window());
+        self.history_text.drain(..excess);
+
+        let history = &self.history_text;
+        repeats(history.len(), threshold, loops, |a, b| Ok::<_, String>(word_overlap_similarity(&history[a], &history[b])))
+            .unwrap_or(false)
+    }
+
+    /// Records a tool call and returns how many of the last `window` calls,
+    /// this one included, were identical to it.
+    pub fn record_tool_call(&mut self, fingerprint: u64, window: usize) -> usize {
+        self.tool_calls.push_back(fingerprint);
+        while self.tool_calls.len() > window { self.tool_calls.pop_front(); }
+        self.tool_calls.iter().filter(|f| **f == fingerprint).count()
+    }
+
+    /// This session's budget: the operator's override, else the policy's.
+    pub fn budget(&self, policy: &CostPolicy) -> f64 {
+        self.budget_usd.unwrap_or(policy.session_budget_usd)
+    }
+
+    pub fn check_economic_throttle(&self, current_cost: f64, policy: &CostPolicy) -> bool {
+        if self.cumulative_cost > self.budget(policy) { return true; }
+        if self.cost_samples < policy.min_samples || current_cost < policy.min_cost_usd {
+            return false;
         }
-        loop_detected
+        // Floor the deviation so a perfectly flat history doesn't turn every
+        // tiny increase into an infinite z-score.
+        let std_dev = self.cost_var.sqrt().max(self.cost_mean * 0.10).max(f64::EPSILON);
+        (current_cost - self.cost_mean) / std_dev > policy.z_threshold
     }
 
-    pub fn check_economic_throttle(&self, current_cost: f64) -> bool {
-        if self.cumulative_cost > 10.0 { return true; }
-        if self.last_cost > 0.0 && current_cost > (self.last_cost * 5.0) && current_cost > 0.10 {
-            return true;
+    /// Books a call's cost. Flagged spikes still count towards the budget but
+    /// are kept out of the baseline so they can't drag it upwards.
+    pub fn record_cost(&mut self, cost: f64, flagged: bool, policy: &CostPolicy) {
+        self.cumulative_cost += cost;
+        self.last_cost = cost;
+        self.cost_history.push_back(cost);
+        if self.cost_history.len() > COST_HISTORY_LEN { self.cost_history.pop_front(); }
+        if flagged { return; }
+
+        if self.cost_samples == 0 {
+            self.cost_mean = cost;
+            self.cost_var = 0.0;
+        } else {
+            let diff = cost - self.cost_mean;
+            let incr = policy.ewma_alpha * diff;
+            self.cost_mean += incr;
+            self.cost_var = (1.0 - policy.ewma_alpha) * (self.cost_var + diff * incr);
+        }
+        self.cost_samples += 1;
+    }
+}
+
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Whether the newest of `len` history turns closes a loop: each of the
+/// last `turns - 1` turns must repeat an earlier one, i.e. score at least
+/// `1.0 - threshold` against it. `consecutive` only looks at the turn right
+/// before; `pairwise` looks at every earlier turn, with matches weakened by
+/// `decay` the further apart they are.
+fn repeats<E>(len: usize, threshold: f32, loops: &LoopPolicy, sim: impl Fn(usize, usize) -> Result<f32, E>) -> Result<bool, E> {
+    if len < loops.turns { return Ok(false); }
+    for turn in len + 1 - loops.turns..len {
+        let earlier = match loops.compare {
+            LoopComparison::Consecutive => turn - 1..turn,
+            LoopComparison::Pairwise => 0..turn,
+        };
+        let mut repeated = false;
+        for prev in earlier.rev() {
+            if sim(prev, turn)? * loops.decay(turn - prev) >= 1.0 - threshold {
+                repeated = true;
+                break;
+            }
         }
-        false
+        if !repeated { return Ok(false); }
     }
+    Ok(true)
+}
+
+fn ewma(mean: f32, sample: f32, samples: u32) -> f32 {
+    if samples == 0 { sample } else { mean + 0.2 * (sample - mean) }
 }
 
 pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
     a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
 }
 
+/// Similarity of two embeddings under `metric`, higher meaning closer.
+/// Vectors of different dimensions (e.g. after switching embedding model)
+/// or with zero length can't be compared and are reported as errors.
+pub fn similarity(metric: SimilarityMetric, a: &[f32], b: &[f32]) -> Result<f32, String> {
+    if a.len() != b.len() {
+        return Err(format!("embedding dimensions differ ({} vs {})", a.len(), b.len()));
+    }
+    match metric {
+        SimilarityMetric::Dot => Ok(dot_product(a, b)),
+        SimilarityMetric::Cosine => {
+            let norms = dot_product(a, a).sqrt() * dot_product(b, b).sqrt();
+            if norms == 0.0 {
+                return Err("zero-length embedding".to_string());
+            }
+            Ok(dot_product(a, b) / norms)
+        }
+        SimilarityMetric::Euclidean => {
+            let distance: f32 = a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt();
+            Ok(1.0 / (1.0 + distance))
+        }
+    }
+}
+
 pub fn word_overlap_similarity(s1: &str, s2: &str) -> f32 {
     let w1: std::collections::HashSet<_> = s1.split_whitespace().map(|s| s.to_lowercase()).collect();
     let w2: std::collections::HashSet<_> = s2.split_whitespace().map(|s| s.to_lowercase()).collect();
@@ -98,27 +421,196 @@ pub fn word_overlap_similarity(s1: &str, s2: &str) -> f32 {
     intersection as f32 / union as f32
 }
 
-// --- AUDIT LOGS ---
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct InterventionLog {
-    timestamp: u64,
-    session_id: String,
-    reason: String,
-    content_snippet: String,
-    savings_est: f64,
-}
-
 // --- APP STATE ---
 
+/// Interventions a slow `/api/logs/stream` subscriber may fall behind by
+/// before it starts missing events.
+const LIVE_LOG_BUFFER: usize = 256;
+
 #[derive(Clone)]
 struct AppState {
     client: Client,
     openai_api_key: String,
-    groq_api_key: String,
     sessions: Arc<DashMap<String, SessionState>>,
-    total_saved_usd: Arc<AtomicU64>,
+    /// Prompt fingerprints per user, for loops that span sessions.
+    user_fingerprints: Arc<fingerprints::UserFingerprints>,
+    /// Sum of `savings_est` over all interventions, in micro-dollars.
+    saved_micro_usd: Arc<AtomicU64>,
+    /// Hot cache of the newest interventions; `audit` holds the full history.
     audit_logs: Arc<Mutex<VecDeque<InterventionLog>>>,
+    audit: Arc<AuditStore>,
+    /// The redb store behind `SENTINEL_STORAGE_KIND=embedded`, which also
+    /// keeps session snapshots.
+    embedded: Option<Arc<embedded::EmbeddedStore>>,
+    /// Fan-out of new interventions to `/api/logs/stream` subscribers.
+    live_logs: tokio::sync::broadcast::Sender<InterventionLog>,
+    /// Problems found by the startup self-check; non-empty means degraded.
+    startup_problems: Arc<Vec<String>>,
+    next_log_id: Arc<AtomicU64>,
+    feedback: Arc<DashMap<String, FeedbackTally>>,
+    budget_alerts: Arc<AtomicU64>,
+    /// Estimated prompt tokens removed by context trimming.
+    trimmed_tokens: Arc<AtomicU64>,
+    /// Shadow model comparisons for `/api/shadow`.
+    shadow: Arc<shadow::ShadowRecords>,
+    /// Totals across instances, when counters are shared (`counters.rs`).
+    cluster: Arc<std::sync::RwLock<Option<counters::ClusterTotals>>>,
+    /// Slack / Discord notices waiting for the next batch.
+    notifier: Arc<notify::Notifier>,
+    pager: Arc<paging::Pager>,
+    /// Request and intervention events for Kafka / NATS.
+    events: Arc<events::EventBus>,
+    latency: Arc<LatencyMetrics>,
+    detectors: Arc<DetectorMetrics>,
+    pool_spend: Arc<DashMap<String, f64>>,
+    tenant_spend: Arc<DashMap<String, f64>>,
+    /// Operator kill-switch, keyed by session id.
+    blocked: Arc<DashMap<String, sessions::BlockEntry>>,
+    /// Registered output schemas, keyed by session id.
+    schemas: Arc<DashMap<String, structured::Registered>>,
+    /// Per-IP request windows and bans.
+    ip_guard: Arc<bans::IpGuard>,
+    /// Signing keys of the OIDC issuer.
+    oidc_keys: Arc<oidc::KeyCache>,
+    quarantine: Arc<DashMap<u64, quarantine::QuarantineEntry>>,
+    /// Initialized MCP clients, keyed by `Mcp-Session-Id`.
+    mcp_clients: Arc<DashMap<String, mcp::McpClient>>,
+    mcp_upstreams: Arc<mcp_proxy::McpUpstreams>,
+    next_quarantine_id: Arc<AtomicU64>,
+    sessions_expired: Arc<AtomicU64>,
+    sessions_lru_evicted: Arc<AtomicU64>,
+    timeseries: Arc<timeseries::TimeSeries>,
+    /// Per-day usage by tenant, for `/api/reports`.
+    usage: Arc<reports::UsageLedger>,
+    /// Loaded again on every config reload.
+    plugins: Arc<std::sync::RwLock<Arc<plugins::PluginHost>>>,
+    /// Compiled from `live_config` again when a reload changes the rules.
+    scripts: Arc<std::sync::RwLock<Arc<scripts::ScriptHost>>>,
+    ner: Arc<ner::Recognizer>,
+    embedding_cache: Arc<passthrough::EmbeddingCache>,
+    /// Responses kept for retries with the same `Idempotency-Key`.
+    idempotency: Arc<idempotency::IdempotencyCache>,
+    /// Cached provider probes of `/readyz`.
+    probes: Arc<health::ProbeCache>,
+    /// Health and rotation of provider keys.
+    keys: Arc<keys::KeyRing>,
+    embedder: Arc<embedder::Embedder>,
+    /// The config this request started with; `live_config` is the latest.
+    config: Arc<Config>,
+    live_config: Arc<std::sync::RwLock<Arc<Config>>>,
+}
+
+/// Router state. Every extraction of `State<AppState>` takes the current
+/// config, so a reload reaches the next request without touching handlers.
+#[derive(Clone)]
+struct RouterState(AppState);
+
+impl axum::extract::FromRef<RouterState> for AppState {
+    fn from_ref(router: &RouterState) -> Self {
+        router.0.with_current_config()
+    }
+}
+
+/// `config` with the prices kept in Postgres, if any, ahead of its own.
+fn with_stored_pricing(audit: &AuditStore, config: Config) -> Config {
+    #[cfg(feature = "postgres")]
+    if let Some(pg) = audit.postgres() {
+        let mut config = config;
+        match pg.pricing() {
+            Ok(prices) => config.pricing.prepend(prices),
+            Err(e) => tracing::error!("Loading prices from Postgres failed: {}", e),
+        }
+        return config;
+    }
+    #[cfg(not(feature = "postgres"))]
+    let _ = audit;
+    config
+}
+
+impl AppState {
+    fn new(client: Client, openai_api_key: String, config: Config, startup_problems: Vec<String>) -> Self {
+        let embedder = embedder::Embedder::new(client.clone(), openai_api_key.clone(), config.embedding_batch.clone(), config.timeouts.embedding());
+        let mcp_upstreams = mcp_proxy::McpUpstreams::new(client.clone());
+        let embedded = embedded::EmbeddedStore::for_config(&config.storage);
+        let audit = Arc::new(AuditStore::for_config(&config, embedded.clone()));
+        let config = Arc::new(with_stored_pricing(&audit, config));
+        Self {
+            client,
+            openai_api_key,
+            sessions: Arc::new(DashMap::new()),
+            user_fingerprints: Arc::new(fingerprints::UserFingerprints::default()),
+            saved_micro_usd: Arc::new(AtomicU64::new(0)),
+            audit_logs: Arc::new(Mutex::new(VecDeque::with_capacity(MAX_AUDIT_LOGS))),
+            audit,
+            embedded,
+            live_logs: tokio::sync::broadcast::channel(LIVE_LOG_BUFFER).0,
+            startup_problems: Arc::new(startup_problems),
+            next_log_id: Arc::new(AtomicU64::new(1)),
+            feedback: Arc::new(DashMap::new()),
+            budget_alerts: Arc::new(AtomicU64::new(0)),
+            trimmed_tokens: Arc::new(AtomicU64::new(0)),
+            shadow: Arc::new(shadow::ShadowRecords::default()),
+            cluster: Arc::new(std::sync::RwLock::new(None)),
+            notifier: Arc::new(notify::Notifier::default()),
+            pager: Arc::new(paging::Pager::default()),
+            events: Arc::new(events::EventBus::new(config.events.buffer)),
+            latency: Arc::new(LatencyMetrics::default()),
+            detectors: Arc::new(DetectorMetrics::default()),
+            pool_spend: Arc::new(DashMap::new()),
+            tenant_spend: Arc::new(DashMap::new()),
+            blocked: Arc::new(DashMap::new()),
+            schemas: Arc::new(DashMap::new()),
+            ip_guard: Arc::new(bans::IpGuard::default()),
+            oidc_keys: Arc::new(oidc::KeyCache::default()),
+            quarantine: Arc::new(DashMap::new()),
+            mcp_clients: Arc::new(DashMap::new()),
+            mcp_upstreams: Arc::new(mcp_upstreams),
+            next_quarantine_id: Arc::new(AtomicU64::new(1)),
+            sessions_expired: Arc::new(AtomicU64::new(0)),
+            sessions_lru_evicted: Arc::new(AtomicU64::new(0)),
+            timeseries: Arc::new(timeseries::TimeSeries::default()),
+            usage: Arc::new(reports::UsageLedger::default()),
+            plugins: Arc::new(std::sync::RwLock::new(Arc::new(plugins::PluginHost::load(&config.plugins)))),
+            scripts: Arc::new(std::sync::RwLock::new(Arc::new(scripts::ScriptHost::load(&config.scripts)))),
+            ner: Arc::new(ner::Recognizer::load(&config.ner)),
+            embedding_cache: Arc::new(passthrough::EmbeddingCache::default()),
+            idempotency: Arc::new(idempotency::IdempotencyCache::default()),
+            probes: Arc::new(health::ProbeCache::default()),
+            keys: Arc::new(keys::KeyRing::default()),
+            embedder: Arc::new(embedder),
+            live_config: Arc::new(std::sync::RwLock::new(config.clone())),
+            config,
+        }
+    }
+
+    fn current_config(&self) -> Arc<Config> {
+        self.live_config.read().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    fn set_config(&self, config: Config) {
+        let config = with_stored_pricing(&self.audit, config);
+        *self.live_config.write().unwrap_or_else(|p| p.into_inner()) = Arc::new(config);
+    }
+
+    fn with_current_config(&self) -> Self {
+        Self { config: self.current_config(), ..self.clone() }
+    }
+
+    /// `Config::tenant_provider`, carrying the key `keys.rs` has in use.
+    fn provider(&self, tenant: Option<&str>, name: &str) -> Result<config::ProviderConfig, String> {
+        let mut provider = self.config.tenant_provider(tenant, name)?;
+        provider.api_key = self.keys.active(name, &provider);
+        Ok(provider)
+    }
+
+    fn total_saved_usd(&self) -> f64 {
+        self.saved_micro_usd.load(Ordering::Relaxed) as f64 / 1_000_000.0
+    }
+
+    #[cfg(test)]
+    fn for_tests(config: Config) -> Self {
+        Self::new(Client::new(), "none".to_string(), config, Vec::new())
+    }
 }
 
 // --- SCHEMAS ---
@@ -133,274 +625,1944 @@ struct ChatRequest {
     extra: serde_json::Value,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct CompletionRequest {
+    model: String,
+    /// A string, an array of strings, or token arrays.
+    #[serde(default)]
+    prompt: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CompletionRequest {
+    /// Text prompts joined by newlines; pre-tokenized prompts have no text to analyse.
+    fn prompt_text(&self) -> String {
+        match &self.prompt {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(items) => items.iter().filter_map(|p| p.as_str()).collect::<Vec<_>>().join("\n"),
+            _ => String::new(),
+        }
+    }
+}
+
+/// One chat-completions message. Fields Sentinel doesn't look at are kept in
+/// `extra` so they reach the provider unchanged.
 #[derive(Debug, Deserialize, Serialize)]
 struct ChatMessage {
     role: String,
-    content: String,
+    /// `null` (or absent) on assistant messages that only carry tool calls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<MessageContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+    /// Set on `role: "tool"` messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    /// Legacy single function call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_call: Option<FunctionCall>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
+/// Either a plain string or an array of typed parts (text, image_url,
+/// input_audio, ...). Only text parts are analysed; all parts are forwarded.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MessageContent {
+    fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts.iter()
+                .filter(|p| p.kind == "text")
+                .filter_map(|p| p.text.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ToolCall {
     #[serde(default)]
-    data: Vec<EmbeddingData>,
+    id: String,
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    function: FunctionCall,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingData {
-    embedding: Vec<f32>,
+fn function_type() -> String {
+    "function".to_string()
 }
 
-#[derive(Debug, Deserialize)]
-struct McpRequest {
-    method: String,
-    params: serde_json::Value,
-    id: serde_json::Value,
+#[derive(Debug, Deserialize, Serialize)]
+struct FunctionCall {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+impl ChatMessage {
+    /// What the detectors see: the text content, or for tool-call-only
+    /// messages the calls themselves, so a repeated call still looks repeated.
+    fn text(&self) -> String {
+        if let Some(content) = &self.content {
+            return content.text();
+        }
+        let calls = self.tool_calls.iter().flatten().map(|c| &c.function).chain(&self.function_call);
+        calls.map(|f| format!("{} {}", f.name, f.arguments)).collect::<Vec<_>>().join("\n")
+    }
 }
 
 // --- MAIN ---
 
-#[tokio::main]
-async fn main() {
-    dotenv::dotenv().ok();
-    tracing_subscriber::registry().with(tracing_subscriber::fmt::layer()).init();
-
-    let state = AppState {
-        client: Client::new(),
-        openai_api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "none".to_string()),
-        groq_api_key: std::env::var("GROQ_API_KEY").unwrap_or_else(|_| "none".to_string()),
-        sessions: Arc::new(DashMap::new()),
-        total_saved_usd: Arc::new(AtomicU64::new(0)),
-        audit_logs: Arc::new(Mutex::new(VecDeque::with_capacity(50))),
-    };
+fn main() {
+    dotenvy::dotenv().ok();
+    let cli = <cli::Cli as clap::Parser>::parse();
+    if let Err(e) = cli::run(cli) {
+        eprintln!("sentinel: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    let app = Router::new()
+/// Runs the proxy; `addrs` overrides the configured listeners when non-empty.
+async fn serve(addrs: Vec<String>) {
+    let _tracer_provider = telemetry::init();
+
+    let config = Config::from_env();
+    let client = upstream_client(&config.timeouts);
+
+    let startup_problems = selfcheck::run(&client, &config).await;
+    for problem in &startup_problems {
+        tracing::warn!("⚠️ Self-check: {}", problem);
+    }
+    if !startup_problems.is_empty() && selfcheck::strict() {
+        tracing::error!("Self-check failed with {} problem(s) in strict mode, refusing to start", startup_problems.len());
+        std::process::exit(1);
+    }
+
+    let openai_api_key = config.providers.get("openai").map_or_else(|| "none".to_string(), |p| p.api_key.clone());
+    let state = AppState::new(client, openai_api_key, config, startup_problems);
+
+    if rbac::is_open(&state.config) {
+        tracing::warn!("⚠️ Neither SENTINEL_ADMIN_TOKENS nor SENTINEL_OIDC_ISSUER set: the admin API is open to anyone who can reach it");
+    }
+
+    audit::restore(&state);
+    sessions::restore(&state);
+    counters::spawn_sync(state.clone());
+    audit::spawn_compactor(state.clone());
+    archive::spawn_archiver(state.clone());
+    sessions::spawn_evictor(state.clone());
+    keys::spawn_checker(state.clone());
+    reports::spawn_reporter(state.clone());
+    notify::spawn_sender(state.clone());
+    events::spawn_publisher(state.clone());
+    reload::spawn_watcher(state.clone());
+    if let Some(addr) = &state.config.grpc_addr {
+        #[cfg(feature = "grpc")]
+        match addr.parse() {
+            Ok(addr) => grpc::spawn(state.clone(), addr),
+            Err(e) => tracing::error!("Invalid SENTINEL_GRPC_ADDR {}: {}", addr, e),
+        }
+        #[cfg(not(feature = "grpc"))]
+        tracing::warn!("SENTINEL_GRPC_ADDR={} ignored: built without the `grpc` feature", addr);
+    }
+
+    let proxy = Router::new()
         .route("/v1/chat/completions", post(chat_completions))
-        .route("/mcp", post(mcp_handler))
+        .route("/v1/completions", post(completions))
+        .route("/v1/embeddings", post(passthrough::embeddings))
+        .route("/v1/moderations", post(passthrough::moderations))
+        .route("/v1/images/generations", post(passthrough::image_generations))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), idempotency::replay))
+        .layer(axum::middleware::map_response(mark_unmodified))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), limits::body_size))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), bans::guard))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), tenancy::identify))
+        .layer(axum::extract::DefaultBodyLimit::disable());
+
+    let app = Router::new()
+        .merge(proxy)
+        .route("/mcp", post(mcp::handler).get(mcp::notifications).delete(mcp::end_session)
+            .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), tenancy::identify)))
         .route("/api/stats", get(get_stats))
+        .route("/api/stats/timeseries", get(get_stats_timeseries))
+        .route("/api/stats/breakdown", get(get_stats_breakdown))
+        .route("/api/stats/detectors", get(get_stats_detectors))
         .route("/api/logs", get(get_logs))
+        .route("/api/logs/export", get(export_logs))
+        .route("/api/logs/stream", get(stream_logs))
+        .route("/api/logs/verify", get(verify_logs))
+        .route("/api/sessions", get(sessions::list_sessions))
+        .route("/api/sessions/export", get(sessions::export_sessions))
+        .route("/api/sessions/import", post(sessions::import_sessions).layer(axum::extract::DefaultBodyLimit::max(sessions::MAX_IMPORT_BYTES)))
+        .route("/api/sessions/{id}", get(sessions::get_session).delete(sessions::delete_session))
+        .route("/api/sessions/{id}/block", post(sessions::block_session))
+        .route("/api/sessions/{id}/unblock", post(sessions::unblock_session))
+        .route("/api/sessions/{id}/schema", put(structured::register).delete(structured::unregister))
+        .route("/api/bans", get(bans::list).post(bans::ban))
+        .route("/api/tenants", get(tenancy::list))
+        .route("/api/shadow", get(shadow::report))
+        .route("/api/reports", get(reports::list))
+        .route("/api/data/{subject}", delete(erasure::erase))
+        .route("/api/bans/{ip}", delete(bans::unban))
+        .route("/metrics", get(get_metrics))
+        .route("/api/interventions/{id}/feedback", post(post_feedback))
+        .route("/api/interventions/{id}/replay", post(replay_intervention))
+        .route("/api/quarantine", get(quarantine::list))
+        .route("/api/quarantine/{id}", get(quarantine::get))
+        .route("/api/quarantine/{id}/approve", post(quarantine::approve))
+        .route("/api/quarantine/{id}/deny", post(quarantine::deny))
+        .route("/api/openapi.json", get(openapi::spec))
+        .route("/api/config/reload", post(reload::handler))
+        .route("/api/readiness", get(health::report))
         .route("/health", get(|| async { "Sentinel is running" }))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
         .fallback_service(tower_http::services::ServeDir::new(".").fallback(tower_http::services::ServeFile::new("index.html")))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), rbac::authorize))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), listener::request_timeout))
+        .layer(axum::middleware::from_fn_with_state(RouterState(state.clone()), client_ip::identify));
+    // The default predicate skips `text/event-stream` and tiny bodies.
+    let app = if state.config.server.compression { app.layer(CompressionLayer::new()) } else { app };
+    let app = app
         .layer(CorsLayer::permissive())
-        .with_state(state);
+        .with_state(RouterState(state.clone()));
 
-    let addr = "127.0.0.1:3000";
-    let listener = TcpListener::bind(addr).await.unwrap();
-    tracing::info!("🛡️ Sentinel SaaS active on {}", addr);
-    axum::serve(listener, app).await.unwrap();
+    let addrs = if addrs.is_empty() { state.config.server.listen.clone() } else { addrs };
+    let listeners = match listener::bind_all(&addrs, &state.config.server) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            tracing::error!("Cannot listen on {}: {}", addrs.join(", "), e);
+            std::process::exit(1);
+        }
+    };
+    let served = match state.config.tls.files() {
+        Some((cert, key)) => tls::serve(listeners, app, cert, key, state.config.tls.reload_secs).await,
+        None => listener::serve(listeners, app).await,
+    };
+    if let Err(e) = served {
+        tracing::error!("Listener failed: {}", e);
+        std::process::exit(1);
+    }
 }
 
 // --- HANDLERS ---
 
 async fn get_stats(State(state): State<AppState>) -> impl IntoResponse {
-    let total = state.total_saved_usd.load(Ordering::Relaxed) as f64 / 100.0;
+    let total = state.total_saved_usd();
+    let detector_precision: serde_json::Map<String, serde_json::Value> = state.feedback.iter()
+        .map(|t| (t.key().clone(), serde_json::json!({
+            "correct": t.correct,
+            "false_positive": t.false_positive,
+            "precision": t.precision(),
+        })))
+        .collect();
     Json(serde_json::json!({
         "active_sessions": state.sessions.len(),
         "total_saved_usd": total,
         "interventions": state.sessions.iter().map(|s| s.interventions).sum::<u32>(),
-        "status": "Healthy"
+        "sessions_evicted": {
+            "expired": state.sessions_expired.load(Ordering::Relaxed),
+            "lru": state.sessions_lru_evicted.load(Ordering::Relaxed),
+        },
+        "budget_alerts": state.budget_alerts.load(Ordering::Relaxed),
+        "detector_precision": detector_precision,
+        "latency": state.latency.snapshot(),
+        "embedding_precheck": state.latency.embedding_snapshot(),
+        "embedding_cache": state.embedding_cache.snapshot(),
+        "embedding_history_bytes": state.sessions.iter().map(|s| s.embedding_bytes()).sum::<usize>(),
+        "fingerprinted_users": state.user_fingerprints.users(),
+        "budget_pools": state.pool_spend.iter().map(|p| serde_json::json!({
+            "pool": p.key(),
+            "spent_usd": *p.value(),
+            "budget_usd": state.config.budget_pools.get(p.key()),
+        })).collect::<Vec<_>>(),
+        "instance": state.config.storage.instance_id,
+        "cluster": *state.cluster.read().unwrap(),
+        "provider_keys": state.keys.snapshot(),
+        "startup_problems": *state.startup_problems,
+        "status": if state.startup_problems.is_empty() { "Healthy" } else { "Degraded" }
     }))
 }
 
-async fn get_logs(State(state): State<AppState>) -> impl IntoResponse {
-    let logs = state.audit_logs.lock().await;
-    Json(logs.clone())
+#[derive(Debug, Deserialize)]
+struct TimeseriesQuery {
+    window: Option<String>,
+    /// `1h` or `1d`; defaults to hourly up to two days, daily beyond.
+    bucket: Option<String>,
+}
+
+/// `GET /api/stats/timeseries?window=24h`: requests, interventions by
+/// detector, cost and savings per bucket, oldest first.
+async fn get_stats_timeseries(State(state): State<AppState>, Query(query): Query<TimeseriesQuery>) -> impl IntoResponse {
+    let bad_request = |msg: &str| (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": msg}))).into_response();
+    let Some(window) = timeseries::parse_window(query.window.as_deref().unwrap_or("24h")) else {
+        return bad_request("window must look like `24h` or `7d`");
+    };
+    if window > 30 * timeseries::DAY {
+        return bad_request("window is limited to 30d");
+    }
+    let step = match query.bucket.as_deref() {
+        None if window <= 2 * timeseries::DAY => timeseries::HOUR,
+        None => timeseries::DAY,
+        Some("1h") => timeseries::HOUR,
+        Some("1d") => timeseries::DAY,
+        Some(_) => return bad_request("bucket must be `1h` or `1d`"),
+    };
+    Json(serde_json::json!({
+        "window_secs": window,
+        "bucket_secs": step,
+        "buckets": state.timeseries.series(now_secs(), window, step),
+    })).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct BreakdownQuery {
+    top: Option<usize>,
+}
+
+/// `GET /api/stats/detectors`: per-detector evaluation cost, trigger counts
+/// and, where reviewers have left feedback, the false-positive rate.
+async fn get_stats_detectors(State(state): State<AppState>) -> impl IntoResponse {
+    let mut stats = state.detectors.snapshot();
+    for t in state.feedback.iter() {
+        stats.entry(t.key().clone()).or_default();
+    }
+    let detectors: serde_json::Map<String, serde_json::Value> = stats.into_iter()
+        .map(|(detector, s)| {
+            let feedback = state.feedback.get(&detector).map(|t| *t).unwrap_or_default();
+            let row = serde_json::json!({
+                "mode": state.config.detector_mode(&detector).as_str(),
+                "evaluations": s.evaluations,
+                "triggers": s.triggers,
+                "avg_eval_us": s.avg_eval_us(),
+                "max_eval_us": s.eval_secs_max * 1e6,
+                "feedback": feedback,
+                "false_positive_rate": feedback.precision().map(|p| 1.0 - p),
+            });
+            (detector, row)
+        })
+        .collect();
+    Json(serde_json::json!({ "detectors": detectors }))
+}
+
+/// `GET /api/stats/breakdown?top=10`: interventions grouped by reason, and
+/// the sessions spending the most and tripping detectors the most.
+async fn get_stats_breakdown(State(state): State<AppState>, Query(query): Query<BreakdownQuery>) -> impl IntoResponse {
+    let top = query.top.unwrap_or(10).clamp(1, 100);
+    let history = audit_history(&state).await;
+
+    let mut by_reason: std::collections::HashMap<(&str, &str), u64> = std::collections::HashMap::new();
+    let mut by_session: std::collections::HashMap<&str, u64> = std::collections::HashMap::new();
+    for log in history.iter().filter(|l| l.enforced()) {
+        *by_reason.entry((log.detector.as_str(), log.reason.as_str())).or_default() += 1;
+        *by_session.entry(log.session_id.as_str()).or_default() += 1;
+    }
+    let mut reasons: Vec<_> = by_reason.into_iter().collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let session_row = |id: &str, interventions: u64| serde_json::json!({
+        "session_id": id,
+        "interventions": interventions,
+        "cumulative_cost": state.sessions.get(id).map(|s| s.cumulative_cost),
+    });
+    let mut by_cost: Vec<(String, f64)> = state.sessions.iter().map(|s| (s.key().clone(), s.cumulative_cost)).collect();
+    by_cost.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let mut by_interventions: Vec<_> = by_session.into_iter().collect();
+    by_interventions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    Json(serde_json::json!({
+        "interventions_by_reason": reasons.iter().map(|((detector, reason), count)| serde_json::json!({
+            "detector": detector,
+            "reason": reason,
+            "count": count,
+        })).collect::<Vec<_>>(),
+        "top_sessions_by_cost": by_cost.iter().take(top).map(|(id, _)| {
+            session_row(id, history.iter().filter(|l| l.enforced() && &l.session_id == id).count() as u64)
+        }).collect::<Vec<_>>(),
+        "top_sessions_by_interventions": by_interventions.iter().take(top).map(|(id, n)| session_row(id, *n)).collect::<Vec<_>>(),
+    }))
+}
+
+/// Prometheus text exposition.
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    use std::fmt::Write;
+    let mut out = String::new();
+    let total = state.total_saved_usd();
+    let _ = writeln!(out, "# TYPE sentinel_active_sessions gauge\nsentinel_active_sessions {}", state.sessions.len());
+    let _ = writeln!(out, "# TYPE sentinel_saved_usd_total counter\nsentinel_saved_usd_total {}", total);
+    let _ = writeln!(out, "# TYPE sentinel_sessions_evicted_total counter\nsentinel_sessions_evicted_total{{reason=\"ttl\"}} {}\nsentinel_sessions_evicted_total{{reason=\"lru\"}} {}",
+        state.sessions_expired.load(Ordering::Relaxed), state.sessions_lru_evicted.load(Ordering::Relaxed));
+    let _ = writeln!(out, "# TYPE sentinel_budget_alerts_total counter\nsentinel_budget_alerts_total {}", state.budget_alerts.load(Ordering::Relaxed));
+    let _ = writeln!(out, "# TYPE sentinel_context_trimmed_tokens_total counter\nsentinel_context_trimmed_tokens_total {}", state.trimmed_tokens.load(Ordering::Relaxed));
+    let _ = writeln!(out, "# TYPE sentinel_events_dropped_total counter\nsentinel_events_dropped_total {}", state.events.dropped.load(Ordering::Relaxed));
+    state.latency.render_prometheus(&mut out);
+    state.detectors.render_prometheus(&mut out);
+    let _ = writeln!(out, "# TYPE sentinel_detector_feedback_total counter");
+    for t in state.feedback.iter() {
+        let detector = metrics::escape_label(t.key());
+        let _ = writeln!(out, "sentinel_detector_feedback_total{{detector=\"{}\",verdict=\"correct\"}} {}", detector, t.correct);
+        let _ = writeln!(out, "sentinel_detector_feedback_total{{detector=\"{}\",verdict=\"false_positive\"}} {}", detector, t.false_positive);
+    }
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+/// `GET /api/logs`. Pagination metadata is sent in `X-Total-Count` and
+/// `X-Next-Cursor` (pass it back as `before` for the next, older page).
+async fn get_logs(State(state): State<AppState>, Query(query): Query<LogQuery>) -> impl IntoResponse {
+    // Filtered and paged queries go to the durable log; the plain dashboard
+    // poll is served from the hot cache.
+    let (total, page) = if state.audit.is_durable() && !query.is_plain() {
+        let store = state.audit.clone();
+        let history = tokio::task::spawn_blocking(move || store.load()).await.unwrap_or_default();
+        query_logs(history.iter(), &query)
+    } else {
+        let logs = state.audit_logs.lock().await;
+        query_logs(logs.iter(), &query)
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-total-count", total.into());
+    if total > page.len()
+        && let Some(oldest) = page.first() {
+        headers.insert("x-next-cursor", oldest.id.into());
+    }
+    (headers, Json(page))
+}
+
+/// `GET /api/logs/stream`: tails new interventions as server-sent events
+/// (`event: intervention`, JSON data). Accepts the `/api/logs` filters;
+/// `limit` and `before` are ignored.
+async fn stream_logs(State(state): State<AppState>, Query(query): Query<LogQuery>) -> impl IntoResponse {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+    use tokio::sync::broadcast::error::RecvError;
+
+    let rx = state.live_logs.subscribe();
+    let query = Arc::new(LogQuery { limit: None, before: None, ..query });
+    let events = futures_util::stream::unfold(rx, move |mut rx| {
+        let query = query.clone();
+        async move {
+            loop {
+                let event = match rx.recv().await {
+                    Ok(log) if query.matches(&log) => Event::default().event("intervention").id(log.id.to_string()).json_data(&log).ok()?,
+                    Ok(_) => continue,
+                    // Tell the client it missed events instead of silently dropping them.
+                    Err(RecvError::Lagged(missed)) => Event::default().event("lagged").data(missed.to_string()),
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok::<_, std::convert::Infallible>(event), rx));
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Full retained intervention history, oldest first: the durable log when
+/// there is one, otherwise whatever the hot cache still holds.
+async fn audit_history(state: &AppState) -> Vec<InterventionLog> {
+    if state.audit.is_durable() {
+        let store = state.audit.clone();
+        tokio::task::spawn_blocking(move || store.load()).await.unwrap_or_default()
+    } else {
+        state.audit_logs.lock().await.iter().cloned().collect()
+    }
+}
+
+/// `GET /api/logs/verify`: walks the audit hash chain over the retained
+/// history and reports the first entry that was edited, dropped or reordered.
+async fn verify_logs(State(state): State<AppState>) -> impl IntoResponse {
+    let mut history = audit_history(&state).await;
+    history.sort_by_key(|l| l.id);
+    let policy = state.config.audit.clone();
+    let report = tokio::task::spawn_blocking(move || audit::verify_chain(&history, &policy)).await.unwrap_or_default();
+    if !report.ok {
+        tracing::error!("Audit chain broken at entry {:?}: {}", report.broken_at, report.problem.as_deref().unwrap_or_default());
+    }
+    Json(report)
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    from: Option<u64>,
+    to: Option<u64>,
+    tenant: Option<String>,
+}
+
+/// `GET /api/logs/export?format=csv|jsonl&from=&to=&tenant=`: the full retained
+/// history as a download, oldest first, for SIEM/BI ingestion.
+async fn export_logs(State(state): State<AppState>, Query(query): Query<ExportQuery>) -> impl IntoResponse {
+    let history = audit_history(&state).await;
+    let filter = LogQuery { from: query.from, to: query.to, tenant: query.tenant, ..Default::default() };
+    let format = query.format;
+
+    let header = (format == ExportFormat::Csv).then(|| audit::CSV_HEADER.to_string());
+    let lines = header.into_iter().chain(
+        history.into_iter()
+            .filter(move |l| filter.matches(l))
+            .map(move |l| audit::export_line(&l, format)),
+    );
+    let body = axum::body::Body::from_stream(futures_util::stream::iter(lines.map(Ok::<_, std::convert::Infallible>)));
+    let disposition = format!("attachment; filename=\"sentinel-audit.{}\"", format.extension());
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, format.content_type().to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedbackRequest {
+    verdict: Verdict,
+}
+
+async fn post_feedback(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(req): Json<FeedbackRequest>,
+) -> impl IntoResponse {
+    // Evicted from the hot cache: fall back to the durable log, read without
+    // holding the cache lock.
+    let mut from_history = None;
+    if !state.audit_logs.lock().await.iter().any(|l| l.id == id) {
+        let store = state.audit.clone();
+        let history = tokio::task::spawn_blocking(move || store.load()).await.unwrap_or_default();
+        from_history = history.into_iter().find(|l| l.id == id);
+    }
+    let mut logs = state.audit_logs.lock().await;
+    let entry = match logs.iter_mut().find(|l| l.id == id) {
+        Some(entry) => entry,
+        None => match from_history.as_mut() {
+            Some(entry) => entry,
+            None => return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Intervention not found"}))).into_response(),
+        },
+    };
+
+    let mut tally = state.feedback.entry(entry.detector.clone()).or_default();
+    if let Some(previous) = entry.feedback.replace(req.verdict) {
+        tally.add(previous, -1);
+    }
+    tally.add(req.verdict, 1);
+    drop(tally);
+    state.audit.append(entry);
+
+    if matches!(entry.detector.as_str(), "semantic_loop" | "fuzzy_loop")
+        && let Some(mut sess) = state.sessions.get_mut(&entry.session_id) {
+        sess.apply_loop_feedback(req.verdict == Verdict::FalsePositive, &state.config.loops);
+    }
+
+    Json(entry.clone()).into_response()
+}
+
+/// `POST /api/interventions/{id}/replay`: sends the original request upstream
+/// once, skipping detectors, so the block can be judged against the real answer.
+async fn replay_intervention(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    let request = {
+        let logs = state.audit_logs.lock().await;
+        match logs.iter().find(|l| l.id == id) {
+            Some(entry) => entry.request.clone(),
+            None => return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Intervention not found"}))).into_response(),
+        }
+    };
+    let Some(request) = request else {
+        return (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"error": "No request recorded for this intervention"}))).into_response();
+    };
+
+    tracing::info!("Replaying intervention #{} for session '{}'", id, request.session_id);
+    match upstream::forward(&state, &request).await {
+        Ok((status, completion)) => Json(serde_json::json!({
+            "intervention_id": id,
+            "upstream_status": status.as_u16(),
+            "completion": completion,
+        })).into_response(),
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+/// `x-sentinel-session`, else the request's `user`, else one per client
+/// address (see `client_ip`), else `default`; under `<tenant>/` for a tenant.
+fn session_id(headers: &HeaderMap, user: Option<&str>) -> String {
+    let header = |name: &str| headers.get(name).and_then(|h| h.to_str().ok());
+    let id = header("x-sentinel-session")
+        .or(user)
+        .or_else(|| header(client_ip::CLIENT_HEADER))
+        .unwrap_or("default");
+    match tenancy::of(headers) {
+        Some(tenant) => format!("{}/{}", tenant, id),
+        None => id.to_string(),
+    }
+}
+
+/// The 403 for a session an operator has blocked, if this one is.
+async fn kill_switch(state: &AppState, headers: &HeaderMap, session_id: &str, model: &str, request: &serde_json::Value) -> Option<Response> {
+    let block_reason = state.blocked.get(session_id).map(|b| b.reason.clone())?;
+    record_intervention(
+        state, &LogContext::new(session_id, model).tenant(tenancy::of(headers)), "kill_switch", "Session Blocked by Operator",
+        block_reason.clone(), savings::avoided(&state.config, "kill_switch", model, Some(request)),
+    ).await;
+    let error_body = serde_json::json!({
+        "error": {
+            "message": state.config.messages.render(
+                &state.config.messages.locale_for(headers), "session_blocked", &[("reason", &block_reason), ("session", session_id)],
+            ),
+            "type": "sentinel_blocked",
+            "param": null,
+            "code": "session_blocked"
+        }
+    });
+    let headers = intervention_headers("blocked", "kill_switch", "Session Blocked by Operator");
+    Some((StatusCode::FORBIDDEN, headers, Json(error_body)).into_response())
+}
+
+/// `x-sentinel-*` headers telling client middleware what Sentinel did to the
+/// exchange: `action` is `blocked`, `redacted`, `truncated`, `quarantined`
+/// or `warned`.
+fn intervention_headers(action: &'static str, detector: &str, reason: &str) -> HeaderMap {
+    let value = |s: &str| {
+        let ascii: String = s.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').collect();
+        HeaderValue::from_str(&ascii).unwrap_or(HeaderValue::from_static(""))
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert("x-sentinel-intervention", HeaderValue::from_static(action));
+    headers.insert("x-sentinel-detector", value(detector));
+    headers.insert("x-sentinel-reason", value(reason));
+    headers
+}
+
+/// Reports warn-mode hits on a forwarded response: `x-sentinel-*` headers
+/// naming the first, and a `sentinel.warnings` list in `body` when there is
+/// one. The completion itself is left as the model produced it.
+fn attach_warnings(config: &Config, headers: &HeaderMap, body: Option<&mut serde_json::Value>, warnings: &[(&str, String)]) -> HeaderMap {
+    let Some((detector, reason)) = warnings.first() else { return HeaderMap::new() };
+    if let Some(body) = body {
+        let locale = config.messages.locale_for(headers);
+        body["sentinel"]["warnings"] = warnings.iter().map(|(detector, reason)| serde_json::json!({
+            "detector": detector,
+            "message": config.messages.render(&locale, "warned", &[("reason", reason), ("detector", detector)]),
+        })).collect();
+    }
+    intervention_headers("warned", detector, reason)
+}
+
+/// Marks proxied responses Sentinel left alone, so every `/v1` response
+/// carries `x-sentinel-intervention`.
+async fn mark_unmodified(mut response: Response) -> Response {
+    if !response.headers().contains_key("x-sentinel-intervention") {
+        response.headers_mut().insert("x-sentinel-intervention", HeaderValue::from_static("none"));
+    }
+    response
+}
+
+/// The key the *client* presented (not the upstream key Sentinel uses).
+fn client_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
 }
 
 async fn chat_completions(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(payload): Json<ChatRequest>,
-) -> impl IntoResponse {
-    let session_id = headers.get("x-sentinel-session")
-        .and_then(|h| h.to_str().ok().map(|s| s.to_string()))
-        .or_else(|| payload.user.clone())
-        .unwrap_or_else(|| "default".to_string());
+) -> Response {
+    let prompt = payload.messages.last().map(ChatMessage::text).unwrap_or_default();
+    let body = serde_json::to_value(&payload).unwrap_or_default();
+    proxy_generation(state, headers, Generation { api: Api::Chat, model: payload.model, user: payload.user, prompt, body }).await
+}
 
-    let provider = headers.get("x-sentinel-provider")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or_else(|| {
-            if payload.model.contains("llama") || payload.model.contains("mixtral") || payload.model.contains("gemma") {
-                "groq"
-            } else {
-                "openai"
-            }
-        });
+/// `POST /v1/completions`: the legacy text-completions API, through the same
+/// pipeline as chat with `prompt` as the analysed text.
+async fn completions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<CompletionRequest>,
+) -> Response {
+    let prompt = payload.prompt_text();
+    let body = serde_json::to_value(&payload).unwrap_or_default();
+    proxy_generation(state, headers, Generation { api: Api::Completions, model: payload.model, user: payload.user, prompt, body }).await
+}
+
+/// Text-generation endpoints that share the detection pipeline. They differ
+/// in where the prompt and the generated text live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Api {
+    Chat,
+    Completions,
+}
+
+impl Api {
+    fn path(self) -> &'static str {
+        match self {
+            Api::Chat => "chat/completions",
+            Api::Completions => "completions",
+        }
+    }
+
+    /// The prompt of `body` as the handlers read it, for re-reading after a rewrite.
+    fn prompt(self, body: &serde_json::Value) -> String {
+        match self {
+            Api::Chat => body["messages"].as_array().and_then(|m| m.last())
+                .and_then(|m| serde_json::from_value::<ChatMessage>(m.clone()).ok())
+                .map(|m| m.text())
+                .unwrap_or_default(),
+            Api::Completions => serde_json::from_value::<CompletionRequest>(body.clone())
+                .map(|r| r.prompt_text())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A generation request reduced to what the pipeline needs. `body` is
+/// forwarded as is.
+struct Generation {
+    api: Api,
+    model: String,
+    user: Option<String>,
+    /// Text the loop detectors compare across turns.
+    prompt: String,
+    body: serde_json::Value,
+}
+
+async fn proxy_generation(state: AppState, headers: HeaderMap, request: Generation) -> Response {
+    if let Some(rejected) = tenancy::qualified_session(request.user.as_deref()) {
+        return rejected;
+    }
+    let session_id = session_id(&headers, request.user.as_deref());
+    let span = tracing::info_span!(
+        "proxy",
+        api = request.api.path(),
+        session_id = %session_id,
+        model = %request.model,
+        provider = tracing::field::Empty,
+        detector = tracing::field::Empty,
+        http.status_code = tracing::field::Empty,
+    );
+    telemetry::continue_trace(&span, &headers);
+    let response = run_pipeline(state, headers, request, session_id).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+/// A detector's finding, before its exemptions and mode apply.
+struct Hit<'a> {
+    detector: &'static str,
+    reason: &'a str,
+    snippet: String,
+    /// Estimated spend a block avoids.
+    savings: f64,
+}
+
+/// Logs `hit` as the session's exemptions and the detector's mode say: a
+/// bypass, a dry run (also reported on the response in `warn` mode) or, in
+/// `block` mode, an intervention whose log id is returned for the caller
+/// to enforce.
+async fn apply_detector(
+    state: &AppState,
+    log_ctx: &LogContext,
+    warnings: &mut Vec<(&'static str, String)>,
+    exempt: bool,
+    mode: DetectorMode,
+    hit: Hit<'_>,
+) -> Option<u64> {
+    if exempt {
+        record_bypass(state, log_ctx, hit.detector, hit.reason).await;
+        None
+    } else if mode != DetectorMode::Block {
+        record_dry_run(state, log_ctx, hit.detector, hit.reason, hit.snippet).await;
+        if mode == DetectorMode::Warn {
+            warnings.push((hit.detector, hit.reason.to_string()));
+        }
+        None
+    } else {
+        Some(record_intervention(state, log_ctx, hit.detector, hit.reason, hit.snippet, hit.savings).await)
+    }
+}
+
+async fn run_pipeline(state: AppState, headers: HeaderMap, request: Generation, session_id: String) -> Response {
+    let received_at = std::time::Instant::now();
+    state.timeseries.record_request(now_secs());
+    state.usage.record_request(now_secs(), tenancy::of(&headers));
+    let Generation { api, model, user, prompt: mut prompt_to_check, body: mut payload } = request;
 
-    let (url, api_key) = match provider {
-        "groq" => ("https://api.groq.com/openai/v1/chat/completions", state.groq_api_key.clone()),
-        _ => ("https://api.openai.com/v1/chat/completions", state.openai_api_key.clone()),
+    if let Some(blocked) = kill_switch(&state, &headers, &session_id, &model, &payload).await {
+        return blocked;
+    }
+    if let Some(reason) = limits::check(&state.config.limits, &payload) {
+        let ctx = LogContext::new(&session_id, &model).tenant(tenancy::of(&headers));
+        return limits::reject(&state, &headers, &ctx, Some(&payload), reason).await;
+    }
+
+    let route = routing::resolve(&state.config.routing_rules, &routing::RequestView {
+        headers: &headers,
+        model: &model,
+        body: &payload,
+    });
+    let routed = route.provider.as_deref().unwrap_or("openai");
+    // An explicit provider header, or `x-sentinel-pin: reset`, moves the pin.
+    let reset = headers.contains_key("x-sentinel-provider") || headers.get("x-sentinel-pin").is_some_and(|h| h == "reset");
+    let (pinned, model) = match state.config.sessions.pin {
+        PinMode::Off => (routed.to_string(), model),
+        mode => state.sessions.entry(session_id.clone()).or_default().pin(mode, routed, &model, reset),
+    };
+    if payload["model"].as_str().is_some_and(|m| m != model) {
+        payload["model"] = serde_json::Value::String(model.clone());
+    }
+    let provider = pinned.as_str();
+    tracing::Span::current().record("provider", provider);
+    let tenant = tenancy::of(&headers).map(str::to_string);
+    let upstream = match state.provider(tenant.as_deref(), provider) {
+        Ok(upstream) => upstream,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e).into_response(),
     };
+    let (url, api_key) = (upstream.endpoint(api.path()), upstream.api_key);
 
-    let prompt_to_check = payload.messages.last()
-        .map(|m| m.content.clone())
-        .unwrap_or_default();
+    let (cost_policy, mut loop_policy) = state.config.tenant_policies(tenant.as_deref(), &model);
+    if let Some(settings) = headers.get("x-sentinel-loop-window").and_then(|h| h.to_str().ok())
+        && let Err(e) = loop_policy.apply(settings) {
+        return (StatusCode::BAD_REQUEST, format!("Invalid x-sentinel-loop-window: {}", e)).into_response();
+    }
+    let hints = match cost_hints::CostHints::from_headers(&headers) {
+        Ok(hints) => hints,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let client_key = client_api_key(&headers);
+    let stored_request = |payload: &serde_json::Value| upstream::StoredRequest {
+        session_id: session_id.clone(),
+        endpoint: api.path().to_string(),
+        provider: provider.to_string(),
+        model: model.clone(),
+        budget_pool: route.budget_pool.clone(),
+        tenant: tenant.clone(),
+        payload: payload.clone(),
+    };
+    let exempt = |detector: &str| state.config.is_exempt(&session_id, client_key, detector);
+    let mode = |detector: &str| state.config.tenant_detector_mode(tenant.as_deref(), detector);
+    let log_ctx = LogContext::new(&session_id, &model).provider(provider).tenant(tenant.as_deref());
+    // Warn-mode hits, reported on the forwarded response.
+    let mut warnings: Vec<(&str, String)> = Vec::new();
+
+    // 0. Scrub rules, named entities, canonical system prompt, scripted rules, policy plugins and rewrite rules
+    let scrubbed = rewrite::scrub(&state.config.rewrite_rules, &headers, &model, &mut payload);
+    let mut redacted = !scrubbed.is_empty();
+    for change in scrubbed {
+        record_rewrite(&state, &log_ctx, rewrite::DETECTOR, &change).await;
+    }
+    if mode(ner::DETECTOR) != DetectorMode::Off && !exempt(ner::DETECTOR) {
+        let found = ner::scan(&state, tenant.as_deref(), &mut payload).await;
+        if let Some(entities) = found.redacted {
+            redacted = true;
+            record_rewrite(&state, &log_ctx, ner::DETECTOR, &format!("Named entities redacted: {}", entities)).await;
+        }
+        if let Some(flagged) = found.flagged {
+            record_dry_run(&state, &log_ctx, ner::DETECTOR, &format!("Named entities: {}", flagged), String::new()).await;
+        }
+    }
+    if redacted {
+        prompt_to_check = api.prompt(&payload);
+    }
+    let canonical = system_prompt::canonical(&state.config.system_prompt, tenant.as_deref(), &headers);
+    if let Some(canonical) = canonical.filter(|_| mode(system_prompt::DETECTOR) != DetectorMode::Off) {
+        let check = system_prompt::check(&payload, canonical);
+        if check != system_prompt::Check::Intact
+            && state.config.system_prompt.action == SystemPromptAction::Correct
+            && system_prompt::correct(&mut payload, canonical)
+        {
+            record_rewrite(&state, &log_ctx, system_prompt::DETECTOR, &format!("{}, restored", check.reason())).await;
+        } else if check != system_prompt::Check::Intact {
+            let (detector, reason) = (system_prompt::DETECTOR, check.reason());
+            let snippet = prompt_to_check.chars().take(50).collect::<String>() + "...";
+            let hit = Hit { detector, reason, snippet, savings: 0.0 };
+            if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                attach_request(&state, log_id, stored_request(&payload)).await;
+                return block_response(&state.config, &headers, api, None, log_id, &model, detector, reason);
+            }
+        }
+    }
+    if mode(scripts::DETECTOR) != DetectorMode::Off {
+        let (session_cost, session_interventions) = state.sessions.get(&session_id)
+            .map_or((0.0, 0), |s| (s.cumulative_cost, s.interventions));
+        let host = state.scripts.read().unwrap_or_else(|p| p.into_inner()).clone();
+        let found = host.check(&scripts::Input {
+            model: &model,
+            provider,
+            tenant: tenant.as_deref(),
+            user: user.as_deref(),
+            prompt: &prompt_to_check,
+            prompt_tokens: savings::estimate_prompt_tokens(&payload),
+            session_cost,
+            session_interventions,
+        });
+        if let Some(reason) = found {
+            let detector = scripts::DETECTOR;
+            let snippet = prompt_to_check.chars().take(50).collect::<String>() + "...";
+            let hit = Hit { detector, reason: &reason, snippet, savings: 0.0 };
+            if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                attach_request(&state, log_id, stored_request(&payload)).await;
+                return block_response(&state.config, &headers, api, None, log_id, &model, detector, &reason);
+            }
+        }
+    }
+    if mode(plugins::DETECTOR) != DetectorMode::Off {
+        let context = serde_json::json!({ "session_id": session_id, "model": model, "tenant": tenant });
+        match plugins::inspect(&state, plugins::Stage::Request, context, &payload).await {
+            plugins::Verdict::Allow => {}
+            plugins::Verdict::Rewrite(body) => {
+                payload = body;
+                record_rewrite(&state, &log_ctx, plugins::DETECTOR, "Request body replaced by a plugin").await;
+            }
+            plugins::Verdict::Block { plugin, reason } => {
+                let (detector, reason) = (plugins::DETECTOR, format!("Plugin `{}`: {}", plugin, reason));
+                let snippet = prompt_to_check.chars().take(50).collect::<String>() + "...";
+                let hit = Hit { detector, reason: &reason, snippet, savings: 0.0 };
+                if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                    attach_request(&state, log_id, stored_request(&payload)).await;
+                    return block_response(&state.config, &headers, api, None, log_id, &model, detector, &reason);
+                }
+            }
+        }
+    }
+
+    for change in rewrite::apply(&state.config.rewrite_rules, &headers, &model, &mut payload) {
+        record_rewrite(&state, &log_ctx, rewrite::DETECTOR, &change).await;
+    }
+    // What the loop detectors, the embedder and snippets see from here on.
+    prompt_to_check = api.prompt(&payload);
 
     // 1. Loop Detection
     let mut is_loop = false;
+    let mut detector = "";
     let mut reason = String::new();
-    let emb_result = get_emb_final_v4(&state.client, &state.openai_api_key, &prompt_to_check).await;
-    
+    let emb_started = std::time::Instant::now();
+    let emb_result = state.embedder.embed(&prompt_to_check)
+        .instrument(tracing::info_span!("embedding"))
+        .await;
+    if has_embedding_key(&state.openai_api_key) {
+        let timed_out = matches!(&emb_result, Err(e) if e == embedder::TIMED_OUT);
+        state.latency.observe_embedding(emb_started.elapsed(), emb_result.is_ok(), timed_out);
+    }
+
     {
-        let mut sess = state.sessions.entry(session_id.clone()).or_insert_with(|| SessionState::new());
+        let _detectors = tracing::info_span!("detectors").entered();
+        let mut sess = state.sessions.entry(session_id.clone()).or_default();
         let val = sess.value_mut();
+        val.touch();
         
+        let loops = &loop_policy;
+        let semantic_threshold = val.effective_threshold(loops.semantic_threshold, true, loops);
+        let fuzzy_threshold = val.effective_threshold(loops.fuzzy_threshold, false, loops);
+
         if let Ok(emb) = emb_result {
-            if val.check_loop(Embedding(emb), 0.20, 3) {
+            let started = std::time::Instant::now();
+            let hit = val.check_loop(Embedding(emb), semantic_threshold, loops)
+                .inspect_err(|e| tracing::warn!("Semantic loop check skipped for session '{}': {}", session_id, e))
+                .unwrap_or(false);
+            state.detectors.observe_eval("semantic_loop", started.elapsed());
+            if hit && mode("semantic_loop") != DetectorMode::Off {
                 is_loop = true;
+                detector = "semantic_loop";
                 reason = "Semantic Loop Detected (Vector Similarity)".to_string();
             }
         }
         
         if !is_loop {
-            if val.check_basic_loop(prompt_to_check.clone(), 0.80, 3) {
+            let started = std::time::Instant::now();
+            let hit = val.check_basic_loop(prompt_to_check.clone(), fuzzy_threshold, loops);
+            state.detectors.observe_eval("fuzzy_loop", started.elapsed());
+            if hit && mode("fuzzy_loop") != DetectorMode::Off {
                 is_loop = true;
+                detector = "fuzzy_loop";
                 reason = "Fuzzy Overlap Detected (String Repetition)".to_string();
             }
         }
     }
 
+    let user_loops = &state.config.user_loops;
+    if user_loops.enabled && let Some(user) = user.as_deref().or(client_key) {
+        let started = std::time::Instant::now();
+        let seen_in = state.user_fingerprints.observe(user, &session_id, &prompt_to_check, now_secs(), user_loops);
+        state.detectors.observe_eval("cross_session_loop", started.elapsed());
+        if !is_loop && seen_in >= user_loops.min_sessions && mode("cross_session_loop") != DetectorMode::Off {
+            is_loop = true;
+            detector = "cross_session_loop";
+            reason = format!("Cross-Session Loop Detected (same prompt in {} sessions)", seen_in);
+        }
+    }
+
     if is_loop {
-        state.total_saved_usd.fetch_add(50, Ordering::Relaxed);
-        
-        // Log intervention
-        let mut logs = state.audit_logs.lock().await;
-        logs.push_back(InterventionLog {
-            timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-            session_id: session_id.clone(),
-            reason: reason.clone(),
-            content_snippet: prompt_to_check.chars().take(50).collect::<String>() + "...",
-            savings_est: 0.50,
-        });
-        if logs.len() > 50 { logs.pop_front(); }
-
-        let error_body = serde_json::json!({
-            "choices": [{
-                "index": 0,
-                "message": {
-                    "role": "assistant",
-                    "content": format!("🚨 SENTINEL: Bloqueado. Motivo: {}", reason)
-                },
-                "finish_reason": "stop"
-            }]
-        });
-        return (StatusCode::OK, Json(error_body)).into_response();
+        tracing::Span::current().record("detector", detector);
+    }
+    let wants_quarantine = state.config.quarantine.enabled
+        || headers.get("x-sentinel-quarantine").is_some_and(|h| h == "1" || h == "true");
+    // A loop that would be blocked is parked instead, while there is room.
+    if is_loop && !exempt(detector) && mode(detector) == DetectorMode::Block && wants_quarantine {
+        if let Some(id) = quarantine::park(&state, stored_request(&payload), detector, &reason) {
+            record_intervention(
+                &state, &log_ctx, detector, &format!("Quarantined: {}", reason),
+                prompt_to_check.chars().take(50).collect::<String>() + "...",
+                0.0,
+            ).await;
+            return (StatusCode::ACCEPTED, intervention_headers("quarantined", detector, &reason), Json(serde_json::json!({
+                "quarantine_id": id,
+                "status": quarantine::QuarantineStatus::Pending,
+                "reason": reason,
+                "retrieve_url": format!("/api/quarantine/{}", id),
+            }))).into_response();
+        }
+        tracing::warn!("Quarantine full, blocking request for session '{}' instead", session_id);
+    }
+    if is_loop {
+        let hit = Hit {
+            detector,
+            reason: &reason,
+            snippet: prompt_to_check.chars().take(50).collect::<String>() + "...",
+            savings: savings::avoided(&state.config, detector, &model, Some(&payload)),
+        };
+        if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+            attach_request(&state, log_id, stored_request(&payload)).await;
+            return block_response(&state.config, &headers, api, None, log_id, &model, detector, &reason);
+        }
     }
 
     // 2. Forward
-    let response = state.client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&payload)
-        .send()
-        .await;
+    if limits::cap_output(cost_policy.max_output_tokens, &model, &mut payload) {
+        tracing::debug!(session_id = %session_id, "Completion budget capped at {} tokens", cost_policy.max_output_tokens);
+    }
+    if let Some(trimmed) = trimming::compress(&state, tenant.as_deref(), &model, &mut payload).await {
+        let saved = trimmed.tokens_before.saturating_sub(trimmed.tokens_after);
+        match trimmed.saved_usd {
+            Some(usd) => tracing::info!(session_id = %session_id, "✂️ Summarized {} old messages, ~{} prompt tokens saved, net ${:.4} after the summary", trimmed.messages, saved, usd),
+            None => tracing::info!(session_id = %session_id, "✂️ Dropped {} old messages, ~{} prompt tokens saved", trimmed.messages, saved),
+        }
+        if trimmed.summary_cost_usd > 0.0 {
+            book_cost(&state, &session_id, route.budget_pool.as_deref(), tenant.as_deref(), trimmed.summary_cost_usd, &cost_policy);
+        }
+        state.trimmed_tokens.fetch_add(saved, Ordering::Relaxed);
+    }
+    if let Some(ceiling) = hints.ceiling()
+        && let Err(prompt_cost) = cost_hints::fit(&state.config.pricing, ceiling, &model, &mut payload)
+    {
+        return cost_hints::reject(&state, &headers, &log_ctx, ceiling, prompt_cost).await;
+    }
+    let sent_at = std::time::Instant::now();
+    let overhead = sent_at - received_at;
+    let response = send_upstream(&state, provider, &model, &url, &api_key, &headers, api, &payload).await;
+
+    let wants_stream = payload["stream"].as_bool().unwrap_or(false);
 
     match response {
+        Ok(res) if wants_stream && res.status().is_success() => {
+            let cost_exempt = exempt("cost_spike");
+            let leak_exempt = exempt("leak");
+            let repetition_exempt = exempt("repetition");
+            let stall_exempt = exempt("logprob_stall");
+            let leak_prompts = prompt_leak::Tracker::for_request(&state.config.prompt_leak, &payload);
+            let (cost_mode, leak_mode) = (mode("cost_spike"), mode("leak"));
+            let (repetition_mode, stall_mode) = (mode("repetition"), mode("logprob_stall"));
+            let mut upstream_headers = state.config.headers.returned(res.headers());
+            upstream_headers.extend(attach_warnings(&state.config, &headers, None, &warnings));
+            upstream_headers.extend(hints.response_headers(None));
+            let mut response = streaming::proxy_stream(state, res, streaming::StreamContext {
+                session_id,
+                budget_pool: route.budget_pool.clone(),
+                tenant: tenant.clone(),
+                cost_policy,
+                cost_exempt,
+                cost_mode,
+                leak_exempt,
+                leak_mode,
+                leak_prompts,
+                repetition_exempt,
+                repetition_mode,
+                stall_exempt,
+                stall_mode,
+                api,
+                provider: provider.to_string(),
+                model,
+                sent_at,
+                overhead,
+            });
+            response.headers_mut().extend(upstream_headers);
+            response
+        }
         Ok(res) => {
             let status = res.status();
-            let mut body: serde_json::Value = res.json().await.unwrap_or_default();
+            let mut upstream_headers = state.config.headers.returned(res.headers());
+            let mut body = match read_body(&state, provider, &model, res).await {
+                Ok(body) => body,
+                Err(response) => return response,
+            };
+            let latency = sent_at.elapsed();
             
+            let request_event = events::RequestEvent {
+                status: status.as_u16(),
+                latency_ms: Some(latency.as_millis() as u64),
+                ..events::RequestEvent::new(&session_id, tenant.as_deref(), provider, api.path(), &model).with_usage(&body)
+            };
             if !status.is_success() {
-                return (status, Json(body)).into_response();
+                events::emit(&state, events::Event::Request(request_event));
+                return (status, upstream_headers, Json(body)).into_response();
+            }
+
+            // Structured output: re-prompt a completion that doesn't match its
+            // schema, unless the detector only logs or warns.
+            let mut schema_errors = Vec::new();
+            let schema = structured::schema_for(&state, &session_id, &headers, &payload);
+            if let Some(schema) = schema.filter(|_| mode(structured::DETECTOR) != DetectorMode::Off && !exempt(structured::DETECTOR))
+                && !response_content(&body).is_empty()
+            {
+                schema_errors = structured::check(&schema, response_content(&body));
+                let retries = if mode(structured::DETECTOR) == DetectorMode::Block { state.config.structured.retries } else { 0 };
+                for _ in 0..retries {
+                    if schema_errors.is_empty() {
+                        break;
+                    }
+                    let Some(retry) = structured::repair_request(&payload, response_content(&body), &schema_errors) else { break };
+                    record_rewrite(&state, &log_ctx, structured::DETECTOR, &format!("Re-prompted: {}", schema_errors[0])).await;
+                    let Some(mut next) = resend(&state, provider, &model, &url, &api_key, &headers, api, &retry).await else { break };
+                    structured::add_usage(&mut next, &body);
+                    body = next;
+                    schema_errors = structured::check(&schema, response_content(&body));
+                }
+            }
+            // Billed tokens include the retries.
+            let request_event = request_event.with_usage(&body);
+
+            let completion_tokens = body["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+            state.latency.observe(provider, &model, None, completion_tokens, sent_at.elapsed(), overhead);
+
+            // Scan everything the model produced: plain/JSON-mode content as well
+            // as tool-call arguments, which carry the payload when content is null.
+            let log_ctx = log_ctx.with_usage(&body);
+            // (detector, reason, log id) of the intervention to apply, if any.
+            let mut blocked: Option<(&str, &str, u64)> = None;
+            let background = state.config.analysis.background;
+            if background && mode("leak") != DetectorMode::Off {
+                tokio::spawn(background_leak_scan(
+                    state.clone(), log_ctx.clone(), body.clone(), stored_request(&payload), exempt("leak"), mode("leak"),
+                ));
+            }
+            let leak = if background || mode("leak") == DetectorMode::Off {
+                None
+            } else {
+                let started = std::time::Instant::now();
+                let hit = leak_reason(&state.config, &payload, &body);
+                state.detectors.observe_eval("leak", started.elapsed());
+                hit
+            };
+            if let Some(reason) = leak {
+                let hit = Hit {
+                    detector: "leak",
+                    reason,
+                    snippet: "[REDACTED SENSITIVE DATA]".to_string(),
+                    savings: savings::avoided(&state.config, "leak", &model, None),
+                };
+                if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt("leak"), mode("leak"), hit).await {
+                    attach_request(&state, log_id, stored_request(&payload)).await;
+                    blocked = Some(("leak", reason, log_id));
+                    // The tokens were still billed, so book them below.
+                }
+            }
+
+            // Stall: a long run of near-certain tokens, when logprobs came back.
+            let stall = &state.config.stall;
+            let logprobs = &body["choices"][0]["logprobs"];
+            if stall.enabled && blocked.is_none() && !logprobs.is_null() && mode("logprob_stall") != DetectorMode::Off {
+                let started = std::time::Instant::now();
+                let mut tracker = logprobs::StallTracker::default();
+                let stalled = tracker.push(&logprobs::token_entropies(logprobs), stall);
+                state.detectors.observe_eval("logprob_stall", started.elapsed());
+                let reason = "Logprob Stall (Low-Entropy Output)";
+                let hit = Hit { detector: "logprob_stall", reason, snippet: format!("{} tokens", tracker.tokens), savings: 0.0 };
+                if stalled
+                    && let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt("logprob_stall"), mode("logprob_stall"), hit).await {
+                    attach_request(&state, log_id, stored_request(&payload)).await;
+                    blocked = Some(("logprob_stall", reason, log_id));
+                }
+            }
+
+            // Degeneration: cut the completion where it starts repeating itself.
+            let mut truncated = HeaderMap::new();
+            if state.config.repetition.enabled && blocked.is_none() && mode("repetition") != DetectorMode::Off {
+                let text = response_content(&body).to_string();
+                let started = std::time::Instant::now();
+                let hit = repetition::scan(&text, &state.config.repetition);
+                state.detectors.observe_eval("repetition", started.elapsed());
+                if let Some(hit) = hit {
+                    let snippet = text[hit.cut_at..].chars().take(50).collect::<String>() + "...";
+                    let found = Hit { detector: "repetition", reason: &hit.reason, snippet, savings: 0.0 };
+                    if apply_detector(&state, &log_ctx, &mut warnings, exempt("repetition"), mode("repetition"), found).await.is_some() {
+                        truncate_response_content(&mut body, api, text[..hit.cut_at].trim_end());
+                        truncated = intervention_headers("truncated", "repetition", &hit.reason);
+                    }
+                }
+            }
+
+            // Toxicity: mask or refuse abusive completions.
+            if state.config.toxicity.enabled && blocked.is_none() && mode(toxicity::DETECTOR) != DetectorMode::Off {
+                let text = response_content(&body).to_string();
+                let started = std::time::Instant::now();
+                let scored = toxicity::score(&state, &state.config.toxicity, &text).await;
+                state.detectors.observe_eval(toxicity::DETECTOR, started.elapsed());
+                let (threshold, action) = state.config.toxicity_for(tenant.as_deref());
+                if scored.score >= threshold {
+                    let (detector, reason) = (toxicity::DETECTOR, toxicity::REASON);
+                    let hit = Hit { detector, reason, snippet: format!("Score {:.2}", scored.score), savings: 0.0 };
+                    match apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                        None => {}
+                        Some(_) if action == ToxicityAction::Redact => {
+                            truncate_response_content(&mut body, api, &toxicity::redact(&text, &scored));
+                            truncated = intervention_headers("redacted", detector, reason);
+                        }
+                        Some(log_id) => {
+                            attach_request(&state, log_id, stored_request(&payload)).await;
+                            blocked = Some((detector, reason, log_id));
+                        }
+                    }
+                }
             }
 
-            if let Some(content) = body["choices"][0]["message"]["content"].as_str() {
-                let content_str = content.to_string();
-                
-                if content_str.contains("SYSTEM_PROMPT:") || content_str.contains("API_KEY=") {
-                    body["choices"][0]["message"]["content"] = serde_json::json!("🛡️ SENTINEL: Bloqueado por filtración de datos.");
-                    
-                    let mut logs = state.audit_logs.lock().await;
-                    logs.push_back(InterventionLog {
-                        timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                        session_id: session_id.clone(),
-                        reason: "Sensitive Data Leak (EchoLeak)".to_string(),
-                        content_snippet: "[REDACTED SENSITIVE DATA]".to_string(),
-                        savings_est: 0.10,
-                    });
-                    
-                    return (status, Json(body)).into_response();
+            // Structured output still invalid after its re-prompts.
+            let schema_reason = schema_errors.first().map(|e| format!("{}: {}", structured::REASON, e));
+            if let Some(reason) = schema_reason.as_deref().filter(|_| blocked.is_none()) {
+                let detector = structured::DETECTOR;
+                let snippet = response_content(&body).chars().take(50).collect::<String>() + "...";
+                let hit = Hit { detector, reason, snippet, savings: 0.0 };
+                if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                    attach_request(&state, log_id, stored_request(&payload)).await;
+                    blocked = Some((detector, reason, log_id));
                 }
+            }
 
-                if let Some(mut sess) = state.sessions.get_mut(&session_id) {
-                    let mut cost = 0.0;
-                    if let Some(usage) = body.get("usage") {
-                        let p = usage.get("prompt_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
-                        let c = usage.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
-                        cost = (p as f64 * 0.00000015) + (c as f64 * 0.00000060);
+            // Policy plugins see what the built-in checks let through.
+            // Outlives the match: `blocked` may borrow it.
+            let plugin_reason: String;
+            if blocked.is_none() && mode(plugins::DETECTOR) != DetectorMode::Off {
+                let context = serde_json::json!({ "session_id": session_id, "model": model, "tenant": tenant, "request": payload });
+                match plugins::inspect(&state, plugins::Stage::Response, context, &body).await {
+                    plugins::Verdict::Allow => {}
+                    plugins::Verdict::Rewrite(new) => {
+                        body = new;
+                        record_rewrite(&state, &log_ctx, plugins::DETECTOR, "Response body replaced by a plugin").await;
                     }
-                    
-                    if sess.check_economic_throttle(cost) {
-                        body["choices"][0]["message"]["content"] = serde_json::json!("🛑 SENTINEL: Gasto excesivo detectado.");
-                        
-                        let mut logs = state.audit_logs.lock().await;
-                        logs.push_back(InterventionLog {
-                            timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                            session_id: session_id.clone(),
-                            reason: "Economic Throttling (Cost Spike)".to_string(),
-                            content_snippet: format!("Cost: ${:.4}", cost),
-                            savings_est: 1.00,
-                        });
+                    plugins::Verdict::Block { plugin, reason } => {
+                        let detector = plugins::DETECTOR;
+                        plugin_reason = format!("Plugin `{}`: {}", plugin, reason);
+                        let snippet = response_content(&body).chars().take(50).collect::<String>() + "...";
+                        let hit = Hit { detector, reason: &plugin_reason, snippet, savings: 0.0 };
+                        if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt(detector), mode(detector), hit).await {
+                            attach_request(&state, log_id, stored_request(&payload)).await;
+                            blocked = Some((detector, plugin_reason.as_str(), log_id));
+                        }
                     }
-                    sess.cumulative_cost += cost;
-                    sess.last_cost = cost;
                 }
             }
-            (status, Json(body)).into_response()
+
+            let request_view = routing::RequestView { headers: &headers, model: &model, body: &payload };
+            let rewritten = (blocked.is_none() && !response_content(&body).is_empty())
+                .then(|| rewrite::apply_response(&state.config.response_rewrite_rules, &request_view, response_content(&body)))
+                .flatten();
+            if let Some((text, changes)) = rewritten {
+                truncate_response_content(&mut body, api, &text);
+                for change in changes {
+                    record_rewrite(&state, &log_ctx, rewrite::DETECTOR, &change).await;
+                }
+            }
+
+            let cost = usage_cost(&state.config.pricing, &model, &body);
+            upstream_headers.extend(hints.response_headers(Some(cost)));
+            let started = std::time::Instant::now();
+            let throttled = book_cost(&state, &session_id, route.budget_pool.as_deref(), tenant.as_deref(), cost, &cost_policy)
+                && mode("cost_spike") != DetectorMode::Off;
+            state.detectors.observe_eval("cost_spike", started.elapsed());
+            events::emit(&state, events::Event::Request(events::RequestEvent { cost_usd: cost, ..request_event }));
+
+            // Already blocked: the cost is booked, but not blocked twice.
+            let enforced = blocked.is_some() && !exempt("cost_spike") && mode("cost_spike") == DetectorMode::Block;
+            if throttled && !enforced {
+                let reason = "Economic Throttling (Cost Spike)";
+                let hit = Hit {
+                    detector: "cost_spike",
+                    reason,
+                    snippet: format!("Cost: ${:.4}", cost),
+                    savings: savings::avoided(&state.config, "cost_spike", &model, None),
+                };
+                if let Some(log_id) = apply_detector(&state, &log_ctx, &mut warnings, exempt("cost_spike"), mode("cost_spike"), hit).await {
+                    attach_request(&state, log_id, stored_request(&payload)).await;
+                    blocked = Some(("cost_spike", reason, log_id));
+                }
+            }
+            match blocked {
+                Some((detector, reason, log_id)) => {
+                    let response = block_response(&state.config, &headers, api, Some(body), log_id, &model, detector, reason);
+                    (upstream_headers, response).into_response()
+                }
+                None => {
+                    let primary = shadow::Side::new(&model, Some(status.as_u16()), latency, cost, &body);
+                    shadow::mirror(&state, tenant.as_deref(), &session_id, api.path(), &payload, primary);
+                    let warned = attach_warnings(&state.config, &headers, Some(&mut body), &warnings);
+                    (status, upstream_headers, warned, truncated, Json(body)).into_response()
+                }
+            }
         }
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Proxy error").into_response(),
+        Err(e) => upstream_error(&e),
     }
 }
 
-// --- MCP HANDLER ---
+/// The leak check of `AnalysisPolicy::background`, run after the client
+/// already has `body`. A hit can't be withheld any more, so in `block` mode
+/// it is recorded and, if configured, the session is blocked from then on.
+async fn background_leak_scan(
+    state: AppState,
+    log_ctx: LogContext,
+    body: serde_json::Value,
+    request: upstream::StoredRequest,
+    exempt: bool,
+    mode: DetectorMode,
+) {
+    let started = std::time::Instant::now();
+    let hit = leak_reason(&state.config, &request.payload, &body);
+    state.detectors.observe_eval("leak", started.elapsed());
+    let Some(reason) = hit else { return };
+    if exempt {
+        record_bypass(&state, &log_ctx, "leak", reason).await;
+    } else if mode != DetectorMode::Block {
+        record_dry_run(&state, &log_ctx, "leak", reason, "[REDACTED SENSITIVE DATA]".to_string()).await;
+    } else {
+        let log_id = record_intervention(
+            &state, &log_ctx, "leak", &format!("{} (after delivery)", reason),
+            "[REDACTED SENSITIVE DATA]".to_string(), 0.0,
+        ).await;
+        attach_request(&state, log_id, request).await;
+        if state.config.analysis.auto_block {
+            tracing::warn!("⛔ Session '{}' auto-blocked after a delivered leak", log_ctx.session_id);
+            state.blocked.insert(log_ctx.session_id.clone(), sessions::BlockEntry {
+                reason: format!("Auto-blocked: {}", reason),
+                blocked_at: now_secs(),
+            });
+        }
+    }
+}
 
-async fn mcp_handler(
-    State(state): State<AppState>,
-    Json(payload): Json<McpRequest>,
-) -> impl IntoResponse {
-    let result = match payload.method.as_str() {
-        "get_sentinel_stats" => {
-            let total = state.total_saved_usd.load(Ordering::Relaxed) as f64 / 100.0;
-            serde_json::json!({
-                "active_sessions": state.sessions.len(),
-                "total_saved_usd": total,
-                "status": "Healthy"
-            })
-        },
-        "audit_session" => {
-            let sid = payload.params["session_id"].as_str().unwrap_or("default");
-            if let Some(sess) = state.sessions.get(sid) {
-                serde_json::json!({
-                    "session_id": sid,
-                    "cumulative_cost": sess.cumulative_cost,
-                    "interventions": sess.interventions,
-                })
-            } else {
-                serde_json::json!({"error": "Session not found"})
+/// Markers of a credential in generated text. System prompts are recognised
+/// by their content instead (`prompt_leak.rs`).
+const LEAK_MARKERS: &[&str] = &["API_KEY="];
+
+const LEAK_REASON: &str = "Sensitive Data Leak (EchoLeak)";
+
+fn leaks_secret(text: &str) -> bool {
+    LEAK_MARKERS.iter().any(|m| text.contains(m))
+}
+
+/// What `body` leaks, if anything: a credential, or a system prompt `payload`
+/// protects.
+fn leak_reason(config: &Config, payload: &serde_json::Value, body: &serde_json::Value) -> Option<&'static str> {
+    let text = response_scan_text(body);
+    if leaks_secret(&text) {
+        Some(LEAK_REASON)
+    } else if prompt_leak::reproduces(&config.prompt_leak, payload, &text) {
+        Some(prompt_leak::REASON)
+    } else {
+        None
+    }
+}
+
+/// Collects the text of the first choice that detectors should look at:
+/// `content` (plain or JSON-mode) plus any tool/function-call arguments, or
+/// `text` for legacy completions.
+fn response_scan_text(body: &serde_json::Value) -> String {
+    let message = &body["choices"][0]["message"];
+    let mut parts: Vec<&str> = Vec::new();
+    parts.extend(body["choices"][0]["text"].as_str());
+    if let Some(content) = message["content"].as_str() {
+        parts.push(content);
+    }
+    if let Some(calls) = message["tool_calls"].as_array() {
+        for call in calls {
+            parts.extend(call["function"]["name"].as_str());
+            parts.extend(call["function"]["arguments"].as_str());
+        }
+    }
+    parts.extend(message["function_call"]["arguments"].as_str());
+    parts.join("\n")
+}
+
+/// The generated text of the first choice, without tool calls.
+fn response_content(body: &serde_json::Value) -> &str {
+    let choice = &body["choices"][0];
+    choice["message"]["content"].as_str().or(choice["text"].as_str()).unwrap_or_default()
+}
+
+/// Replaces the first choice's text (shortened or rewritten) with `text`,
+/// keeping the rest of the message.
+fn truncate_response_content(body: &mut serde_json::Value, api: Api, text: &str) {
+    let choice = &mut body["choices"][0];
+    match api {
+        Api::Chat => choice["message"]["content"] = serde_json::json!(text),
+        Api::Completions => choice["text"] = serde_json::json!(text),
+    }
+    choice["finish_reason"] = serde_json::json!("content_filter");
+}
+
+/// Overwrites the first choice with plain text (an assistant message for
+/// chat), dropping any tool calls so the client doesn't execute a blocked action.
+fn replace_response_message(body: &mut serde_json::Value, api: Api, text: &str) {
+    let choice = &mut body["choices"][0];
+    match api {
+        Api::Chat => choice["message"] = serde_json::json!({ "role": "assistant", "content": text }),
+        Api::Completions => choice["text"] = serde_json::json!(text),
+    }
+    choice["finish_reason"] = serde_json::json!("content_filter");
+}
+
+/// The client's view of an intervention, shaped by the detector's
+/// `BlockPolicy` and worded in the client's locale. `upstream` is the
+/// completion to rewrite, or `None` when the request was stopped before forwarding.
+#[allow(clippy::too_many_arguments)]
+fn block_response(config: &Config, headers: &HeaderMap, api: Api, upstream: Option<serde_json::Value>, log_id: u64, model: &str, detector: &str, reason: &str) -> Response {
+    let behavior = config.block.for_detector(detector);
+    let key = match (upstream.is_some(), behavior.include_reason) {
+        (true, true) => "withheld",
+        (true, false) => "withheld_plain",
+        (false, true) => "blocked",
+        (false, false) => "blocked_plain",
+    };
+    let locale = config.messages.locale_for(headers);
+    let text = config.messages.render(&locale, key, &[("reason", reason), ("detector", detector)]);
+    let status = StatusCode::from_u16(behavior.status).unwrap_or(StatusCode::OK);
+    let headers = intervention_headers(if upstream.is_some() { "redacted" } else { "blocked" }, detector, reason);
+    match behavior.style {
+        BlockStyle::Error => (status, headers, Json(serde_json::json!({
+            "error": {
+                "message": text,
+                "type": "sentinel_blocked",
+                "param": null,
+                "code": detector
             }
-        },
-        _ => serde_json::json!({"error": "Method not found"}),
+        }))).into_response(),
+        BlockStyle::Message => {
+            let body = match upstream {
+                Some(mut body) => {
+                    replace_response_message(&mut body, api, &text);
+                    body
+                }
+                None => blocked_completion(api, log_id, model, &text),
+            };
+            (status, headers, Json(body)).into_response()
+        }
+    }
+}
+
+/// A complete response object for a request that was never forwarded, so
+/// strict SDKs parse it like any other. Usage is zero: nothing was billed.
+fn blocked_completion(api: Api, log_id: u64, model: &str, text: &str) -> serde_json::Value {
+    let (id, object) = match api {
+        Api::Chat => (format!("chatcmpl-sentinel-{}", log_id), "chat.completion"),
+        Api::Completions => (format!("cmpl-sentinel-{}", log_id), "text_completion"),
     };
+    let mut body = serde_json::json!({
+        "id": id,
+        "object": object,
+        "created": now_secs(),
+        "model": model,
+        "choices": [{ "index": 0, "logprobs": null }],
+        "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 },
+    });
+    replace_response_message(&mut body, api, text);
+    body
+}
 
-    Json(serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": payload.id,
-        "result": result
-    }))
+/// Runs the economic throttle for `cost`, books it on the session (and its
+/// budget pool and tenant, if any) and fires any budget alerts. Returns
+/// whether the call should be throttled.
+fn book_cost(state: &AppState, session_id: &str, budget_pool: Option<&str>, tenant: Option<&str>, cost: f64, policy: &CostPolicy) -> bool {
+    let Some(mut sess) = state.sessions.get_mut(session_id) else { return false };
+    let mut throttled = sess.check_economic_throttle(cost, policy);
+    let spent_before = sess.cumulative_cost;
+    sess.record_cost(cost, throttled, policy);
+    let spent_after = sess.cumulative_cost;
+    let budget = sess.budget(policy);
+    drop(sess);
+    state.timeseries.record_cost(now_secs(), cost);
+    state.usage.record_cost(now_secs(), tenant, session_id, cost);
+
+    fire_budget_alerts(state, "session", session_id, spent_before, spent_after, budget);
+
+    if let Some(pool) = budget_pool {
+        let (pool_before, pool_after) = add_spend(&state.pool_spend, pool, cost);
+        if let Some(&budget) = state.config.budget_pools.get(pool) {
+            fire_budget_alerts(state, "pool", pool, pool_before, pool_after, budget);
+            throttled |= pool_after > budget;
+        }
+    }
+    if let Some(tenant) = tenant {
+        let (tenant_before, tenant_after) = add_spend(&state.tenant_spend, tenant, cost);
+        if let Some(budget) = state.config.tenant(tenant).and_then(|t| t.budget_usd) {
+            fire_budget_alerts(state, "tenant", tenant, tenant_before, tenant_after, budget);
+            throttled |= tenant_after > budget;
+        }
+    }
+    throttled
+}
+
+/// Adds `cost` to a shared tally; returns the totals before and after.
+fn add_spend(tally: &DashMap<String, f64>, key: &str, cost: f64) -> (f64, f64) {
+    let mut spend = tally.entry(key.to_string()).or_default();
+    let before = *spend;
+    *spend += cost;
+    (before, *spend)
 }
 
-async fn get_emb_final_v4(client: &Client, api_key: &str, text: &str) -> Result<Vec<f32>, String> {
-    if api_key == "none" || api_key.contains("xxxx") {
-        return Err("No Key".to_string());
+fn fire_budget_alerts(state: &AppState, scope: &'static str, id: &str, before: f64, after: f64, budget: f64) {
+    for level in alerts::crossed_levels(before, after, budget, &state.config.alerts.budget_levels) {
+        state.budget_alerts.fetch_add(1, Ordering::Relaxed);
+        let alert = alerts::BudgetAlert {
+            timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+            scope,
+            id: id.to_string(),
+            level,
+            spent_usd: after,
+            budget_usd: budget,
+        };
+        notify::raise(state, notify::Notice::budget(&alert, &state.config.notify));
+        alerts::dispatch(&state.client, &state.config.alerts, alert);
     }
-    let res = client.post("https://api.openai.com/v1/embeddings")
+}
+
+fn usage_cost(pricing: &pricing::Pricing, model: &str, body: &serde_json::Value) -> f64 {
+    let Some(usage) = body.get("usage") else { return 0.0 };
+    let p = usage.get("prompt_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+    let c = usage.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+    pricing.cost(model, p, c)
+}
+
+fn has_embedding_key(api_key: &str) -> bool {
+    api_key != "none" && !api_key.contains("xxxx")
+}
+
+/// The shared outbound client, with the configured connect and read timeouts.
+fn upstream_client(timeouts: &config::TimeoutPolicy) -> Client {
+    let mut builder = Client::builder();
+    if let Some(connect) = timeouts.connect() {
+        builder = builder.connect_timeout(connect);
+    }
+    if let Some(read) = timeouts.upstream_read() {
+        builder = builder.read_timeout(read);
+    }
+    builder.build().unwrap_or_else(|e| {
+        tracing::error!("Could not build the HTTP client with timeouts, using defaults: {}", e);
+        Client::new()
+    })
+}
+
+/// A failed upstream call: a timeout is a 504 the agent can tell apart from
+/// a policy block, anything else stays a bare proxy error.
+pub(crate) fn upstream_error(e: &reqwest::Error) -> Response {
+    if !e.is_timeout() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Proxy error").into_response();
+    }
+    tracing::warn!("Upstream timed out: {}", e);
+    (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!({
+        "error": {
+            "message": "The upstream provider did not respond in time",
+            "type": "sentinel_timeout",
+            "param": null,
+            "code": "upstream_timeout"
+        }
+    }))).into_response()
+}
+
+/// The upstream answer's JSON body, null when it is not JSON. A body that
+/// times out is counted and answered like an upstream timeout.
+pub(crate) async fn read_body(state: &AppState, provider: &str, model: &str, res: reqwest::Response) -> Result<serde_json::Value, Response> {
+    match res.json().await {
+        Ok(body) => Ok(body),
+        Err(e) if e.is_timeout() => {
+            state.latency.observe_body_timeout(provider, model);
+            Err(upstream_error(&e))
+        }
+        Err(_) => Ok(serde_json::Value::Null),
+    }
+}
+
+/// Sends `payload` to `provider`: the mock answers locally, anything else
+/// goes out through the VCR (`vcr.rs`). Records the call's latency and
+/// outcome, and takes a key the provider answers with 401 out of rotation.
+#[allow(clippy::too_many_arguments)]
+async fn send_upstream(
+    state: &AppState, provider: &str, model: &str, url: &str, api_key: &str, headers: &HeaderMap, api: Api, payload: &serde_json::Value,
+) -> reqwest::Result<reqwest::Response> {
+    let sent_at = std::time::Instant::now();
+    let upstream_span = tracing::info_span!("upstream", provider = %provider, url = %url);
+    let upstream_request = state.client
+        .post(url)
+        .headers(state.config.headers.forward(headers))
         .header("Authorization", format!("Bearer {}", api_key))
-        .json(&serde_json::json!({"input": text, "model": "text-embedding-3-small"}))
-        .send().await.map_err(|e| e.to_string())?;
-    
-    let data: EmbeddingResponse = res.json().await.map_err(|e| e.to_string())?;
-    if let Some(first) = data.data.get(0) {
-        Ok(first.embedding.clone())
+        .headers(telemetry::trace_headers(&upstream_span))
+        .json(payload);
+    let response = if provider == mock::PROVIDER && state.config.mock.enabled {
+        Ok(mock::respond(&state.config.mock, api.path(), payload).await)
     } else {
-        Err("No embedding".to_string())
+        vcr::send(&state.config.vcr, upstream_request, api.path(), payload)
+            .instrument(upstream_span)
+            .await
+    };
+    let outcome = metrics::UpstreamOutcome::of(&response);
+    state.latency.observe_upstream(provider, model, sent_at.elapsed(), outcome);
+    if response.as_ref().is_ok_and(|res| res.status() == StatusCode::UNAUTHORIZED) {
+        keys::rejected(state, provider, api_key);
+    }
+    response
+}
+
+/// Sends a follow-up request (a structured-output re-prompt) the way the
+/// original went; `None` when it fails.
+#[allow(clippy::too_many_arguments)]
+async fn resend(
+    state: &AppState, provider: &str, model: &str, url: &str, api_key: &str, headers: &HeaderMap, api: Api, payload: &serde_json::Value,
+) -> Option<serde_json::Value> {
+    let response = send_upstream(state, provider, model, url, api_key, headers, api, payload).await;
+    match response.and_then(|r| r.error_for_status()) {
+        Ok(response) => response.json().await.ok(),
+        Err(e) => {
+            tracing::warn!("Re-prompt to {} failed: {}", provider, e.without_url());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_first_expensive_call_not_flagged() {
+        let policy = CostPolicy::default();
+        let sess = SessionState::new();
+        assert!(!sess.check_economic_throttle(2.0, &policy));
+    }
+
+    #[test]
+    fn test_cost_spike_against_baseline() {
+        let policy = CostPolicy::default();
+        let mut sess = SessionState::new();
+        for cost in [0.11, 0.12, 0.10, 0.11] {
+            assert!(!sess.check_economic_throttle(cost, &policy));
+            sess.record_cost(cost, false, &policy);
+        }
+        assert!(sess.check_economic_throttle(0.90, &policy));
+    }
+
+    #[test]
+    fn test_scan_text_includes_tool_arguments() {
+        let body = serde_json::json!({
+            "choices": [{ "message": { "content": null, "tool_calls": [
+                { "function": { "name": "write_file", "arguments": "{\"data\":\"API_KEY=sk\"}" } }
+            ]}}]
+        });
+        assert!(response_scan_text(&body).contains("API_KEY="));
+    }
+
+    #[test]
+    fn test_chat_request_accepts_tool_messages() {
+        let raw = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "user", "content": "list files" },
+                { "role": "assistant", "content": null, "refusal": null, "tool_calls": [
+                    { "id": "call_1", "type": "function", "function": { "name": "ls", "arguments": "{\"path\":\"/\"}" } }
+                ]},
+                { "role": "tool", "tool_call_id": "call_1", "name": "ls", "content": "bin etc" }
+            ]
+        });
+        let req: ChatRequest = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(req.messages[1].text(), "ls {\"path\":\"/\"}");
+        assert_eq!(req.messages[2].text(), "bin etc");
+
+        let forwarded = serde_json::to_value(&req).unwrap();
+        assert_eq!(forwarded["messages"][1]["tool_calls"], raw["messages"][1]["tool_calls"]);
+        assert_eq!(forwarded["messages"][1]["refusal"], serde_json::Value::Null);
+        assert!(forwarded["messages"][1].as_object().unwrap().contains_key("refusal"));
+        assert_eq!(forwarded["messages"][2]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn test_multimodal_content_forwarded_untouched() {
+        let raw = serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "what is in" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA", "detail": "low" } },
+                { "type": "text", "text": "this picture?" }
+            ]}]
+        });
+        let req: ChatRequest = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(req.messages[0].text(), "what is in\nthis picture?");
+        assert_eq!(serde_json::to_value(&req).unwrap()["messages"], raw["messages"]);
+    }
+
+    #[test]
+    fn test_replace_message_drops_tool_calls() {
+        let mut body = serde_json::json!({
+            "choices": [{ "message": { "content": null, "tool_calls": [{}] }, "finish_reason": "tool_calls" }]
+        });
+        replace_response_message(&mut body, Api::Chat, "blocked");
+        assert_eq!(body["choices"][0]["message"]["content"], "blocked");
+        assert!(body["choices"][0]["message"].get("tool_calls").is_none());
+        assert_eq!(body["choices"][0]["finish_reason"], "content_filter");
+    }
+
+    #[test]
+    fn test_block_response_follows_detector_policy() {
+        let mut config = Config::default();
+        config.block.detectors.insert("leak".to_string(), config::BlockBehavior {
+            style: BlockStyle::Error, status: 451, include_reason: false,
+        });
+        let headers = HeaderMap::new();
+        let blocked = block_response(&config, &headers, Api::Chat, None, 1, "gpt-4o", "fuzzy_loop", "Fuzzy Overlap");
+        assert_eq!(blocked.status(), StatusCode::OK);
+        assert_eq!(blocked.headers()["x-sentinel-intervention"], "blocked");
+        let withheld = block_response(&config, &headers, Api::Chat, Some(serde_json::json!({})), 2, "gpt-4o", "leak", "Leak");
+        assert_eq!(withheld.status().as_u16(), 451);
+        assert_eq!(withheld.headers()["x-sentinel-intervention"], "redacted");
+        assert_eq!(withheld.headers()["x-sentinel-detector"], "leak");
+    }
+
+    #[test]
+    fn test_blocked_completion_is_well_formed() {
+        let body = blocked_completion(Api::Chat, 7, "gpt-4o", "blocked");
+        assert_eq!(body["id"], "chatcmpl-sentinel-7");
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(body["choices"][0]["finish_reason"], "content_filter");
+        assert_eq!(body["usage"]["total_tokens"], 0);
+        let legacy = blocked_completion(Api::Completions, 7, "gpt-3.5-turbo-instruct", "blocked");
+        assert_eq!(legacy["object"], "text_completion");
+        assert_eq!(legacy["choices"][0]["text"], "blocked");
+    }
+
+    #[test]
+    fn test_adaptive_threshold_tracks_chatty_session() {
+        let policy = LoopPolicy::default();
+        let mut sess = SessionState::new();
+        assert_eq!(sess.effective_threshold(0.8, false, &policy), 0.8);
+        for _ in 0..6 {
+            sess.check_basic_loop("run the test suite again please".to_string(), 0.8, &policy);
+            sess.check_basic_loop("run the test suite again now".to_string(), 0.8, &policy);
+        }
+        let adapted = sess.effective_threshold(0.8, false, &policy);
+        assert!(adapted < 0.8);
+        assert!(adapted >= 0.8 * policy.min_factor);
+    }
+
+    #[test]
+    fn test_loop_feedback_moves_sensitivity() {
+        let policy = LoopPolicy::default();
+        let mut sess = SessionState::new();
+        sess.apply_loop_feedback(true, &policy);
+        assert!(sess.loop_sensitivity < 1.0);
+        sess.apply_loop_feedback(false, &policy);
+        sess.apply_loop_feedback(false, &policy);
+        assert_eq!(sess.loop_sensitivity, 1.0);
+    }
+
+    #[test]
+    fn test_interventions_counted_per_reason() {
+        let mut sess = SessionState::new();
+        sess.record_intervention("fuzzy_loop");
+        sess.record_intervention("fuzzy_loop");
+        sess.record_intervention("leak");
+        assert_eq!(sess.interventions, 3);
+        assert_eq!(sess.interventions_by_reason["fuzzy_loop"], 2);
+        assert_eq!(sess.interventions_by_reason["leak"], 1);
+    }
+
+    #[test]
+    fn test_session_pins_first_upstream() {
+        let mut sess = SessionState::new();
+        assert_eq!(sess.pin(PinMode::Off, "groq", "llama-3", false), ("groq".to_string(), "llama-3".to_string()));
+        assert_eq!(sess.pin(PinMode::Provider, "openai", "gpt-4o", false), ("openai".to_string(), "gpt-4o".to_string()));
+        assert_eq!(sess.pin(PinMode::Provider, "groq", "llama-3", false), ("openai".to_string(), "llama-3".to_string()));
+        assert_eq!(sess.pin(PinMode::Model, "groq", "llama-3", false), ("openai".to_string(), "llama-3".to_string()));
+        assert_eq!(sess.pin(PinMode::Model, "groq", "mixtral", false), ("openai".to_string(), "llama-3".to_string()));
+        assert_eq!(sess.pin(PinMode::Model, "groq", "mixtral", true), ("groq".to_string(), "mixtral".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_background_leak_scan_auto_blocks_session() {
+        let mut config = Config::default();
+        config.analysis = config::AnalysisPolicy { background: true, auto_block: true };
+        let state = AppState::for_tests(config);
+        let body = serde_json::json!({ "choices": [{ "message": { "content": "API_KEY=sk-123" } }] });
+        let request = upstream::StoredRequest {
+            session_id: "agent".to_string(),
+            endpoint: "chat/completions".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            budget_pool: None,
+            tenant: None,
+            payload: serde_json::json!({}),
+        };
+        background_leak_scan(state.clone(), LogContext::new("agent", "gpt-4o"), body, request, false, DetectorMode::Block).await;
+        assert!(state.blocked.contains_key("agent"));
+        assert_eq!(state.audit_logs.lock().await.back().unwrap().detector, "leak");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_body_is_a_gateway_timeout() {
+        use tokio::io::AsyncWriteExt;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 100\r\n\r\n{\"choices\"";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        });
+        let state = AppState::for_tests(Config::default());
+        let client = reqwest::Client::builder().timeout(std::time::Duration::from_millis(300)).build().unwrap();
+        let res = client.get(format!("http://{}/", addr)).send().await.unwrap();
+        let response = read_body(&state, "openai", "gpt-4o", res).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(state.latency.snapshot()[0]["timeouts"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_scrubbed_text_reaches_neither_embedder_nor_audit_log() {
+        let state = AppState::for_tests(Config {
+            rewrite_rules: vec![rewrite::Rule::parse("scrub email").unwrap()],
+            providers: std::collections::HashMap::from([(mock::PROVIDER.to_string(), config::ProviderConfig::new("mock://local/v1", "none"))]),
+            mock: config::MockPolicy { enabled: true, ..Default::default() },
+            ..Default::default()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert("x-sentinel-provider", "mock".parse().unwrap());
+        headers.insert("x-sentinel-session", "scrubbed".parse().unwrap());
+        let prompt = "Mail the report to jane.doe@example.com right now please";
+        for _ in 0..3 {
+            let body = serde_json::json!({ "model": "gpt-4o", "messages": [{ "role": "user", "content": prompt }] });
+            let request = Generation { api: Api::Chat, model: "gpt-4o".to_string(), user: None, prompt: prompt.to_string(), body };
+            proxy_generation(state.clone(), headers.clone(), request).await;
+        }
+
+        let embedded = state.embedder.seen.lock().unwrap().clone();
+        assert_eq!(embedded.len(), 3);
+        assert!(embedded.iter().all(|text| text == "Mail the report to [EMAIL] right now please"));
+        let logs = state.audit_logs.lock().await;
+        assert!(logs.iter().any(|entry| entry.detector == "fuzzy_loop"));
+        for entry in logs.iter() {
+            assert!(!serde_json::to_string(entry).unwrap().contains("jane.doe"), "{:?}", entry);
+        }
+    }
+
+    #[test]
+    fn test_warnings_leave_completion_untouched() {
+        let config = Config::default();
+        let mut body = serde_json::json!({ "choices": [{ "message": { "content": "hi" } }] });
+        assert!(attach_warnings(&config, &HeaderMap::new(), Some(&mut body), &[]).is_empty());
+        let warnings = [("leak", "Sensitive Data Leak".to_string())];
+        let marks = attach_warnings(&config, &HeaderMap::new(), Some(&mut body), &warnings);
+        assert_eq!(marks["x-sentinel-intervention"], "warned");
+        assert_eq!(body["choices"][0]["message"]["content"], "hi");
+        assert_eq!(body["sentinel"]["warnings"][0]["detector"], "leak");
+        assert_eq!(config.detector_mode("leak"), DetectorMode::Block);
+    }
+
+    #[test]
+    fn test_similarity_metrics() {
+        let (a, b) = ([3.0, 4.0], [6.0, 8.0]);
+        assert!((similarity(SimilarityMetric::Cosine, &a, &b).unwrap() - 1.0).abs() < 1e-6);
+        assert_eq!(similarity(SimilarityMetric::Dot, &a, &b).unwrap(), 50.0);
+        assert!((similarity(SimilarityMetric::Euclidean, &a, &b).unwrap() - 1.0 / 6.0).abs() < 1e-6);
+        assert!(similarity(SimilarityMetric::Cosine, &a, &[1.0]).is_err());
+        assert!(similarity(SimilarityMetric::Cosine, &a, &[0.0, 0.0]).is_err());
+    }
+
+    #[test]
+    fn test_loop_check_restarts_on_dimension_change() {
+        let mut sess = SessionState::new();
+        let loops = LoopPolicy { turns: 2, storage: EmbeddingStorage::F32, ..LoopPolicy::default() };
+        // Unnormalized but parallel vectors still count as a loop under cosine.
+        assert_eq!(sess.check_loop(Embedding(vec![1.0, 0.0]), 0.02, &loops), Ok(false));
+        assert_eq!(sess.check_loop(Embedding(vec![5.0, 0.01]), 0.02, &loops), Ok(true));
+        assert!(sess.check_loop(Embedding(vec![1.0, 0.0, 0.0]), 0.02, &loops).is_err());
+        assert_eq!(sess.history.len(), 1);
+    }
+
+    #[test]
+    fn test_pairwise_window_catches_oscillation() {
+        let turns = ["deploy the service to staging now", "roll back the staging deployment", "deploy the service to staging now", "roll back the staging deployment"];
+        let run = |loops: &LoopPolicy| {
+            let mut sess = SessionState::new();
+            turns.iter().map(|t| sess.check_basic_loop(t.to_string(), 0.2, loops)).collect::<Vec<_>>()
+        };
+        let mut loops = LoopPolicy::default();
+        assert_eq!(run(&loops), vec![false; 4]);
+        loops.apply("compare=pairwise").unwrap();
+        assert_eq!(run(&loops), vec![false, false, false, true]);
+        // A two-turn gap halves the match, which no longer clears 0.8.
+        loops.apply("decay=1").unwrap();
+        assert_eq!(run(&loops), vec![false; 4]);
+        assert!(loops.apply("window=3").is_err());
+        // Callers stay within the operator's ceilings.
+        assert!(loops.apply("history=51").is_err());
+        assert!(loops.apply("turns=1").is_err());
+        assert!(loops.apply("decay=1e9").is_err());
+        loops.apply("turns=10 history=50").unwrap();
+        assert_eq!(loops.window(), 50);
+    }
+
+    #[test]
+    fn test_int8_history_is_compact_and_close() {
+        let v: Vec<f32> = (0..1536).map(|i| ((i as f32) * 0.37).sin() / 40.0).collect();
+        let stored = StoredEmbedding::store(Embedding(v.clone()), EmbeddingStorage::Int8);
+        assert_eq!(stored.size_bytes(), 1536);
+        let sim = similarity(SimilarityMetric::Cosine, &v, &stored.to_f32()).unwrap();
+        assert!(sim > 0.999, "{}", sim);
+
+        let mut sess = SessionState::new();
+        let loops = LoopPolicy { history_len: 3, ..LoopPolicy::default() };
+        for _ in 0..6 {
+            let _ = sess.check_loop(Embedding(v.clone()), 0.02, &loops);
+        }
+        assert_eq!(sess.history.len(), 3);
+        assert_eq!(sess.embedding_bytes(), 3 * 1536);
+    }
+
+    #[test]
+    fn test_cost_budget_ceiling() {
+        let policy = CostPolicy::default();
+        let mut sess = SessionState::new();
+        sess.record_cost(10.5, false, &policy);
+        assert!(sess.check_economic_throttle(0.0, &policy));
     }
 }
@@ -0,0 +1,526 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use rand::{TryRngCore, rngs::OsRng};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{AppState, mcp_proxy};
+use crate::audit::{InterventionLog, LogQuery, query_logs};
+use crate::sessions::BlockEntry;
+
+// --- MCP SERVER ---
+// `/mcp` speaks the Model Context Protocol over JSON-RPC 2.0 with the
+// streamable HTTP transport. `POST` carries client requests (answered with
+// plain JSON): the `initialize` handshake, `ping`, `logging/setLevel`,
+// `tools/list` and `tools/call`. `GET` opens an SSE stream on which Sentinel
+// pushes a `notifications/message` for every intervention, and `DELETE` ends
+// the session. The pre-MCP method names (`get_sentinel_stats`,
+// `audit_session`, `reset_session`) still work and return the bare tool result.
+//
+// With `SENTINEL_MCP_TOKEN` set, every call must carry it as a bearer token
+// and the control tools (`reset_session`, `block_session`, `set_budget`)
+// become available. Without it only the read-only tools are offered.
+// Tools of downstream MCP servers are listed and called through here too,
+// and, since they may cost money, likewise only with a token.
+
+pub const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+// JSON-RPC error codes.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// MCP (syslog) log levels, least severe first.
+const LEVELS: &[&str] = &["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"];
+
+/// A client that completed `initialize`.
+#[derive(Debug, Clone)]
+pub struct McpClient {
+    /// Index into `LEVELS` of the least severe notification it wants.
+    pub min_level: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct McpRequest {
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Absent on notifications, which get no reply.
+    id: Option<Value>,
+}
+
+/// Rejects callers without the configured bearer token. The digests are
+/// compared in constant time, so timing reveals neither content nor length.
+fn check_token(state: &AppState, headers: &HeaderMap) -> Result<(), Box<Response>> {
+    let Some(token) = &state.config.mcp.token else { return Ok(()) };
+    let presented = headers.get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if presented.is_some_and(|p| bool::from(Sha256::digest(p).ct_eq(&Sha256::digest(token)))) {
+        Ok(())
+    } else {
+        Err(Box::new((StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "Invalid or missing MCP token").into_response()))
+    }
+}
+
+/// Control tools need an authenticated caller, i.e. a configured token.
+fn control_allowed(state: &AppState) -> bool {
+    state.config.mcp.token.is_some()
+}
+
+/// The session a request belongs to. A stale id is a 404, which tells the
+/// client to initialize again; clients that never got one may omit it.
+fn client_session(state: &AppState, headers: &HeaderMap) -> Result<Option<String>, Box<Response>> {
+    let Some(id) = headers.get(SESSION_HEADER).and_then(|h| h.to_str().ok()) else { return Ok(None) };
+    if state.mcp_clients.contains_key(id) {
+        Ok(Some(id.to_string()))
+    } else {
+        Err(Box::new((StatusCode::NOT_FOUND, "Unknown MCP session").into_response()))
+    }
+}
+
+/// `POST /mcp`
+pub async fn handler(State(state): State<AppState>, headers: HeaderMap, Json(request): Json<McpRequest>) -> Response {
+    if let Err(response) = check_token(&state, &headers) {
+        return *response;
+    }
+    let session = if request.method == "initialize" {
+        None
+    } else {
+        match client_session(&state, &headers) {
+            Ok(session) => session,
+            Err(response) => return *response,
+        }
+    };
+    let Some(id) = request.id else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let mut new_session = None;
+    let outcome = match request.method.as_str() {
+        "initialize" => match new_session_id() {
+            Ok(session) => {
+                state.mcp_clients.insert(session.clone(), McpClient { min_level: level_rank("info") });
+                new_session = Some(session);
+                Ok(initialize(&request.params))
+            }
+            Err(e) => Err((INTERNAL_ERROR, format!("Cannot create a session id: {}", e))),
+        },
+        "ping" => Ok(json!({})),
+        "logging/setLevel" => match (request.params["level"].as_str().and_then(|l| LEVELS.iter().position(|x| *x == l)), session) {
+            (Some(rank), Some(session)) => {
+                if let Some(mut client) = state.mcp_clients.get_mut(&session) {
+                    client.min_level = rank;
+                }
+                Ok(json!({}))
+            }
+            (None, _) => Err((INVALID_PARAMS, "Unknown log level".to_string())),
+            (_, None) => Err((INVALID_PARAMS, "logging/setLevel needs an Mcp-Session-Id".to_string())),
+        },
+        "tools/list" => {
+            let mut tools = tools(control_allowed(&state));
+            if let Some(list) = tools.as_array_mut().filter(|_| control_allowed(&state)) {
+                list.extend(state.mcp_upstreams.list_tools(&state.config.mcp.upstreams).await);
+            }
+            Ok(json!({ "tools": tools }))
+        }
+        "tools/call" => match request.params["name"].as_str() {
+            Some(name) if mcp_proxy::split_name(&state, name).is_some() && !control_allowed(&state) => {
+                Ok(tool_result(json!({ "error": format!("`{}` needs SENTINEL_MCP_TOKEN to be configured", name) })))
+            }
+            Some(name) if mcp_proxy::split_name(&state, name).is_some() => {
+                mcp_proxy::call(&state, &headers, session.as_deref(), name, &request.params["arguments"]).await
+            }
+            Some(name) => call_tool(&state, name, &request.params["arguments"]).await.map(tool_result),
+            None => Err((INVALID_PARAMS, "Missing tool name".to_string())),
+        },
+        "get_sentinel_stats" => call_tool(&state, "stats", &request.params).await,
+        "audit_session" | "reset_session" => call_tool(&state, &request.method, &request.params).await,
+        other => Err((METHOD_NOT_FOUND, format!("Method not found: {}", other))),
+    };
+    let reply = match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    };
+    let mut response = Json(reply).into_response();
+    if let Some(session) = new_session.and_then(|s| HeaderValue::from_str(&s).ok()) {
+        response.headers_mut().insert(SESSION_HEADER, session);
+    }
+    response
+}
+
+/// `GET /mcp`: the server-to-client stream. Each intervention arrives as a
+/// `notifications/message` at `warning` (enforced) or `info` (dry run,
+/// bypass), filtered by the session's `logging/setLevel`.
+pub async fn notifications(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+    use tokio::sync::broadcast::error::RecvError;
+
+    let accepts_sse = headers.get(header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|a| a.contains("text/event-stream"));
+    if !accepts_sse {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    if let Err(response) = check_token(&state, &headers) {
+        return *response;
+    }
+    let session = match client_session(&state, &headers) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    let rx = state.live_logs.subscribe();
+    let events = futures_util::stream::unfold((rx, state, session), |(mut rx, state, session)| async move {
+        loop {
+            let log = match rx.recv().await {
+                Ok(log) => log,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            };
+            let min_level = match &session {
+                // Gone after `DELETE /mcp`: end the stream.
+                Some(id) => state.mcp_clients.get(id)?.min_level,
+                None => level_rank("info"),
+            };
+            let Some(message) = notification(&log, min_level) else { continue };
+            let event = Event::default().event("message").json_data(&message).ok()?;
+            return Some((Ok::<_, std::convert::Infallible>(event), (rx, state, session)));
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// `DELETE /mcp`: the client is done with its session.
+pub async fn end_session(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(response) = check_token(&state, &headers) {
+        return *response;
+    }
+    match client_session(&state, &headers) {
+        Ok(Some(id)) => {
+            state.mcp_clients.remove(&id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id").into_response(),
+        Err(response) => *response,
+    }
+}
+
+/// 128 bits from the operating system's CSPRNG; the id is all a client
+/// needs to read another's notifications.
+fn new_session_id() -> Result<String, rand::rand_core::OsError> {
+    let mut bytes = [0u8; 16];
+    OsRng.try_fill_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn level_rank(level: &str) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap_or(0)
+}
+
+/// The JSON-RPC notification for `log`, unless it is below `min_level`.
+fn notification(log: &InterventionLog, min_level: usize) -> Option<Value> {
+    let level = if log.enforced() { "warning" } else { "info" };
+    (level_rank(level) >= min_level).then(|| json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/message",
+        "params": { "level": level, "logger": "sentinel.interventions", "data": log },
+    }))
+}
+
+/// Agrees on the client's protocol version when we know it, else offers our latest.
+fn initialize(params: &Value) -> Value {
+    let version = params["protocolVersion"].as_str()
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false }, "logging": {} },
+        "serverInfo": { "name": "sentinel", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Sentinel guards LLM traffic against loops, leaks and cost spikes. Use these tools to inspect sessions and interventions, and to stop a runaway session.",
+    })
+}
+
+const CONTROL_TOOLS: &[&str] = &["reset_session", "block_session", "set_budget"];
+
+/// Tool descriptors; the control tools only for an authorized caller.
+fn tools(control: bool) -> Value {
+    let session_id = json!({ "type": "string", "description": "Session id (the x-sentinel-session header or request user)." });
+    let detector = json!({ "type": "string", "description": "Detector key, e.g. semantic_loop, leak, cost_spike." });
+    let all = json!([
+        {
+            "name": "stats",
+            "description": "Overall Sentinel status: active sessions, blocked sessions and estimated savings.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "audit_session",
+            "description": "Cost, budget and intervention counts for one session.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id }, "required": ["session_id"] },
+        },
+        {
+            "name": "get_logs",
+            "description": "Most recent interventions, newest last, optionally filtered.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": session_id,
+                    "detector": detector,
+                    "limit": { "type": "integer", "minimum": 1, "maximum": 200, "default": 20 },
+                },
+            },
+        },
+        {
+            "name": "list_interventions",
+            "description": "Search the full intervention history. Pass `next_cursor` back as `before` for older entries.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": session_id,
+                    "detector": detector,
+                    "query": { "type": "string", "description": "Case-insensitive text to find in the content snippet." },
+                    "since": { "type": "integer", "description": "Only entries at or after this Unix time (seconds)." },
+                    "before": { "type": "integer", "description": "Only entries with a lower id (pagination cursor)." },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": 200, "default": 50 },
+                },
+            },
+        },
+        {
+            "name": "block_session",
+            "description": "Reject all further requests from a session until an operator unblocks it.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": session_id,
+                    "reason": { "type": "string", "description": "Shown to the blocked client." },
+                },
+                "required": ["session_id"],
+            },
+        },
+        {
+            "name": "reset_session",
+            "description": "Forget a session's loop history and cost counters.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id }, "required": ["session_id"] },
+        },
+        {
+            "name": "set_budget",
+            "description": "Override a session's spending budget in USD; null restores the configured budget.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "session_id": session_id,
+                    "budget_usd": { "type": ["number", "null"], "minimum": 0 },
+                },
+                "required": ["session_id", "budget_usd"],
+            },
+        },
+    ]);
+    let visible = all.as_array().into_iter().flatten()
+        .filter(|t| control || !t["name"].as_str().is_some_and(|n| CONTROL_TOOLS.contains(&n)))
+        .cloned()
+        .collect();
+    Value::Array(visible)
+}
+
+/// Wraps a tool's JSON output as MCP content; errors are reported in-band.
+fn tool_result(output: Value) -> Value {
+    let is_error = output.get("error").is_some();
+    json!({
+        "content": [{ "type": "text", "text": output.to_string() }],
+        "structuredContent": output,
+        "isError": is_error,
+    })
+}
+
+async fn call_tool(state: &AppState, name: &str, args: &Value) -> Result<Value, (i64, String)> {
+    let session_id = || args["session_id"].as_str().ok_or((INVALID_PARAMS, "Missing session_id".to_string()));
+    if CONTROL_TOOLS.contains(&name) && !control_allowed(state) {
+        return Ok(json!({ "error": format!("`{}` needs SENTINEL_MCP_TOKEN to be configured", name) }));
+    }
+    Ok(match name {
+        "stats" => json!({
+            "active_sessions": state.sessions.len(),
+            "blocked_sessions": state.blocked.len(),
+            "total_saved_usd": state.total_saved_usd(),
+            "status": if state.startup_problems.is_empty() { "Healthy" } else { "Degraded" },
+        }),
+        "audit_session" => {
+            let sid = session_id()?;
+            match state.sessions.get(sid) {
+                Some(sess) => json!({
+                    "session_id": sid,
+                    "cumulative_cost": sess.cumulative_cost,
+                    "interventions": sess.interventions,
+                    "interventions_by_reason": sess.interventions_by_reason,
+                    "budget_usd": sess.budget(&state.config.cost),
+                    "blocked": state.blocked.contains_key(sid),
+                }),
+                None => json!({ "error": "Session not found" }),
+            }
+        }
+        "block_session" => {
+            let sid = session_id()?;
+            let reason = args["reason"].as_str().unwrap_or("Blocked via MCP").to_string();
+            tracing::warn!("⛔ Session '{}' blocked via MCP: {}", sid, reason);
+            let entry = BlockEntry { reason, blocked_at: crate::now_secs() };
+            state.blocked.insert(sid.to_string(), entry.clone());
+            json!({ "session_id": sid, "blocked": entry })
+        }
+        "reset_session" => {
+            let sid = session_id()?;
+            match state.sessions.remove(sid) {
+                Some(_) => json!({ "session_id": sid, "reset": true }),
+                None => json!({ "error": "Session not found" }),
+            }
+        }
+        "get_logs" => {
+            let query = LogQuery {
+                session_id: args["session_id"].as_str().map(str::to_string),
+                detector: args["detector"].as_str().map(str::to_string),
+                limit: Some(args["limit"].as_u64().unwrap_or(20).clamp(1, 200) as usize),
+                ..LogQuery::default()
+            };
+            // The hot cache; the tool is for a quick look, not an export.
+            let logs = state.audit_logs.lock().await;
+            let (total, page) = query_logs(logs.iter(), &query);
+            json!({ "total": total, "logs": page })
+        }
+        "list_interventions" => {
+            let query = LogQuery {
+                session_id: args["session_id"].as_str().map(str::to_string),
+                detector: args["detector"].as_str().map(str::to_string),
+                q: args["query"].as_str().map(str::to_string),
+                from: args["since"].as_u64(),
+                before: args["before"].as_u64(),
+                limit: Some(args["limit"].as_u64().unwrap_or(50).clamp(1, 200) as usize),
+                ..LogQuery::default()
+            };
+            let history = crate::audit_history(state).await;
+            let (total, page) = query_logs(history.iter(), &query);
+            let next_cursor = (total > page.len()).then(|| page.first().map(|l| l.id)).flatten();
+            json!({ "total": total, "interventions": page, "next_cursor": next_cursor })
+        }
+        "set_budget" => {
+            let sid = session_id()?;
+            let budget = match &args["budget_usd"] {
+                Value::Null => None,
+                v => Some(v.as_f64().filter(|b| *b >= 0.0).ok_or((INVALID_PARAMS, "budget_usd must be a non-negative number or null".to_string()))?),
+            };
+            let mut sess = state.sessions.entry(sid.to_string()).or_default();
+            sess.budget_usd = budget;
+            tracing::info!("Session '{}' budget set via MCP: {:?}", sid, budget);
+            json!({ "session_id": sid, "budget_usd": budget, "spent_usd": sess.cumulative_cost })
+        }
+        other => return Err((INVALID_PARAMS, format!("Unknown tool: {}", other))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn rpc(state: &AppState, body: Value) -> (StatusCode, Value) {
+        let request = serde_json::from_value(body).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer ops-token"));
+        let response = handler(State(state.clone()), headers, Json(request)).await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_handshake_and_tool_calls() {
+        let mut config = Config::default();
+        config.mcp.token = Some("ops-token".to_string());
+        let state = AppState::for_tests(config);
+        let (_, init) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "protocolVersion": "2025-03-26" } })).await;
+        assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+        assert!(init["result"]["capabilities"]["tools"].is_object());
+
+        let (status, _) = rpc(&state, json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let (_, list) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" })).await;
+        let names: Vec<&str> = list["result"]["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert!(["stats", "audit_session", "block_session", "get_logs", "set_budget", "list_interventions"].iter().all(|n| names.contains(n)));
+
+        let (_, call) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": { "name": "block_session", "arguments": { "session_id": "agent-7" } } })).await;
+        assert_eq!(call["result"]["isError"], false);
+        assert!(state.blocked.contains_key("agent-7"));
+
+        let (_, budget) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 6, "method": "tools/call", "params": { "name": "set_budget", "arguments": { "session_id": "agent-7", "budget_usd": 2.5 } } })).await;
+        assert_eq!(budget["result"]["structuredContent"]["budget_usd"], 2.5);
+        assert_eq!(state.sessions.get("agent-7").unwrap().budget(&state.config.cost), 2.5);
+
+        let (_, bad) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": { "name": "block_session", "arguments": {} } })).await;
+        assert_eq!(bad["error"]["code"], INVALID_PARAMS);
+        let (_, unknown) = rpc(&state, json!({ "jsonrpc": "2.0", "id": 5, "method": "resources/list" })).await;
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+
+        let request = serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 7, "method": "ping" })).unwrap();
+        let anonymous = handler(State(state.clone()), HeaderMap::new(), Json(request)).await;
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer ops-tokem"));
+        let request = serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 8, "method": "ping" })).unwrap();
+        assert_eq!(handler(State(state.clone()), headers, Json(request)).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_control_tools_need_a_token() {
+        let listed = |control| tools(control).as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert!(!listed(false).iter().any(|n| CONTROL_TOOLS.contains(&n.as_str())));
+        assert!(CONTROL_TOOLS.iter().all(|n| listed(true).iter().any(|l| l == n)));
+    }
+
+    #[tokio::test]
+    async fn test_proxied_tools_need_a_token() {
+        let mut config = Config::default();
+        // Unroutable: the call must be refused before anything is sent.
+        let upstream = crate::config::McpUpstream { url: "http://127.0.0.1:9/mcp".to_string(), token: None, cost_per_call_usd: 1.0 };
+        config.mcp.upstreams.insert("paid".to_string(), upstream);
+        let state = AppState::for_tests(config);
+        let request = serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "name": "paid__search", "arguments": {} } })).unwrap();
+        let response = handler(State(state.clone()), HeaderMap::new(), Json(request)).await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let reply: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(reply["result"]["isError"], true);
+        assert!(reply["result"]["structuredContent"]["error"].as_str().unwrap().contains("SENTINEL_MCP_TOKEN"));
+        assert!(state.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_and_notification_levels() {
+        let state = AppState::for_tests(Config::default());
+        let request = serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} })).unwrap();
+        let response = handler(State(state.clone()), HeaderMap::new(), Json(request)).await;
+        let session = response.headers()[SESSION_HEADER].clone();
+        assert!(state.mcp_clients.contains_key(session.to_str().unwrap()));
+        assert_eq!(session.len(), 32);
+        assert_ne!(new_session_id().unwrap(), new_session_id().unwrap());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(SESSION_HEADER, HeaderValue::from_static("stale"));
+        assert_eq!(end_session(State(state.clone()), headers).await.status(), StatusCode::NOT_FOUND);
+        let mut headers = HeaderMap::new();
+        headers.insert(SESSION_HEADER, session);
+        assert_eq!(end_session(State(state.clone()), headers).await.status(), StatusCode::NO_CONTENT);
+        assert!(state.mcp_clients.is_empty());
+
+        let log: InterventionLog = serde_json::from_value(json!({
+            "id": 1, "timestamp": 0, "session_id": "s", "reason": "Dry run: loop", "detector": "fuzzy_loop",
+            "content_snippet": "", "savings_est": 0.0, "model": "gpt-4o", "dry_run": true,
+        })).unwrap();
+        assert_eq!(notification(&log, level_rank("info")).unwrap()["params"]["level"], "info");
+        assert!(notification(&log, level_rank("warning")).is_none());
+    }
+}
@@ -0,0 +1,285 @@
+use axum::http::{HeaderMap, header};
+use dashmap::DashMap;
+use reqwest::Client;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::AppState;
+use crate::audit::{AUDIT_TARGET, LogContext, record_bypass, record_dry_run, record_intervention};
+use crate::config::{DetectorMode, McpUpstream};
+use crate::events::{Event, RequestEvent};
+
+// --- MCP PROXY ---
+// Sentinel can front other MCP servers. Their tools are listed on `/mcp` as
+// `<upstream>__<tool>` and every call to one goes through the same checks as
+// a completion before and after it is forwarded:
+//   - `tool_loop`: the same tool with the same arguments `tool_loop_repeats`
+//     times within the session's last `tool_loop_window` calls;
+//   - `cost_spike`: the session's budget is already spent (calls cost the
+//     upstream's configured `_COST`);
+//   - `leak`: secrets in the tool's result.
+// Each call also gets an audit line. The session is `x-sentinel-session`, else
+// the MCP session id.
+
+const SEPARATOR: &str = "__";
+
+/// Our MCP client sessions with the downstream servers.
+pub struct McpUpstreams {
+    client: Client,
+    /// Upstream name -> its `Mcp-Session-Id` (empty when it issues none).
+    sessions: DashMap<String, String>,
+    next_id: AtomicU64,
+}
+
+impl McpUpstreams {
+    pub fn new(client: Client) -> Self {
+        Self { client, sessions: DashMap::new(), next_id: AtomicU64::new(1) }
+    }
+
+    /// Downstream tools under their prefixed names. Unreachable servers are
+    /// skipped so one bad upstream doesn't hide the rest.
+    pub async fn list_tools(&self, upstreams: &HashMap<String, McpUpstream>) -> Vec<Value> {
+        let mut names: Vec<&String> = upstreams.keys().collect();
+        names.sort();
+        let mut tools = Vec::new();
+        for name in names {
+            match self.request(name, &upstreams[name], "tools/list", json!({})).await {
+                Ok(result) => tools.extend(result["tools"].as_array().into_iter().flatten().map(|tool| {
+                    let mut tool = tool.clone();
+                    tool["name"] = json!(format!("{}{}{}", name, SEPARATOR, tool["name"].as_str().unwrap_or_default()));
+                    tool
+                })),
+                Err(e) => tracing::warn!("MCP upstream '{}' did not list its tools: {}", name, e),
+            }
+        }
+        tools
+    }
+
+    /// One JSON-RPC call, opening (or reopening, after a 404) the session first.
+    async fn request(&self, name: &str, upstream: &McpUpstream, method: &str, params: Value) -> Result<Value, String> {
+        let mut retried = false;
+        loop {
+            let session = self.session(name, upstream).await?;
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            match self.post(upstream, Some(&session), &body).await {
+                Err(PostError::SessionExpired) if !retried => {
+                    self.sessions.remove(name);
+                    retried = true;
+                }
+                Err(PostError::SessionExpired) => return Err("session expired".to_string()),
+                Err(PostError::Other(e)) => return Err(e),
+                Ok((_, None)) => return Err("no reply".to_string()),
+                Ok((_, Some(reply))) => {
+                    if let Some(error) = reply.get("error") {
+                        return Err(error["message"].as_str().unwrap_or("error").to_string());
+                    }
+                    return Ok(reply["result"].clone());
+                }
+            }
+        }
+    }
+
+    async fn session(&self, name: &str, upstream: &McpUpstream) -> Result<String, String> {
+        if let Some(session) = self.sessions.get(name) {
+            return Ok(session.clone());
+        }
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": crate::mcp::PROTOCOL_VERSIONS[0],
+                "capabilities": {},
+                "clientInfo": { "name": "sentinel", "version": env!("CARGO_PKG_VERSION") },
+            },
+        });
+        let (session, _) = self.post(upstream, None, &initialize).await.map_err(PostError::into_message)?;
+        let session = session.unwrap_or_default();
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        self.post(upstream, Some(&session), &initialized).await.map_err(PostError::into_message)?;
+        self.sessions.insert(name.to_string(), session.clone());
+        Ok(session)
+    }
+
+    /// POSTs a message; the reply may come back as JSON or as an SSE stream.
+    async fn post(&self, upstream: &McpUpstream, session: Option<&str>, body: &Value) -> Result<(Option<String>, Option<Value>), PostError> {
+        let mut request = self.client.post(&upstream.url)
+            .header(header::ACCEPT, "application/json, text/event-stream")
+            .json(body);
+        if let Some(token) = &upstream.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(session) = session.filter(|s| !s.is_empty()) {
+            request = request.header("mcp-session-id", session);
+        }
+        let res = request.send().await.map_err(|e| PostError::Other(e.to_string()))?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND && session.is_some_and(|s| !s.is_empty()) {
+            return Err(PostError::SessionExpired);
+        }
+        if !res.status().is_success() {
+            return Err(PostError::Other(format!("HTTP {}", res.status())));
+        }
+        let session = res.headers().get("mcp-session-id").and_then(|h| h.to_str().ok()).map(str::to_string);
+        let sse = res.headers().get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|c| c.starts_with("text/event-stream"));
+        let text = res.text().await.map_err(|e| PostError::Other(e.to_string()))?;
+        let reply = if sse {
+            text.lines()
+                .filter_map(|l| l.strip_prefix("data:"))
+                .filter_map(|d| serde_json::from_str::<Value>(d.trim()).ok())
+                .find(|m| m["id"] == body["id"])
+        } else if text.trim().is_empty() {
+            None
+        } else {
+            Some(serde_json::from_str(&text).map_err(|e| PostError::Other(e.to_string()))?)
+        };
+        Ok((session, reply))
+    }
+}
+
+enum PostError {
+    SessionExpired,
+    Other(String),
+}
+
+impl PostError {
+    fn into_message(self) -> String {
+        match self {
+            PostError::SessionExpired => "session expired".to_string(),
+            PostError::Other(e) => e,
+        }
+    }
+}
+
+/// The upstream and its own tool name, if `name` is a proxied tool.
+pub fn split_name<'a>(state: &'a AppState, name: &'a str) -> Option<(&'a str, &'a McpUpstream, &'a str)> {
+    let (upstream, tool) = name.split_once(SEPARATOR)?;
+    let config = state.config.mcp.upstreams.get(upstream)?;
+    Some((upstream, config, tool))
+}
+
+/// Same tool, same arguments (object keys are compared sorted).
+fn fingerprint(tool: &str, args: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tool.hash(&mut hasher);
+    args.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// An MCP tool result telling the agent Sentinel stopped the call.
+fn refused(text: String) -> Value {
+    json!({ "content": [{ "type": "text", "text": text }], "isError": true })
+}
+
+/// Runs a proxied `tools/call` through the detectors and forwards it.
+/// Returns the MCP tool result; interventions come back as `isError` results.
+pub async fn call(state: &AppState, headers: &HeaderMap, mcp_session: Option<&str>, name: &str, args: &Value) -> Result<Value, (i64, String)> {
+    let Some((upstream_name, upstream, tool)) = split_name(state, name) else {
+        return Err((-32602, format!("Unknown tool: {}", name)));
+    };
+    let session_id = crate::session_id(headers, mcp_session);
+    let model = format!("mcp:{}", upstream_name);
+    let log_ctx = LogContext::new(&session_id, &model).provider(upstream_name);
+    let locale = state.config.messages.locale_for(headers);
+    let client_key = crate::client_api_key(headers);
+    let exempt = |detector: &str| state.config.is_exempt(&session_id, client_key, detector);
+    let mode = |detector: &str| state.config.detector_mode(detector);
+    let snippet = format!("{} {}", tool, args).chars().take(50).collect::<String>() + "...";
+
+    if let Some(block) = state.blocked.get(&session_id).map(|b| b.reason.clone()) {
+        record_intervention(state, &log_ctx, "kill_switch", "Session Blocked by Operator", snippet, 0.0).await;
+        return Ok(refused(state.config.messages.render(&locale, "session_blocked", &[("reason", &block), ("session", &session_id)])));
+    }
+
+    let policy = &state.config.mcp;
+    let (repeats, over_budget, budget) = {
+        let mut sess = state.sessions.entry(session_id.clone()).or_default();
+        sess.touch();
+        let repeats = sess.record_tool_call(fingerprint(name, args), policy.tool_loop_window);
+        let budget = sess.budget(&state.config.cost);
+        (repeats, sess.cumulative_cost > budget, budget)
+    };
+    let hit = if repeats >= policy.tool_loop_repeats && mode("tool_loop") != DetectorMode::Off {
+        Some(("tool_loop", format!("Tool Loop Detected ({} identical `{}` calls)", repeats, name)))
+    } else if over_budget && mode("cost_spike") != DetectorMode::Off {
+        Some(("cost_spike", format!("Session Budget Exhausted (${:.2})", budget)))
+    } else {
+        None
+    };
+    if let Some((detector, reason)) = hit {
+        if exempt(detector) {
+            record_bypass(state, &log_ctx, detector, &reason).await;
+        } else if mode(detector) != DetectorMode::Block {
+            record_dry_run(state, &log_ctx, detector, &reason, snippet.clone()).await;
+        } else {
+            record_intervention(state, &log_ctx, detector, &reason, snippet, upstream.cost_per_call_usd).await;
+            return Ok(refused(state.config.messages.render(&locale, "blocked", &[("reason", &reason), ("detector", detector)])));
+        }
+    }
+
+    let outcome = state.mcp_upstreams.request(upstream_name, upstream, "tools/call", json!({ "name": tool, "arguments": args })).await;
+    tracing::info!(
+        target: AUDIT_TARGET,
+        session_id = %session_id,
+        upstream = upstream_name,
+        tool,
+        ok = outcome.is_ok(),
+        cost_usd = upstream.cost_per_call_usd,
+        "mcp_tool_call"
+    );
+    crate::events::emit(state, Event::Request(RequestEvent {
+        status: if outcome.is_ok() { 200 } else { 502 },
+        cost_usd: if outcome.is_ok() { upstream.cost_per_call_usd } else { 0.0 },
+        ..RequestEvent::new(&session_id, None, upstream_name, "tools/call", tool)
+    }));
+    let mut result = outcome.map_err(|e| (-32603, format!("MCP upstream '{}': {}", upstream_name, e)))?;
+    if upstream.cost_per_call_usd > 0.0 {
+        crate::book_cost(state, &session_id, None, None, upstream.cost_per_call_usd, &state.config.cost);
+    }
+
+    let text: Vec<&str> = result["content"].as_array().into_iter().flatten().filter_map(|c| c["text"].as_str()).collect();
+    let leaked = mode("leak") != DetectorMode::Off
+        && (crate::leaks_secret(&text.join("\n")) || crate::leaks_secret(&result["structuredContent"].to_string()));
+    let reason = crate::LEAK_REASON;
+    if leaked && exempt("leak") {
+        record_bypass(state, &log_ctx, "leak", reason).await;
+    } else if leaked && mode("leak") != DetectorMode::Block {
+        record_dry_run(state, &log_ctx, "leak", reason, "[REDACTED SENSITIVE DATA]".to_string()).await;
+    } else if leaked {
+        record_intervention(state, &log_ctx, "leak", reason, "[REDACTED SENSITIVE DATA]".to_string(), 0.0).await;
+        result = refused(state.config.messages.render(&locale, "withheld", &[("reason", reason), ("detector", "leak")]));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_repeated_identical_calls_are_refused() {
+        let mut config = Config::default();
+        config.mcp.upstreams.insert("fs".to_string(), McpUpstream {
+            // Never reached: the third call is refused before forwarding.
+            url: "http://127.0.0.1:9/mcp".to_string(),
+            token: None,
+            cost_per_call_usd: 0.0,
+        });
+        let state = AppState::for_tests(config);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-sentinel-session", "agent-1".parse().unwrap());
+        let args = json!({ "path": "/tmp/a" });
+        for _ in 0..2 {
+            assert!(call(&state, &headers, None, "fs__read_file", &args).await.is_err());
+        }
+        let refused = call(&state, &headers, None, "fs__read_file", &args).await.unwrap();
+        assert_eq!(refused["isError"], true);
+        assert!(split_name(&state, "other__read_file").is_none());
+        assert_eq!(fingerprint("t", &json!({ "b": 1, "a": 2 })), fingerprint("t", &json!({ "a": 2, "b": 1 })));
+    }
+}
@@ -0,0 +1,144 @@
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+
+// --- INTERVENTION MESSAGES ---
+// Text shown to clients when Sentinel steps in, per locale. Built-in `en` and
+// `es` catalogs can be extended or overridden with `<locale>.json` files in
+// `SENTINEL_MESSAGES_DIR` (a flat `{ "key": "template" }` object). Templates
+// take `{reason}`, `{detector}`, `{session}` and `{budget}` placeholders.
+//
+// The locale comes from `x-sentinel-locale`, then `Accept-Language`, then
+// `SENTINEL_LOCALE` (default `en`).
+
+const EN: &[(&str, &str)] = &[
+    ("blocked", "Sentinel blocked this request: {reason}"),
+    ("blocked_plain", "Sentinel blocked this request."),
+    ("withheld", "Sentinel withheld this response: {reason}"),
+    ("withheld_plain", "Sentinel withheld this response."),
+    ("warned", "Sentinel flagged this exchange: {reason}"),
+    ("session_blocked", "Sentinel: this session has been blocked by an operator ({reason})"),
+    ("budget_exhausted", "Sentinel: this session has exhausted its ${budget} budget"),
+    ("too_large", "Sentinel rejected this request as too large: {reason}"),
+    ("request_budget", "Sentinel: this request cannot fit its ${budget} cost ceiling"),
+];
+
+const ES: &[(&str, &str)] = &[
+    ("blocked", "Sentinel bloqueó esta solicitud: {reason}"),
+    ("blocked_plain", "Sentinel bloqueó esta solicitud."),
+    ("withheld", "Sentinel retuvo esta respuesta: {reason}"),
+    ("withheld_plain", "Sentinel retuvo esta respuesta."),
+    ("warned", "Sentinel marcó este intercambio: {reason}"),
+    ("session_blocked", "Sentinel: un operador bloqueó esta sesión ({reason})"),
+    ("budget_exhausted", "Sentinel: esta sesión agotó su presupuesto de ${budget}"),
+    ("too_large", "Sentinel rechazó esta solicitud por ser demasiado grande: {reason}"),
+    ("request_budget", "Sentinel: esta solicitud no cabe en su límite de costo de ${budget}"),
+];
+
+type Catalog = HashMap<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct Messages {
+    /// Keyed by lowercase locale tag (`en`, `pt-br`).
+    catalogs: HashMap<String, Catalog>,
+    default_locale: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        let catalog = |entries: &[(&str, &str)]| entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Self {
+            catalogs: HashMap::from([("en".to_string(), catalog(EN)), ("es".to_string(), catalog(ES))]),
+            default_locale: "en".to_string(),
+        }
+    }
+}
+
+impl Messages {
+    pub fn from_env() -> Self {
+        let mut messages = Self::default();
+        if let Ok(locale) = crate::config::var("SENTINEL_LOCALE") {
+            messages.default_locale = locale.trim().to_ascii_lowercase();
+        }
+        let Ok(dir) = crate::config::var("SENTINEL_MESSAGES_DIR") else { return messages };
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::error!("Cannot read message catalogs in {}: {}", dir, e);
+                return messages;
+            }
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") { continue; }
+            let Some(locale) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|src| serde_json::from_str::<Catalog>(&src).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(catalog) => messages.catalogs.entry(locale.to_ascii_lowercase()).or_default().extend(catalog),
+                Err(e) => tracing::error!("Ignoring message catalog {}: {}", path.display(), e),
+            }
+        }
+        messages
+    }
+
+    /// The best locale we have a catalog for, from the request headers.
+    pub fn locale_for(&self, headers: &HeaderMap) -> String {
+        let header = |name: &str| headers.get(name).and_then(|h| h.to_str().ok()).unwrap_or_default();
+        let mut wanted: Vec<(f32, String)> = header("accept-language").split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim().to_ascii_lowercase();
+                let q = parts.find_map(|p| p.trim().strip_prefix("q=")?.parse().ok()).unwrap_or(1.0);
+                (!tag.is_empty() && tag != "*" && q > 0.0).then_some((q, tag))
+            })
+            .collect();
+        // Stable sort keeps header order among equal weights.
+        wanted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let explicit = header("x-sentinel-locale").trim().to_ascii_lowercase();
+
+        std::iter::once(explicit).chain(wanted.into_iter().map(|(_, tag)| tag))
+            .filter(|tag| !tag.is_empty())
+            .find_map(|tag| {
+                let primary = tag.split('-').next().unwrap_or_default().to_string();
+                [tag, primary].into_iter().find(|t| self.catalogs.contains_key(t))
+            })
+            .unwrap_or_else(|| self.default_locale.clone())
+    }
+
+    /// Fills in `key` for `locale`, falling back to the default locale and
+    /// then the built-in English text.
+    pub fn render(&self, locale: &str, key: &str, vars: &[(&str, &str)]) -> String {
+        let template = [locale, self.default_locale.as_str(), "en"].iter()
+            .find_map(|l| self.catalogs.get(*l)?.get(key))
+            .map_or(key, String::as_str);
+        vars.iter().fold(template.to_string(), |text, (name, value)| text.replace(&format!("{{{}}}", name), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_negotiation() {
+        let messages = Messages::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(messages.locale_for(&headers), "en");
+        headers.insert("accept-language", "fr-CA, es-MX;q=0.8, en;q=0.5".parse().unwrap());
+        assert_eq!(messages.locale_for(&headers), "es");
+        headers.insert("x-sentinel-locale", "EN".parse().unwrap());
+        assert_eq!(messages.locale_for(&headers), "en");
+    }
+
+    #[test]
+    fn test_render_fills_placeholders_and_falls_back() {
+        let mut messages = Messages::default();
+        messages.catalogs.insert("de".to_string(), Catalog::from([("blocked".to_string(), "Blockiert: {reason}".to_string())]));
+        assert_eq!(messages.render("de", "blocked", &[("reason", "Schleife")]), "Blockiert: Schleife");
+        assert_eq!(messages.render("de", "blocked_plain", &[]), "Sentinel blocked this request.");
+        assert_eq!(
+            messages.render("es", "budget_exhausted", &[("budget", "5.00")]),
+            "Sentinel: esta sesión agotó su presupuesto de $5.00"
+        );
+    }
+}
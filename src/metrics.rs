@@ -0,0 +1,369 @@
+use dashmap::DashMap;
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// --- LATENCY / THROUGHPUT METRICS ---
+// Keyed by (provider, model). TTFT is only observable on streamed responses;
+// throughput is measured on every response that reports completion tokens.
+// Upstream latency is time to response headers and is recorded for every
+// attempt, failed ones included, so it pairs with the error counters.
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Histogram {
+    /// Per-bucket (not cumulative) counts; slower samples only show in `count`.
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub sum_secs: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.sum_secs += secs;
+        self.count += 1;
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum_secs * 1000.0 / self.count as f64)
+    }
+
+    /// Upper bound of the bucket holding quantile `q`, capped at the last bucket.
+    pub fn quantile_ms(&self, q: f64) -> Option<f64> {
+        if self.count == 0 { return None; }
+        let target = ((q * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bound, n) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            seen += n;
+            if seen >= target { return Some(bound * 1000.0); }
+        }
+        LATENCY_BUCKETS.last().map(|b| b * 1000.0)
+    }
+
+    /// Prometheus histogram lines. `labels` is empty or `k="v",...` without braces.
+    pub fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, n) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            cumulative += n;
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum_secs);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelLatency {
+    pub upstream: Histogram,
+    /// Upstream answered with a non-2xx status.
+    pub http_errors: u64,
+    /// Upstream could not be reached at all.
+    pub transport_errors: u64,
+    /// Upstream took longer than `SENTINEL_UPSTREAM_TIMEOUT_SECS` to send
+    /// anything or to finish the body.
+    pub timeouts: u64,
+    pub requests: u64,
+    pub streamed: u64,
+    /// Time spent inside Sentinel before the request was forwarded.
+    pub overhead_ms_sum: f64,
+    pub ttft_ms_sum: f64,
+    pub ttft_samples: u64,
+    pub completion_tokens: u64,
+    pub generation_secs: f64,
+}
+
+impl ModelLatency {
+    pub fn avg_overhead_ms(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.overhead_ms_sum / self.requests as f64)
+    }
+
+    pub fn avg_ttft_ms(&self) -> Option<f64> {
+        (self.ttft_samples > 0).then(|| self.ttft_ms_sum / self.ttft_samples as f64)
+    }
+
+    pub fn tokens_per_sec(&self) -> Option<f64> {
+        (self.generation_secs > 0.0).then(|| self.completion_tokens as f64 / self.generation_secs)
+    }
+
+    pub fn error_rate(&self) -> Option<f64> {
+        (self.upstream.count > 0).then(|| (self.http_errors + self.transport_errors + self.timeouts) as f64 / self.upstream.count as f64)
+    }
+}
+
+/// Outcome of one upstream attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamOutcome {
+    Success,
+    HttpError,
+    TransportError,
+    Timeout,
+}
+
+impl UpstreamOutcome {
+    pub fn of(response: &Result<reqwest::Response, reqwest::Error>) -> Self {
+        match response {
+            Ok(res) if res.status().is_success() => Self::Success,
+            Ok(_) => Self::HttpError,
+            Err(e) if e.is_timeout() => Self::Timeout,
+            Err(_) => Self::TransportError,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyMetrics {
+    by_model: DashMap<(String, String), ModelLatency>,
+    /// The embedding call made before forwarding, for loop detection.
+    embedding: std::sync::Mutex<Histogram>,
+    embedding_errors: AtomicU64,
+    embedding_timeouts: AtomicU64,
+}
+
+impl LatencyMetrics {
+    /// Records how long the upstream took to answer (or fail).
+    pub fn observe_upstream(&self, provider: &str, model: &str, latency: Duration, outcome: UpstreamOutcome) {
+        let mut entry = self.by_model.entry((provider.to_string(), model.to_string())).or_default();
+        entry.upstream.observe(latency);
+        match outcome {
+            UpstreamOutcome::Success => {}
+            UpstreamOutcome::HttpError => entry.http_errors += 1,
+            UpstreamOutcome::TransportError => entry.transport_errors += 1,
+            UpstreamOutcome::Timeout => entry.timeouts += 1,
+        }
+    }
+
+    /// An answer whose body timed out after it was recorded as a success.
+    pub fn observe_body_timeout(&self, provider: &str, model: &str) {
+        self.by_model.entry((provider.to_string(), model.to_string())).or_default().timeouts += 1;
+    }
+
+    /// Timeouts count as errors too.
+    pub fn observe_embedding(&self, latency: Duration, ok: bool, timed_out: bool) {
+        self.embedding.lock().unwrap().observe(latency);
+        if !ok {
+            self.embedding_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if timed_out {
+            self.embedding_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn embedding_snapshot(&self) -> serde_json::Value {
+        let h = self.embedding.lock().unwrap();
+        serde_json::json!({
+            "calls": h.count,
+            "errors": self.embedding_errors.load(Ordering::Relaxed),
+            "timeouts": self.embedding_timeouts.load(Ordering::Relaxed),
+            "avg_ms": h.mean_ms(),
+            "p95_ms": h.quantile_ms(0.95),
+        })
+    }
+
+    /// Records one completed response. `ttft` is `None` for non-streamed calls.
+    pub fn observe(
+        &self,
+        provider: &str,
+        model: &str,
+        ttft: Option<std::time::Duration>,
+        completion_tokens: u64,
+        generation: std::time::Duration,
+        overhead: std::time::Duration,
+    ) {
+        let mut entry = self.by_model.entry((provider.to_string(), model.to_string())).or_default();
+        entry.requests += 1;
+        entry.overhead_ms_sum += overhead.as_secs_f64() * 1000.0;
+        if let Some(ttft) = ttft {
+            entry.streamed += 1;
+            entry.ttft_ms_sum += ttft.as_secs_f64() * 1000.0;
+            entry.ttft_samples += 1;
+        }
+        if completion_tokens > 0 {
+            entry.completion_tokens += completion_tokens;
+            entry.generation_secs += generation.as_secs_f64();
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<serde_json::Value> {
+        self.by_model.iter().map(|e| {
+            let (provider, model) = e.key();
+            serde_json::json!({
+                "provider": provider,
+                "model": model,
+                "requests": e.requests,
+                "streamed": e.streamed,
+                "upstream_attempts": e.upstream.count,
+                "avg_upstream_ms": e.upstream.mean_ms(),
+                "p95_upstream_ms": e.upstream.quantile_ms(0.95),
+                "http_errors": e.http_errors,
+                "transport_errors": e.transport_errors,
+                "timeouts": e.timeouts,
+                "error_rate": e.error_rate(),
+                "avg_overhead_ms": e.avg_overhead_ms(),
+                "avg_ttft_ms": e.avg_ttft_ms(),
+                "tokens_per_sec": e.tokens_per_sec(),
+            })
+        }).collect()
+    }
+
+    pub fn render_prometheus(&self, out: &mut String) {
+        out.push_str("# TYPE sentinel_upstream_requests_total counter\n");
+        out.push_str("# TYPE sentinel_overhead_seconds summary\n");
+        out.push_str("# TYPE sentinel_ttft_seconds summary\n");
+        out.push_str("# TYPE sentinel_completion_tokens_total counter\n");
+        out.push_str("# TYPE sentinel_generation_seconds_total counter\n");
+        out.push_str("# TYPE sentinel_upstream_latency_seconds histogram\n");
+        out.push_str("# TYPE sentinel_upstream_errors_total counter\n");
+        for e in self.by_model.iter() {
+            let (provider, model) = e.key();
+            let labels = format!("provider=\"{}\",model=\"{}\"", escape_label(provider), escape_label(model));
+            let _ = writeln!(out, "sentinel_upstream_requests_total{{{}}} {}", labels, e.requests);
+            let _ = writeln!(out, "sentinel_overhead_seconds_sum{{{}}} {}", labels, e.overhead_ms_sum / 1000.0);
+            let _ = writeln!(out, "sentinel_overhead_seconds_count{{{}}} {}", labels, e.requests);
+            let _ = writeln!(out, "sentinel_ttft_seconds_sum{{{}}} {}", labels, e.ttft_ms_sum / 1000.0);
+            let _ = writeln!(out, "sentinel_ttft_seconds_count{{{}}} {}", labels, e.ttft_samples);
+            let _ = writeln!(out, "sentinel_completion_tokens_total{{{}}} {}", labels, e.completion_tokens);
+            let _ = writeln!(out, "sentinel_generation_seconds_total{{{}}} {}", labels, e.generation_secs);
+            e.upstream.render(out, "sentinel_upstream_latency_seconds", &labels);
+            let _ = writeln!(out, "sentinel_upstream_errors_total{{{},kind=\"http\"}} {}", labels, e.http_errors);
+            let _ = writeln!(out, "sentinel_upstream_errors_total{{{},kind=\"transport\"}} {}", labels, e.transport_errors);
+            let _ = writeln!(out, "sentinel_upstream_errors_total{{{},kind=\"timeout\"}} {}", labels, e.timeouts);
+        }
+        out.push_str("# TYPE sentinel_embedding_latency_seconds histogram\n");
+        self.embedding.lock().unwrap().render(out, "sentinel_embedding_latency_seconds", "");
+        let _ = writeln!(out, "# TYPE sentinel_embedding_errors_total counter\nsentinel_embedding_errors_total {}", self.embedding_errors.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# TYPE sentinel_embedding_timeouts_total counter\nsentinel_embedding_timeouts_total {}", self.embedding_timeouts.load(Ordering::Relaxed));
+    }
+}
+
+// --- DETECTOR METRICS ---
+// How long each detector takes to evaluate and how often it fires (enforced,
+// exempted or dry-run alike). Evaluations are far below the latency buckets,
+// so only sum / count / max are kept.
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DetectorStats {
+    pub evaluations: u64,
+    pub triggers: u64,
+    pub eval_secs_sum: f64,
+    pub eval_secs_max: f64,
+}
+
+impl DetectorStats {
+    pub fn avg_eval_us(&self) -> Option<f64> {
+        (self.evaluations > 0).then(|| self.eval_secs_sum * 1e6 / self.evaluations as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DetectorMetrics {
+    by_detector: DashMap<String, DetectorStats>,
+}
+
+impl DetectorMetrics {
+    pub fn observe_eval(&self, detector: &str, took: Duration) {
+        let mut entry = self.by_detector.entry(detector.to_string()).or_default();
+        let secs = took.as_secs_f64();
+        entry.evaluations += 1;
+        entry.eval_secs_sum += secs;
+        entry.eval_secs_max = entry.eval_secs_max.max(secs);
+    }
+
+    pub fn record_trigger(&self, detector: &str) {
+        self.by_detector.entry(detector.to_string()).or_default().triggers += 1;
+    }
+
+    pub fn snapshot(&self) -> std::collections::BTreeMap<String, DetectorStats> {
+        self.by_detector.iter().map(|e| (e.key().clone(), e.value().clone())).collect()
+    }
+
+    pub fn render_prometheus(&self, out: &mut String) {
+        out.push_str("# TYPE sentinel_detector_eval_seconds summary
+");
+        out.push_str("# TYPE sentinel_detector_eval_seconds_max gauge
+");
+        out.push_str("# TYPE sentinel_detector_triggers_total counter
+");
+        for (detector, s) in self.snapshot() {
+            let labels = format!("detector=\"{}\"", escape_label(&detector));
+            let _ = writeln!(out, "sentinel_detector_eval_seconds_sum{{{}}} {}", labels, s.eval_secs_sum);
+            let _ = writeln!(out, "sentinel_detector_eval_seconds_count{{{}}} {}", labels, s.evaluations);
+            let _ = writeln!(out, "sentinel_detector_eval_seconds_max{{{}}} {}", labels, s.eval_secs_max);
+            let _ = writeln!(out, "sentinel_detector_triggers_total{{{}}} {}", labels, s.triggers);
+        }
+    }
+}
+
+pub fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_streamed_and_plain() {
+        let m = LatencyMetrics::default();
+        m.observe("openai", "gpt-4o", Some(Duration::from_millis(200)), 100, Duration::from_secs(2), Duration::from_millis(4));
+        m.observe("openai", "gpt-4o", None, 0, Duration::from_secs(1), Duration::from_millis(2));
+        let e = m.by_model.get(&("openai".to_string(), "gpt-4o".to_string())).unwrap();
+        assert_eq!(e.requests, 2);
+        assert_eq!(e.avg_ttft_ms(), Some(200.0));
+        assert_eq!(e.tokens_per_sec(), Some(50.0));
+        assert_eq!(e.avg_overhead_ms(), Some(3.0));
+    }
+
+    #[test]
+    fn test_upstream_histogram_and_error_rate() {
+        let m = LatencyMetrics::default();
+        m.observe_upstream("groq", "llama", Duration::from_millis(40), UpstreamOutcome::Success);
+        m.observe_upstream("groq", "llama", Duration::from_millis(80), UpstreamOutcome::Success);
+        m.observe_upstream("groq", "llama", Duration::from_millis(900), UpstreamOutcome::HttpError);
+        m.observe_upstream("groq", "llama", Duration::from_secs(60), UpstreamOutcome::TransportError);
+        let e = m.by_model.get(&("groq".to_string(), "llama".to_string())).unwrap();
+        assert_eq!(e.error_rate(), Some(0.5));
+        assert_eq!(e.upstream.quantile_ms(0.5), Some(100.0));
+        assert_eq!(e.upstream.quantile_ms(0.99), Some(30_000.0));
+
+        let mut out = String::new();
+        e.upstream.render(&mut out, "lat", "provider=\"groq\"");
+        assert!(out.contains("lat_bucket{provider=\"groq\",le=\"0.05\"} 1\n"));
+        assert!(out.contains("lat_bucket{provider=\"groq\",le=\"+Inf\"} 4\n"));
+    }
+
+    #[test]
+    fn test_upstream_timeouts_count_as_errors() {
+        let m = LatencyMetrics::default();
+        m.observe_upstream("groq", "llama", Duration::from_millis(40), UpstreamOutcome::Success);
+        m.observe_upstream("groq", "llama", Duration::from_secs(300), UpstreamOutcome::Timeout);
+        let e = m.by_model.get(&("groq".to_string(), "llama".to_string())).unwrap();
+        assert_eq!((e.timeouts, e.error_rate()), (1, Some(0.5)));
+    }
+
+    #[test]
+    fn test_detector_stats() {
+        let m = DetectorMetrics::default();
+        m.observe_eval("fuzzy_loop", Duration::from_micros(100));
+        m.observe_eval("fuzzy_loop", Duration::from_micros(300));
+        m.record_trigger("fuzzy_loop");
+        m.record_trigger("kill_switch");
+        let stats = m.snapshot();
+        assert_eq!(stats["fuzzy_loop"].evaluations, 2);
+        assert_eq!(stats["fuzzy_loop"].triggers, 1);
+        assert!((stats["fuzzy_loop"].avg_eval_us().unwrap() - 200.0).abs() < 1e-6);
+        assert_eq!(stats["kill_switch"].avg_eval_us(), None);
+
+        let mut out = String::new();
+        m.render_prometheus(&mut out);
+        assert!(out.contains("sentinel_detector_triggers_total{detector=\"fuzzy_loop\"} 1\n"));
+    }
+}
@@ -0,0 +1,183 @@
+use axum::http::{HeaderValue, StatusCode, header::CONTENT_TYPE};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::config::MockPolicy;
+use crate::savings::estimate_prompt_tokens;
+
+// --- MOCK PROVIDER ---
+// With `SENTINEL_MOCK_ENABLED=true`, `provider mock` in a routing rule (or
+// `x-sentinel-provider: mock`) answers requests locally, so the detectors,
+// dashboard and MCP tools can be shown and tested without any API key; off,
+// there is no `mock` provider to route to. Chat and completions replies
+// come from `SENTINEL_MOCK_REPLIES="*refund*=Refunds take 5 days;hello*=Hi!"`:
+// the first pattern matching the last user message picks the template, else
+// `SENTINEL_MOCK_REPLY`. Templates take `{model}` and `{prompt}`. Usage is
+// estimated like the savings are (about four characters per token), so
+// costs, budgets and the cost-spike detector behave as with a real model.
+// Streams arrive as one content chunk plus a usage chunk.
+//
+// Embeddings are a deterministic hash of the input, so identical text
+// embeds identically; moderations flag nothing. `SENTINEL_MOCK_LATENCY_MS`
+// delays every reply. Anything else answers 404.
+
+pub const PROVIDER: &str = "mock";
+
+/// Dimensions of mock embeddings.
+const DIMENSIONS: usize = 64;
+
+/// Characters of the prompt put into a `{prompt}` reply.
+const MAX_ECHO_CHARS: usize = 200;
+
+/// The reply text for the latest user turn of `body`.
+fn reply(policy: &MockPolicy, model: &str, body: &Value) -> String {
+    let prompt = last_prompt(body);
+    let template = policy.replies.iter()
+        .find(|(pattern, _)| crate::routing::glob_match(pattern, &prompt))
+        .map_or(policy.default_reply.as_str(), |(_, reply)| reply.as_str());
+    template
+        .replace("{model}", model)
+        .replace("{prompt}", &prompt.chars().take(MAX_ECHO_CHARS).collect::<String>())
+}
+
+fn last_prompt(body: &Value) -> String {
+    if let Some(prompt) = body["prompt"].as_str() {
+        return prompt.to_string();
+    }
+    let messages = body["messages"].as_array().map_or(&[][..], Vec::as_slice);
+    let Some(message) = messages.iter().rev().find(|m| m["role"] == "user") else { return String::new() };
+    match &message["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|p| p["text"].as_str()).collect::<Vec<_>>().join(" "),
+        _ => String::new(),
+    }
+}
+
+fn usage(body: &Value, text: &str) -> Value {
+    let prompt_tokens = estimate_prompt_tokens(body);
+    let completion_tokens = text.chars().count().div_ceil(4) as u64;
+    json!({ "prompt_tokens": prompt_tokens, "completion_tokens": completion_tokens, "total_tokens": prompt_tokens + completion_tokens })
+}
+
+/// Unit-length vector derived from `text`.
+fn embedding(text: &str) -> Vec<f32> {
+    let mut values = Vec::with_capacity(DIMENSIONS);
+    let mut block = 0u32;
+    while values.len() < DIMENSIONS {
+        let digest = Sha256::new().chain_update(block.to_le_bytes()).chain_update(text.as_bytes()).finalize();
+        values.extend(digest.iter().map(|b| *b as f32 / 127.5 - 1.0));
+        block += 1;
+    }
+    values.truncate(DIMENSIONS);
+    let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt().max(f32::EPSILON);
+    values.iter().map(|v| v / norm).collect()
+}
+
+/// The body the mock answers `path` with, and whether it is an event stream.
+fn answer(policy: &MockPolicy, path: &str, body: &Value) -> (StatusCode, Value, bool) {
+    let model = body["model"].as_str().unwrap_or("mock");
+    let created = crate::now_secs();
+    match path {
+        "chat/completions" | "completions" => {
+            let text = reply(policy, model, body);
+            let usage = usage(body, &text);
+            let chat = path == "chat/completions";
+            let choice = if chat {
+                json!({ "index": 0, "message": { "role": "assistant", "content": text }, "finish_reason": "stop" })
+            } else {
+                json!({ "index": 0, "text": text, "finish_reason": "stop" })
+            };
+            let object = if chat { "chat.completion" } else { "text_completion" };
+            let response = json!({ "id": format!("mock-{}", created), "object": object, "created": created, "model": model, "choices": [choice], "usage": usage });
+            (StatusCode::OK, response, body["stream"].as_bool().unwrap_or(false))
+        }
+        "embeddings" => {
+            let inputs: Vec<String> = match &body["input"] {
+                Value::String(text) => vec![text.clone()],
+                Value::Array(items) => items.iter().map(|i| i.as_str().map_or_else(|| i.to_string(), str::to_string)).collect(),
+                other => vec![other.to_string()],
+            };
+            let tokens: u64 = inputs.iter().map(|i| i.chars().count().div_ceil(4) as u64).sum();
+            let data: Vec<Value> = inputs.iter().enumerate()
+                .map(|(index, input)| json!({ "object": "embedding", "index": index, "embedding": embedding(input) }))
+                .collect();
+            (StatusCode::OK, json!({ "object": "list", "model": model, "data": data, "usage": { "prompt_tokens": tokens, "total_tokens": tokens } }), false)
+        }
+        "moderations" => {
+            let count = body["input"].as_array().map_or(1, Vec::len);
+            let results = vec![json!({ "flagged": false, "categories": {}, "category_scores": {} }); count];
+            (StatusCode::OK, json!({ "id": format!("modr-mock-{}", created), "model": model, "results": results }), false)
+        }
+        other => (StatusCode::NOT_FOUND, json!({
+            "error": { "message": format!("The mock provider does not implement `{}`", other), "type": "invalid_request_error", "param": null, "code": "not_found" }
+        }), false),
+    }
+}
+
+/// One content chunk, one usage chunk and `[DONE]`, as an upstream stream.
+fn stream(response: &Value) -> String {
+    let choice = &response["choices"][0];
+    let delta = match choice["message"]["content"].as_str() {
+        Some(text) => json!({ "index": 0, "delta": { "role": "assistant", "content": text }, "finish_reason": "stop" }),
+        None => json!({ "index": 0, "text": choice["text"], "finish_reason": "stop" }),
+    };
+    let head = json!({ "id": response["id"], "object": "chat.completion.chunk", "created": response["created"], "model": response["model"] });
+    let mut content = head.clone();
+    content["choices"] = json!([delta]);
+    let mut usage = head;
+    usage["choices"] = json!([]);
+    usage["usage"] = response["usage"].clone();
+    format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\n", content, usage)
+}
+
+/// Answers a request for `path` (e.g. `chat/completions`) locally.
+pub async fn respond(policy: &MockPolicy, path: &str, body: &Value) -> reqwest::Response {
+    if policy.latency_ms > 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(policy.latency_ms)).await;
+    }
+    let (status, response, streamed) = answer(policy, path.trim_start_matches('/'), body);
+    let (text, content_type) = if streamed {
+        (stream(&response), "text/event-stream")
+    } else {
+        (response.to_string(), "application/json")
+    };
+    let mut reply = axum::http::Response::new(text);
+    *reply.status_mut() = status;
+    reply.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    reqwest::Response::from(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templated_replies_with_usage() {
+        let policy = MockPolicy {
+            replies: vec![("*refund*".to_string(), "Refunds take 5 days ({model})".to_string())],
+            ..MockPolicy::default()
+        };
+        let chat = json!({"model": "gpt-4o", "messages": [
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "Where is my refund?"},
+        ]});
+        let (status, body, streamed) = answer(&policy, "chat/completions", &chat);
+        assert_eq!((status, streamed), (StatusCode::OK, false));
+        assert_eq!(body["choices"][0]["message"]["content"], "Refunds take 5 days (gpt-4o)");
+        assert_eq!(body["usage"]["completion_tokens"], 7);
+        assert_eq!(body["usage"]["prompt_tokens"], estimate_prompt_tokens(&chat));
+
+        let other = json!({"model": "m", "prompt": "hello", "stream": true});
+        let (_, body, streamed) = answer(&policy, "completions", &other);
+        assert!(streamed);
+        assert_eq!(body["choices"][0]["text"], "Mock reply from m to: hello");
+        assert!(stream(&body).ends_with("data: [DONE]\n\n"));
+
+        let (_, body, _) = answer(&policy, "embeddings", &json!({"model": "e", "input": ["a", "a", "b"]}));
+        let vectors: Vec<&Value> = body["data"].as_array().unwrap().iter().map(|d| &d["embedding"]).collect();
+        assert_eq!(vectors[0].as_array().unwrap().len(), DIMENSIONS);
+        assert_eq!(vectors[0], vectors[1]);
+        assert_ne!(vectors[0], vectors[2]);
+        assert_eq!(answer(&policy, "images/generations", &json!({})).0, StatusCode::NOT_FOUND);
+    }
+}
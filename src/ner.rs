@@ -0,0 +1,422 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::AppState;
+use crate::config::NerPolicy;
+
+// --- NAMED ENTITIES ---
+// Patterns catch e-mail addresses and card numbers (`scrub` rewrite rules),
+// not "Maria Keller from Northwind, 12 Elm Street". Built with `--features
+// ner`, Sentinel runs a local token-classification model (ONNX, e.g. a BERT
+// NER export) over the text of every chat message or prompt before it is
+// forwarded. `SENTINEL_NER_MODEL` is the `.onnx` file; the tokenizer
+// (`tokenizer.json`) and the labels (`id2label` of `config.json`) are read
+// from the same directory unless `SENTINEL_NER_TOKENIZER` /
+// `SENTINEL_NER_LABELS` say otherwise. Entities scoring below
+// `SENTINEL_NER_MIN_SCORE` (0.7) are ignored.
+//
+// Labels map to `person` (PER), `location` (LOC, GPE, ADDR...),
+// `organization` (ORG) and `misc`, and each kind has an action:
+// `allow`, `flag` (audit-logged as the `ner` detector, nothing else) or
+// `redact` (replaced with `[PERSON]`, `[LOCATION]`, ... and logged as a
+// rewrite). `SENTINEL_NER_ACTIONS="person=redact,location=redact"` sets
+// them (default: flag all but `misc`); a tenant's `ner.<kind>=<action>`
+// overrides them. The model runs locally and before anything else reads
+// the prompt, so redacted entities never reach the detectors, plugins, the
+// embeddings API, the audit log or the provider; flagged ones do. The model
+// never blocks a request: it fails open.
+
+pub const DETECTOR: &str = "ner";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EntityKind {
+    Person,
+    Location,
+    Organization,
+    Misc,
+}
+
+impl EntityKind {
+    pub const ALL: [EntityKind; 4] = [EntityKind::Person, EntityKind::Location, EntityKind::Organization, EntityKind::Misc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EntityKind::Person => "person",
+            EntityKind::Location => "location",
+            EntityKind::Organization => "organization",
+            EntityKind::Misc => "misc",
+        }
+    }
+
+    fn placeholder(self) -> &'static str {
+        match self {
+            EntityKind::Person => "[PERSON]",
+            EntityKind::Location => "[LOCATION]",
+            EntityKind::Organization => "[ORGANIZATION]",
+            EntityKind::Misc => "[ENTITY]",
+        }
+    }
+
+    /// The kind of a model label (`B-PER`, `I-ORG`, `LOC`, ...); `None` for `O`.
+    #[cfg_attr(not(feature = "ner"), allow(dead_code))]
+    fn from_label(label: &str) -> Option<Self> {
+        let tag = label.split_once('-').map_or(label, |(_, tag)| tag).to_ascii_uppercase();
+        match tag.as_str() {
+            "O" | "" => None,
+            "PER" | "PERSON" | "NAME" => Some(EntityKind::Person),
+            "LOC" | "LOCATION" | "GPE" | "ADDR" | "ADDRESS" | "FAC" => Some(EntityKind::Location),
+            "ORG" | "ORGANIZATION" => Some(EntityKind::Organization),
+            _ => Some(EntityKind::Misc),
+        }
+    }
+}
+
+impl FromStr for EntityKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EntityKind::ALL.into_iter()
+            .find(|k| k.name() == s.to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown entity kind `{}` (person, location, organization, misc)", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EntityAction {
+    #[default]
+    Allow,
+    Flag,
+    Redact,
+}
+
+impl FromStr for EntityAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(EntityAction::Allow),
+            "flag" => Ok(EntityAction::Flag),
+            "redact" => Ok(EntityAction::Redact),
+            other => Err(format!("unknown entity action `{}` (allow, flag, redact)", other)),
+        }
+    }
+}
+
+/// A recognised span of `text`, by byte offsets.
+#[cfg_attr(not(feature = "ner"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub start: usize,
+    pub end: usize,
+    pub score: f32,
+}
+
+/// One classified token: its byte span, label and probability.
+#[cfg_attr(not(feature = "ner"), allow(dead_code))]
+struct Token<'a> {
+    start: usize,
+    end: usize,
+    label: &'a str,
+    score: f32,
+}
+
+/// Merges BIO-tagged tokens into entities averaging at least `min_score`.
+#[cfg_attr(not(feature = "ner"), allow(dead_code))]
+fn group(tokens: &[Token], min_score: f32) -> Vec<Entity> {
+    let mut entities: Vec<(Entity, usize)> = Vec::new();
+    let mut open = false;
+    for token in tokens {
+        let Some(kind) = EntityKind::from_label(token.label) else {
+            open = false;
+            continue;
+        };
+        let begins = token.label.starts_with("B-");
+        match entities.last_mut() {
+            Some((entity, n)) if open && !begins && entity.kind == kind => {
+                entity.end = token.end;
+                entity.score += token.score;
+                *n += 1;
+            }
+            _ => entities.push((Entity { kind, start: token.start, end: token.end, score: token.score }, 1)),
+        }
+        open = true;
+    }
+    entities.into_iter()
+        .map(|(entity, n)| Entity { score: entity.score / n as f32, ..entity })
+        .filter(|e| e.score >= min_score)
+        .collect()
+}
+
+/// `text` with the entities whose action is `redact` replaced, and the
+/// entities found by kind and action.
+fn apply(text: &str, entities: &[Entity], action: impl Fn(EntityKind) -> EntityAction) -> (String, Vec<(EntityKind, EntityAction)>) {
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut found = Vec::new();
+    for entity in entities {
+        let action = action(entity.kind);
+        if action == EntityAction::Allow {
+            continue;
+        }
+        found.push((entity.kind, action));
+        if action == EntityAction::Redact && entity.start >= copied {
+            out.push_str(&text[copied..entity.start]);
+            out.push_str(entity.kind.placeholder());
+            copied = entity.end;
+        }
+    }
+    out.push_str(&text[copied..]);
+    (out, found)
+}
+
+/// `3 person, 1 location` for the audit log.
+fn summary(found: &[(EntityKind, EntityAction)], action: EntityAction) -> Option<String> {
+    let mut counts: BTreeMap<EntityKind, usize> = BTreeMap::new();
+    for (kind, _) in found.iter().filter(|(_, a)| *a == action) {
+        *counts.entry(*kind).or_default() += 1;
+    }
+    let parts: Vec<String> = counts.iter().map(|(kind, n)| format!("{} {}", n, kind.name())).collect();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+#[derive(Default)]
+pub struct Recognizer {
+    #[cfg(feature = "ner")]
+    model: Option<onnx::Model>,
+}
+
+impl Recognizer {
+    #[cfg(feature = "ner")]
+    pub fn load(policy: &NerPolicy) -> Self {
+        let Some(path) = &policy.model else { return Self::default() };
+        match onnx::Model::load(path, policy) {
+            Ok(model) => {
+                tracing::info!("Loaded NER model {} ({} labels)", path, model.labels.len());
+                Self { model: Some(model) }
+            }
+            Err(e) => {
+                tracing::error!("Cannot load NER model {}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    #[cfg(not(feature = "ner"))]
+    pub fn load(policy: &NerPolicy) -> Self {
+        if let Some(path) = &policy.model {
+            tracing::warn!("SENTINEL_NER_MODEL={} ignored: built without the `ner` feature", path);
+        }
+        Self::default()
+    }
+
+    #[cfg(feature = "ner")]
+    fn enabled(&self) -> bool {
+        self.model.is_some()
+    }
+
+    #[cfg(not(feature = "ner"))]
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "ner")]
+    fn entities(&self, text: &str, min_score: f32) -> Vec<Entity> {
+        let Some(model) = &self.model else { return Vec::new() };
+        model.entities(text, min_score).unwrap_or_else(|e| {
+            tracing::warn!("NER skipped: {}", e);
+            Vec::new()
+        })
+    }
+
+    #[cfg(not(feature = "ner"))]
+    fn entities(&self, _text: &str, _min_score: f32) -> Vec<Entity> {
+        Vec::new()
+    }
+}
+
+/// What a scan found: summaries of flagged and redacted entities.
+#[derive(Debug, Default)]
+pub struct Findings {
+    pub flagged: Option<String>,
+    pub redacted: Option<String>,
+}
+
+/// Runs the model over the text of `payload`'s messages (or prompt),
+/// redacting in place as the tenant's actions say.
+pub async fn scan(state: &AppState, tenant: Option<&str>, payload: &mut Value) -> Findings {
+    let recognizer = state.ner.clone();
+    if !recognizer.enabled() {
+        return Findings::default();
+    }
+    let config = state.config.clone();
+    let tenant = tenant.map(str::to_string);
+    let body = payload.clone();
+    let scanned = tokio::task::spawn_blocking(move || {
+        let mut body = body;
+        let action = |kind| config.ner_action(tenant.as_deref(), kind);
+        let mut found = Vec::new();
+        let mut visit = |value: &mut Value| {
+            let Some(text) = value.as_str() else { return };
+            let entities = recognizer.entities(text, config.ner.min_score);
+            let (redacted, hits) = apply(text, &entities, action);
+            if hits.iter().any(|(_, a)| *a == EntityAction::Redact) {
+                *value = Value::String(redacted);
+            }
+            found.extend(hits);
+        };
+        if let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) {
+            for content in messages.iter_mut().filter_map(|m| m.get_mut("content")) {
+                match content {
+                    Value::Array(parts) => parts.iter_mut().filter_map(|p| p.get_mut("text")).for_each(&mut visit),
+                    content => visit(content),
+                }
+            }
+        }
+        match body.get_mut("prompt") {
+            Some(Value::Array(prompts)) => prompts.iter_mut().for_each(&mut visit),
+            Some(prompt) => visit(prompt),
+            None => {}
+        }
+        (body, found)
+    }).await;
+    let Ok((body, found)) = scanned else { return Findings::default() };
+    let findings = Findings {
+        flagged: summary(&found, EntityAction::Flag),
+        redacted: summary(&found, EntityAction::Redact),
+    };
+    if findings.redacted.is_some() {
+        *payload = body;
+    }
+    findings
+}
+
+#[cfg(feature = "ner")]
+mod onnx {
+    use ort::session::Session;
+    use ort::value::Tensor;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use tokenizers::Tokenizer;
+
+    use super::{Entity, Token, group};
+    use crate::config::NerPolicy;
+
+    /// Tokens per model call; longer texts are classified in windows.
+    const WINDOW: usize = 510;
+
+    pub struct Model {
+        session: Mutex<Session>,
+        tokenizer: Tokenizer,
+        pub labels: Vec<String>,
+        token_types: bool,
+    }
+
+    impl Model {
+        pub fn load(path: &str, policy: &NerPolicy) -> Result<Self, String> {
+            let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+            let tokenizer_path = policy.tokenizer.clone().unwrap_or_else(|| dir.join("tokenizer.json").display().to_string());
+            let tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| format!("{}: {}", tokenizer_path, e))?;
+            let labels = if policy.labels.is_empty() { labels_from_config(dir)? } else { policy.labels.clone() };
+            let session = Session::builder()
+                .and_then(|b| b.commit_from_file(path))
+                .map_err(|e| e.to_string())?;
+            let token_types = session.inputs.iter().any(|i| i.name == "token_type_ids");
+            Ok(Self { session: Mutex::new(session), tokenizer, labels, token_types })
+        }
+
+        pub fn entities(&self, text: &str, min_score: f32) -> Result<Vec<Entity>, String> {
+            // With `[CLS]` / `[SEP]` (or the model's equivalents), as the model was trained.
+            let encoding = self.tokenizer.encode(text, true).map_err(|e| e.to_string())?;
+            let (ids, offsets, special) = (encoding.get_ids(), encoding.get_offsets(), encoding.get_special_tokens_mask());
+            let mut tokens = Vec::with_capacity(ids.len());
+            for ((window, spans), special) in ids.chunks(WINDOW).zip(offsets.chunks(WINDOW)).zip(special.chunks(WINDOW)) {
+                let scores = self.classify(window)?;
+                for ((&(start, end), (label, score)), _) in spans.iter().zip(scores).zip(special).filter(|(_, s)| **s == 0) {
+                    tokens.push(Token { start, end, label: &self.labels[label], score });
+                }
+            }
+            Ok(group(&tokens, min_score))
+        }
+
+        /// The best label index and its probability for each token of `ids`.
+        fn classify(&self, ids: &[u32]) -> Result<Vec<(usize, f32)>, String> {
+            let n = ids.len();
+            let tensor = |values: Vec<i64>| Tensor::from_array(([1usize, n], values.into_boxed_slice())).map_err(|e| e.to_string());
+            let mut inputs = vec![
+                ("input_ids", tensor(ids.iter().map(|&id| id as i64).collect())?.into_dyn()),
+                ("attention_mask", tensor(vec![1; n])?.into_dyn()),
+            ];
+            if self.token_types {
+                inputs.push(("token_type_ids", tensor(vec![0; n])?.into_dyn()));
+            }
+            let mut session = self.session.lock().unwrap_or_else(|p| p.into_inner());
+            let outputs = session.run(inputs).map_err(|e| e.to_string())?;
+            let (_, logits) = outputs[0].try_extract_tensor::<f32>().map_err(|e| e.to_string())?;
+            let width = self.labels.len();
+            if logits.len() != n * width {
+                return Err(format!("expected {} x {} logits, got {}", n, width, logits.len()));
+            }
+            Ok(logits.chunks(width).map(|row| {
+                let max = row.iter().copied().fold(f32::MIN, f32::max);
+                let total: f32 = row.iter().map(|l| (l - max).exp()).sum();
+                let best = row.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map_or(0, |(i, _)| i);
+                (best, 1.0 / total)
+            }).collect())
+        }
+    }
+
+    /// `id2label` of the model's `config.json`, in id order.
+    fn labels_from_config(dir: &Path) -> Result<Vec<String>, String> {
+        let path = dir.join("config.json");
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let config: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let map = config["id2label"].as_object().ok_or("config.json has no `id2label`; set SENTINEL_NER_LABELS")?;
+        let mut labels: Vec<(usize, String)> = map.iter()
+            .filter_map(|(id, label)| Some((id.parse().ok()?, label.as_str()?.to_string())))
+            .collect();
+        labels.sort();
+        Ok(labels.into_iter().map(|(_, label)| label).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entities_group_and_follow_actions() {
+        let text = "Maria Keller from Northwind lives at 12 Elm Street.";
+        let tokens = [
+            Token { start: 0, end: 5, label: "B-PER", score: 0.99 },
+            Token { start: 6, end: 12, label: "I-PER", score: 0.97 },
+            Token { start: 13, end: 17, label: "O", score: 0.99 },
+            Token { start: 18, end: 27, label: "B-ORG", score: 0.91 },
+            Token { start: 28, end: 33, label: "O", score: 0.99 },
+            Token { start: 34, end: 36, label: "O", score: 0.99 },
+            Token { start: 37, end: 39, label: "B-LOC", score: 0.8 },
+            Token { start: 40, end: 43, label: "I-LOC", score: 0.85 },
+            Token { start: 44, end: 50, label: "I-LOC", score: 0.9 },
+            Token { start: 50, end: 51, label: "B-MISC", score: 0.3 },
+        ];
+        let entities = group(&tokens, 0.7);
+        assert_eq!(entities.iter().map(|e| (e.kind, &text[e.start..e.end])).collect::<Vec<_>>(), [
+            (EntityKind::Person, "Maria Keller"),
+            (EntityKind::Organization, "Northwind"),
+            (EntityKind::Location, "12 Elm Street"),
+        ]);
+
+        let action = |kind| match kind {
+            EntityKind::Person | EntityKind::Location => EntityAction::Redact,
+            EntityKind::Organization => EntityAction::Flag,
+            EntityKind::Misc => EntityAction::Allow,
+        };
+        let (redacted, found) = apply(text, &entities, action);
+        assert_eq!(redacted, "[PERSON] from Northwind lives at [LOCATION].");
+        assert_eq!(summary(&found, EntityAction::Redact).as_deref(), Some("1 person, 1 location"));
+        assert_eq!(summary(&found, EntityAction::Flag).as_deref(), Some("1 organization"));
+        assert_eq!("ORGANIZATION".parse::<EntityKind>(), Ok(EntityKind::Organization));
+        assert!("redact-all".parse::<EntityAction>().is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::alerts::BudgetAlert;
+use crate::audit::InterventionLog;
+use crate::config::{NotifyPolicy, NotifyTarget};
+
+// --- CHAT NOTIFICATIONS ---
+// Interventions and budget alerts can be posted to Slack and Discord incoming
+// webhooks (`SENTINEL_SLACK_WEBHOOK_URL`, `SENTINEL_DISCORD_WEBHOOK_URL`),
+// each with its own minimum severity. Notices are queued and sent every
+// `SENTINEL_NOTIFY_BATCH_SECS` as one message per target, at most
+// `SENTINEL_NOTIFY_MAX_PER_MINUTE` messages a minute; a burst beyond that
+// waits, and past `MAX_QUEUED` the oldest notices are dropped and counted in
+// the next message. A notice carries the session, reason, cost and a link to
+// the log entry, never prompt content.
+//
+// A detector's hits are `warning` unless `CRITICAL_DETECTORS` or
+// `SENTINEL_DETECTOR_SEVERITY` say otherwise; critical notices also page
+// on-call (see `paging.rs`).
+
+/// Notices waiting per target before the oldest are dropped.
+const MAX_QUEUED: usize = 200;
+
+/// Notices per message; keeps Discord under its 2000-character limit.
+const MAX_PER_MESSAGE: usize = 10;
+
+/// Longest reason quoted in a notice, in characters.
+const MAX_REASON_CHARS: usize = 120;
+
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Detectors whose hits are critical: data leaving, or an operator kill switch.
+const CRITICAL_DETECTORS: &[&str] = &["leak", "kill_switch"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chat {
+    Slack,
+    Discord,
+}
+
+impl Chat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Chat::Slack => "Slack",
+            Chat::Discord => "Discord",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            other => Err(format!("unknown severity `{}` (info, warning, critical)", other)),
+        }
+    }
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Severity::Info => "ℹ️",
+            Severity::Warning => "⚠️",
+            Severity::Critical => "🚨",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Notice {
+    pub severity: Severity,
+    /// Detector name, or the budget that was crossed.
+    pub title: String,
+    pub session: Option<String>,
+    pub reason: String,
+    pub cost: Option<String>,
+    pub link: Option<String>,
+}
+
+impl Notice {
+    /// Dry runs and exempted hits are informational; otherwise the
+    /// detector's severity.
+    pub fn intervention(log: &InterventionLog, policy: &NotifyPolicy) -> Self {
+        let severity = if log.dry_run || log.bypassed {
+            Severity::Info
+        } else {
+            severity_of(policy, &log.detector)
+        };
+        Self {
+            severity,
+            title: log.detector.clone(),
+            session: Some(log.session_id.clone()),
+            reason: log.reason.clone(),
+            cost: (log.savings_est > 0.0).then(|| format!("~${:.4} saved", log.savings_est)),
+            link: policy.public_url.as_ref().map(|base| format!("{}/api/logs?before={}&limit=1", base, log.id + 1)),
+        }
+    }
+
+    /// Critical once the whole budget is spent, a warning before.
+    pub fn budget(alert: &BudgetAlert, policy: &NotifyPolicy) -> Self {
+        let filter = match alert.scope {
+            "session" => Some("session_id"),
+            "tenant" => Some("tenant"),
+            _ => None,
+        };
+        Self {
+            severity: if alert.level >= 1.0 { Severity::Critical } else { Severity::Warning },
+            title: format!("{} budget", alert.scope),
+            session: (alert.scope == "session").then(|| alert.id.clone()),
+            reason: format!("{} '{}' crossed {:.0}% of its budget", alert.scope, alert.id, alert.level * 100.0),
+            cost: Some(format!("${:.4} of ${:.2}", alert.spent_usd, alert.budget_usd)),
+            link: policy.public_url.as_deref().zip(filter).map(|(base, key)| format!("{}/api/logs?{}={}", base, key, crate::rbac::encode(&alert.id))),
+        }
+    }
+
+    /// One line of plain text, for pagers and email subjects.
+    pub fn summary(&self) -> String {
+        let mut parts = vec![format!("[{}] {}", self.severity.as_str(), self.title)];
+        parts.extend(self.session.as_ref().map(|s| format!("session {}", s)));
+        parts.push(self.reason.chars().take(MAX_REASON_CHARS).collect());
+        parts.extend(self.cost.clone());
+        parts.join(" · ")
+    }
+}
+
+fn severity_of(policy: &NotifyPolicy, detector: &str) -> Severity {
+    match policy.detector_severity.get(detector) {
+        Some(severity) => *severity,
+        None if CRITICAL_DETECTORS.contains(&detector) => Severity::Critical,
+        None => Severity::Warning,
+    }
+}
+
+/// Sends a notice wherever its severity routes it: chat and, if severe
+/// enough, the pager.
+pub fn raise(state: &AppState, notice: Notice) {
+    crate::paging::page(state, &notice);
+    state.notifier.notify(&state.config.notify, notice);
+}
+
+#[derive(Default)]
+struct Outbox {
+    queued: VecDeque<Notice>,
+    /// Notices dropped since the last message.
+    dropped: usize,
+    /// When the messages of the last minute went out.
+    sent_at: VecDeque<u64>,
+}
+
+#[derive(Default)]
+pub struct Notifier {
+    outboxes: Mutex<HashMap<Chat, Outbox>>,
+}
+
+impl Notifier {
+    /// Queues `notice` for every target whose minimum severity it meets.
+    pub fn notify(&self, policy: &NotifyPolicy, notice: Notice) {
+        let mut targets = policy.targets.iter().filter(|t| notice.severity >= t.min_severity).peekable();
+        if targets.peek().is_none() {
+            return;
+        }
+        let mut outboxes = self.outboxes.lock().unwrap_or_else(|p| p.into_inner());
+        for target in targets {
+            let outbox = outboxes.entry(target.chat).or_default();
+            if outbox.queued.len() >= MAX_QUEUED {
+                outbox.queued.pop_front();
+                outbox.dropped += 1;
+            }
+            outbox.queued.push_back(notice.clone());
+        }
+    }
+
+    /// One message per target that has notices queued and room under its
+    /// rate limit.
+    fn due(&self, policy: &NotifyPolicy, now: u64) -> Vec<(NotifyTarget, Value)> {
+        let mut outboxes = self.outboxes.lock().unwrap_or_else(|p| p.into_inner());
+        policy.targets.iter().filter_map(|target| {
+            let outbox = outboxes.get_mut(&target.chat)?;
+            while outbox.sent_at.front().is_some_and(|t| now.saturating_sub(*t) >= 60) {
+                outbox.sent_at.pop_front();
+            }
+            if outbox.queued.is_empty() || outbox.sent_at.len() >= policy.max_per_minute {
+                return None;
+            }
+            let take = outbox.queued.len().min(MAX_PER_MESSAGE);
+            let batch: Vec<Notice> = outbox.queued.drain(..take).collect();
+            outbox.sent_at.push_back(now);
+            Some((target.clone(), payload(target.chat, &batch, std::mem::take(&mut outbox.dropped))))
+        }).collect()
+    }
+}
+
+fn payload(chat: Chat, notices: &[Notice], dropped: usize) -> Value {
+    let mut lines: Vec<String> = notices.iter().map(|n| render(chat, n)).collect();
+    if dropped > 0 {
+        lines.push(format!("…and {} older notices dropped (rate limit)", dropped));
+    }
+    let text = lines.join("\n");
+    match chat {
+        Chat::Slack => json!({"text": text}),
+        Chat::Discord => json!({"content": text, "allowed_mentions": {"parse": []}}),
+    }
+}
+
+/// One line of a message, in the chat's own markup.
+fn render(chat: Chat, notice: &Notice) -> String {
+    let title = escape(chat, &notice.title);
+    let mut line = match chat {
+        Chat::Slack => format!("{} *{}*", notice.severity.icon(), title),
+        Chat::Discord => format!("{} **{}**", notice.severity.icon(), title),
+    };
+    if let Some(session) = &notice.session {
+        line.push_str(&format!(" · session {}", escape(chat, session)));
+    }
+    let reason: String = notice.reason.chars().take(MAX_REASON_CHARS).collect();
+    line.push_str(&format!(" · {}", escape(chat, &reason)));
+    if let Some(cost) = &notice.cost {
+        line.push_str(&format!(" · {}", escape(chat, cost)));
+    }
+    if let Some(link) = &notice.link {
+        match chat {
+            Chat::Slack => line.push_str(&format!(" · <{}|view log>", link)),
+            Chat::Discord => line.push_str(&format!(" · [view log](<{}>)", link)),
+        }
+    }
+    line
+}
+
+/// Keeps session ids and reasons from being read as markup or mentions.
+pub fn escape(chat: Chat, text: &str) -> String {
+    match chat {
+        Chat::Slack => text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+        Chat::Discord => text.chars().fold(String::new(), |mut out, c| {
+            if "\\*_~`|[]<>".contains(c) {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        }),
+    }
+}
+
+/// Sends due messages every `SENTINEL_NOTIFY_BATCH_SECS`.
+pub fn spawn_sender(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(state.config.notify.batch_secs));
+        loop {
+            tick.tick().await;
+            let config = state.current_config();
+            for (target, body) in state.notifier.due(&config.notify, crate::now_secs()) {
+                post(&state, target, body);
+            }
+        }
+    });
+}
+
+fn post(state: &AppState, target: NotifyTarget, body: Value) {
+    let client = state.client.clone();
+    tokio::spawn(async move {
+        let sent = client.post(&target.url).json(&body).timeout(POST_TIMEOUT).send().await
+            .and_then(|r| r.error_for_status());
+        // The webhook URL is a credential, so it is not logged.
+        if let Err(e) = sent {
+            tracing::error!("{} notification failed: {}", target.chat.as_str(), e.without_url());
+        }
+    });
+}
+
+/// Posts one message to every target at once, whatever its minimum
+/// severity; `text` renders it in the chat's markup.
+pub fn broadcast(state: &AppState, text: impl Fn(Chat) -> String) {
+    for target in &state.config.notify.targets {
+        let text = text(target.chat);
+        let body = match target.chat {
+            Chat::Slack => json!({"text": text}),
+            Chat::Discord => json!({"content": text, "allowed_mentions": {"parse": []}}),
+        };
+        post(state, target.clone(), body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(severity: Severity, reason: &str) -> Notice {
+        Notice { severity, title: "leak".to_string(), session: Some("acme/u1".to_string()), reason: reason.to_string(), cost: None, link: None }
+    }
+
+    #[test]
+    fn test_batches_by_severity_and_rate_limit() {
+        let target = |chat, min_severity| NotifyTarget { chat, url: "http://hook".to_string(), min_severity };
+        let policy = NotifyPolicy {
+            targets: vec![target(Chat::Slack, Severity::Warning), target(Chat::Discord, Severity::Critical)],
+            max_per_minute: 1,
+            ..Default::default()
+        };
+        let notifier = Notifier::default();
+        notifier.notify(&policy, notice(Severity::Info, "dry run"));
+        notifier.notify(&policy, notice(Severity::Warning, "loop <!channel>"));
+        notifier.notify(&policy, Notice { link: Some("https://s.example/api/logs?before=8&limit=1".to_string()), ..notice(Severity::Critical, "secret") });
+
+        let due = notifier.due(&policy, 1_000);
+        assert_eq!(due.len(), 2);
+        let slack = due[0].1["text"].as_str().unwrap();
+        assert_eq!(slack.lines().count(), 2);
+        assert!(slack.contains("loop &lt;!channel&gt;") && slack.contains("<https://s.example/api/logs?before=8&limit=1|view log>"));
+        assert_eq!(due[1].1["content"].as_str().unwrap().lines().count(), 1);
+
+        notifier.notify(&policy, notice(Severity::Critical, "again"));
+        assert!(notifier.due(&policy, 1_030).is_empty());
+        assert_eq!(notifier.due(&policy, 1_060).len(), 2);
+    }
+}
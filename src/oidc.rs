@@ -0,0 +1,181 @@
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::{Jwk, JwkSet}};
+use serde_json::Value;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::config::OidcPolicy;
+use crate::rbac::Principal;
+
+// --- OIDC ---
+// Bearer tokens on the admin API that are not static admin tokens are
+// checked as JWTs from `SENTINEL_OIDC_ISSUER`: signature against the
+// issuer's JWKS, then `iss`, `exp` and `aud` (`SENTINEL_OIDC_AUDIENCE`,
+// required). The role claim picks the highest Sentinel role that
+// `SENTINEL_OIDC_ROLES` maps its names to; a tenant claim confines the
+// caller to that tenant's logs and sessions (see `rbac::scope`). Keys are
+// cached and refetched every `SENTINEL_OIDC_JWKS_REFRESH_SECS`, or sooner
+// when a token names a `kid` we have not seen (key rotation).
+
+/// Signature algorithms we accept; symmetric ones would let anyone holding
+/// the JWKS forge tokens.
+const ASYMMETRIC: &[Algorithm] = &[
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384, Algorithm::EdDSA,
+];
+
+/// An unknown `kid` refetches the keys at most this often.
+const MIN_REFETCH_SECS: u64 = 60;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct CachedKeys {
+    /// The JWKS URL or issuer they came from; a reload that changes it
+    /// invalidates them.
+    source: String,
+    fetched_at: u64,
+    keys: JwkSet,
+}
+
+#[derive(Default)]
+pub struct KeyCache {
+    cached: RwLock<Option<CachedKeys>>,
+}
+
+impl KeyCache {
+    /// The signing key for `kid`, fetching the JWKS when it is missing,
+    /// stale, or does not have the key.
+    async fn key(&self, client: &reqwest::Client, policy: &OidcPolicy, kid: Option<&str>) -> Result<Jwk, String> {
+        let source = policy.jwks_url.clone().or_else(|| policy.issuer.clone()).unwrap_or_default();
+        let now = crate::now_secs();
+        let (found, age) = {
+            let cached = self.cached.read().unwrap_or_else(|p| p.into_inner());
+            match cached.as_ref().filter(|c| c.source == source) {
+                Some(c) => (find(&c.keys, kid), Some(now.saturating_sub(c.fetched_at))),
+                None => (None, None),
+            }
+        };
+        let refetch = match (&found, age) {
+            (_, None) => true,
+            (Some(_), Some(age)) => age >= policy.jwks_refresh_secs,
+            (None, Some(age)) => age >= MIN_REFETCH_SECS,
+        };
+        let unknown = || format!("no signing key {}", kid.map_or("(token has no kid)".to_string(), |k| format!("`{}`", k)));
+        if !refetch {
+            return found.ok_or_else(unknown);
+        }
+        match fetch(client, policy).await {
+            Ok(keys) => {
+                let key = find(&keys, kid);
+                *self.cached.write().unwrap_or_else(|p| p.into_inner()) = Some(CachedKeys { source, fetched_at: now, keys });
+                key.ok_or_else(unknown)
+            }
+            Err(e) => {
+                tracing::warn!("Could not fetch OIDC signing keys: {}", e);
+                found.ok_or(e)
+            }
+        }
+    }
+}
+
+/// The key a token's `kid` names; without one, the set's only key.
+fn find(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid).cloned(),
+        None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+        None => None,
+    }
+}
+
+async fn fetch(client: &reqwest::Client, policy: &OidcPolicy) -> Result<JwkSet, String> {
+    let get = |url: String| async move {
+        let response = client.get(&url).timeout(FETCH_TIMEOUT).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("{}: {}", url, e))?;
+        response.json::<Value>().await.map_err(|e| format!("{}: {}", url, e))
+    };
+    let jwks_url = match &policy.jwks_url {
+        Some(url) => url.clone(),
+        None => {
+            let issuer = policy.issuer.as_deref().ok_or("OIDC is not configured")?;
+            let discovery = get(format!("{}/.well-known/openid-configuration", issuer)).await?;
+            discovery["jwks_uri"].as_str().ok_or("discovery document has no jwks_uri")?.to_string()
+        }
+    };
+    serde_json::from_value(get(jwks_url).await?).map_err(|e| format!("malformed JWKS: {}", e))
+}
+
+/// Verifies `token` and maps its claims to a principal.
+pub async fn verify(state: &AppState, token: &str) -> Result<Principal, String> {
+    let policy = &state.config.oidc;
+    let issuer = policy.issuer.as_deref().ok_or("OIDC is not configured")?;
+    let audience = policy.audience.as_deref().ok_or("SENTINEL_OIDC_AUDIENCE is not set")?;
+    let header = jsonwebtoken::decode_header(token).map_err(|e| format!("malformed token: {}", e))?;
+    if !ASYMMETRIC.contains(&header.alg) {
+        return Err(format!("{:?} tokens are not accepted", header.alg));
+    }
+    let jwk = state.oidc_keys.key(&state.client, policy, header.kid.as_deref()).await?;
+    let key = DecodingKey::from_jwk(&jwk).map_err(|e| format!("unusable signing key: {}", e))?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)
+        .map_err(|e| format!("invalid token: {}", e))?
+        .claims;
+    let principal = principal(policy, &claims)?;
+    if let Some(tenant) = &principal.tenant && state.config.tenant(tenant).is_none() {
+        return Err(format!("unknown tenant `{}`", tenant));
+    }
+    Ok(principal)
+}
+
+/// The highest role the role claim grants, and the tenant claim.
+pub fn principal(policy: &OidcPolicy, claims: &Value) -> Result<Principal, String> {
+    let role = claim(claims, &policy.role_claim).iter()
+        .filter_map(|name| policy.role_for(name))
+        .max()
+        .ok_or_else(|| format!("no Sentinel role in the `{}` claim", policy.role_claim))?;
+    let tenant = claim(claims, &policy.tenant_claim).into_iter().next();
+    Ok(Principal { role, tenant })
+}
+
+/// String values of a claim: a top-level name (namespaced URL claims contain
+/// dots), else a dotted path into nested objects. A string or an array of them.
+fn claim(claims: &Value, name: &str) -> Vec<String> {
+    let value = claims.get(name).or_else(|| name.split('.').try_fold(claims, |v, key| v.get(key)));
+    match value {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rbac::Role;
+
+    #[test]
+    fn test_principal_from_claims() {
+        let policy = OidcPolicy {
+            role_claim: "realm_access.roles".to_string(),
+            role_map: [("sre".to_string(), Role::Operator)].into(),
+            ..Default::default()
+        };
+        let claims = serde_json::json!({"sub": "u1", "realm_access": {"roles": ["offline_access", "viewer", "sre"]}, "tenant": "acme"});
+        let p = principal(&policy, &claims).unwrap();
+        assert_eq!((p.role, p.tenant.as_deref()), (Role::Operator, Some("acme")));
+        assert!(principal(&policy, &serde_json::json!({"realm_access": {"roles": ["offline_access"]}})).is_err());
+
+        let namespaced = OidcPolicy {
+            role_claim: "https://example.com/roles".to_string(),
+            role_map: [("platform".to_string(), Role::Admin)].into(),
+            ..Default::default()
+        };
+        let p = principal(&namespaced, &serde_json::json!({"https://example.com/roles": "platform"})).unwrap();
+        assert_eq!((p.role, p.tenant), (Role::Admin, None));
+        // Role names only count through the mapping.
+        assert!(principal(&namespaced, &serde_json::json!({"https://example.com/roles": ["admin", "operator"]})).is_err());
+    }
+}
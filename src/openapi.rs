@@ -0,0 +1,723 @@
+use axum::{Json, http::Method, response::IntoResponse};
+use serde_json::{Value, json};
+
+// --- OPENAPI ---
+// `GET /api/openapi.json` describes every route the router serves, so SDKs
+// and gateway configs can be generated rather than read out of the source.
+// The document is written by hand next to the handlers it mirrors: a route
+// added in `main()` needs an entry in `paths()` (the test below keeps the two
+// lists in step).
+
+pub async fn spec() -> impl IntoResponse {
+    Json(document())
+}
+
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Sentinel",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "OpenAI-compatible proxy that stops agent loops, leaks and runaway spend before they reach the provider, plus the admin API behind the dashboard.",
+        },
+        "paths": with_roles(paths()),
+        "components": {
+            "schemas": schemas(),
+            "parameters": parameters(),
+            "headers": response_headers(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "description": "Provider key forwarded upstream; also the MCP control token on /mcp." },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+                "adminToken": { "type": "http", "scheme": "bearer", "description": "`SENTINEL_ADMIN_TOKENS` token or a JWT from `SENTINEL_OIDC_ISSUER`; `x-sentinel-role` on each admin operation names the role it needs." },
+            },
+        },
+    })
+}
+
+/// Marks each admin operation with the role `rbac` demands for it.
+fn with_roles(mut paths: Value) -> Value {
+    for (path, item) in paths.as_object_mut().into_iter().flatten() {
+        for (method, operation) in item.as_object_mut().into_iter().flatten() {
+            let method = match method.as_str() {
+                "get" => Method::GET,
+                "post" => Method::POST,
+                "put" => Method::PUT,
+                "delete" => Method::DELETE,
+                _ => continue,
+            };
+            if let Some(role) = crate::rbac::required_role(&method, path) {
+                operation["security"] = json!([{ "adminToken": [] }]);
+                operation["x-sentinel-role"] = json!(role.as_str());
+            }
+        }
+    }
+    paths
+}
+
+/// Every route, assembled from one fragment per group so no single `json!`
+/// literal outgrows the macro's recursion limit.
+fn paths() -> Value {
+    let mut paths = serde_json::Map::new();
+    for group in [proxy_paths(), stats_paths(), session_paths(), operator_paths(), service_paths()] {
+        if let Value::Object(group) = group {
+            paths.extend(group);
+        }
+    }
+    Value::Object(paths)
+}
+
+/// The OpenAI-compatible proxy and the MCP endpoint.
+fn proxy_paths() -> Value {
+    json!({
+        "/v1/chat/completions": { "post": proxied("Chat completion", "ChatRequest") },
+        "/v1/completions": { "post": proxied("Legacy text completion", "CompletionRequest") },
+        "/v1/embeddings": { "post": proxied("Embeddings, leak-checked and budgeted", "OpenAiRequest") },
+        "/v1/moderations": { "post": proxied("Moderations, leak-checked and budgeted", "OpenAiRequest") },
+        "/v1/images/generations": { "post": proxied("Image generation, leak-checked and budgeted", "OpenAiRequest") },
+        "/mcp": {
+            "post": {
+                "summary": "MCP JSON-RPC endpoint (initialize, tools/list, tools/call, ...)",
+                "tags": ["mcp"],
+                "security": [{}, { "bearer": [] }],
+                "parameters": [{ "$ref": "#/components/parameters/McpSession" }],
+                "requestBody": body("JsonRpcRequest"),
+                "responses": {
+                    "200": ok("JsonRpcResponse"),
+                    "202": { "description": "Notification accepted" },
+                    "401": { "description": "Missing or wrong MCP token" },
+                    "404": { "description": "Unknown MCP session" },
+                },
+            },
+            "get": {
+                "summary": "Server-sent `notifications/message` stream for an MCP session",
+                "tags": ["mcp"],
+                "parameters": [{ "$ref": "#/components/parameters/McpSession" }],
+                "responses": { "200": event_stream("JSON-RPC notifications") },
+            },
+            "delete": {
+                "summary": "End an MCP session",
+                "tags": ["mcp"],
+                "security": [{}, { "bearer": [] }],
+                "parameters": [{ "$ref": "#/components/parameters/McpSession" }],
+                "responses": {
+                    "204": { "description": "Session ended" },
+                    "400": { "description": "Missing `Mcp-Session-Id`" },
+                    "401": { "description": "Missing or wrong MCP token" },
+                    "404": { "description": "Unknown MCP session" },
+                },
+            },
+        },
+    })
+}
+
+/// Dashboard statistics and the audit log.
+fn stats_paths() -> Value {
+    json!({
+        "/api/stats": { "get": admin("Totals, savings, precision and health", &[], ok("Stats")) },
+        "/api/stats/timeseries": { "get": admin("Requests, interventions, cost and savings per bucket", &[
+            query("window", "Look-back such as `24h` or `7d`", "string"),
+            query("bucket", "`1h` or `1d`", "string"),
+        ], ok_free()) },
+        "/api/stats/breakdown": { "get": admin("Cost and savings by model, provider and team", &[
+            query("top", "Rows per dimension", "integer"),
+        ], ok_free()) },
+        "/api/stats/detectors": { "get": admin("Per-detector evaluation cost, triggers and false-positive rate", &[], ok_free()) },
+        "/api/logs": { "get": admin("Search the intervention history, newest first", &log_query(), log_page()) },
+        "/api/logs/export": { "get": admin("Download the retained history, oldest first", &[
+            query("format", "`jsonl` (default) or `csv`", "string"),
+            query("from", "Unix seconds, inclusive", "integer"),
+            query("to", "Unix seconds, inclusive", "integer"),
+            query("tenant", "Only this tenant's interventions", "string"),
+        ], json!({
+            "description": "Export",
+            "content": { "application/x-ndjson": {}, "text/csv": {} },
+        })) },
+        "/api/logs/verify": { "get": admin("Check the audit hash chain over the retained history", &[], ok("ChainReport")) },
+        "/api/logs/stream": { "get": admin("Live interventions as server-sent events", &log_query(), event_stream("One InterventionLog per event")) },
+    })
+}
+
+/// Sessions, their blocks and output schemas.
+fn session_paths() -> Value {
+    json!({
+        "/api/sessions": { "get": admin("Active sessions, most recent first", &[
+            query("offset", "Rows to skip", "integer"),
+            query("limit", "Page size", "integer"),
+            query("tenant", "Only sessions in this tenant's namespace", "string"),
+        ], ok("SessionPage")) },
+        "/api/sessions/export": { "get": admin("Snapshot of live sessions, blocks and spend, for warm-starting another instance", &[], ok("SessionSnapshot")) },
+        "/api/sessions/import": { "post": with_body(
+            admin("Load a snapshot from `/api/sessions/export`; its entries replace local ones with the same key", &[], ok_free()),
+            json!({ "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SessionSnapshot" } } } }),
+        ) },
+        "/api/sessions/{id}": {
+            "get": admin("One session's spend, interventions and recent prompts", &[path_id("string")], ok("SessionDetail")),
+            "delete": admin("Forget a session's loop history and spend", &[path_id("string")], ok_free()),
+        },
+        "/api/sessions/{id}/block": { "post": with_body(
+            admin("Reject all further traffic for a session", &[path_id("string")], ok_free()),
+            json!({ "required": false, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/BlockRequest" } } } }),
+        ) },
+        "/api/sessions/{id}/unblock": { "post": admin("Lift an operator block", &[path_id("string")], ok_free()) },
+        "/api/sessions/{id}/schema": {
+            "put": with_body(
+                admin("Register the JSON schema the session's completions must match", &[path_id("string")], ok_free()),
+                json!({ "required": true, "content": { "application/json": { "schema": { "type": "object" } } } }),
+            ),
+            "delete": admin("Stop validating the session's completions", &[path_id("string")], ok_free()),
+        },
+    })
+}
+
+/// Erasure, reports, bans, feedback and the quarantine queue.
+fn operator_paths() -> Value {
+    json!({
+        "/api/data/{subject}": { "delete": admin("Erase a user's or session's prompts, embeddings and audit snippets", &[
+            json!({ "name": "subject", "in": "path", "required": true, "schema": { "type": "string" }, "description": "User or session id, `<tenant>/<id>` for a tenant's; an unqualified id only matches untenanted sessions" }),
+        ], ok("ErasureReceipt")) },
+        "/api/shadow": { "get": admin("Shadow model comparison: per model pair summary and the newest records", &[
+            query("limit", "Newest comparisons to include (default 20)", "integer"),
+        ], ok("ShadowReport")) },
+        "/api/reports": { "get": admin("Spend, savings, interventions and top sessions per day or week, per tenant", &[
+            query("period", "`day` (default) or `week`", "string"),
+            query("count", "Periods to include, newest first (default 7 days or 4 weeks)", "integer"),
+            query("tenant", "Only this tenant", "string"),
+            query("top", "Sessions listed per tenant (default `SENTINEL_REPORT_TOP`)", "integer"),
+        ], ok("UsageReports")) },
+        "/api/tenants": { "get": admin("Spend, budget and live sessions per tenant", &[], ok_list("Tenant")) },
+        "/api/bans": {
+            "get": admin("Client IPs currently banned, newest first", &[], ok_list("Ban")),
+            "post": with_body(admin("Ban a client IP", &[], ok("Ban")), body("BanRequest")),
+        },
+        "/api/bans/{ip}": { "delete": admin("Lift a ban", &[json!({ "name": "ip", "in": "path", "required": true, "schema": { "type": "string" } })], ok_free()) },
+        "/api/interventions/{id}/feedback": { "post": with_body(
+            admin("Mark an intervention correct or a false positive", &[path_id("integer")], ok_free()),
+            body("FeedbackRequest"),
+        ) },
+        "/api/interventions/{id}/replay": { "post": admin("Re-run a logged prompt through today's detectors", &[path_id("integer")], ok_free()) },
+        "/api/quarantine": { "get": admin("Responses held for review", &[
+            query("status", "`pending`, `approved`, `denied`, `completed` or `failed`", "string"),
+        ], ok_list("QuarantineEntry")) },
+        "/api/quarantine/{id}": { "get": admin("One held response", &[path_id("integer")], ok("QuarantineEntry")) },
+        "/api/quarantine/{id}/approve": { "post": admin("Release a held response", &[path_id("integer")], ok_free()) },
+        "/api/quarantine/{id}/deny": { "post": admin("Discard a held response", &[path_id("integer")], ok_free()) },
+    })
+}
+
+/// The document itself, reloads, metrics and probes.
+fn service_paths() -> Value {
+    json!({
+        "/api/openapi.json": { "get": admin("This document", &[], ok_free()) },
+        "/api/config/reload": { "post": admin("Re-read the environment and `--config` file without restarting", &[], ok("Reloaded")) },
+        "/api/readiness": { "get": admin("The readiness checks with their errors, and the configuration warnings", &[], ok("ReadinessReport")) },
+        "/metrics": { "get": {
+            "summary": "Prometheus metrics",
+            "tags": ["admin"],
+            "responses": { "200": { "description": "Text exposition format", "content": { "text/plain": {} } } },
+        } },
+        "/health": { "get": {
+            "summary": "Liveness probe (alias of `/healthz`)",
+            "tags": ["health"],
+            "responses": { "200": { "description": "Sentinel is running", "content": { "text/plain": {} } } },
+        } },
+        "/healthz": { "get": {
+            "summary": "Liveness probe",
+            "tags": ["health"],
+            "responses": { "200": { "description": "The process is serving requests", "content": { "text/plain": {} } } },
+        } },
+        "/readyz": { "get": {
+            "summary": "Readiness probe: audit storage and, optionally, provider keys; details in `/api/readiness`",
+            "tags": ["health"],
+            "responses": {
+                "200": ok("Readiness"),
+                "503": { "description": "A check failed", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Readiness" } } } },
+            },
+        } },
+    })
+}
+
+/// An OpenAI-compatible route: the provider's own body and status, plus the
+/// Sentinel request and response headers and the block/quarantine outcomes.
+fn proxied(summary: &str, request: &str) -> Value {
+    let sentinel_headers = json!({
+        "x-sentinel-intervention": { "$ref": "#/components/headers/Intervention" },
+        "x-sentinel-detector": { "$ref": "#/components/headers/Detector" },
+        "x-sentinel-reason": { "$ref": "#/components/headers/Reason" },
+        "x-sentinel-cost": { "$ref": "#/components/headers/Cost" },
+        "x-sentinel-max-cost": { "$ref": "#/components/headers/MaxCost" },
+        "x-sentinel-budget-remaining": { "$ref": "#/components/headers/BudgetRemaining" },
+    });
+    json!({
+        "summary": summary,
+        "tags": ["proxy"],
+        "security": [{ "bearer": [] }, { "apiKey": [] }],
+        "parameters": [
+            { "$ref": "#/components/parameters/Session" },
+            { "$ref": "#/components/parameters/Provider" },
+            { "$ref": "#/components/parameters/Pin" },
+            { "$ref": "#/components/parameters/LoopWindow" },
+            { "$ref": "#/components/parameters/Locale" },
+            { "$ref": "#/components/parameters/Team" },
+            { "$ref": "#/components/parameters/Tenant" },
+            { "$ref": "#/components/parameters/Agent" },
+            { "$ref": "#/components/parameters/MaxCost" },
+            { "$ref": "#/components/parameters/BudgetRemaining" },
+            { "$ref": "#/components/parameters/IdempotencyKey" },
+        ],
+        "requestBody": body(request),
+        "responses": {
+            "200": {
+                "description": "Upstream response, possibly redacted, truncated or annotated. With `stream: true`, server-sent chunks whose last one carries a `sentinel` object.",
+                "headers": sentinel_headers,
+                "content": {
+                    "application/json": { "schema": { "$ref": "#/components/schemas/OpenAiResponse" } },
+                    "text/event-stream": {},
+                },
+            },
+            "202": { "description": "Response held for review", "headers": sentinel_headers, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Quarantined" } } } },
+            "400": { "description": "Malformed Sentinel header", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "403": { "description": "Blocked by a detector, the kill switch, an IP ban or the tenant check", "headers": sentinel_headers, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "409": { "description": "A request with the same `Idempotency-Key` is still in progress", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "413": { "description": "Body, message count or prompt length over the configured limits", "headers": sentinel_headers, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "422": { "description": "The `Idempotency-Key` was used for a different request", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "429": { "description": "Session, team or per-request budget exhausted, or the per-IP rate limit hit", "headers": sentinel_headers, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "504": { "description": "Upstream timed out (`sentinel_timeout`)", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            "default": { "description": "Upstream error, passed through", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+        },
+    })
+}
+
+fn admin(summary: &str, parameters: &[Value], response: Value) -> Value {
+    let mut op = json!({ "summary": summary, "tags": ["admin"], "responses": { "200": response } });
+    if !parameters.is_empty() {
+        op["parameters"] = json!(parameters);
+    }
+    op
+}
+
+fn with_body(mut op: Value, body: Value) -> Value {
+    op["requestBody"] = body;
+    op
+}
+
+fn log_page() -> Value {
+    let mut page = ok_list("InterventionLog");
+    page["headers"] = json!({
+        "x-total-count": { "schema": { "type": "integer" } },
+        "x-next-cursor": { "description": "Pass as `before` for the next page", "schema": { "type": "integer" } },
+    });
+    page
+}
+
+fn body(schema: &str) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } } })
+}
+
+fn ok(schema: &str) -> Value {
+    json!({ "description": "OK", "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } } })
+}
+
+fn ok_list(schema: &str) -> Value {
+    json!({ "description": "OK", "content": { "application/json": { "schema": {
+        "type": "array", "items": { "$ref": format!("#/components/schemas/{}", schema) },
+    } } } })
+}
+
+fn ok_free() -> Value {
+    json!({ "description": "OK", "content": { "application/json": { "schema": { "type": "object" } } } })
+}
+
+fn event_stream(description: &str) -> Value {
+    json!({ "description": description, "content": { "text/event-stream": {} } })
+}
+
+fn query(name: &str, description: &str, ty: &str) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": { "type": ty } })
+}
+
+fn path_id(ty: &str) -> Value {
+    json!({ "name": "id", "in": "path", "required": true, "schema": { "type": ty } })
+}
+
+fn log_query() -> Vec<Value> {
+    vec![
+        query("session_id", "Exact session id", "string"),
+        query("tenant", "Tenant name", "string"),
+        query("detector", "Detector key, e.g. `semantic_loop` or `leak`", "string"),
+        query("reason", "Case-insensitive substring of the reason", "string"),
+        query("from", "Unix seconds, inclusive", "integer"),
+        query("to", "Unix seconds, inclusive", "integer"),
+        query("q", "Full-text search over the content snippet", "string"),
+        query("limit", "Page size", "integer"),
+        query("before", "Cursor: only entries older than this id", "integer"),
+    ]
+}
+
+fn parameters() -> Value {
+    let header = |name: &str, description: &str| json!({
+        "name": name, "in": "header", "required": false, "description": description, "schema": { "type": "string" },
+    });
+    json!({
+        "Session": header("x-sentinel-session", "Session id for loop and budget tracking; falls back to the body's `user`, then one session per client IP and user agent"),
+        "Provider": header("x-sentinel-provider", "Force an upstream provider instead of model-based routing; `mock` answers locally"),
+        "Pin": header("x-sentinel-pin", "`reset` re-pins the session to this request's provider and model (`SENTINEL_SESSION_PIN`)"),
+        "LoopWindow": header("x-sentinel-loop-window", "Per-request loop policy, e.g. `turns=3 history=8 compare=pairwise decay=4` (`decay` is a half-life in turns); values above `SENTINEL_LOOP_MAX_TURNS` (10), `SENTINEL_LOOP_MAX_HISTORY` (50) or `SENTINEL_LOOP_MAX_DECAY` (50) get a 400"),
+        "Locale": header("x-sentinel-locale", "Language for block messages"),
+        "Team": header("x-team", "Team to attribute spend to"),
+        "Tenant": header("x-sentinel-tenant", "Tenant for requests whose API key doesn't identify one; only tenants without keys"),
+        "Agent": header("x-sentinel-agent", "Agent whose canonical system prompt applies (`SENTINEL_CANONICAL_PROMPTS_DIR`)"),
+        "MaxCost": header("x-sentinel-max-cost", "Cost ceiling in USD for this request only; the completion budget is lowered to fit"),
+        "BudgetRemaining": header("x-sentinel-budget-remaining", "What the caller has left in USD; also a ceiling for this request, echoed back less the actual cost"),
+        "IdempotencyKey": header("idempotency-key", "Retries with the same key get the first response back instead of a second upstream call"),
+        "McpSession": header("mcp-session-id", "Session issued by `initialize`"),
+    })
+}
+
+fn response_headers() -> Value {
+    json!({
+        "Intervention": {
+            "description": "What Sentinel did to the exchange",
+            "schema": { "type": "string", "enum": ["blocked", "redacted", "truncated", "quarantined", "warned", "none"] },
+        },
+        "Detector": { "description": "Detector key that acted", "schema": { "type": "string" } },
+        "Reason": { "description": "Human-readable reason", "schema": { "type": "string" } },
+        "Cost": { "description": "Actual cost of the call in USD (non-streaming)", "schema": { "type": "string" } },
+        "MaxCost": { "description": "The per-request cost ceiling that applied", "schema": { "type": "string" } },
+        "BudgetRemaining": { "description": "`x-sentinel-budget-remaining` less the actual cost", "schema": { "type": "string" } },
+    })
+}
+
+/// Every schema, merged from fragments like `paths`.
+fn schemas() -> Value {
+    let mut schemas = serde_json::Map::new();
+    for group in [traffic_schemas(), admin_schemas()] {
+        if let Value::Object(group) = group {
+            schemas.extend(group);
+        }
+    }
+    Value::Object(schemas)
+}
+
+/// Proxied requests and responses, audit entries and session snapshots.
+fn traffic_schemas() -> Value {
+    json!({
+        "OpenAiRequest": { "type": "object", "description": "Provider request body, forwarded as-is", "additionalProperties": true },
+        "OpenAiResponse": {
+            "type": "object",
+            "additionalProperties": true,
+            "properties": {
+                "sentinel": { "type": "object", "description": "Present when Sentinel warned about or changed the response", "additionalProperties": true },
+            },
+        },
+        "ChatRequest": {
+            "type": "object",
+            "required": ["model", "messages"],
+            "additionalProperties": true,
+            "properties": {
+                "model": { "type": "string" },
+                "messages": { "type": "array", "items": {
+                    "type": "object",
+                    "required": ["role"],
+                    "properties": { "role": { "type": "string" }, "content": {} },
+                    "additionalProperties": true,
+                } },
+                "stream": { "type": "boolean" },
+                "user": { "type": "string" },
+                "logprobs": { "type": "boolean" },
+            },
+        },
+        "CompletionRequest": {
+            "type": "object",
+            "required": ["model", "prompt"],
+            "additionalProperties": true,
+            "properties": {
+                "model": { "type": "string" },
+                "prompt": { "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }] },
+                "stream": { "type": "boolean" },
+                "user": { "type": "string" },
+            },
+        },
+        "Error": {
+            "type": "object",
+            "properties": { "error": {
+                "type": "object",
+                "properties": {
+                    "message": { "type": "string" },
+                    "type": { "type": "string", "description": "`sentinel_blocked` for Sentinel's own refusals" },
+                    "param": { "type": ["string", "null"] },
+                    "code": { "type": ["string", "null"], "description": "Detector key or `session_blocked`" },
+                },
+            } },
+        },
+        "Quarantined": {
+            "type": "object",
+            "properties": {
+                "quarantine_id": { "type": "integer" },
+                "status": { "const": "pending" },
+                "reason": { "type": "string" },
+                "retrieve_url": { "type": "string" },
+            },
+        },
+        "InterventionLog": {
+            "type": "object",
+            "required": ["id", "timestamp", "session_id", "detector", "reason", "content_snippet", "savings_est"],
+            "properties": {
+                "id": { "type": "integer" },
+                "timestamp": { "type": "integer", "description": "Unix seconds" },
+                "session_id": { "type": "string" },
+                "detector": { "type": "string" },
+                "reason": { "type": "string" },
+                "content_snippet": { "type": "string" },
+                "savings_est": { "type": "number" },
+                "feedback": { "type": ["string", "null"], "enum": ["correct", "false_positive", null] },
+                "bypassed": { "type": "boolean" },
+                "dry_run": { "type": "boolean" },
+                "model": { "type": ["string", "null"] },
+                "provider": { "type": ["string", "null"] },
+                "tenant": { "type": ["string", "null"] },
+                "prev_hash": { "type": "string", "description": "Hash of the previous entry in the audit chain" },
+                "hash": { "type": "string", "description": "SHA-256 over `prev_hash` and this entry's content (not feedback)" },
+                "signature": { "type": "string", "description": "HMAC of `hash`, on signed checkpoints" },
+                "snippet_digest": { "type": "string", "description": "SHA-256 of an erased `content_snippet`" },
+            },
+        },
+        "SessionSnapshot": { "type": "object", "required": ["version", "sessions"], "properties": {
+            "version": { "type": "integer", "description": "Format version; currently 1" },
+            "exported_at": { "type": "integer" },
+            "sessions": { "type": "object", "description": "Full session state by id", "additionalProperties": { "type": "object" } },
+            "blocked": { "type": "object", "additionalProperties": { "type": "object" } },
+            "pool_spend": { "type": "object", "additionalProperties": { "type": "number" } },
+            "tenant_spend": { "type": "object", "additionalProperties": { "type": "number" } },
+        } },
+    })
+}
+
+/// Admin API bodies.
+fn admin_schemas() -> Value {
+    json!({
+        "ShadowReport": { "type": "object", "properties": {
+            "enabled": { "type": "boolean" },
+            "percent": { "type": "number" },
+            "shadow_model": { "type": "string", "nullable": true },
+            "pairs": { "type": "array", "items": { "type": "object", "properties": {
+                "primary_model": { "type": "string" },
+                "shadow_model": { "type": "string" },
+                "compared": { "type": "integer" },
+                "primary": { "$ref": "#/components/schemas/ShadowSide" },
+                "shadow": { "$ref": "#/components/schemas/ShadowSide" },
+                "cost_ratio": { "type": "number", "nullable": true, "description": "Shadow cost over primary cost" },
+                "avg_similarity": { "type": "number", "nullable": true, "description": "Mean word overlap of the answers, 0-1" },
+            } } },
+            "recent": { "type": "array", "items": { "type": "object", "description": "Both sides' status, latency, cost and text (or `text_digest`, as the snippet policy allows)" } },
+        } },
+        "UsageReports": { "type": "object", "properties": {
+            "period": { "type": "string", "enum": ["day", "week"] },
+            "reports": { "type": "array", "items": { "type": "object", "properties": {
+                "start": { "type": "integer" },
+                "end": { "type": "integer" },
+                "totals": { "$ref": "#/components/schemas/UsageSummary" },
+                "tenants": { "type": "array", "items": { "$ref": "#/components/schemas/UsageSummary" } },
+            } } },
+        } },
+        "UsageSummary": { "type": "object", "properties": {
+            "tenant": { "type": "string", "description": "Absent for the totals and for traffic without a tenant" },
+            "requests": { "type": "integer" },
+            "cost_usd": { "type": "number" },
+            "saved_usd": { "type": "number" },
+            "interventions": { "type": "object", "additionalProperties": { "type": "integer" } },
+            "top_sessions": { "type": "array", "items": { "type": "object", "properties": {
+                "session_id": { "type": "string" },
+                "cost_usd": { "type": "number" },
+                "interventions": { "type": "integer" },
+            } } },
+        } },
+        "ShadowSide": { "type": "object", "properties": {
+            "errors": { "type": "integer" },
+            "avg_latency_ms": { "type": "number" },
+            "cost_usd": { "type": "number" },
+            "avg_completion_tokens": { "type": "number" },
+        } },
+        "Readiness": { "type": "object", "properties": {
+            "status": { "type": "string", "enum": ["ready", "not_ready"] },
+            "storage": { "type": "string", "enum": ["ok", "fail"] },
+            "providers": { "type": "object", "additionalProperties": { "type": "string", "enum": ["ok", "fail"] } },
+        } },
+        "ReadinessReport": { "type": "object", "properties": {
+            "status": { "type": "string", "enum": ["ready", "not_ready"] },
+            "config": { "type": "object", "properties": { "warnings": { "type": "array", "items": { "type": "string" } } } },
+            "storage": { "$ref": "#/components/schemas/Check" },
+            "providers": { "type": "object", "additionalProperties": { "$ref": "#/components/schemas/Check" } },
+        } },
+        "Check": { "type": "object", "properties": {
+            "ok": { "type": "boolean" },
+            "error": { "type": "string" },
+            "checked_at": { "type": "integer" },
+        } },
+        "ErasureReceipt": { "type": "object", "properties": {
+            "subject": { "type": "string" },
+            "erased_at": { "type": "integer" },
+            "sessions": { "type": "integer", "description": "Live sessions dropped" },
+            "prompts": { "type": "integer" },
+            "embeddings": { "type": "integer" },
+            "fingerprints": { "type": "integer" },
+            "quarantined_requests": { "type": "integer" },
+            "idempotent_responses": { "type": "integer" },
+            "usage_entries": { "type": "integer", "description": "Per-day usage entries of the erased sessions; day totals stay" },
+            "shadow_records": { "type": "integer", "description": "Shadow model comparisons of the erased sessions" },
+            "schemas": { "type": "integer", "description": "Output schemas registered for the erased sessions" },
+            "audit_snippets": { "type": "integer" },
+            "receipt_id": { "type": "string", "description": "SHA-256 over the other fields" },
+        } },
+        "ChainReport": { "type": "object", "properties": {
+            "ok": { "type": "boolean" },
+            "verified": { "type": "integer" },
+            "unchained": { "type": "integer", "description": "Entries written before the chain existed" },
+            "checkpoints": { "type": "integer" },
+            "signed_through": { "type": ["integer", "null"], "description": "Newest entry covered by a valid signature" },
+            "head": { "type": ["string", "null"], "description": "Hash of the newest entry" },
+            "broken_at": { "type": ["integer", "null"] },
+            "problem": { "type": ["string", "null"] },
+        } },
+        "Tenant": { "type": "object", "properties": {
+            "name": { "type": "string" },
+            "spent_usd": { "type": "number" },
+            "budget_usd": { "type": ["number", "null"] },
+            "active_sessions": { "type": "integer" },
+            "interventions": { "type": "integer", "description": "Across the tenant's live sessions" },
+        } },
+        "Stats": {
+            "type": "object",
+            "additionalProperties": true,
+            "properties": {
+                "active_sessions": { "type": "integer" },
+                "total_saved_usd": { "type": "number" },
+                "status": { "type": "string", "enum": ["Healthy", "Degraded"] },
+                "startup_problems": { "type": "array", "items": { "type": "string" } },
+                "provider_keys": { "type": "object", "description": "Per provider: `active` (index of the key in use), `rotated_at` and `keys`, each with its last four characters, `ok`, `error` and `checked_at`" },
+                "embedding_history_bytes": { "type": "integer" },
+                "fingerprinted_users": { "type": "integer", "description": "Users with prompt fingerprints in the cross-session loop window" },
+                "instance": { "type": "string", "description": "`SENTINEL_INSTANCE_ID`; the other counters are this instance's" },
+                "cluster": { "type": ["object", "null"], "description": "Totals across instances (`total`) and per instance (`instances`), when counters are shared through Postgres" },
+            },
+        },
+        "SessionPage": { "type": "object", "properties": {
+            "total": { "type": "integer" },
+            "offset": { "type": "integer" },
+            "limit": { "type": "integer" },
+            "sessions": { "type": "array", "items": { "$ref": "#/components/schemas/SessionSummary" } },
+        } },
+        "SessionSummary": { "type": "object", "properties": {
+            "id": { "type": "string" },
+            "cumulative_cost": { "type": "number" },
+            "created_at": { "type": "integer" },
+            "last_activity": { "type": "integer" },
+            "interventions": { "type": "integer" },
+            "history_len": { "type": "integer" },
+            "blocked": { "type": "boolean" },
+        } },
+        "SessionDetail": { "type": "object", "additionalProperties": true, "properties": {
+            "id": { "type": "string" },
+            "cumulative_cost": { "type": "number" },
+            "interventions": { "type": "integer" },
+            "cost_trajectory": { "type": "array", "items": { "type": "number" } },
+            "recent_prompts": { "type": "array", "items": { "type": "string" } },
+            "semantic_similarity": { "type": "array", "items": { "type": ["number", "null"] } },
+            "fuzzy_similarity": { "type": "array", "items": { "type": "number" } },
+        } },
+        "Reloaded": { "type": "object", "properties": {
+            "reloaded": { "type": "boolean" },
+            "problems": { "type": "array", "items": { "type": "string" } },
+            "restart_required": { "type": "array", "items": { "type": "string" }, "description": "Changed settings that only apply after a restart" },
+        } },
+        "BlockRequest": { "type": "object", "properties": { "reason": { "type": "string" } } },
+        "Ban": { "type": "object", "properties": {
+            "ip": { "type": "string" },
+            "reason": { "type": "string" },
+            "banned_at": { "type": "integer" },
+            "expires_at": { "type": ["integer", "null"], "description": "Null until lifted" },
+            "manual": { "type": "boolean", "description": "Placed by an operator rather than the rate limit" },
+        } },
+        "BanRequest": {
+            "type": "object",
+            "required": ["ip"],
+            "properties": {
+                "ip": { "type": "string" },
+                "reason": { "type": "string" },
+                "secs": { "type": "integer", "description": "Omit to ban until lifted" },
+            },
+        },
+        "FeedbackRequest": {
+            "type": "object",
+            "required": ["verdict"],
+            "properties": { "verdict": { "type": "string", "enum": ["correct", "false_positive"] } },
+        },
+        "QuarantineEntry": { "type": "object", "additionalProperties": true, "properties": {
+            "id": { "type": "integer" },
+            "created_at": { "type": "integer" },
+            "resolved_at": { "type": ["integer", "null"] },
+            "detector": { "type": "string" },
+            "reason": { "type": "string" },
+            "status": { "type": "string", "enum": ["pending", "approved", "denied", "completed", "failed"] },
+            "upstream_status": { "type": ["integer", "null"] },
+            "result": { "description": "Upstream response once approved" },
+        } },
+        "JsonRpcRequest": {
+            "type": "object",
+            "required": ["jsonrpc", "method"],
+            "properties": {
+                "jsonrpc": { "const": "2.0" },
+                "id": { "type": ["integer", "string", "null"] },
+                "method": { "type": "string" },
+                "params": { "type": "object" },
+            },
+        },
+        "JsonRpcResponse": {
+            "type": "object",
+            "properties": {
+                "jsonrpc": { "const": "2.0" },
+                "id": { "type": ["integer", "string", "null"] },
+                "result": {},
+                "error": { "type": "object", "properties": { "code": { "type": "integer" }, "message": { "type": "string" } } },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documents_every_route_and_resolves_refs() {
+        let doc = document();
+        let main = include_str!("main.rs");
+        let routes: Vec<&str> = main.lines()
+            .filter_map(|l| l.trim().strip_prefix(".route(\""))
+            .filter_map(|l| l.split('"').next())
+            .collect();
+        assert!(routes.len() > 20);
+        for route in routes {
+            assert!(doc["paths"].get(route).is_some(), "{} is not in the OpenAPI document", route);
+        }
+        assert_eq!(doc["paths"]["/api/config/reload"]["post"]["x-sentinel-role"], "admin");
+        assert_eq!(doc["paths"]["/api/sessions/{id}/block"]["post"]["x-sentinel-role"], "operator");
+        assert!(doc["paths"]["/mcp"]["delete"]["responses"].get("204").is_some());
+
+        fn refs(v: &Value, out: &mut Vec<String>) {
+            match v {
+                Value::Object(m) => {
+                    if let Some(r) = m.get("$ref").and_then(Value::as_str) { out.push(r.to_string()); }
+                    m.values().for_each(|v| refs(v, out));
+                }
+                Value::Array(a) => a.iter().for_each(|v| refs(v, out)),
+                _ => {}
+            }
+        }
+        let mut all = Vec::new();
+        refs(&doc, &mut all);
+        for r in all {
+            let pointer = r.trim_start_matches('#');
+            assert!(doc.pointer(pointer).is_some(), "dangling {}", r);
+        }
+    }
+}
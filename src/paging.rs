@@ -0,0 +1,218 @@
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::header::ContentType};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::config::PagingPolicy;
+use crate::notify::{Notice, Severity};
+
+// --- ON-CALL PAGING ---
+// Notices at or above `SENTINEL_PAGE_MIN_SEVERITY` (critical: leaks, the
+// kill switch, exhausted budgets, and whatever `SENTINEL_DETECTOR_SEVERITY`
+// raises) page on-call through every configured channel: a PagerDuty Events
+// v2 integration, Opsgenie, and/or email over SMTP. Loop blocks and other
+// warnings stay in chat (`notify.rs`). Pages are sent at once, not batched;
+// the same detector and session pages at most once per
+// `SENTINEL_PAGE_COOLDOWN_SECS`, and PagerDuty / Opsgenie group repeats
+// under one incident by their dedup key. So that a detector firing across
+// many sessions at once doesn't page for each of them, one detector pages at
+// most `SENTINEL_PAGE_MAX_PER_DETECTOR` (5; 0 sets no ceiling) times per
+// cooldown; the rest stay in chat and the audit log.
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+#[derive(Default)]
+pub struct Pager {
+    /// When each dedup key last paged, and for which detector.
+    last_paged: Mutex<HashMap<String, (String, u64)>>,
+}
+
+impl Pager {
+    /// Whether `key` may page for `detector` at `now`; records the page if so.
+    fn admit(&self, key: &str, detector: &str, now: u64, policy: &PagingPolicy) -> bool {
+        let mut last_paged = self.last_paged.lock().unwrap_or_else(|p| p.into_inner());
+        last_paged.retain(|_, (_, t)| now.saturating_sub(*t) < policy.cooldown_secs);
+        if last_paged.contains_key(key) {
+            return false;
+        }
+        let recent = last_paged.values().filter(|(d, _)| d == detector).count();
+        if policy.max_per_detector > 0 && recent >= policy.max_per_detector {
+            tracing::debug!("Not paging for {}: {} pages from `{}` within the cooldown", key, recent, detector);
+            return false;
+        }
+        last_paged.insert(key.to_string(), (detector.to_string(), now));
+        true
+    }
+}
+
+/// Pages on-call about `notice` if it is severe enough and not cooling down.
+pub fn page(state: &AppState, notice: &Notice) {
+    let policy = &state.config.paging;
+    if notice.severity < policy.min_severity || !policy.enabled() {
+        return;
+    }
+    let key = dedup_key(notice);
+    if !state.pager.admit(&key, &notice.title, crate::now_secs(), policy) {
+        return;
+    }
+    tracing::warn!("📟 Paging on-call: {}", notice.summary());
+
+    if let Some(routing_key) = &policy.pagerduty_routing_key {
+        post("PagerDuty", state.client.post(PAGERDUTY_URL).json(&pagerduty_event(routing_key, notice, &key)));
+    }
+    if let Some(api_key) = &policy.opsgenie_api_key {
+        let request = state.client.post(&policy.opsgenie_url)
+            .header("Authorization", format!("GenieKey {}", api_key))
+            .json(&opsgenie_alert(notice, &key));
+        post("Opsgenie", request);
+    }
+    if policy.email_enabled() {
+        let (policy, notice) = (policy.clone(), notice.clone());
+        tokio::spawn(async move {
+            if let Err(e) = email(&policy, &notice).await {
+                tracing::error!("Alert email failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Groups repeats of one problem: the detector (or budget) and the session,
+/// or the reason when there is no session.
+fn dedup_key(notice: &Notice) -> String {
+    format!("sentinel/{}/{}", notice.title, notice.session.as_deref().unwrap_or(&notice.reason))
+}
+
+fn post(service: &'static str, request: reqwest::RequestBuilder) {
+    tokio::spawn(async move {
+        let sent = request.timeout(SEND_TIMEOUT).send().await.and_then(|r| r.error_for_status());
+        if let Err(e) = sent {
+            tracing::error!("{} page failed: {}", service, e.without_url());
+        }
+    });
+}
+
+fn details(notice: &Notice) -> Value {
+    json!({
+        "session": notice.session,
+        "reason": notice.reason,
+        "cost": notice.cost,
+        "link": notice.link,
+    })
+}
+
+fn truncate(text: String, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text,
+    }
+}
+
+/// An Events API v2 trigger; its severities match ours by name.
+fn pagerduty_event(routing_key: &str, notice: &Notice, key: &str) -> Value {
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": key,
+        "payload": {
+            "summary": truncate(notice.summary(), 1024),
+            "source": "sentinel",
+            "severity": notice.severity.as_str(),
+            "component": notice.title,
+            "custom_details": details(notice),
+        },
+        "links": notice.link.iter().map(|href| json!({"href": href, "text": "Sentinel audit log"})).collect::<Vec<_>>(),
+    })
+}
+
+fn opsgenie_alert(notice: &Notice, key: &str) -> Value {
+    let priority = match notice.severity {
+        Severity::Critical => "P1",
+        Severity::Warning => "P3",
+        Severity::Info => "P5",
+    };
+    json!({
+        "message": truncate(notice.summary(), 130),
+        "alias": key,
+        "description": notice.reason,
+        "priority": priority,
+        "source": "sentinel",
+        "tags": ["sentinel", notice.title],
+        "details": details(notice),
+    })
+}
+
+async fn email(policy: &PagingPolicy, notice: &Notice) -> Result<(), String> {
+    let mut body = vec![
+        format!("Severity: {}", notice.severity.as_str()),
+        format!("Event: {}", notice.title),
+    ];
+    body.extend(notice.session.as_ref().map(|s| format!("Session: {}", s)));
+    body.push(format!("Reason: {}", notice.reason));
+    body.extend(notice.cost.as_ref().map(|c| format!("Cost: {}", c)));
+    body.extend(notice.link.as_ref().map(|l| format!("Log entry: {}", l)));
+    send_email(policy, &policy.email_to, &truncate(notice.summary(), 200), body.join("\n")).await
+}
+
+/// Sends a plain-text email through `SENTINEL_SMTP_URL`, from
+/// `SENTINEL_ALERT_EMAIL_FROM`; does nothing when either is unset.
+pub async fn send_email(policy: &PagingPolicy, to: &[String], subject: &str, body: String) -> Result<(), String> {
+    let (Some(url), Some(from)) = (&policy.smtp_url, &policy.email_from) else {
+        return Ok(());
+    };
+    let mut message = Message::builder()
+        .from(from.parse().map_err(|e| format!("SENTINEL_ALERT_EMAIL_FROM: {}", e))?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in to {
+        message = message.to(to.parse().map_err(|e| format!("recipient `{}`: {}", to, e))?);
+    }
+    let message = message.body(body).map_err(|e| e.to_string())?;
+
+    let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+        .map_err(|e| format!("SENTINEL_SMTP_URL: {}", e))?
+        .timeout(Some(SEND_TIMEOUT))
+        .build();
+    transport.send(message).await.map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_bodies_and_cooldown() {
+        let notice = Notice {
+            severity: Severity::Critical,
+            title: "leak".to_string(),
+            session: Some("acme/u1".to_string()),
+            reason: "API key in completion".to_string(),
+            cost: None,
+            link: Some("https://s.example/api/logs?before=8&limit=1".to_string()),
+        };
+        let key = dedup_key(&notice);
+        let event = pagerduty_event("rk", &notice, &key);
+        assert_eq!(event["dedup_key"], "sentinel/leak/acme/u1");
+        assert_eq!(event["payload"]["severity"], "critical");
+        assert_eq!(event["links"][0]["href"], "https://s.example/api/logs?before=8&limit=1");
+        assert_eq!(opsgenie_alert(&notice, &key)["priority"], "P1");
+        assert_eq!(truncate("ééé".to_string(), 2), "éé");
+
+        let pager = Pager::default();
+        let policy = PagingPolicy { max_per_detector: 2, ..Default::default() };
+        assert!(pager.admit(&key, "leak", 1_000, &policy));
+        assert!(!pager.admit(&key, "leak", 1_299, &policy));
+        assert!(pager.admit("sentinel/leak/other", "leak", 1_299, &policy));
+        // The detector's ceiling holds for new sessions, not other detectors.
+        assert!(!pager.admit("sentinel/leak/third", "leak", 1_299, &policy));
+        assert!(pager.admit("sentinel/kill_switch/all", "kill_switch", 1_299, &policy));
+        assert!(pager.admit(&key, "leak", 1_300, &policy));
+        assert!(!pager.admit("sentinel/leak/third", "leak", 1_300, &policy));
+        assert!(pager.admit("sentinel/leak/third", "leak", 1_599, &policy));
+        let unlimited = PagingPolicy { max_per_detector: 0, ..Default::default() };
+        assert!((0..20).all(|n| pager.admit(&format!("sentinel/leak/s{}", n), "leak", 2_000, &unlimited)));
+    }
+}
@@ -0,0 +1,370 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::Instrument;
+
+use crate::audit::{AUDIT_TARGET, LogContext, record_bypass, record_dry_run, record_intervention};
+use crate::config::{CostPolicy, DetectorMode};
+use crate::events::{Event, RequestEvent};
+use crate::metrics::UpstreamOutcome;
+use crate::{AppState, routing, savings, telemetry};
+
+// --- PASSTHROUGH ENDPOINTS ---
+// Non-generation OpenAI endpoints, proxied so Sentinel can be an agent's only
+// egress. They get routing, the kill switch, budget enforcement, cost booking
+// and audit logging, but none of the text detectors.
+
+/// Where a passthrough request goes, after routing.
+struct Target {
+    session_id: String,
+    provider: String,
+    budget_pool: Option<String>,
+    tenant: Option<String>,
+    cost_policy: CostPolicy,
+    /// Allowlisted client headers to pass on.
+    forward_headers: HeaderMap,
+}
+
+/// Shared preamble: session, kill switch, routing and the session budget.
+/// `Err` is the response to send instead of forwarding.
+async fn prepare(state: &AppState, headers: &HeaderMap, model: &str, user: Option<&str>, body: &serde_json::Value) -> Result<Target, Response> {
+    if let Some(rejected) = crate::tenancy::qualified_session(user) {
+        return Err(rejected);
+    }
+    let session_id = crate::session_id(headers, user);
+    if let Some(blocked) = crate::kill_switch(state, headers, &session_id, model, body).await {
+        return Err(blocked);
+    }
+    state.timeseries.record_request(crate::now_secs());
+
+    let route = routing::resolve(&state.config.routing_rules, &routing::RequestView { headers, model, body });
+    let provider = route.provider.unwrap_or_else(|| "openai".to_string());
+    let tenant = crate::tenancy::of(headers).map(str::to_string);
+    state.usage.record_request(crate::now_secs(), tenant.as_deref());
+    let (cost_policy, _) = state.config.tenant_policies(tenant.as_deref(), model);
+    let log_ctx = LogContext::new(&session_id, model).provider(&provider).tenant(tenant.as_deref());
+
+    let (over_budget, budget) = {
+        let mut sess = state.sessions.entry(session_id.clone()).or_default();
+        sess.touch();
+        (sess.cumulative_cost > sess.budget(&cost_policy), sess.budget(&cost_policy))
+    };
+    let mode = state.config.tenant_detector_mode(tenant.as_deref(), "cost_spike");
+    if over_budget && mode != DetectorMode::Off {
+        let reason = "Session Budget Exhausted";
+        let snippet = format!("Budget: ${:.2}", budget);
+        if state.config.is_exempt(&session_id, crate::client_api_key(headers), "cost_spike") {
+            record_bypass(state, &log_ctx, "cost_spike", reason).await;
+        } else if mode != DetectorMode::Block {
+            // These responses have no message to annotate, so `warn` logs too.
+            record_dry_run(state, &log_ctx, "cost_spike", reason, snippet).await;
+        } else {
+            record_intervention(
+                state, &log_ctx, "cost_spike", reason, snippet,
+                savings::avoided(&state.config, "cost_spike", model, Some(body)),
+            ).await;
+            let marks = crate::intervention_headers("blocked", "cost_spike", reason);
+            return Err((StatusCode::TOO_MANY_REQUESTS, marks, Json(serde_json::json!({
+                "error": {
+                    "message": state.config.messages.render(
+                        &state.config.messages.locale_for(headers), "budget_exhausted",
+                        &[("budget", &format!("{:.2}", budget)), ("session", &session_id)],
+                    ),
+                    "type": "sentinel_budget",
+                    "param": null,
+                    "code": "budget_exhausted"
+                }
+            }))).into_response());
+        }
+    }
+
+    let forward_headers = state.config.headers.forward(headers);
+    Ok(Target { session_id, provider, budget_pool: route.budget_pool, tenant, cost_policy, forward_headers })
+}
+
+/// One audit line per egress call, for the `SENTINEL_LOG_AUDIT_FILE` trail,
+/// and a request event.
+fn log_egress(state: &AppState, target: &Target, endpoint: &str, model: &str, status: StatusCode, cost_usd: f64, response: &serde_json::Value) {
+    crate::events::emit(state, Event::Request(RequestEvent {
+        status: status.as_u16(),
+        cost_usd,
+        ..RequestEvent::new(&target.session_id, target.tenant.as_deref(), &target.provider, endpoint, model).with_usage(response)
+    }));
+    tracing::info!(
+        target: AUDIT_TARGET,
+        session_id = %target.session_id,
+        provider = %target.provider,
+        endpoint,
+        model,
+        status = status.as_u16(),
+        cost_usd,
+        "egress"
+    );
+}
+
+/// POSTs `body` to `path` on the target's provider. The returned headers are
+/// the allowlisted subset of the upstream response's.
+async fn post_upstream(state: &AppState, target: &Target, model: &str, path: &str, body: &serde_json::Value) -> Result<(StatusCode, HeaderMap, serde_json::Value), Response> {
+    let upstream = state.provider(target.tenant.as_deref(), &target.provider)
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e).into_response())?;
+    let url = upstream.endpoint(path);
+    let span = tracing::info_span!("upstream", provider = %target.provider, url = %url);
+    let sent_at = std::time::Instant::now();
+    let res = if target.provider == crate::mock::PROVIDER && state.config.mock.enabled {
+        Ok(crate::mock::respond(&state.config.mock, path, body).await)
+    } else {
+        state.client
+            .post(&url)
+            .headers(target.forward_headers.clone())
+            .header("Authorization", format!("Bearer {}", upstream.api_key))
+            .headers(telemetry::trace_headers(&span))
+            .json(body)
+            .send()
+            .instrument(span)
+            .await
+    };
+    state.latency.observe_upstream(&target.provider, model, sent_at.elapsed(), UpstreamOutcome::of(&res));
+    if res.as_ref().is_ok_and(|r| r.status() == StatusCode::UNAUTHORIZED) {
+        crate::keys::rejected(state, &target.provider, &upstream.api_key);
+    }
+
+    let res = res.map_err(|e| crate::upstream_error(&e))?;
+    let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let headers = state.config.headers.returned(res.headers());
+    Ok((status, headers, crate::read_body(state, &target.provider, model, res).await?))
+}
+
+// --- EMBEDDINGS ---
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmbeddingsRequest {
+    model: String,
+    input: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    /// `dimensions`, `encoding_format`, ...; part of the cache key.
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Cached upstream responses, so agents re-embedding the same text don't pay
+/// twice. `user` is not part of the key: the same input embeds identically.
+#[derive(Debug, Default)]
+pub struct EmbeddingCache {
+    /// Key -> (inserted at, session that paid for it, response body).
+    entries: DashMap<u64, (u64, String, serde_json::Value)>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    saved_micro_usd: AtomicU64,
+}
+
+impl EmbeddingCache {
+    fn key(provider: &str, req: &EmbeddingsRequest) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        provider.hash(&mut hasher);
+        req.model.hash(&mut hasher);
+        req.input.to_string().hash(&mut hasher);
+        serde_json::Value::Object(req.extra.clone()).to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(&self, key: u64, now: u64, ttl_secs: u64) -> Option<serde_json::Value> {
+        let entry = self.entries.get(&key)?;
+        let (inserted_at, _, body) = entry.value();
+        if ttl_secs > 0 && now.saturating_sub(*inserted_at) > ttl_secs {
+            drop(entry);
+            self.entries.remove(&key);
+            return None;
+        }
+        Some(body.clone())
+    }
+
+    fn insert(&self, key: u64, now: u64, session_id: &str, body: serde_json::Value, max_entries: usize) {
+        if self.entries.len() >= max_entries {
+            let oldest = self.entries.iter().min_by_key(|e| e.value().0).map(|e| *e.key());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (now, session_id.to_string(), body));
+    }
+
+    /// Drops the entries first embedded for sessions `erase` selects;
+    /// returns how many.
+    pub fn forget_sessions(&self, erase: impl Fn(&str) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, (_, session_id, _)| !erase(session_id));
+        before.saturating_sub(self.entries.len())
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "entries": self.entries.len(),
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "saved_usd": self.saved_micro_usd.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        })
+    }
+}
+
+/// `POST /v1/embeddings`. Cache hits cost nothing and carry `x-sentinel-cache: hit`.
+pub async fn embeddings(State(state): State<AppState>, headers: HeaderMap, Json(req): Json<EmbeddingsRequest>) -> Response {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    let target = match prepare(&state, &headers, &req.model, req.user.as_deref(), &body).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let policy = &state.config.embedding_cache;
+    let cache = &state.embedding_cache;
+    let key = policy.enabled.then(|| EmbeddingCache::key(&target.provider, &req));
+    if let Some(hit) = key.and_then(|k| cache.get(k, crate::now_secs(), policy.ttl_secs)) {
+        let avoided = crate::usage_cost(&state.config.pricing, &req.model, &hit);
+        cache.hits.fetch_add(1, Ordering::Relaxed);
+        cache.saved_micro_usd.fetch_add((avoided * 1_000_000.0).round() as u64, Ordering::Relaxed);
+        return ([("x-sentinel-cache", "hit")], Json(hit)).into_response();
+    }
+
+    let (status, upstream_headers, response) = match post_upstream(&state, &target, &req.model, "embeddings", &body).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+    let mut cost = 0.0;
+    if status.is_success() {
+        cost = crate::usage_cost(&state.config.pricing, &req.model, &response);
+        crate::book_cost(&state, &target.session_id, target.budget_pool.as_deref(), target.tenant.as_deref(), cost, &target.cost_policy);
+        if let Some(key) = key {
+            cache.misses.fetch_add(1, Ordering::Relaxed);
+            cache.insert(key, crate::now_secs(), &target.session_id, response.clone(), policy.max_entries);
+        }
+    }
+    log_egress(&state, &target, "embeddings", &req.model, status, cost, &response);
+    (status, upstream_headers, [("x-sentinel-cache", "miss")], Json(response)).into_response()
+}
+
+// --- MODERATIONS ---
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModerationsRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    input: serde_json::Value,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `POST /v1/moderations`. Free upstream, so only routed and audited.
+pub async fn moderations(State(state): State<AppState>, headers: HeaderMap, Json(req): Json<ModerationsRequest>) -> Response {
+    let model = req.model.clone().unwrap_or_else(|| "omni-moderation-latest".to_string());
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    let target = match prepare(&state, &headers, &model, None, &body).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let (status, upstream_headers, response) = match post_upstream(&state, &target, &model, "moderations", &body).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+    log_egress(&state, &target, "moderations", &model, status, 0.0, &response);
+    (status, upstream_headers, Json(response)).into_response()
+}
+
+// --- IMAGES ---
+// Only JSON generation is proxied; edits and variations are multipart uploads.
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImagesRequest {
+    /// OpenAI's default when the field is omitted.
+    #[serde(default = "default_image_model")]
+    model: String,
+    prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quality: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn default_image_model() -> String {
+    "dall-e-2".to_string()
+}
+
+/// `POST /v1/images/generations`. Priced per image (or per token for
+/// token-billed models) and booked against the session budget.
+pub async fn image_generations(State(state): State<AppState>, headers: HeaderMap, Json(req): Json<ImagesRequest>) -> Response {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    let target = match prepare(&state, &headers, &req.model, req.user.as_deref(), &body).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    let (status, upstream_headers, response) = match post_upstream(&state, &target, &req.model, "images/generations", &body).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    let mut cost = 0.0;
+    if status.is_success() {
+        let images = response["data"].as_array().map_or(req.n.unwrap_or(1), |d| d.len() as u64);
+        cost = state.config.pricing.image_cost(
+            &req.model,
+            req.size.as_deref().unwrap_or("1024x1024"),
+            req.quality.as_deref().unwrap_or("standard"),
+            images,
+            &response["usage"],
+        );
+        crate::book_cost(&state, &target.session_id, target.budget_pool.as_deref(), target.tenant.as_deref(), cost, &target.cost_policy);
+    }
+    log_egress(&state, &target, "images/generations", &req.model, status, cost, &response);
+    (status, upstream_headers, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(input: &str, user: Option<&str>) -> EmbeddingsRequest {
+        EmbeddingsRequest {
+            model: "text-embedding-3-small".to_string(),
+            input: serde_json::json!(input),
+            user: user.map(str::to_string),
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    #[test]
+    fn test_cache_key_ignores_user_and_expires() {
+        let cache = EmbeddingCache::default();
+        let key = EmbeddingCache::key("openai", &request("hello", Some("a")));
+        assert_eq!(key, EmbeddingCache::key("openai", &request("hello", Some("b"))));
+        assert_ne!(key, EmbeddingCache::key("openai", &request("hello!", None)));
+        assert_ne!(key, EmbeddingCache::key("groq", &request("hello", None)));
+
+        cache.insert(key, 100, "a", serde_json::json!({"data": []}), 10);
+        assert!(cache.get(key, 150, 60).is_some());
+        assert!(cache.get(key, 200, 60).is_none());
+        assert_eq!(cache.entries.len(), 0);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest_when_full() {
+        let cache = EmbeddingCache::default();
+        for (key, at) in [(1, 30), (2, 10), (3, 20)] {
+            cache.insert(key, at, "a", serde_json::Value::Null, 2);
+        }
+        assert!(cache.entries.contains_key(&1));
+        assert!(!cache.entries.contains_key(&2));
+        assert!(cache.entries.contains_key(&3));
+    }
+}
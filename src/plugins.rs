@@ -0,0 +1,289 @@
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::AppState;
+use crate::config::PluginPolicy;
+
+// --- POLICY PLUGINS ---
+// Custom checks without waiting for a release: built with `--features
+// plugins`, every `*.wasm` module in `SENTINEL_PLUGINS_DIR` is loaded at
+// startup and on every config reload, and consulted in file-name order. A
+// module exports `memory`, `sentinel_alloc(len: i32) -> i32`, and
+// `inspect_request` and/or `inspect_response`, both
+// `(ptr: i32, len: i32) -> i64`. Sentinel writes
+// `{"stage", "session_id", "model", "tenant", "body"}` as JSON into memory
+// from `sentinel_alloc` (response inspection also gets the forwarded
+// `request`) and calls the export. It returns 0 to allow, or
+// `ptr << 32 | len` of a JSON verdict in its memory:
+// `{"action": "allow"}`, `{"action": "block", "reason": "..."}` or
+// `{"action": "rewrite", "body": {...}}`.
+//
+// A rewrite is handed to the next module and then forwarded (or returned);
+// routing has already happened, so rewriting `model` doesn't reroute. The
+// first block wins and is logged as the `plugin` detector, so its mode and
+// exemptions apply as for built-in detectors. Requests are inspected before
+// loop detection, non-streaming responses after the built-in checks.
+//
+// Modules get no imports (no WASI, no host calls), a fresh instance per
+// call, `SENTINEL_PLUGIN_FUEL` units of fuel and `SENTINEL_PLUGIN_MAX_MEMORY_MB`
+// of memory. A module that traps, runs out, points its verdict outside its
+// memory, or answers something else is logged and skipped: plugins fail
+// open.
+
+pub const DETECTOR: &str = "plugin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Request,
+    Response,
+}
+
+impl Stage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Request => "request",
+            Stage::Response => "response",
+        }
+    }
+
+    #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+    fn export(self) -> &'static str {
+        match self {
+            Stage::Request => "inspect_request",
+            Stage::Response => "inspect_response",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Block { plugin: String, reason: String },
+    /// The body to continue with.
+    Rewrite(Value),
+}
+
+impl Verdict {
+    /// `plugin`'s answer; empty output means allow.
+    fn parse(plugin: &str, out: &[u8]) -> Result<Self, String> {
+        if out.is_empty() {
+            return Ok(Verdict::Allow);
+        }
+        let verdict: Value = serde_json::from_slice(out).map_err(|e| format!("verdict is not JSON: {}", e))?;
+        match verdict["action"].as_str() {
+            Some("allow") => Ok(Verdict::Allow),
+            Some("block") => Ok(Verdict::Block {
+                plugin: plugin.to_string(),
+                reason: verdict["reason"].as_str().unwrap_or("blocked by policy").to_string(),
+            }),
+            Some("rewrite") if verdict["body"].is_object() => Ok(Verdict::Rewrite(verdict["body"].clone())),
+            Some("rewrite") => Err("rewrite without an object `body`".to_string()),
+            other => Err(format!("unknown action {:?}", other)),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+pub struct Plugin {
+    /// File name without `.wasm`.
+    pub name: String,
+    stages: Vec<Stage>,
+    #[cfg(feature = "plugins")]
+    module: wasmtime::Module,
+}
+
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<Plugin>,
+}
+
+impl PluginHost {
+    pub fn load(policy: &PluginPolicy) -> Self {
+        let Some(dir) = &policy.dir else { return Self::default() };
+        #[cfg(not(feature = "plugins"))]
+        {
+            tracing::warn!("SENTINEL_PLUGINS_DIR={} ignored: built without the `plugins` feature", dir);
+            Self::default()
+        }
+        #[cfg(feature = "plugins")]
+        {
+            let plugins = wasm::load_dir(std::path::Path::new(dir));
+            tracing::info!("Loaded {} policy plugin(s) from {}", plugins.len(), dir);
+            Self { plugins }
+        }
+    }
+
+    fn run_all(&self, policy: &PluginPolicy, stage: Stage, mut input: Value, mut body: Value) -> Verdict {
+        let mut rewritten = false;
+        for plugin in self.plugins.iter().filter(|p| p.stages.contains(&stage)) {
+            input["body"] = body.clone();
+            let verdict = plugin.call(policy, stage, &input).and_then(|out| Verdict::parse(&plugin.name, &out));
+            match verdict {
+                Ok(Verdict::Allow) => {}
+                Ok(Verdict::Rewrite(new)) => {
+                    body = new;
+                    rewritten = true;
+                }
+                Ok(block) => return block,
+                Err(e) => tracing::warn!("Plugin `{}` skipped on the {}: {}", plugin.name, stage.as_str(), e),
+            }
+        }
+        if rewritten { Verdict::Rewrite(body) } else { Verdict::Allow }
+    }
+}
+
+impl Plugin {
+    #[cfg(feature = "plugins")]
+    fn call(&self, policy: &PluginPolicy, stage: Stage, input: &Value) -> Result<Vec<u8>, String> {
+        wasm::call(&self.module, policy, stage.export(), input.to_string().as_bytes())
+    }
+
+    #[cfg(not(feature = "plugins"))]
+    fn call(&self, _policy: &PluginPolicy, _stage: Stage, _input: &Value) -> Result<Vec<u8>, String> {
+        Err("built without the `plugins` feature".to_string())
+    }
+}
+
+/// Runs every plugin for `stage` over `body`; `context` carries the other
+/// input fields.
+pub async fn inspect(state: &AppState, stage: Stage, mut context: Value, body: &Value) -> Verdict {
+    let host: Arc<PluginHost> = state.plugins.read().unwrap_or_else(|p| p.into_inner()).clone();
+    if !host.plugins.iter().any(|p| p.stages.contains(&stage)) {
+        return Verdict::Allow;
+    }
+    context["stage"] = Value::from(stage.as_str());
+    let (policy, body) = (state.config.plugins.clone(), body.clone());
+    tokio::task::spawn_blocking(move || host.run_all(&policy, stage, context, body))
+        .await
+        .unwrap_or(Verdict::Allow)
+}
+
+#[cfg(feature = "plugins")]
+mod wasm {
+    use std::path::Path;
+    use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    use super::{Plugin, Stage};
+    use crate::config::PluginPolicy;
+
+    pub fn load_dir(dir: &Path) -> Vec<Plugin> {
+        let engine = match Engine::new(Config::new().consume_fuel(true)) {
+            Ok(engine) => engine,
+            Err(e) => {
+                tracing::error!("Cannot start the plugin engine: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut paths: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|x| x == "wasm"))
+                .collect(),
+            Err(e) => {
+                tracing::error!("Cannot read SENTINEL_PLUGINS_DIR {}: {}", dir.display(), e);
+                return Vec::new();
+            }
+        };
+        paths.sort();
+        paths.iter().filter_map(|path| {
+            compile(&engine, path).inspect_err(|e| tracing::error!("Skipping plugin {}: {}", path.display(), e)).ok()
+        }).collect()
+    }
+
+    fn compile(engine: &Engine, path: &Path) -> Result<Plugin, String> {
+        let module = Module::from_file(engine, path).map_err(|e| e.to_string())?;
+        if module.imports().next().is_some() {
+            return Err("plugins may not import anything".to_string());
+        }
+        let exports: Vec<&str> = module.exports().map(|e| e.name()).collect();
+        for required in ["memory", "sentinel_alloc"] {
+            if !exports.contains(&required) {
+                return Err(format!("missing export `{}`", required));
+            }
+        }
+        let stages: Vec<Stage> = [Stage::Request, Stage::Response].into_iter().filter(|s| exports.contains(&s.export())).collect();
+        if stages.is_empty() {
+            return Err("exports neither `inspect_request` nor `inspect_response`".to_string());
+        }
+        let name = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        Ok(Plugin { name, stages, module })
+    }
+
+    /// Calls `export` on a fresh instance with `input`; the verdict bytes,
+    /// empty for allow.
+    pub fn call(module: &Module, policy: &PluginPolicy, export: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+        let limits = StoreLimitsBuilder::new().memory_size(policy.max_memory_mb as usize * 1024 * 1024).build();
+        let mut store: Store<StoreLimits> = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(policy.fuel).map_err(|e| e.to_string())?;
+        let instance = Instance::new(&mut store, module, &[]).map_err(|e| e.to_string())?;
+        let memory = instance.get_memory(&mut store, "memory").ok_or("`memory` is not a memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "sentinel_alloc").map_err(|e| e.to_string())?;
+        let inspect = instance.get_typed_func::<(i32, i32), i64>(&mut store, export).map_err(|e| e.to_string())?;
+
+        let len = i32::try_from(input.len()).map_err(|_| "input too large")?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+        memory.write(&mut store, ptr as u32 as usize, input).map_err(|e| e.to_string())?;
+        let packed = inspect.call(&mut store, (ptr, len)).map_err(|e| e.to_string())? as u64;
+        if packed == 0 {
+            return Ok(Vec::new());
+        }
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if out_ptr.checked_add(out_len).is_none_or(|end| end > memory.data_size(&store)) {
+            return Err(format!("verdict at {}+{} is outside the module's memory", out_ptr, out_len));
+        }
+        let mut out = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut out).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdicts_parse_and_rewrites_chain() {
+        assert_eq!(Verdict::parse("p", b""), Ok(Verdict::Allow));
+        assert_eq!(Verdict::parse("p", br#"{"action":"allow"}"#), Ok(Verdict::Allow));
+        assert_eq!(
+            Verdict::parse("pii", br#"{"action":"block","reason":"SSN in prompt"}"#),
+            Ok(Verdict::Block { plugin: "pii".to_string(), reason: "SSN in prompt".to_string() }),
+        );
+        let rewrite = Verdict::parse("p", br#"{"action":"rewrite","body":{"model":"gpt-4o-mini"}}"#).unwrap();
+        assert_eq!(rewrite, Verdict::Rewrite(serde_json::json!({"model": "gpt-4o-mini"})));
+        assert!(Verdict::parse("p", br#"{"action":"rewrite"}"#).is_err());
+        assert!(Verdict::parse("p", b"yes").is_err());
+
+        // No plugins: everything is allowed.
+        let host = PluginHost::default();
+        let verdict = host.run_all(&PluginPolicy::default(), Stage::Request, serde_json::json!({}), serde_json::json!({"a": 1}));
+        assert_eq!(verdict, Verdict::Allow);
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn test_wasm_verdicts_are_read_inside_memory_only() {
+        // Blocks requests with the verdict at 1024; answers responses with
+        // a 4 GiB verdict at 0, which must be refused before it is read.
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 1024) "{\22action\22:\22block\22,\22reason\22:\22no\22}")
+            (func (export "sentinel_alloc") (param i32) (result i32) i32.const 0)
+            (func (export "inspect_request") (param i32 i32) (result i64) i64.const 0x0000040000000020)
+            (func (export "inspect_response") (param i32 i32) (result i64) i64.const 0x00000000ffffffff))"#;
+        let dir = std::env::temp_dir().join(format!("sentinel-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("guard.wasm"), wat).unwrap();
+        let policy = PluginPolicy { dir: Some(dir.to_string_lossy().into_owned()), ..Default::default() };
+        let host = PluginHost::load(&policy);
+        assert_eq!(host.plugins.len(), 1);
+
+        let input = serde_json::json!({"session_id": "s", "model": "gpt-4o"});
+        let verdict = host.run_all(&policy, Stage::Request, input.clone(), serde_json::json!({}));
+        assert_eq!(verdict, Verdict::Block { plugin: "guard".to_string(), reason: "no".to_string() });
+        let err = host.plugins[0].call(&policy, Stage::Response, &input).unwrap_err();
+        assert!(err.contains("outside the module's memory"), "{}", err);
+        assert_eq!(host.run_all(&policy, Stage::Response, input, serde_json::json!({})), Verdict::Allow);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,448 @@
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::SessionState;
+use crate::audit::InterventionLog;
+use crate::config::{AuditPolicy, StoragePolicy};
+use crate::keys::Ring;
+use crate::pricing::ModelPrice;
+use crate::reports::SavedUsage;
+
+// --- POSTGRES STORAGE ---
+// With `SENTINEL_STORAGE_KIND=postgres` the audit log lives in the
+// `audit_log` table instead of the JSONL file; retention, compaction,
+// erasure and archival behave the same. Audit ids are counted per process,
+// so rows are keyed by the instance that wrote them as well, but every
+// instance reads, compacts and erases all of them. Migrations in
+// `migrations/` are embedded in the binary and run on startup. Each
+// instance also saves its sessions, tenant and pool spend, provider key
+// state and usage ledger on every session sweep and loads them on startup
+// (`sessions.rs`); prices in
+// `sentinel_pricing` take precedence over the configured ones; aggregate
+// counters are shared through `sentinel_counters` (`counters.rs`).
+//
+// Writes go through one background task so appends never wait on the
+// database; reads flush that queue first, so they see every earlier write.
+// The pool and the writer run on a small runtime of their own, so the
+// synchronous `AuditStore` calls can wait on it from any thread, whatever
+// runtime (if any) the caller is on.
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
+
+const WRITE_BATCH: usize = 256;
+const WRITE_ATTEMPTS: u32 = 3;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("sentinel-postgres")
+            .enable_all()
+            .build()
+            .expect("cannot start the Postgres runtime")
+    })
+}
+
+/// Runs `f` to completion from synchronous `AuditStore` code. `f` runs on
+/// the store's runtime and a scoped thread waits for it, since a runtime
+/// cannot be blocked on from one of its own (or another's) async tasks.
+fn block_on<F>(f: F) -> F::Output
+where
+    F: std::future::Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| scope.spawn(|| runtime().block_on(f)).join())
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+enum Write {
+    Upsert(Box<InterventionLog>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// What an instance keeps in Postgres besides the audit log.
+#[derive(Default)]
+pub struct SavedState {
+    pub sessions: Vec<(String, SessionState)>,
+    pub tenant_spend: Vec<(String, f64)>,
+    pub pool_spend: Vec<(String, f64)>,
+    pub keys: Vec<(String, Ring)>,
+    pub usage: SavedUsage,
+}
+
+pub struct PgStore {
+    pool: PgPool,
+    /// `StoragePolicy::instance_id`, owner of the audit entries written here.
+    instance: String,
+    writes: mpsc::UnboundedSender<Write>,
+}
+
+impl PgStore {
+    /// Connects, applies pending migrations and starts the writer.
+    pub fn connect(policy: &StoragePolicy) -> Result<Self, String> {
+        let url = policy.database_url.as_deref().ok_or("SENTINEL_DATABASE_URL is not set")?;
+        block_on(async {
+            let pool = PgPoolOptions::new()
+                .max_connections(policy.max_connections)
+                .connect(url)
+                .await
+                .map_err(|e| format!("cannot connect to Postgres: {}", e))?;
+            MIGRATOR.run(&pool).await.map_err(|e| format!("migrations failed: {}", e))?;
+            let (writes, rx) = mpsc::unbounded_channel();
+            runtime().spawn(run_writer(pool.clone(), policy.instance_id.clone(), rx));
+            Ok(Self { pool, instance: policy.instance_id.clone(), writes })
+        })
+    }
+
+    pub fn ping(&self) -> Result<(), sqlx::Error> {
+        block_on(sqlx::query("SELECT 1").execute(&self.pool)).map(|_| ())
+    }
+
+    pub fn upsert(&self, log: &InterventionLog) {
+        let _ = self.writes.send(Write::Upsert(Box::new(log.clone())));
+    }
+
+    /// Waits until every write queued so far is committed (or given up on).
+    fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.writes.send(Write::Flush(done)).is_ok() {
+            let _ = block_on(wait);
+        }
+    }
+
+    /// All retained entries, from every instance, oldest first.
+    pub fn load(&self, policy: &AuditPolicy, now: u64) -> Result<Vec<InterventionLog>, sqlx::Error> {
+        Ok(self.load_rows(policy, now)?.into_iter().map(|(_, log)| log).collect())
+    }
+
+    /// Retained entries with the instance that wrote each, oldest first.
+    fn load_rows(&self, policy: &AuditPolicy, now: u64) -> Result<Vec<(String, InterventionLog)>, sqlx::Error> {
+        self.flush();
+        let since = if policy.retention_secs == 0 { 0 } else { now.saturating_sub(policy.retention_secs) };
+        let limit = if policy.max_entries == 0 { i64::MAX } else { policy.max_entries as i64 };
+        let rows: Vec<(String, Json<InterventionLog>)> = block_on(
+            sqlx::query_as("SELECT instance, entry FROM audit_log WHERE ts >= $1 ORDER BY ts DESC, id DESC LIMIT $2")
+                .bind(since as i64)
+                .bind(limit)
+                .fetch_all(&self.pool),
+        )?;
+        Ok(rows.into_iter().rev().map(|(instance, Json(log))| (instance, log)).collect())
+    }
+
+    /// Keeps only retained entries `keep` selects, applying `edit` to them.
+    /// Returns how many rows were deleted.
+    pub fn rewrite(
+        &self,
+        policy: &AuditPolicy,
+        keep: impl Fn(&InterventionLog) -> bool,
+        mut edit: impl FnMut(&mut InterventionLog),
+    ) -> Result<usize, sqlx::Error> {
+        let mut rows = self.load_rows(policy, crate::now_secs())?;
+        rows.retain(|(_, log)| keep(log));
+        let mut changed = Vec::new();
+        for (instance, log) in &mut rows {
+            let before = serde_json::to_string(log).unwrap_or_default();
+            edit(log);
+            if serde_json::to_string(log).unwrap_or_default() != before {
+                changed.push((instance.clone(), log.clone()));
+            }
+        }
+        let (instances, ids): (Vec<String>, Vec<i64>) = rows.iter().map(|(instance, log)| (instance.clone(), log.id as i64)).unzip();
+        block_on(async {
+            let mut tx = self.pool.begin().await?;
+            let deleted = sqlx::query("DELETE FROM audit_log WHERE (instance, id) NOT IN (SELECT * FROM UNNEST($1::text[], $2::bigint[]))")
+                .bind(&instances)
+                .bind(&ids)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            for (instance, log) in &changed {
+                upsert(&mut tx, instance, log).await?;
+            }
+            tx.commit().await?;
+            Ok(deleted as usize)
+        })
+    }
+
+    pub fn archived_through(&self) -> u64 {
+        block_on(
+            sqlx::query_scalar::<_, i64>("SELECT value FROM sentinel_meta WHERE key = $1")
+                .bind(self.watermark_key())
+                .fetch_optional(&self.pool),
+        )
+        .inspect_err(|e| tracing::error!("Reading the archive watermark failed: {}", e))
+        .ok()
+        .flatten()
+        .unwrap_or(0) as u64
+    }
+
+    pub fn set_archived_through(&self, id: u64) -> Result<(), sqlx::Error> {
+        block_on(
+            sqlx::query("INSERT INTO sentinel_meta (key, value) VALUES ($1, $2) ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value")
+                .bind(self.watermark_key())
+                .bind(id as i64)
+                .execute(&self.pool),
+        )
+        .map(|_| ())
+    }
+
+    /// This instance's archive watermark in `sentinel_meta`.
+    fn watermark_key(&self) -> String {
+        format!("archived_through/{}", self.instance)
+    }
+
+    /// Replaces `instance`'s saved state with `saved` in one transaction.
+    pub fn save_state(&self, instance: &str, saved: &SavedState) -> Result<(), sqlx::Error> {
+        let (ids, sessions): (Vec<&str>, Vec<String>) = saved.sessions.iter()
+            .map(|(id, session)| (id.as_str(), serde_json::to_string(session).unwrap_or_default()))
+            .unzip();
+        let spend: Vec<(&str, &str, f64)> = saved.tenant_spend.iter().map(|(name, spent)| ("tenant", name.as_str(), *spent))
+            .chain(saved.pool_spend.iter().map(|(name, spent)| ("pool", name.as_str(), *spent)))
+            .collect();
+        let (providers, rings): (Vec<&str>, Vec<String>) = saved.keys.iter()
+            .map(|(provider, ring)| (provider.as_str(), serde_json::to_string(ring).unwrap_or_default()))
+            .unzip();
+        let usage = serde_json::to_value(&saved.usage).unwrap_or_default();
+        block_on(async {
+            let mut tx = self.pool.begin().await?;
+            for table in ["sentinel_sessions", "sentinel_spend", "sentinel_provider_keys"] {
+                sqlx::query(&format!("DELETE FROM {} WHERE instance = $1", table))
+                    .bind(instance)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(
+                "INSERT INTO sentinel_sessions (instance, id, state, updated_at) \
+                 SELECT $1, id, state::jsonb, $4 FROM UNNEST($2::text[], $3::text[]) AS s (id, state)",
+            )
+            .bind(instance)
+            .bind(&ids)
+            .bind(&sessions)
+            .bind(crate::now_secs() as i64)
+            .execute(&mut *tx)
+            .await?;
+            for (kind, name, spent) in &spend {
+                sqlx::query("INSERT INTO sentinel_spend (instance, kind, name, spent_usd) VALUES ($1, $2, $3, $4)")
+                    .bind(instance)
+                    .bind(*kind)
+                    .bind(*name)
+                    .bind(*spent)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query(
+                "INSERT INTO sentinel_provider_keys (instance, provider, ring) \
+                 SELECT $1, provider, ring::jsonb FROM UNNEST($2::text[], $3::text[]) AS k (provider, ring)",
+            )
+            .bind(instance)
+            .bind(&providers)
+            .bind(&rings)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "INSERT INTO sentinel_usage (instance, ledger, updated_at) VALUES ($1, $2, $3) \
+                 ON CONFLICT (instance) DO UPDATE SET ledger = EXCLUDED.ledger, updated_at = EXCLUDED.updated_at",
+            )
+            .bind(instance)
+            .bind(Json(usage))
+            .bind(crate::now_secs() as i64)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await
+        })
+    }
+
+    /// `instance`'s saved state; rows that no longer parse are skipped.
+    pub fn load_state(&self, instance: &str) -> Result<SavedState, sqlx::Error> {
+        block_on(async {
+            let sessions: Vec<(String, Json<serde_json::Value>)> =
+                sqlx::query_as("SELECT id, state FROM sentinel_sessions WHERE instance = $1")
+                    .bind(instance)
+                    .fetch_all(&self.pool)
+                    .await?;
+            let spend: Vec<(String, String, f64)> =
+                sqlx::query_as("SELECT kind, name, spent_usd FROM sentinel_spend WHERE instance = $1")
+                    .bind(instance)
+                    .fetch_all(&self.pool)
+                    .await?;
+            let keys: Vec<(String, Json<serde_json::Value>)> =
+                sqlx::query_as("SELECT provider, ring FROM sentinel_provider_keys WHERE instance = $1")
+                    .bind(instance)
+                    .fetch_all(&self.pool)
+                    .await?;
+            let usage: Option<(Json<serde_json::Value>,)> =
+                sqlx::query_as("SELECT ledger FROM sentinel_usage WHERE instance = $1")
+                    .bind(instance)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            let mut saved = SavedState::default();
+            if let Some((Json(ledger),)) = usage {
+                match serde_json::from_value(ledger) {
+                    Ok(usage) => saved.usage = usage,
+                    Err(e) => tracing::warn!("Skipping stored usage: {}", e),
+                }
+            }
+            for (id, Json(state)) in sessions {
+                match serde_json::from_value(state) {
+                    Ok(session) => saved.sessions.push((id, session)),
+                    Err(e) => tracing::warn!("Skipping stored session {}: {}", id, e),
+                }
+            }
+            for (kind, name, spent) in spend {
+                match kind.as_str() {
+                    "tenant" => saved.tenant_spend.push((name, spent)),
+                    "pool" => saved.pool_spend.push((name, spent)),
+                    _ => {}
+                }
+            }
+            for (provider, Json(ring)) in keys {
+                match serde_json::from_value(ring) {
+                    Ok(ring) => saved.keys.push((provider, ring)),
+                    Err(e) => tracing::warn!("Skipping stored keys of provider '{}': {}", provider, e),
+                }
+            }
+            Ok(saved)
+        })
+    }
+
+    /// The prices in `sentinel_pricing`, first match first.
+    pub fn pricing(&self) -> Result<Vec<(String, ModelPrice)>, sqlx::Error> {
+        let rows: Vec<(String, f64, f64)> = block_on(
+            sqlx::query_as("SELECT pattern, input_per_mtok, output_per_mtok FROM sentinel_pricing ORDER BY position, pattern")
+                .fetch_all(&self.pool),
+        )?;
+        Ok(rows.into_iter()
+            .map(|(pattern, input_per_mtok, output_per_mtok)| (pattern, ModelPrice { input_per_mtok, output_per_mtok }))
+            .collect())
+    }
+
+    /// Adds `deltas` to this instance's counters in one transaction.
+    pub fn add_counters(&self, instance: &str, deltas: &[(&str, u64)]) -> Result<(), sqlx::Error> {
+        block_on(async {
+            let mut tx = self.pool.begin().await?;
+            for (name, delta) in deltas {
+                sqlx::query(
+                    "INSERT INTO sentinel_counters (instance, name, value, updated_at) VALUES ($1, $2, $3, $4) \
+                     ON CONFLICT (instance, name) DO UPDATE SET value = sentinel_counters.value + EXCLUDED.value, updated_at = EXCLUDED.updated_at",
+                )
+                .bind(instance)
+                .bind(*name)
+                .bind(*delta as i64)
+                .bind(crate::now_secs() as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await
+        })
+    }
+
+    /// Every instance's counters as `(instance, name, value)`.
+    pub fn counters(&self) -> Result<Vec<(String, String, i64)>, sqlx::Error> {
+        block_on(sqlx::query_as("SELECT instance, name, value FROM sentinel_counters").fetch_all(&self.pool))
+    }
+}
+
+async fn upsert(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, instance: &str, log: &InterventionLog) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log (instance, id, ts, session_id, tenant, detector, entry) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+         ON CONFLICT (instance, id) DO UPDATE SET ts = EXCLUDED.ts, session_id = EXCLUDED.session_id, \
+         tenant = EXCLUDED.tenant, detector = EXCLUDED.detector, entry = EXCLUDED.entry",
+    )
+    .bind(instance)
+    .bind(log.id as i64)
+    .bind(log.timestamp as i64)
+    .bind(&log.session_id)
+    .bind(&log.tenant)
+    .bind(&log.detector)
+    .bind(Json(log))
+    .execute(&mut **tx)
+    .await
+    .map(|_| ())
+}
+
+/// Commits queued upserts in batches, retrying a failed batch a few times
+/// before dropping it, and answers flushes once everything before them is done.
+async fn run_writer(pool: PgPool, instance: String, mut rx: mpsc::UnboundedReceiver<Write>) {
+    let mut batch = Vec::with_capacity(WRITE_BATCH);
+    while rx.recv_many(&mut batch, WRITE_BATCH).await > 0 {
+        let mut logs = Vec::new();
+        let mut flushes = Vec::new();
+        for write in batch.drain(..) {
+            match write {
+                Write::Upsert(log) => logs.push(log),
+                Write::Flush(done) => flushes.push(done),
+            }
+        }
+        if !logs.is_empty() {
+            for attempt in 1..=WRITE_ATTEMPTS {
+                match write_batch(&pool, &instance, &logs).await {
+                    Ok(()) => break,
+                    Err(e) if attempt == WRITE_ATTEMPTS => {
+                        tracing::error!("Audit log write failed, {} entries lost: {}", logs.len(), e);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Audit log write failed (attempt {}): {}", attempt, e);
+                        tokio::time::sleep(std::time::Duration::from_millis(200 * attempt as u64)).await;
+                    }
+                }
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+async fn write_batch(pool: &PgPool, instance: &str, logs: &[Box<InterventionLog>]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for log in logs {
+        upsert(&mut tx, instance, log).await?;
+    }
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Connects as `instance` to `SENTINEL_TEST_DATABASE_URL`, if it is set.
+    fn connect(instance: &str) -> Option<PgStore> {
+        let url = std::env::var("SENTINEL_TEST_DATABASE_URL").ok()?;
+        let policy = StoragePolicy { database_url: Some(url), instance_id: instance.to_string(), ..StoragePolicy::default() };
+        Some(PgStore::connect(&policy).unwrap())
+    }
+
+    fn log(id: u64, detector: &str) -> InterventionLog {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "timestamp": crate::now_secs(), "session_id": "s", "detector": detector,
+            "reason": "r", "content_snippet": "", "savings_est": 0.0,
+        })).unwrap()
+    }
+
+    // A current-thread runtime, like `#[tokio::main(flavor = "current_thread")]`.
+    #[tokio::test]
+    async fn test_instances_share_the_log() {
+        let (a, b) = (format!("test-a-{}", std::process::id()), format!("test-b-{}", std::process::id()));
+        let (Some(first), Some(second)) = (connect(&a), connect(&b)) else { return };
+        let policy = AuditPolicy::default();
+        first.upsert(&log(1, &a));
+        second.upsert(&log(1, &b));
+        let ours = |store: &PgStore| store.load(&policy, crate::now_secs()).unwrap().into_iter()
+            .map(|l| l.detector)
+            .filter(|d| *d == a || *d == b)
+            .collect::<Vec<_>>();
+        // The same id from two instances: both kept, both seen by each.
+        assert_eq!(ours(&first).len(), 2);
+        assert_eq!(ours(&second).len(), 2);
+
+        // Either instance erases the other's entries, and only those it drops.
+        first.rewrite(&policy, |l| l.detector != b, |_| {}).unwrap();
+        assert_eq!(ours(&second), vec![a.clone()]);
+        second.rewrite(&policy, |l| l.detector != a, |_| {}).unwrap();
+        assert!(ours(&first).is_empty());
+    }
+}
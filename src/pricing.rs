@@ -0,0 +1,143 @@
+// --- MODEL PRICING ---
+// USD per 1M tokens. Patterns use `*` wildcards and are matched in order, so
+// more specific names must come first.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+const fn price(input_per_mtok: f64, output_per_mtok: f64) -> ModelPrice {
+    ModelPrice { input_per_mtok, output_per_mtok }
+}
+
+const BUILTIN: &[(&str, ModelPrice)] = &[
+    ("gpt-4o-mini*", price(0.15, 0.60)),
+    ("gpt-4o*", price(2.50, 10.00)),
+    ("gpt-4.1-nano*", price(0.10, 0.40)),
+    ("gpt-4.1-mini*", price(0.40, 1.60)),
+    ("gpt-4.1*", price(2.00, 8.00)),
+    ("gpt-4-turbo*", price(10.00, 30.00)),
+    ("gpt-4*", price(30.00, 60.00)),
+    ("gpt-3.5-turbo*", price(0.50, 1.50)),
+    ("o1-mini*", price(1.10, 4.40)),
+    ("o1*", price(15.00, 60.00)),
+    ("o3-mini*", price(1.10, 4.40)),
+    ("gpt-image-1*", price(5.00, 40.00)),
+    ("text-embedding-3-small*", price(0.02, 0.0)),
+    ("text-embedding-3-large*", price(0.13, 0.0)),
+    ("llama-3.1-8b*", price(0.05, 0.08)),
+    ("llama-3.3-70b*", price(0.59, 0.79)),
+    ("llama3-70b*", price(0.59, 0.79)),
+    ("llama3-8b*", price(0.05, 0.08)),
+    ("mixtral-8x7b*", price(0.24, 0.24)),
+    ("gemma2-9b*", price(0.20, 0.20)),
+];
+
+/// Used for models missing from the table (the old flat gpt-4o-mini rate).
+const FALLBACK: ModelPrice = price(0.15, 0.60);
+
+/// USD per generated image for models billed per image: (model pattern,
+/// size, quality, price). Empty size/quality match anything; first hit wins.
+const IMAGE_PRICES: &[(&str, &str, &str, f64)] = &[
+    ("dall-e-3*", "1024x1024", "hd", 0.080),
+    ("dall-e-3*", "1024x1024", "", 0.040),
+    ("dall-e-3*", "", "hd", 0.120),
+    ("dall-e-3*", "", "", 0.080),
+    ("dall-e-2*", "256x256", "", 0.016),
+    ("dall-e-2*", "512x512", "", 0.018),
+    ("dall-e-2*", "", "", 0.020),
+];
+
+#[derive(Debug, Clone)]
+pub struct Pricing {
+    table: Vec<(String, ModelPrice)>,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self { table: BUILTIN.iter().map(|(p, m)| (p.to_string(), *m)).collect() }
+    }
+}
+
+impl Pricing {
+    /// Built-in table with `SENTINEL_PRICING="my-model*:1.0/2.0,..."` entries
+    /// (input/output per 1M tokens) taking precedence.
+    pub fn from_env() -> Self {
+        let mut pricing = Self::default();
+        let overrides: Vec<(String, ModelPrice)> = crate::config::var("SENTINEL_PRICING")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let (pattern, prices) = entry.trim().rsplit_once(':')?;
+                let (i, o) = prices.split_once('/')?;
+                Some((pattern.to_string(), price(i.parse().ok()?, o.parse().ok()?)))
+            })
+            .collect();
+        pricing.prepend(overrides);
+        pricing
+    }
+
+    /// Puts `entries` ahead of the current table.
+    pub fn prepend(&mut self, entries: Vec<(String, ModelPrice)>) {
+        self.table.splice(0..0, entries);
+    }
+
+    pub fn lookup(&self, model: &str) -> Option<ModelPrice> {
+        self.table.iter()
+            .find(|(pattern, _)| crate::routing::glob_match(pattern, model))
+            .map(|(_, p)| *p)
+    }
+
+    pub fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        let p = self.lookup(model).unwrap_or(FALLBACK);
+        (prompt_tokens as f64 * p.input_per_mtok + completion_tokens as f64 * p.output_per_mtok) / 1_000_000.0
+    }
+
+    /// Cost of an image generation. Token-billed models report `usage`
+    /// (`input_tokens` / `output_tokens`); the rest are priced per image.
+    pub fn image_cost(&self, model: &str, size: &str, quality: &str, n: u64, usage: &serde_json::Value) -> f64 {
+        if let (Some(input), Some(output)) = (usage["input_tokens"].as_u64(), usage["output_tokens"].as_u64()) {
+            return self.cost(model, input, output);
+        }
+        IMAGE_PRICES.iter()
+            .find(|(pattern, s, q, _)| {
+                crate::routing::glob_match(pattern, model)
+                    && (s.is_empty() || *s == size)
+                    && (q.is_empty() || *q == quality)
+            })
+            .map_or(0.0, |(.., usd)| usd * n as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specific_patterns_win() {
+        let pricing = Pricing::default();
+        assert_eq!(pricing.lookup("gpt-4o-mini-2024-07-18"), Some(price(0.15, 0.60)));
+        assert_eq!(pricing.lookup("gpt-4o"), Some(price(2.50, 10.00)));
+        assert_eq!(pricing.lookup("unknown-model"), None);
+    }
+
+    #[test]
+    fn test_image_cost() {
+        let pricing = Pricing::default();
+        let none = serde_json::Value::Null;
+        assert!((pricing.image_cost("dall-e-3", "1024x1024", "hd", 2, &none) - 0.16).abs() < 1e-9);
+        assert!((pricing.image_cost("dall-e-3", "1792x1024", "standard", 1, &none) - 0.08).abs() < 1e-9);
+        assert!((pricing.image_cost("dall-e-2", "512x512", "", 1, &none) - 0.018).abs() < 1e-9);
+        let usage = serde_json::json!({ "input_tokens": 1_000_000, "output_tokens": 0 });
+        assert!((pricing.image_cost("gpt-image-1", "1024x1024", "high", 1, &usage) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cost_uses_fallback() {
+        let pricing = Pricing::default();
+        let cost = pricing.cost("unknown-model", 1_000_000, 1_000_000);
+        assert!((cost - 0.75).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,174 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+
+use crate::AppState;
+use crate::upstream::{self, StoredRequest};
+
+// --- QUARANTINE ---
+// With quarantine on (globally or via `x-sentinel-quarantine: 1`), requests
+// the loop detectors would block are parked here instead. An operator approves
+// or denies them; approved ones are forwarded and the client collects the
+// completion from `GET /api/quarantine/{id}`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantineStatus {
+    Pending,
+    /// Approved and currently being forwarded.
+    Approved,
+    Denied,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineEntry {
+    pub id: u64,
+    pub created_at: u64,
+    pub resolved_at: Option<u64>,
+    pub detector: String,
+    pub reason: String,
+    pub status: QuarantineStatus,
+    pub request: StoredRequest,
+    pub upstream_status: Option<u16>,
+    pub result: Option<serde_json::Value>,
+}
+
+/// Parks a request. Returns `None` when the queue is full of pending entries,
+/// in which case the caller falls back to a plain block.
+pub fn park(state: &AppState, request: StoredRequest, detector: &str, reason: &str) -> Option<u64> {
+    let max = state.config.quarantine.max_entries;
+    if state.quarantine.len() >= max {
+        // Make room by dropping the oldest resolved entry.
+        let oldest_resolved = state.quarantine.iter()
+            .filter(|e| !matches!(e.status, QuarantineStatus::Pending | QuarantineStatus::Approved))
+            .map(|e| e.id)
+            .min()?;
+        state.quarantine.remove(&oldest_resolved);
+    }
+
+    let id = state.next_quarantine_id.fetch_add(1, Ordering::Relaxed);
+    tracing::info!("Request for session '{}' quarantined as #{} ({})", request.session_id, id, reason);
+    state.quarantine.insert(id, QuarantineEntry {
+        id,
+        created_at: crate::now_secs(),
+        resolved_at: None,
+        detector: detector.to_string(),
+        reason: reason.to_string(),
+        status: QuarantineStatus::Pending,
+        request,
+        upstream_status: None,
+        result: None,
+    });
+    Some(id)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListFilter {
+    status: Option<QuarantineStatus>,
+}
+
+/// `GET /api/quarantine?status=pending`
+pub async fn list(State(state): State<AppState>, Query(filter): Query<ListFilter>) -> impl IntoResponse {
+    let mut entries: Vec<QuarantineEntry> = state.quarantine.iter()
+        .filter(|e| filter.status.is_none_or(|s| e.status == s))
+        .map(|e| e.clone())
+        .collect();
+    entries.sort_by_key(|e| e.id);
+    Json(entries)
+}
+
+/// `GET /api/quarantine/{id}`: status and, once forwarded, the completion.
+pub async fn get(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    match state.quarantine.get(&id) {
+        Some(entry) => Json(entry.clone()).into_response(),
+        None => not_found(),
+    }
+}
+
+/// `POST /api/quarantine/{id}/approve`: forwards the request upstream.
+pub async fn approve(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    let request = {
+        let Some(mut entry) = state.quarantine.get_mut(&id) else { return not_found() };
+        if entry.status != QuarantineStatus::Pending {
+            return conflict(entry.status);
+        }
+        entry.status = QuarantineStatus::Approved;
+        entry.request.clone()
+    };
+
+    let outcome = upstream::forward(&state, &request).await;
+
+    let Some(mut entry) = state.quarantine.get_mut(&id) else { return not_found() };
+    entry.resolved_at = Some(crate::now_secs());
+    match outcome {
+        Ok((status, body)) => {
+            entry.status = QuarantineStatus::Completed;
+            entry.upstream_status = Some(status.as_u16());
+            entry.result = Some(body);
+        }
+        Err(e) => {
+            entry.status = QuarantineStatus::Failed;
+            entry.result = Some(serde_json::json!({"error": e}));
+        }
+    }
+    Json(entry.clone()).into_response()
+}
+
+/// `POST /api/quarantine/{id}/deny`
+pub async fn deny(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    let Some(mut entry) = state.quarantine.get_mut(&id) else { return not_found() };
+    if entry.status != QuarantineStatus::Pending {
+        return conflict(entry.status);
+    }
+    entry.status = QuarantineStatus::Denied;
+    entry.resolved_at = Some(crate::now_secs());
+    Json(entry.clone()).into_response()
+}
+
+fn not_found() -> axum::response::Response {
+    (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Quarantine entry not found"}))).into_response()
+}
+
+fn conflict(status: QuarantineStatus) -> axum::response::Response {
+    (StatusCode::CONFLICT, Json(serde_json::json!({"error": "Entry already resolved", "status": status}))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, QuarantinePolicy};
+
+    fn request() -> StoredRequest {
+        StoredRequest {
+            session_id: "agent".to_string(),
+            endpoint: "chat/completions".to_string(),
+            provider: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            budget_pool: None,
+            tenant: None,
+            payload: serde_json::json!({}),
+        }
+    }
+
+    #[test]
+    fn test_park_recycles_resolved_entries_only() {
+        let state = AppState::for_tests(Config {
+            quarantine: QuarantinePolicy { enabled: true, max_entries: 2 },
+            ..Config::default()
+        });
+        let first = park(&state, request(), "fuzzy_loop", "loop").unwrap();
+        park(&state, request(), "fuzzy_loop", "loop").unwrap();
+        assert!(park(&state, request(), "fuzzy_loop", "loop").is_none());
+
+        state.quarantine.get_mut(&first).unwrap().status = QuarantineStatus::Denied;
+        assert!(park(&state, request(), "fuzzy_loop", "loop").is_some());
+        assert!(!state.quarantine.contains_key(&first));
+    }
+}
@@ -0,0 +1,224 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::str::FromStr;
+
+use crate::AppState;
+use crate::config::Config;
+
+// --- ADMIN ROLES ---
+// Each admin token (`SENTINEL_ADMIN_TOKENS`) carries a role. Viewers read
+// stats, logs, sessions and metrics; operators may also act on traffic:
+// reset and block sessions, ban addresses, settle quarantined requests,
+// replay and give feedback on interventions. Everything else that changes
+// the running instance (config reload and whatever is added later) needs an
+// admin. The proxy routes, `/health` and `/mcp` (see `SENTINEL_MCP_TOKEN`)
+// are not admin routes. Without any token or OIDC issuer configured the
+// admin API stays open, as it was before roles existed.
+//
+// Callers signed in through OIDC (`oidc.rs`) may also be confined to one
+// tenant: they only get that tenant's logs, sessions and reports, and may
+// only erase its data subjects.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "operator" => Ok(Self::Operator),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("unknown role `{}` (viewer, operator, admin)", other)),
+        }
+    }
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// Who is calling the admin API.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub role: Role,
+    /// Set for callers confined to one tenant.
+    pub tenant: Option<String>,
+}
+
+/// Admin paths operators may change: traffic control, not configuration.
+const OPERATOR_PREFIXES: &[&str] = &["/api/sessions", "/api/bans", "/api/quarantine", "/api/interventions"];
+
+/// The role a request needs, or `None` for routes outside the admin API.
+pub fn required_role(method: &Method, path: &str) -> Option<Role> {
+    if path == "/api/openapi.json" || !(path == "/metrics" || path.starts_with("/api/")) {
+        return None;
+    }
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return Some(Role::Viewer);
+    }
+    if OPERATOR_PREFIXES.iter().any(|p| path == *p || path.starts_with(&format!("{}/", p))) {
+        return Some(Role::Operator);
+    }
+    Some(Role::Admin)
+}
+
+/// With neither static tokens nor OIDC configured, anyone may use the admin API.
+pub fn is_open(config: &Config) -> bool {
+    config.admin.tokens.is_empty() && !config.oidc.enabled()
+}
+
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Resolves a bearer token: a static admin token, else an OIDC JWT.
+pub async fn identify(state: &AppState, token: Option<&str>) -> Result<Principal, String> {
+    let token = token.ok_or("missing admin token")?;
+    if let Some(role) = state.config.admin.role(token) {
+        return Ok(Principal { role, tenant: None });
+    }
+    if state.config.oidc.enabled() {
+        return crate::oidc::verify(state, token).await;
+    }
+    Err("unknown admin token".to_string())
+}
+
+/// Middleware over the whole router: 401 without a valid admin token, 403
+/// when its role is below what the route needs or the route is outside the
+/// caller's tenant.
+pub async fn authorize(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(required) = required_role(request.method(), request.uri().path()).filter(|_| !is_open(&state.config)) else {
+        return next.run(request).await;
+    };
+    let principal = match identify(&state, bearer(request.headers())).await {
+        Ok(principal) => principal,
+        Err(reason) => return rejection(StatusCode::UNAUTHORIZED, "invalid_admin_token", reason),
+    };
+    if principal.role < required {
+        tracing::warn!("{} refused {} {}", principal.role.as_str(), request.method(), request.uri().path());
+        return rejection(StatusCode::FORBIDDEN, "insufficient_role", format!("this action needs the {} role, the caller has {}", required.as_str(), principal.role.as_str()));
+    }
+    if let Some(tenant) = &principal.tenant
+        && let Err(reason) = scope(tenant, &mut request) {
+        return rejection(StatusCode::FORBIDDEN, "outside_tenant", reason);
+    }
+    next.run(request).await
+}
+
+/// Admin routes a tenant-bound caller may list, filtered to its tenant.
+const TENANT_FILTERED: &[&str] = &["/api/logs", "/api/logs/export", "/api/logs/stream", "/api/sessions", "/api/reports"];
+
+/// Confines a tenant-bound request: list routes get their `tenant` filter
+/// forced, session and erasure routes must name a `<tenant>/` session or
+/// user, anything else is refused.
+pub fn scope(tenant: &str, request: &mut Request) -> Result<(), String> {
+    let path = request.uri().path().to_string();
+    if TENANT_FILTERED.contains(&path.as_str()) {
+        let mut pairs: Vec<&str> = request.uri().query().unwrap_or_default()
+            .split('&')
+            .filter(|p| !p.is_empty() && p.split('=').next() != Some("tenant"))
+            .collect();
+        let forced = format!("tenant={}", encode(tenant));
+        pairs.push(&forced);
+        let uri = format!("{}?{}", path, pairs.join("&"));
+        *request.uri_mut() = uri.parse().map_err(|_| "malformed query".to_string())?;
+        return Ok(());
+    }
+    if let Some(id) = path.strip_prefix("/api/sessions/").or_else(|| path.strip_prefix("/api/data/"))
+        && let Some(rest) = id.strip_prefix(&encode(tenant))
+        && (rest.starts_with("%2F") || rest.starts_with("%2f")) {
+        return Ok(());
+    }
+    Err(format!("only `{}` logs, sessions and reports are available to this caller", tenant))
+}
+
+/// Percent-encodes everything but unreserved characters.
+pub fn encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn rejection(status: StatusCode, code: &str, reason: String) -> Response {
+    let mut response = (status, Json(serde_json::json!({
+        "error": {
+            "message": format!("Sentinel: {}", reason),
+            "type": "sentinel_auth",
+            "param": null,
+            "code": code
+        }
+    }))).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_role_by_route() {
+        assert_eq!(required_role(&Method::GET, "/api/logs"), Some(Role::Viewer));
+        assert_eq!(required_role(&Method::GET, "/metrics"), Some(Role::Viewer));
+        assert_eq!(required_role(&Method::POST, "/api/sessions/a/block"), Some(Role::Operator));
+        assert_eq!(required_role(&Method::DELETE, "/api/bans/203.0.113.7"), Some(Role::Operator));
+        assert_eq!(required_role(&Method::POST, "/api/config/reload"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::POST, "/api/sessionsx"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::POST, "/v1/chat/completions"), None);
+        assert_eq!(required_role(&Method::GET, "/api/openapi.json"), None);
+        assert_eq!(required_role(&Method::GET, "/health"), None);
+
+        let headers: HeaderMap = [(header::AUTHORIZATION, "Bearer tok-o".parse().unwrap())].into_iter().collect();
+        assert_eq!(bearer(&headers), Some("tok-o"));
+        assert!(Role::Admin > Role::Operator && Role::Operator > Role::Viewer);
+        assert!("auditor".parse::<Role>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_tokens_grant_their_role() {
+        let admin = crate::config::AdminAuthPolicy { tokens: [("v".to_string(), Role::Viewer), ("o".to_string(), Role::Operator)].into() };
+        let state = AppState::for_tests(Config { admin, ..Default::default() });
+        assert_eq!(identify(&state, Some("o")).await.unwrap().role, Role::Operator);
+        assert!(identify(&state, Some("o")).await.unwrap().tenant.is_none());
+        assert!(identify(&state, Some("nope")).await.is_err());
+        assert!(identify(&state, None).await.is_err());
+        assert!(!is_open(&state.config));
+    }
+
+    #[test]
+    fn test_scope_confines_tenant_callers() {
+        let request = |uri: &str| Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let mut logs = request("/api/logs?detector=leak&tenant=other");
+        assert!(scope("acme", &mut logs).is_ok());
+        assert_eq!(logs.uri().query(), Some("detector=leak&tenant=acme"));
+        assert!(scope("acme", &mut request("/api/sessions/acme%2Fbot-1/block")).is_ok());
+        assert!(scope("acme", &mut request("/api/data/acme%2Falice")).is_ok());
+        assert!(scope("acme", &mut request("/api/data/alice")).is_err());
+        assert!(scope("acme", &mut request("/api/sessions/other%2Fbot-1")).is_err());
+        assert!(scope("acme", &mut request("/api/sessions/acme-evil%2Fbot")).is_err());
+        assert!(scope("acme", &mut request("/api/stats")).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::AppState;
+use crate::config::{self, Config};
+
+// --- HOT RELOAD ---
+// `POST /api/config/reload`, or an edit to the `--config` file when
+// `SENTINEL_CONFIG_WATCH_SECS` is set, re-reads the environment and file and
+// swaps in the new `Config`. Scripted rules are compiled again if they
+// changed and policy plugins are loaded again from their directory. Requests
+// already in flight finish on the config they started with; sessions, audit
+// history and blocks are untouched.
+
+#[derive(Debug, Serialize)]
+pub struct Reloaded {
+    /// What the startup self-check's static pass finds in the new config.
+    pub problems: Vec<String>,
+    /// Changed sections that are only read at startup.
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Settings copied into long-lived components when the process starts.
+/// Scripts and plugins are loaded again by `reload`, and OIDC is read per
+/// request (its cached keys follow the issuer), so none of them are listed.
+fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.audit != new.audit {
+        changed.push("audit");
+    }
+    if old.storage != new.storage {
+        changed.push("storage");
+    }
+    if old.archive != new.archive {
+        changed.push("archive");
+    }
+    if old.embedding_batch != new.embedding_batch {
+        changed.push("embedding_batch");
+    }
+    if old.sessions.sweep_interval_secs != new.sessions.sweep_interval_secs {
+        changed.push("sessions.sweep_interval_secs");
+    }
+    if old.notify.batch_secs != new.notify.batch_secs {
+        changed.push("notify.batch_secs");
+    }
+    if old.events != new.events {
+        changed.push("events");
+    }
+    if old.grpc_addr != new.grpc_addr {
+        changed.push("grpc_addr");
+    }
+    if old.server.listen != new.server.listen || old.server.backlog != new.server.backlog || old.server.tcp_keepalive != new.server.tcp_keepalive
+        || old.server.compression != new.server.compression
+    {
+        changed.push("server");
+    }
+    if old.timeouts != new.timeouts {
+        changed.push("timeouts");
+    }
+    if old.tls.cert_path != new.tls.cert_path || old.tls.key_path != new.tls.key_path {
+        changed.push("tls");
+    }
+    // The model is loaded once; thresholds and actions are read per request.
+    if old.ner.model != new.ner.model || ner_files_changed(&old.ner, &new.ner) {
+        changed.push("ner.model");
+    }
+    changed
+}
+
+#[cfg(feature = "ner")]
+fn ner_files_changed(old: &config::NerPolicy, new: &config::NerPolicy) -> bool {
+    old.tokenizer != new.tokenizer || old.labels != new.labels
+}
+
+#[cfg(not(feature = "ner"))]
+fn ner_files_changed(_: &config::NerPolicy, _: &config::NerPolicy) -> bool {
+    false
+}
+
+pub fn reload(state: &AppState) -> Result<Reloaded, String> {
+    if let Some(path) = config::file_path() {
+        config::load_file(&path)?;
+    }
+    let new = Config::from_env();
+    let old = state.current_config();
+    let report = Reloaded {
+        problems: crate::selfcheck::static_problems(&new),
+        restart_required: restart_required(&old, &new),
+    };
+    if old.scripts != new.scripts {
+        let host = Arc::new(crate::scripts::ScriptHost::load(&new.scripts));
+        *state.scripts.write().unwrap_or_else(|p| p.into_inner()) = host;
+    }
+    if old.plugins.dir != new.plugins.dir || new.plugins.dir.is_some() {
+        let host = Arc::new(crate::plugins::PluginHost::load(&new.plugins));
+        *state.plugins.write().unwrap_or_else(|p| p.into_inner()) = host;
+    }
+    state.set_config(new);
+    tracing::info!("🔄 Configuration reloaded");
+    for problem in &report.problems {
+        tracing::warn!("⚠️ Reloaded config: {}", problem);
+    }
+    if !report.restart_required.is_empty() {
+        tracing::warn!("Changed settings need a restart to apply: {}", report.restart_required.join(", "));
+    }
+    Ok(report)
+}
+
+/// `POST /api/config/reload`
+pub async fn handler(State(state): State<AppState>) -> impl IntoResponse {
+    match reload(&state) {
+        Ok(report) => Json(serde_json::json!({
+            "reloaded": true,
+            "problems": report.problems,
+            "restart_required": report.restart_required,
+        })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+/// Polls the config file's modification time and reloads when it changes.
+pub fn spawn_watcher(state: AppState) {
+    let (Some(path), secs) = (config::file_path(), state.config.config_watch_secs) else { return };
+    if secs == 0 { return; }
+    let modified = |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    tokio::spawn(async move {
+        let mut seen: Option<SystemTime> = modified(&path);
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(secs));
+        loop {
+            tick.tick().await;
+            let now = modified(&path);
+            if now == seen { continue; }
+            seen = now;
+            if let Err(e) = reload(&state) {
+                tracing::error!("Config reload failed, keeping the previous config: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_swaps_config_and_keeps_sessions() {
+        let path = std::env::temp_dir().join(format!("sentinel-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "[sentinel]\nsession_budget_usd = 5\n").unwrap();
+        config::load_file(&path).unwrap();
+        let state = AppState::for_tests(Config::from_env());
+        state.sessions.insert("agent".to_string(), crate::SessionState::default());
+        assert_eq!(state.config.cost.session_budget_usd, 5.0);
+
+        std::fs::write(&path, "[sentinel]\nsession_budget_usd = 7.5\n").unwrap();
+        let report = reload(&state).unwrap();
+        assert!(report.restart_required.is_empty());
+        assert_eq!(state.with_current_config().config.cost.session_budget_usd, 7.5);
+        assert!(state.sessions.contains_key("agent"));
+
+        std::fs::write(&path, "[sentinel\n").unwrap();
+        assert!(reload(&state).is_err());
+        assert_eq!(state.current_config().cost.session_budget_usd, 7.5);
+
+        std::fs::write(&path, "[sentinel]\nsession_budget_usd = 7.5\nner_model = \"ner.onnx\"\naudit_retention_days = 3\n").unwrap();
+        assert_eq!(reload(&state).unwrap().restart_required, ["audit", "ner.model"]);
+        std::fs::remove_file(&path).ok();
+    }
+}
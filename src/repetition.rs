@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::config::RepetitionPolicy;
+
+// --- DEGENERATION ---
+// A model stuck repeating itself inside one completion burns output tokens on
+// nothing. The `repetition` detector looks for a word n-gram occurring
+// `min_repeats` times, or a paragraph coming back (near-)verbatim twice more.
+// When enforced, the completion is cut where the repetition starts so the
+// first copy survives.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Degeneration {
+    /// Byte offset of the first repeated copy; the text before it is kept.
+    pub cut_at: usize,
+    pub reason: String,
+}
+
+pub fn scan(text: &str, policy: &RepetitionPolicy) -> Option<Degeneration> {
+    let text = truncate_chars(text, policy.max_scan_chars);
+    let ngrams = repeated_ngram(text, policy);
+    let paragraphs = repeated_paragraph(text, policy);
+    // Report whichever started repeating first.
+    match (ngrams, paragraphs) {
+        (Some(a), Some(b)) => Some(if b.cut_at < a.cut_at { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn repeated_ngram(text: &str, policy: &RepetitionPolicy) -> Option<Degeneration> {
+    let n = policy.ngram;
+    let words: Vec<(usize, String)> = text.split_whitespace()
+        .map(|w| (w.as_ptr() as usize - text.as_ptr() as usize, w.to_lowercase()))
+        .collect();
+    if n == 0 || words.len() < n {
+        return None;
+    }
+    // n-gram -> (occurrences, offset of the second one)
+    let mut seen: HashMap<Vec<&str>, (usize, usize)> = HashMap::new();
+    for (i, window) in words.windows(n).enumerate() {
+        let key: Vec<&str> = window.iter().map(|(_, w)| w.as_str()).collect();
+        let entry = seen.entry(key).or_insert((0, 0));
+        entry.0 += 1;
+        if entry.0 == 2 {
+            entry.1 = words[i].0;
+        }
+        if entry.0 >= policy.min_repeats {
+            let phrase: Vec<&str> = window.iter().map(|(_, w)| w.as_str()).collect();
+            return Some(Degeneration {
+                cut_at: entry.1,
+                reason: format!("Degenerate Repetition (\"{}\" x{})", phrase.join(" "), entry.0),
+            });
+        }
+    }
+    None
+}
+
+fn repeated_paragraph(text: &str, policy: &RepetitionPolicy) -> Option<Degeneration> {
+    let paragraphs: Vec<(usize, &str)> = text.split("\n\n")
+        .map(str::trim)
+        .map(|p| (p.as_ptr() as usize - text.as_ptr() as usize, p))
+        .filter(|(_, p)| p.chars().count() >= policy.min_paragraph_chars)
+        .collect();
+    // Per paragraph: later near-copies and where the first of them starts.
+    let mut copies: Vec<(usize, usize)> = vec![(0, 0); paragraphs.len()];
+    for (j, (offset, paragraph)) in paragraphs.iter().enumerate() {
+        let Some(i) = (0..j).find(|&i| crate::word_overlap_similarity(paragraphs[i].1, paragraph) >= policy.paragraph_similarity) else {
+            continue;
+        };
+        copies[i].0 += 1;
+        if copies[i].0 == 1 {
+            copies[i].1 = *offset;
+        }
+        if copies[i].0 >= 2 {
+            return Some(Degeneration {
+                cut_at: copies[i].1,
+                reason: format!("Degenerate Repetition (paragraph x{})", copies[i].0 + 1),
+            });
+        }
+    }
+    None
+}
+
+fn truncate_chars(text: &str, max: usize) -> &str {
+    text.char_indices().nth(max).map_or(text, |(i, _)| &text[..i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cuts_at_first_repeated_copy() {
+        let policy = RepetitionPolicy::default();
+        let looped = format!("Here is the plan. {}", "I will now check the file again. ".repeat(6));
+        let hit = scan(&looped, &policy).unwrap();
+        assert_eq!(&looped[..hit.cut_at], "Here is the plan. I will now check the file again. ");
+
+        let paragraph = "The migration failed because the schema version table is missing a row for the current release.";
+        let text = format!("Summary first.\n\n{p}\n\nThen:\n\n{p}\n\n{p}", p = paragraph);
+        let hit = scan(&text, &policy).unwrap();
+        assert_eq!(hit.reason, "Degenerate Repetition (paragraph x3)");
+        assert_eq!(&text[..hit.cut_at], &format!("Summary first.\n\n{}\n\nThen:\n\n", paragraph));
+
+        assert!(scan("A normal answer that mentions the file once and the plan once.", &policy).is_none());
+    }
+}
@@ -0,0 +1,430 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::timeseries::DAY;
+
+// --- USAGE REPORTS ---
+// Spend, savings, requests and enforced interventions are booked per UTC
+// day and tenant, with each session's share, and kept for
+// `SENTINEL_REPORT_RETAIN_DAYS` (92). They are saved with the sessions
+// (embedded or Postgres storage) and travel in session snapshots; erasing a
+// session drops its share, not the day's totals.
+// `GET /api/reports?period=day|week` folds them into one report per period
+// (weeks start on Monday), newest first: totals, then each tenant with its
+// `SENTINEL_REPORT_TOP` (5) most expensive sessions. `count` picks how many
+// periods (7 days or 4 weeks by default) and `tenant` narrows to one; a
+// tenant-bound caller only ever sees its own.
+//
+// With `SENTINEL_REPORT_DELIVERY=day` or `week`, the report of each period
+// that closes is posted to the Slack / Discord webhooks and, given
+// `SENTINEL_REPORT_EMAIL_TO`, emailed through the paging SMTP settings. The
+// period last seen is saved with the ledger, so one that closed while
+// Sentinel was down is still sent after the restart.
+
+/// Sessions booked per day and tenant; past it, new sessions only count
+/// towards the totals.
+const MAX_SESSIONS_PER_DAY: usize = 10_000;
+
+/// Tenants listed in a delivered report.
+const MAX_DELIVERED_TENANTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "day" | "daily" => Ok(Period::Day),
+            "week" | "weekly" => Ok(Period::Week),
+            other => Err(format!("unknown period `{}` (day, week)", other)),
+        }
+    }
+}
+
+impl Period {
+    fn len(self) -> u64 {
+        match self {
+            Period::Day => DAY,
+            Period::Week => 7 * DAY,
+        }
+    }
+
+    /// Start of the period containing `now`.
+    fn start(self, now: u64) -> u64 {
+        let day = now - now % DAY;
+        match self {
+            Period::Day => day,
+            // 1970-01-01 was a Thursday.
+            Period::Week => day - ((day / DAY + 3) % 7) * DAY,
+        }
+    }
+
+    fn default_count(self) -> usize {
+        match self {
+            Period::Day => 7,
+            Period::Week => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub cost_usd: f64,
+    pub interventions: u64,
+}
+
+/// One tenant's usage on one UTC day.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DayUsage {
+    /// Unix seconds at the start of the day.
+    pub day: u64,
+    pub tenant: Option<String>,
+    pub requests: u64,
+    pub cost_usd: f64,
+    pub saved_usd: f64,
+    pub interventions: BTreeMap<String, u64>,
+    pub sessions: HashMap<String, SessionUsage>,
+}
+
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    days: Mutex<BTreeMap<(u64, Option<String>), DayUsage>>,
+    /// The delivery period and the start of the one last seen open.
+    open_period: Mutex<Option<(Period, u64)>>,
+}
+
+/// What the ledger keeps across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedUsage {
+    pub days: Vec<DayUsage>,
+    #[serde(default)]
+    pub open_period: Option<(Period, u64)>,
+}
+
+impl UsageLedger {
+    fn with_day(&self, now: u64, tenant: Option<&str>, f: impl FnOnce(&mut DayUsage)) {
+        let day = now - now % DAY;
+        let mut days = self.days.lock().unwrap_or_else(|p| p.into_inner());
+        let usage = days.entry((day, tenant.map(str::to_string)))
+            .or_insert_with(|| DayUsage { day, tenant: tenant.map(str::to_string), ..DayUsage::default() });
+        f(usage);
+    }
+
+    fn with_session(&self, now: u64, tenant: Option<&str>, session_id: &str, f: impl FnOnce(&mut DayUsage, Option<&mut SessionUsage>)) {
+        self.with_day(now, tenant, |usage| {
+            if !usage.sessions.contains_key(session_id) && usage.sessions.len() >= MAX_SESSIONS_PER_DAY {
+                return f(usage, None);
+            }
+            let mut session = usage.sessions.remove(session_id).unwrap_or_default();
+            f(usage, Some(&mut session));
+            usage.sessions.insert(session_id.to_string(), session);
+        });
+    }
+
+    pub fn record_request(&self, now: u64, tenant: Option<&str>) {
+        self.with_day(now, tenant, |u| u.requests += 1);
+    }
+
+    pub fn record_cost(&self, now: u64, tenant: Option<&str>, session_id: &str, usd: f64) {
+        self.with_session(now, tenant, session_id, |u, session| {
+            u.cost_usd += usd;
+            if let Some(session) = session {
+                session.cost_usd += usd;
+            }
+        });
+    }
+
+    pub fn record_intervention(&self, now: u64, tenant: Option<&str>, session_id: &str, detector: &str, saved_usd: f64) {
+        self.with_session(now, tenant, session_id, |u, session| {
+            *u.interventions.entry(detector.to_string()).or_default() += 1;
+            u.saved_usd += saved_usd;
+            if let Some(session) = session {
+                session.interventions += 1;
+            }
+        });
+    }
+
+    /// Forgets days older than `retain_days`.
+    pub fn prune(&self, now: u64, retain_days: u64) {
+        let cutoff = (now - now % DAY).saturating_sub(retain_days * DAY);
+        self.days.lock().unwrap_or_else(|p| p.into_inner()).retain(|(day, _), _| *day >= cutoff);
+    }
+
+    pub fn days(&self) -> Vec<DayUsage> {
+        self.days.lock().unwrap_or_else(|p| p.into_inner()).values().cloned().collect()
+    }
+
+    pub fn restore(&self, days: Vec<DayUsage>) {
+        let mut ledger = self.days.lock().unwrap_or_else(|p| p.into_inner());
+        for usage in days {
+            ledger.insert((usage.day, usage.tenant.clone()), usage);
+        }
+    }
+
+    pub fn saved(&self) -> SavedUsage {
+        SavedUsage { days: self.days(), open_period: *self.open_period.lock().unwrap_or_else(|p| p.into_inner()) }
+    }
+
+    pub fn restore_saved(&self, saved: SavedUsage) {
+        self.restore(saved.days);
+        *self.open_period.lock().unwrap_or_else(|p| p.into_inner()) = saved.open_period;
+    }
+
+    /// Drops the share of sessions `matches` selects from every day; how
+    /// many entries were dropped. Day totals are aggregates and stay.
+    pub fn forget_sessions(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut days = self.days.lock().unwrap_or_else(|p| p.into_inner());
+        let mut dropped = 0;
+        for usage in days.values_mut() {
+            let before = usage.sessions.len();
+            usage.sessions.retain(|id, _| !matches(id));
+            dropped += before - usage.sessions.len();
+        }
+        dropped
+    }
+
+    /// Reports for the `count` periods up to the one containing `now`,
+    /// newest first; periods without usage are left out.
+    pub fn reports(&self, period: Period, now: u64, count: usize, tenant: Option<&str>, top: usize) -> Vec<Report> {
+        let days = self.days.lock().unwrap_or_else(|p| p.into_inner());
+        (0..count as u64).filter_map(|back| {
+            let start = period.start(now).checked_sub(back * period.len())?;
+            let end = start + period.len();
+            let mut tenants: BTreeMap<Option<&str>, Vec<&DayUsage>> = BTreeMap::new();
+            for usage in days.range((start, None)..(end, None)).map(|(_, u)| u) {
+                if tenant.is_none_or(|t| usage.tenant.as_deref() == Some(t)) {
+                    tenants.entry(usage.tenant.as_deref()).or_default().push(usage);
+                }
+            }
+            if tenants.is_empty() {
+                return None;
+            }
+            let all: Vec<&DayUsage> = tenants.values().flatten().copied().collect();
+            Some(Report {
+                period,
+                start,
+                end,
+                totals: summarize(None, &all, top),
+                tenants: tenants.iter().map(|(tenant, days)| summarize(*tenant, days, top)).collect(),
+            })
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopSession {
+    pub session_id: String,
+    pub cost_usd: f64,
+    pub interventions: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    /// Absent for the totals and for traffic without a tenant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub requests: u64,
+    pub cost_usd: f64,
+    pub saved_usd: f64,
+    pub interventions: BTreeMap<String, u64>,
+    pub top_sessions: Vec<TopSession>,
+}
+
+impl Summary {
+    fn total_interventions(&self) -> u64 {
+        self.interventions.values().sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub period: Period,
+    pub start: u64,
+    pub end: u64,
+    pub totals: Summary,
+    pub tenants: Vec<Summary>,
+}
+
+fn summarize(tenant: Option<&str>, days: &[&DayUsage], top: usize) -> Summary {
+    let mut interventions = BTreeMap::new();
+    let mut sessions: HashMap<&str, SessionUsage> = HashMap::new();
+    for usage in days {
+        for (detector, n) in &usage.interventions {
+            *interventions.entry(detector.clone()).or_default() += n;
+        }
+        for (id, session) in &usage.sessions {
+            let total = sessions.entry(id.as_str()).or_default();
+            total.cost_usd += session.cost_usd;
+            total.interventions += session.interventions;
+        }
+    }
+    let mut top_sessions: Vec<TopSession> = sessions.into_iter()
+        .map(|(id, s)| TopSession { session_id: id.to_string(), cost_usd: s.cost_usd, interventions: s.interventions })
+        .collect();
+    top_sessions.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd).then_with(|| a.session_id.cmp(&b.session_id)));
+    top_sessions.truncate(top);
+    Summary {
+        tenant: tenant.map(str::to_string),
+        requests: days.iter().map(|u| u.requests).sum(),
+        cost_usd: days.iter().map(|u| u.cost_usd).sum(),
+        saved_usd: days.iter().map(|u| u.saved_usd).sum(),
+        interventions,
+        top_sessions,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub period: Option<String>,
+    pub count: Option<usize>,
+    pub tenant: Option<String>,
+    pub top: Option<usize>,
+}
+
+/// `GET /api/reports?period=week`.
+pub async fn list(State(state): State<AppState>, Query(query): Query<ReportQuery>) -> Response {
+    let period = match query.period.as_deref().unwrap_or("day").parse::<Period>() {
+        Ok(period) => period,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    };
+    let policy = &state.config.reports;
+    let count = query.count.unwrap_or(period.default_count()).clamp(1, 1 + policy.retain_days as usize);
+    let top = query.top.unwrap_or(policy.top_sessions).min(100);
+    Json(serde_json::json!({
+        "period": period,
+        "reports": state.usage.reports(period, crate::now_secs(), count, query.tenant.as_deref(), top),
+    })).into_response()
+}
+
+fn date(secs: u64) -> String {
+    let (year, month, day, ..) = crate::archive::utc(secs);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// `report` as a message; `escape` guards names against the chat's markup.
+fn render(report: &Report, escape: impl Fn(&str) -> String) -> String {
+    let line = |s: &Summary| format!(
+        "${:.2} spent · ${:.2} saved · {} requests · {} interventions",
+        s.cost_usd, s.saved_usd, s.requests, s.total_interventions(),
+    );
+    let period = match report.period {
+        Period::Day => date(report.start),
+        Period::Week => format!("week of {}", date(report.start)),
+    };
+    let mut lines = vec![format!("Sentinel usage, {} (UTC)", period), line(&report.totals)];
+    for summary in report.tenants.iter().filter(|s| s.tenant.is_some()).take(MAX_DELIVERED_TENANTS) {
+        lines.push(format!("{}: {}", escape(summary.tenant.as_deref().unwrap_or_default()), line(summary)));
+    }
+    if report.tenants.len() > MAX_DELIVERED_TENANTS {
+        lines.push(format!("…and {} more tenants", report.tenants.len() - MAX_DELIVERED_TENANTS));
+    }
+    if !report.totals.top_sessions.is_empty() {
+        let top: Vec<String> = report.totals.top_sessions.iter()
+            .map(|s| format!("{} ${:.2}", escape(&s.session_id), s.cost_usd))
+            .collect();
+        lines.push(format!("Top sessions: {}", top.join(", ")));
+    }
+    lines.join("\n")
+}
+
+fn deliver(state: &AppState, report: &Report) {
+    crate::notify::broadcast(state, |chat| render(report, |s| crate::notify::escape(chat, s)));
+    let policy = &state.config.reports;
+    if !policy.email_to.is_empty() {
+        let (paging, to) = (state.config.paging.clone(), policy.email_to.clone());
+        let subject = render(report, str::to_string).lines().next().unwrap_or_default().to_string();
+        let body = render(report, str::to_string);
+        tokio::spawn(async move {
+            if let Err(e) = crate::paging::send_email(&paging, &to, &subject, body).await {
+                tracing::error!("Report email failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Prunes the ledger and, with `SENTINEL_REPORT_DELIVERY`, sends the report
+/// of each period that closes.
+pub fn spawn_reporter(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            tick.tick().await;
+            let config = state.current_config();
+            let now = crate::now_secs();
+            state.usage.prune(now, config.reports.retain_days);
+            let start = config.reports.deliver.map(|period| (period, period.start(now)));
+            let previous = std::mem::replace(&mut *state.usage.open_period.lock().unwrap_or_else(|p| p.into_inner()), start);
+            if let (Some((period, start)), Some((p, previous))) = (start, previous)
+                && p == period && previous < start
+            {
+                let top = config.reports.top_sessions;
+                if let Some(report) = state.usage.reports(period, previous, 1, None, top).into_iter().next() {
+                    deliver(&state.with_current_config(), &report);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_fold_days_into_weeks_per_tenant() {
+        let ledger = UsageLedger::default();
+        // Monday 2026-10-05 and the Sunday and Monday around it.
+        let monday = 20_731 * DAY;
+        assert_eq!(Period::Week.start(monday + 3 * DAY + 5), monday);
+        ledger.record_request(monday - DAY, Some("acme"));
+        ledger.record_cost(monday - DAY, Some("acme"), "acme/u1", 1.0);
+        ledger.record_request(monday + 60, Some("acme"));
+        ledger.record_cost(monday + 60, Some("acme"), "acme/u1", 0.5);
+        ledger.record_cost(monday + DAY, Some("acme"), "acme/u2", 2.0);
+        ledger.record_intervention(monday + DAY, Some("acme"), "acme/u1", "fuzzy_loop", 0.25);
+        ledger.record_cost(monday + 2 * DAY, None, "anon", 0.1);
+
+        let weeks = ledger.reports(Period::Week, monday + 2 * DAY, 2, None, 5);
+        assert_eq!(weeks.len(), 2);
+        let this_week = &weeks[0];
+        assert_eq!((this_week.start, this_week.end), (monday, monday + 7 * DAY));
+        assert!((this_week.totals.cost_usd - 2.6).abs() < 1e-9);
+        assert_eq!(this_week.tenants.len(), 2);
+        let acme = this_week.tenants.iter().find(|s| s.tenant.as_deref() == Some("acme")).unwrap();
+        assert_eq!(acme.requests, 1);
+        assert_eq!(acme.interventions["fuzzy_loop"], 1);
+        assert_eq!(acme.top_sessions[0], TopSession { session_id: "acme/u2".to_string(), cost_usd: 2.0, interventions: 0 });
+        assert_eq!(acme.top_sessions[1].interventions, 1);
+        assert_eq!(weeks[1].totals.cost_usd, 1.0);
+
+        let days = ledger.reports(Period::Day, monday + 2 * DAY, 7, Some("acme"), 1);
+        assert_eq!(days.iter().map(|r| r.start).collect::<Vec<_>>(), vec![monday + DAY, monday, monday - DAY]);
+        assert_eq!(days[0].totals.top_sessions.len(), 1);
+        assert!(render(&weeks[0], str::to_string).starts_with("Sentinel usage, week of 2026-10-05"));
+
+        ledger.prune(monday + 2 * DAY, 1);
+        assert_eq!(ledger.days().len(), 2);
+
+        assert_eq!(ledger.forget_sessions(|id| id == "acme/u1"), 1);
+        let restored = UsageLedger::default();
+        restored.restore_saved(serde_json::from_str(&serde_json::to_string(&ledger.saved()).unwrap()).unwrap());
+        let acme = restored.reports(Period::Day, monday + DAY, 1, Some("acme"), 5).remove(0).totals;
+        assert_eq!((acme.cost_usd, acme.top_sessions.len()), (2.0, 1));
+    }
+}
@@ -0,0 +1,672 @@
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::routing::{Conditions, RequestView};
+
+// --- REQUEST REWRITING ---
+// Changes made to a request before it leaves for the provider. One rule per
+// line of `SENTINEL_REWRITE_RULES` (or `SENTINEL_REWRITE_FILE`), optionally
+// behind the conditions routing rules use:
+//
+//   strip logit_bias
+//   if model == gpt-4o* then max temperature 0.7
+//   if header x-team == support then system "Never include customer account numbers."
+//   scrub email,card,ssn,phone
+//
+// `strip <path>` removes a (dot-separated) field, `max <path> <n>` lowers a
+// number above `n`, `system "<text>"` adds a system message after the
+// leading ones of a chat request, and `scrub <kinds>` replaces e-mail
+// addresses, card numbers (Luhn-checked), US social security numbers and
+// phone numbers in the messages or prompt with `[EMAIL]`, `[CARD]`, ... (`pii`
+// for all four). Every rule that applies runs, top to bottom: `scrub` rules
+// before anything else reads the prompt, so detectors, plugins, embeddings
+// and audit snippets only see the scrubbed text, and the others after the
+// request-side detectors and plugins. Each change is audit-logged as the
+// `rewrite` detector without being counted as an intervention.
+//
+// Completions get the same treatment from `SENTINEL_RESPONSE_REWRITE_RULES`
+// (or `SENTINEL_RESPONSE_REWRITE_FILE`), whose conditions see the request:
+//
+//   if header x-team == support then append "AI-generated; verify before sending."
+//   strip markdown
+//   link https://wiki.internal/ https://docs.example.com/
+//   normalize
+//
+// `append "<text>"` adds a paragraph at the end, `strip markdown` drops
+// heading and quote marks, paired emphasis and code marks and fences (code
+// keeps its text, `__init__` stays) and turns links into `text (url)`,
+// `strip html` drops tags and decodes the common entities, `link <from>
+// <to>` rewrites URL prefixes and `normalize` trims trailing spaces and extra
+// blank lines. Line ends and a final newline are kept. They apply to the text of
+// non-streamed completions, after the response-side detectors and plugins.
+
+pub const DETECTOR: &str = "rewrite";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pii {
+    Email,
+    Card,
+    Ssn,
+    Phone,
+}
+
+impl Pii {
+    const ALL: [Pii; 4] = [Pii::Email, Pii::Card, Pii::Ssn, Pii::Phone];
+
+    fn name(self) -> &'static str {
+        match self {
+            Pii::Email => "email",
+            Pii::Card => "card",
+            Pii::Ssn => "ssn",
+            Pii::Phone => "phone",
+        }
+    }
+
+    fn placeholder(self) -> &'static str {
+        match self {
+            Pii::Email => "[EMAIL]",
+            Pii::Card => "[CARD]",
+            Pii::Ssn => "[SSN]",
+            Pii::Phone => "[PHONE]",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Strip(String),
+    Max(String, f64),
+    System(String),
+    Scrub(Vec<Pii>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    conditions: Conditions,
+    action: Action,
+}
+
+/// Splits `[if <conditions> then] <verb> <args>`.
+fn split_rule(src: &str) -> Result<(Conditions, &str, &str), String> {
+    let src = src.trim();
+    let (conditions, action) = match src.strip_prefix("if ") {
+        Some(rest) => {
+            let (conds, action) = rest.split_once(" then ").ok_or("rule is missing `then`")?;
+            (Conditions::parse(conds)?, action.trim())
+        }
+        None => (Conditions::default(), src),
+    };
+    let (verb, args) = action.split_once(char::is_whitespace).map_or((action, ""), |(v, a)| (v, a.trim()));
+    Ok((conditions, verb, args))
+}
+
+/// `args` without surrounding quotes, with `\n` as a line break.
+fn quoted(args: &str) -> String {
+    args.strip_prefix('"').and_then(|a| a.strip_suffix('"')).unwrap_or(args).replace("\\n", "\n")
+}
+
+impl Rule {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let (conditions, verb, args) = split_rule(src)?;
+        let action = match (verb, args.split_whitespace().collect::<Vec<_>>().as_slice()) {
+            ("strip", [path]) => Action::Strip(path.to_string()),
+            ("max", [path, ceiling]) => Action::Max(
+                path.to_string(),
+                ceiling.parse().map_err(|_| format!("`{}` is not a number", ceiling))?,
+            ),
+            ("system", _) if !args.is_empty() => Action::System(quoted(args)),
+            ("scrub", [kinds]) => Action::Scrub(parse_kinds(kinds)?),
+            _ => return Err(format!("cannot parse action `{} {}`", verb, args)),
+        };
+        Ok(Self { conditions, action })
+    }
+}
+
+fn parse_kinds(src: &str) -> Result<Vec<Pii>, String> {
+    let mut kinds = Vec::new();
+    for name in src.split(',').filter(|n| !n.is_empty()) {
+        match name {
+            "pii" => kinds.extend(Pii::ALL),
+            _ => kinds.push(Pii::ALL.into_iter().find(|k| k.name() == name).ok_or_else(|| format!("unknown kind `{}`", name))?),
+        }
+    }
+    Ok(kinds)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ResponseAction {
+    Append(String),
+    StripMarkdown,
+    StripHtml,
+    Link(String, String),
+    Normalize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseRule {
+    conditions: Conditions,
+    action: ResponseAction,
+}
+
+impl ResponseRule {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let (conditions, verb, args) = split_rule(src)?;
+        let action = match (verb, args.split_whitespace().collect::<Vec<_>>().as_slice()) {
+            ("append", _) if !args.is_empty() => ResponseAction::Append(quoted(args)),
+            ("strip", ["markdown"]) => ResponseAction::StripMarkdown,
+            ("strip", ["html"]) => ResponseAction::StripHtml,
+            ("link", [from, to]) => ResponseAction::Link(from.to_string(), to.to_string()),
+            ("normalize", []) => ResponseAction::Normalize,
+            _ => return Err(format!("cannot parse action `{} {}`", verb, args)),
+        };
+        Ok(Self { conditions, action })
+    }
+}
+
+/// Parses a rule list, returning the valid rules and an error per bad line.
+pub fn parse_rules<R>(src: &str, parse: impl Fn(&str) -> Result<R, String>) -> (Vec<R>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for line in src.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') { continue; }
+        match parse(line) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(format!("{}: {}", line, e)),
+        }
+    }
+    (rules, errors)
+}
+
+/// Applies every matching `scrub` rule to `body`; a description of each change made.
+pub fn scrub(rules: &[Rule], headers: &HeaderMap, model: &str, body: &mut Value) -> Vec<String> {
+    apply_where(rules, headers, model, body, |action| matches!(action, Action::Scrub(_)))
+}
+
+/// Applies every other matching rule to `body`; a description of each change made.
+pub fn apply(rules: &[Rule], headers: &HeaderMap, model: &str, body: &mut Value) -> Vec<String> {
+    apply_where(rules, headers, model, body, |action| !matches!(action, Action::Scrub(_)))
+}
+
+fn apply_where(rules: &[Rule], headers: &HeaderMap, model: &str, body: &mut Value, pick: fn(&Action) -> bool) -> Vec<String> {
+    let mut changes = Vec::new();
+    for rule in rules.iter().filter(|r| pick(&r.action)) {
+        if !rule.conditions.matches(&RequestView { headers, model, body: &*body }) {
+            continue;
+        }
+        changes.extend(match &rule.action {
+            Action::Strip(path) => strip(body, path).then(|| format!("Stripped `{}`", path)),
+            Action::Max(path, ceiling) => lower(body, path, *ceiling).map(|was| format!("Lowered `{}` from {} to {}", path, was, ceiling)),
+            Action::System(text) => add_system(body, text).then(|| "Added a system message".to_string()),
+            Action::Scrub(kinds) => {
+                let found = scrub_body(body, kinds);
+                let counts: Vec<String> = Pii::ALL.iter()
+                    .map(|k| (k, found.iter().filter(|f| *f == k).count()))
+                    .filter(|(_, n)| *n > 0)
+                    .map(|(k, n)| format!("{} {}", n, k.name()))
+                    .collect();
+                (!counts.is_empty()).then(|| format!("Scrubbed {}", counts.join(", ")))
+            }
+        });
+    }
+    changes
+}
+
+/// Applies every matching rule to a completion's `text`; the new text and a
+/// description of each change, or `None` when nothing changed.
+pub fn apply_response(rules: &[ResponseRule], request: &RequestView, text: &str) -> Option<(String, Vec<String>)> {
+    let mut text = text.to_string();
+    let mut changes = Vec::new();
+    for rule in rules.iter().filter(|r| r.conditions.matches(request)) {
+        let (new, change) = match &rule.action {
+            ResponseAction::Append(disclaimer) if text.trim_end().ends_with(disclaimer.as_str()) => continue,
+            ResponseAction::Append(disclaimer) => {
+                let end = if text.ends_with('\n') { "\n" } else { "" };
+                (format!("{}\n\n{}{}", text.trim_end(), disclaimer, end), "Appended a disclaimer".to_string())
+            }
+            ResponseAction::StripMarkdown => (strip_markdown(&text), "Stripped markdown".to_string()),
+            ResponseAction::StripHtml => (strip_html(&text), "Stripped HTML".to_string()),
+            ResponseAction::Link(from, to) => {
+                let n = text.matches(from.as_str()).count();
+                (text.replace(from.as_str(), to), format!("Rewrote {} link(s) to {}", n, to))
+            }
+            ResponseAction::Normalize => (normalize(&text), "Normalized formatting".to_string()),
+        };
+        if new != text {
+            text = new;
+            changes.push(change);
+        }
+    }
+    (!changes.is_empty()).then_some((text, changes))
+}
+
+fn strip_markdown(text: &str) -> String {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let fences: Vec<usize> = lines.iter().enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with("```"))
+        .map(|(i, _)| i)
+        .collect();
+    // Fences go in pairs; one left open is kept.
+    let fences = &fences[..fences.len() / 2 * 2];
+    let mut out = String::with_capacity(text.len());
+    let mut in_block = false;
+    for (i, line) in lines.iter().enumerate() {
+        if fences.contains(&i) {
+            in_block = !in_block;
+            continue;
+        }
+        if in_block {
+            out.push_str(line);
+            continue;
+        }
+        let body = line.trim_end_matches(['\r', '\n']);
+        // `## Title`, but not `#hashtag`.
+        let heading = body.trim_start_matches('#');
+        let body = if heading.len() < body.len() && (heading.is_empty() || heading.starts_with(' ')) { heading.trim_start() } else { body };
+        let body = body.strip_prefix("> ").unwrap_or(body);
+        out.push_str(&markdown_links(&without_markup(body)));
+        out.push_str(&line[line.trim_end_matches(['\r', '\n']).len()..]);
+    }
+    out
+}
+
+/// `line` without the backticks around its code spans and the emphasis
+/// markers (`**`, `__`, `~~`) that pair up outside them.
+fn without_markup(line: &str) -> String {
+    let mut drop: Vec<(usize, usize)> = Vec::new();
+    let mut code: Vec<(usize, usize)> = Vec::new();
+    let tick_run = |at: usize| line[at..].find(|c| c != '`').unwrap_or(line.len() - at);
+    let mut i = 0;
+    while let Some(n) = line[i..].find('`') {
+        let open = i + n;
+        let run = tick_run(open);
+        // The next run of exactly as many backticks closes the span.
+        let mut search = open + run;
+        let close = loop {
+            let Some(n) = line[search..].find('`') else { break None };
+            let len = tick_run(search + n);
+            if len == run {
+                break Some(search + n);
+            }
+            search += n + len;
+        };
+        let Some(close) = close else {
+            i = open + run;
+            continue;
+        };
+        // One space of padding on both sides belongs to the marks.
+        let inner = &line[open + run..close];
+        let pad = usize::from(inner.len() > 2 && inner.starts_with(' ') && inner.ends_with(' ') && !inner.trim().is_empty());
+        drop.push((open, open + run + pad));
+        drop.push((close - pad, close + run));
+        code.push((open, close + run));
+        i = close + run;
+    }
+
+    let in_code = |at: usize| code.iter().any(|&(start, end)| start <= at && at < end);
+    for marker in ["**", "__", "~~"] {
+        let found: Vec<usize> = line.match_indices(marker).map(|(at, _)| at).filter(|&at| !in_code(at)).collect();
+        let mut k = 0;
+        while k + 1 < found.len() {
+            let (open, close) = (found[k], found[k + 1]);
+            let inner = &line[open + marker.len()..close];
+            let before = line[..open].chars().next_back();
+            let after = line[close + marker.len()..].chars().next();
+            let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            let paired = !inner.is_empty() && !inner.starts_with(char::is_whitespace) && !inner.ends_with(char::is_whitespace)
+                // Underscores inside a word or around a lone identifier
+                // (`__init__`) are part of the text.
+                && (marker != "__" || (!word(before) && !word(after) && !inner.chars().all(|c| c.is_alphanumeric() || c == '_')));
+            if paired {
+                drop.push((open, open + marker.len()));
+                drop.push((close, close + marker.len()));
+                k += 2;
+            } else {
+                k += 1;
+            }
+        }
+    }
+
+    drop.sort_unstable();
+    let mut out = String::with_capacity(line.len());
+    let mut copied = 0;
+    for (start, end) in drop {
+        out.push_str(&line[copied..start.max(copied)]);
+        copied = copied.max(end);
+    }
+    out.push_str(&line[copied..]);
+    out
+}
+
+/// `[text](url)` as `text (url)` and `![alt](url)` as `alt`.
+fn markdown_links(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let parsed = rest[open + 1..].split_once("](").and_then(|(label, tail)| {
+            let close = tail.find(')')?;
+            (!label.contains('[')).then_some((label, &tail[..close], &tail[close + 1..]))
+        });
+        let Some((label, url, tail)) = parsed else {
+            out.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+        match rest[..open].strip_suffix('!') {
+            Some(before) => {
+                out.push_str(before);
+                out.push_str(label);
+            }
+            None => {
+                out.push_str(&rest[..open]);
+                out.push_str(&format!("{} ({})", label, url));
+            }
+        }
+        rest = tail;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn strip_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        let is_tag = rest[open + 1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        match rest[open..].find('>').filter(|_| is_tag) {
+            Some(close) => {
+                out.push_str(&rest[..open]);
+                rest = &rest[open + close + 1..];
+            }
+            None => {
+                out.push_str(&rest[..=open]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    [("&nbsp;", " "), ("&lt;", "<"), ("&gt;", ">"), ("&quot;", "\""), ("&#39;", "'"), ("&amp;", "&")]
+        .iter()
+        .fold(out, |text, (entity, plain)| text.replace(entity, plain))
+}
+
+/// LF line ends, no trailing spaces, at most one blank line in a row; a
+/// final line end is kept.
+fn normalize(text: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() && out.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        out.push(line);
+    }
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    let mut normalized = out.join("\n");
+    if text.ends_with('\n') && !normalized.is_empty() {
+        normalized.push('\n');
+    }
+    normalized
+}
+
+/// The parent object of `path` and the last key.
+fn parent<'a, 'p>(body: &'a mut Value, path: &'p str) -> Option<(&'a mut serde_json::Map<String, Value>, &'p str)> {
+    let (parents, key) = path.rsplit_once('.').map_or((None, path), |(p, k)| (Some(p), k));
+    let object = match parents {
+        Some(parents) => parents.split('.').try_fold(body, |v, k| v.get_mut(k))?,
+        None => body,
+    };
+    Some((object.as_object_mut()?, key))
+}
+
+fn strip(body: &mut Value, path: &str) -> bool {
+    parent(body, path).is_some_and(|(object, key)| object.remove(key).is_some())
+}
+
+/// Lowers the number at `path` to `ceiling`; what it was, if it was higher.
+fn lower(body: &mut Value, path: &str, ceiling: f64) -> Option<f64> {
+    let (object, key) = parent(body, path)?;
+    let value = object.get_mut(key)?;
+    let was = value.as_f64().filter(|v| *v > ceiling)?;
+    *value = if value.is_f64() { Value::from(ceiling) } else { Value::from(ceiling.floor() as i64) };
+    Some(was)
+}
+
+fn add_system(body: &mut Value, text: &str) -> bool {
+    let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) else { return false };
+    if messages.iter().any(|m| m["role"] == "system" && m["content"] == text) {
+        return false;
+    }
+    let at = messages.iter().take_while(|m| matches!(m["role"].as_str(), Some("system" | "developer"))).count();
+    messages.insert(at, serde_json::json!({ "role": "system", "content": text }));
+    true
+}
+
+/// Scrubs the text of every message (or the prompt); the kinds replaced.
+fn scrub_body(body: &mut Value, kinds: &[Pii]) -> Vec<Pii> {
+    let mut found = Vec::new();
+    let mut scrub = |value: &mut Value| {
+        if let Some(text) = value.as_str() {
+            let (clean, hits) = scrub_text(text, kinds);
+            if !hits.is_empty() {
+                *value = Value::String(clean);
+                found.extend(hits);
+            }
+        }
+    };
+    if let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) {
+        for content in messages.iter_mut().filter_map(|m| m.get_mut("content")) {
+            match content {
+                Value::Array(parts) => parts.iter_mut().filter_map(|p| p.get_mut("text")).for_each(&mut scrub),
+                content => scrub(content),
+            }
+        }
+    }
+    match body.get_mut("prompt") {
+        Some(Value::Array(prompts)) => prompts.iter_mut().for_each(&mut scrub),
+        Some(prompt) => scrub(prompt),
+        None => {}
+    }
+    found
+}
+
+fn scrub_text(text: &str, kinds: &[Pii]) -> (String, Vec<Pii>) {
+    let mut found = Vec::new();
+    let mut text = text.to_string();
+    if kinds.contains(&Pii::Email) {
+        text = scrub_emails(&text, &mut found);
+    }
+    if kinds.iter().any(|k| *k != Pii::Email) {
+        text = scrub_numbers(&text, kinds, &mut found);
+    }
+    (text, found)
+}
+
+fn scrub_emails(text: &str, found: &mut Vec<Pii>) -> String {
+    let mut out = String::with_capacity(text.len());
+    for piece in text.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end();
+        let edge = |c: char| !c.is_alphanumeric();
+        let start = word.len() - word.trim_start_matches(edge).len();
+        let core = word.trim_matches(edge);
+        if is_email(core) {
+            out.push_str(&word[..start]);
+            out.push_str(Pii::Email.placeholder());
+            out.push_str(&word[start + core.len()..]);
+            found.push(Pii::Email);
+        } else {
+            out.push_str(word);
+        }
+        out.push_str(&piece[word.len()..]);
+    }
+    out
+}
+
+fn is_email(s: &str) -> bool {
+    let Some((local, domain)) = s.split_once('@') else { return false };
+    !local.is_empty()
+        && local.chars().all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-'))
+}
+
+/// Replaces runs of digits (with the separators numbers are written with)
+/// that look like one of `kinds`.
+fn scrub_numbers(text: &str, kinds: &[Pii], found: &mut Vec<Pii>) -> String {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let opens = matches!(c, '+' | '(') && chars.get(i + 1).is_some_and(|(_, d)| d.is_ascii_digit());
+        let glued = i > 0 && chars[i - 1].1.is_alphanumeric();
+        if !(c.is_ascii_digit() || opens) || glued {
+            i += 1;
+            continue;
+        }
+        // The run ends at its last digit; at most two separators in a row.
+        let (mut end, mut gap) = (i, 0);
+        for (j, &(_, ch)) in chars.iter().enumerate().skip(i + 1) {
+            if ch.is_ascii_digit() {
+                (end, gap) = (j, 0);
+            } else if matches!(ch, ' ' | '-' | '.' | '(' | ')') && gap < 2 {
+                gap += 1;
+            } else {
+                break;
+            }
+        }
+        let stop = chars.get(end + 1).map_or(text.len(), |(at, _)| *at);
+        let followed = chars.get(end + 1).is_some_and(|(_, ch)| ch.is_alphanumeric());
+        if let Some(kind) = classify(&text[start..stop], kinds).filter(|_| !followed) {
+            out.push_str(&text[copied..start]);
+            out.push_str(kind.placeholder());
+            copied = stop;
+            found.push(kind);
+        }
+        i = end + 1;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+fn classify(raw: &str, kinds: &[Pii]) -> Option<Pii> {
+    let digits: String = raw.chars().filter(char::is_ascii_digit).collect();
+    let separators: Vec<char> = raw.chars().filter(|c| !c.is_ascii_digit()).collect();
+    [Pii::Ssn, Pii::Card, Pii::Phone].into_iter().filter(|k| kinds.contains(k)).find(|kind| match kind {
+        Pii::Ssn => is_ssn(raw),
+        Pii::Card => (13..=19).contains(&digits.len()) && luhn(&digits),
+        // A lone `.` is a decimal point, not a phone number.
+        Pii::Phone => (10..=15).contains(&digits.len()) && !separators.is_empty() && separators != ['.'],
+        Pii::Email => false,
+    })
+}
+
+fn is_ssn(raw: &str) -> bool {
+    let bytes = raw.as_bytes();
+    bytes.len() == 11 && bytes.iter().enumerate().all(|(i, b)| if i == 3 || i == 6 { *b == b'-' } else { b.is_ascii_digit() })
+}
+
+fn luhn(digits: &str) -> bool {
+    let sum: u32 = digits.bytes().rev().enumerate().map(|(i, b)| {
+        let d = (b - b'0') as u32;
+        if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d }
+    }).sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rules_strip_cap_inject_and_scrub() {
+        let (rules, errors) = parse_rules(concat!(
+            "strip logit_bias\n",
+            "if model == gpt-4o* then max temperature 0.7\n",
+            "if header x-team == support then system \"Never include account numbers.\"\n",
+            "scrub pii\n",
+            "scrub email,fax\n",
+        ), Rule::parse);
+        assert_eq!(rules.len(), 4);
+        assert_eq!(errors.len(), 1);
+
+        let mut body = json!({
+            "model": "gpt-4o",
+            "temperature": 1.2,
+            "logit_bias": {"50256": -100},
+            "messages": [
+                {"role": "system", "content": "You are support."},
+                {"role": "user", "content": "I'm jane.doe@example.com, card 4111 1111 1111 1111, SSN 123-45-6789, call +1 (555) 010-2345. Pi is 3.14159265358979."},
+            ],
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert("x-team", "support".parse().unwrap());
+        assert_eq!(scrub(&rules, &headers, "gpt-4o", &mut body), ["Scrubbed 1 email, 1 card, 1 ssn, 1 phone"]);
+        let changes = apply(&rules, &headers, "gpt-4o", &mut body);
+        assert_eq!(changes, [
+            "Stripped `logit_bias`",
+            "Lowered `temperature` from 1.2 to 0.7",
+            "Added a system message",
+        ]);
+        assert!(body.get("logit_bias").is_none());
+        assert_eq!(body["temperature"], 0.7);
+        assert_eq!(body["messages"][1], json!({"role": "system", "content": "Never include account numbers."}));
+        assert_eq!(
+            body["messages"][2]["content"],
+            "I'm [EMAIL], card [CARD], SSN [SSN], call [PHONE]. Pi is 3.14159265358979.",
+        );
+
+        // Nothing left to change, and conditions that don't hold.
+        assert!(apply(&rules, &headers, "gpt-4o", &mut body).is_empty());
+        assert!(scrub(&rules, &headers, "gpt-4o", &mut body).is_empty());
+        let mut other = json!({"temperature": 1.2, "prompt": "order 12345"});
+        assert!(apply(&rules, &HeaderMap::new(), "llama-3", &mut other).is_empty());
+    }
+
+    #[test]
+    fn test_response_rules_clean_up_completions() {
+        let (rules, errors) = parse_rules(concat!(
+            "strip markdown\n",
+            "strip html\n",
+            "link https://wiki.internal/ https://docs.example.com/\n",
+            "normalize\n",
+            "if model == gpt-4o then append \"AI-generated; verify before sending.\"\n",
+            "strip tables\n",
+        ), ResponseRule::parse);
+        assert_eq!(rules.len(), 5);
+        assert_eq!(errors.len(), 1);
+
+        let headers = HeaderMap::new();
+        let request = json!({});
+        let view = RequestView { headers: &headers, model: "gpt-4o", body: &request };
+        let text = "## Steps  \r\n\n\n\nRun **`make`**, see [the guide](https://wiki.internal/build) &amp; <b>retry</b>.\n```sh\nmake\n```\n#hashtag\n";
+        let (text, changes) = apply_response(&rules, &view, text).unwrap();
+        assert_eq!(
+            text,
+            "Steps\n\nRun make, see the guide (https://docs.example.com/build) & retry.\nmake\n#hashtag\n\nAI-generated; verify before sending.\n",
+        );
+        assert_eq!(changes, [
+            "Stripped markdown",
+            "Stripped HTML",
+            "Rewrote 1 link(s) to https://docs.example.com/",
+            "Normalized formatting",
+            "Appended a disclaimer",
+        ]);
+        // Already clean.
+        assert_eq!(apply_response(&rules, &view, &text), None);
+
+        // Only paired marks go, and code keeps its text.
+        assert_eq!(
+            strip_markdown("Call `__init__` or __init__; **bold**, __two words__ and `` a`b `` but not ` alone\r\n"),
+            "Call __init__ or __init__; bold, two words and a`b but not ` alone\r\n",
+        );
+        assert_eq!(strip_markdown("```py\ndef __init__(self, **kw):\n```\n"), "def __init__(self, **kw):\n");
+        assert_eq!(strip_markdown("snake_case and 2 ** 3\n"), "snake_case and 2 ** 3\n");
+        assert_eq!(normalize("a  \n\n\nb\n\n"), "a\n\nb\n");
+        let plain = "Line one\nLine two\n";
+        let (rules, _) = parse_rules("strip markdown\nnormalize\n", ResponseRule::parse);
+        assert_eq!(apply_response(&rules, &view, plain), None);
+    }
+}
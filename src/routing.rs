@@ -0,0 +1,222 @@
+use axum::http::HeaderMap;
+
+// --- ROUTING RULES ---
+// One rule per line (or separated by `;`):
+//
+//   if header x-team == research and model == gpt-4o* then provider azure-eu, budget research-pool
+//
+// Conditions: `header <name>`, `model`, `body <dot.path>` compared with `==` or
+// `!=` (values may use `*` as a wildcard). Actions: `provider <name>`,
+// `budget <pool>`. Rules are evaluated top to bottom; the first match wins.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Header(String),
+    Model,
+    Body(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    field: Field,
+    negate: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Route {
+    pub provider: Option<String>,
+    pub budget_pool: Option<String>,
+}
+
+/// The `if` part of a rule; rewrite rules (`rewrite.rs`) use the same.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conditions(Vec<Condition>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    conditions: Conditions,
+    route: Route,
+}
+
+/// What a rule gets to look at.
+pub struct RequestView<'a> {
+    pub headers: &'a HeaderMap,
+    pub model: &'a str,
+    pub body: &'a serde_json::Value,
+}
+
+impl Rule {
+    pub fn route(&self) -> &Route {
+        &self.route
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let src = src.trim();
+        let rest = src.strip_prefix("if ").ok_or("rule must start with `if`")?;
+        let (conds, actions) = rest.split_once(" then ").ok_or("rule is missing `then`")?;
+        let conditions = Conditions::parse(conds)?;
+
+        let mut route = Route::default();
+        for action in actions.split(',') {
+            match action.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["provider", name] => route.provider = Some(name.to_string()),
+                ["budget", pool] => route.budget_pool = Some(pool.to_string()),
+                _ => return Err(format!("cannot parse action `{}`", action.trim())),
+            }
+        }
+        Ok(Self { conditions, route })
+    }
+
+    pub fn matches(&self, req: &RequestView) -> bool {
+        self.conditions.matches(req)
+    }
+}
+
+impl Conditions {
+    /// Parses `<condition> and <condition> ...`.
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut conditions = Vec::new();
+        for cond in src.split(" and ") {
+            let tokens: Vec<&str> = cond.split_whitespace().collect();
+            let (field, op, value) = match tokens.as_slice() {
+                ["header", name, op, value] => (Field::Header(name.to_ascii_lowercase()), *op, *value),
+                ["body", path, op, value] => (Field::Body(path.to_string()), *op, *value),
+                ["model", op, value] => (Field::Model, *op, *value),
+                _ => return Err(format!("cannot parse condition `{}`", cond.trim())),
+            };
+            let negate = match op {
+                "==" => false,
+                "!=" => true,
+                other => return Err(format!("unknown operator `{}`", other)),
+            };
+            conditions.push(Condition { field, negate, pattern: value.to_string() });
+        }
+        Ok(Self(conditions))
+    }
+
+    /// True when every condition holds (so always for none).
+    pub fn matches(&self, req: &RequestView) -> bool {
+        self.0.iter().all(|c| {
+            let value = match &c.field {
+                Field::Header(name) => req.headers.get(name).and_then(|h| h.to_str().ok()).map(str::to_string),
+                Field::Model => Some(req.model.to_string()),
+                Field::Body(path) => lookup(req.body, path),
+            };
+            let hit = value.is_some_and(|v| glob_match(&c.pattern, &v));
+            hit != c.negate
+        })
+    }
+}
+
+/// Parses a rule list, returning the valid rules and an error per bad line.
+pub fn parse_rules(src: &str) -> (Vec<Rule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for line in src.split(['\n', ';']).map(str::trim) {
+        if line.is_empty() || line.starts_with('#') { continue; }
+        match Rule::parse(line) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(format!("{}: {}", line, e)),
+        }
+    }
+    (rules, errors)
+}
+
+/// Resolves the route for a request: configured rules first, then the
+/// built-in defaults (`x-sentinel-provider` header, model-name heuristics).
+pub fn resolve(rules: &[Rule], req: &RequestView) -> Route {
+    let mut route = rules.iter()
+        .find(|r| r.matches(req))
+        .map(|r| r.route.clone())
+        .unwrap_or_default();
+
+    if route.provider.is_none() {
+        let from_header = req.headers.get("x-sentinel-provider").and_then(|h| h.to_str().ok());
+        route.provider = Some(from_header.map(str::to_string).unwrap_or_else(|| default_provider(req.model).to_string()));
+    }
+    route
+}
+
+fn default_provider(model: &str) -> &'static str {
+    if model.contains("llama") || model.contains("mixtral") || model.contains("gemma") {
+        "groq"
+    } else {
+        "openai"
+    }
+}
+
+fn lookup(body: &serde_json::Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(body, |v, key| v.get(key))?;
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Case-sensitive match where `*` matches any run of characters.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !value.starts_with(first) || !value[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view<'a>(headers: &'a HeaderMap, model: &'a str, body: &'a serde_json::Value) -> RequestView<'a> {
+        RequestView { headers, model, body }
+    }
+
+    #[test]
+    fn test_rule_matches_header_and_model() {
+        let rule = Rule::parse("if header x-team == research and model == gpt-4o* then provider azure-eu, budget research-pool").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-team", "research".parse().unwrap());
+        let body = serde_json::json!({});
+        assert!(rule.matches(&view(&headers, "gpt-4o-mini", &body)));
+        assert!(!rule.matches(&view(&headers, "llama-3", &body)));
+
+        let route = resolve(&[rule], &view(&headers, "gpt-4o", &body));
+        assert_eq!(route.provider.as_deref(), Some("azure-eu"));
+        assert_eq!(route.budget_pool.as_deref(), Some("research-pool"));
+    }
+
+    #[test]
+    fn test_body_condition_and_defaults() {
+        let (rules, errors) = parse_rules("if body metadata.env != prod then provider groq\nif nonsense");
+        assert_eq!(rules.len(), 1);
+        assert_eq!(errors.len(), 1);
+
+        let headers = HeaderMap::new();
+        let prod = serde_json::json!({"metadata": {"env": "prod"}});
+        assert_eq!(resolve(&rules, &view(&headers, "gpt-4o", &prod)).provider.as_deref(), Some("openai"));
+        assert_eq!(resolve(&rules, &view(&headers, "llama-3", &prod)).provider.as_deref(), Some("groq"));
+        let dev = serde_json::json!({"metadata": {"env": "dev"}});
+        assert_eq!(resolve(&rules, &view(&headers, "gpt-4o", &dev)).provider.as_deref(), Some("groq"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("gpt-4*", "gpt-4o"));
+        assert!(glob_match("*mini", "gpt-4o-mini"));
+        assert!(glob_match("a*c*e", "abcde"));
+        assert!(!glob_match("gpt-4*", "gpt-3.5"));
+        assert!(glob_match("exact", "exact"));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::{Config, SavingsMethod};
+
+// --- SAVINGS ESTIMATION ---
+// `estimated` prices the request that was never forwarded: its prompt at
+// roughly four characters per token plus its completion budget (`max_tokens`,
+// or a configured default), at the target model's rate. Interventions made
+// after the upstream call (leaks, cost spikes) avoided nothing and count as
+// zero. `flat` books a fixed amount per detector; `none` disables savings.
+
+/// USD saved by the intervention `detector` made. `request` is the blocked
+/// request body, or `None` when the upstream call had already been made.
+pub fn avoided(config: &Config, detector: &str, model: &str, request: Option<&serde_json::Value>) -> f64 {
+    let policy = &config.savings;
+    match policy.method {
+        SavingsMethod::None => 0.0,
+        SavingsMethod::Flat => policy.flat.get(detector).copied().unwrap_or(0.0),
+        SavingsMethod::Estimated => request.map_or(0.0, |req| {
+            let completion = ["max_completion_tokens", "max_tokens"].iter()
+                .find_map(|k| req[k].as_u64())
+                .unwrap_or(policy.default_completion_tokens);
+            config.pricing.cost(model, estimate_prompt_tokens(req), completion)
+        }),
+    }
+}
+
+/// Rough token count of a chat request's messages, including the few tokens
+/// of framing each message costs, or of a completions `prompt` / embeddings `input`.
+pub fn estimate_prompt_tokens(request: &serde_json::Value) -> u64 {
+    let text_tokens = |text: &str| text.chars().count().div_ceil(4) as u64;
+    match request.get("prompt").or_else(|| request.get("input")).unwrap_or(&serde_json::Value::Null) {
+        serde_json::Value::String(prompt) => return text_tokens(prompt),
+        serde_json::Value::Array(prompts) => return prompts.iter().filter_map(|p| p.as_str()).map(text_tokens).sum(),
+        _ => {}
+    }
+    let Some(messages) = request["messages"].as_array() else { return 0 };
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+/// Rough token count of one chat message, framing and tool-call arguments
+/// included.
+pub fn estimate_message_tokens(message: &serde_json::Value) -> u64 {
+    let arguments: usize = message["tool_calls"].as_array().map_or(0, |calls| {
+        calls.iter().filter_map(|c| c["function"]["arguments"].as_str()).map(|a| a.chars().count()).sum()
+    });
+    let chars: usize = arguments + match &message["content"] {
+        serde_json::Value::String(text) => text.chars().count(),
+        serde_json::Value::Array(parts) => parts.iter()
+            .filter_map(|p| p["text"].as_str())
+            .map(|t| t.chars().count())
+            .sum(),
+        _ => 0,
+    };
+    chars.div_ceil(4) as u64 + 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimated_savings_use_model_pricing() {
+        let config = Config::default();
+        let request = serde_json::json!({
+            "messages": [{ "role": "user", "content": "x".repeat(3_996) }],
+            "max_tokens": 1_000,
+        });
+        assert_eq!(estimate_prompt_tokens(&request), 1_003);
+        // gpt-4o: $2.50 in / $10.00 out per 1M tokens.
+        let saved = avoided(&config, "fuzzy_loop", "gpt-4o", Some(&request));
+        assert!((saved - (1_003.0 * 2.5 + 1_000.0 * 10.0) / 1_000_000.0).abs() < 1e-12);
+        assert_eq!(avoided(&config, "leak", "gpt-4o", None), 0.0);
+    }
+
+    #[test]
+    fn test_flat_and_none_methods() {
+        let mut config = Config::default();
+        config.savings.method = SavingsMethod::Flat;
+        assert_eq!(avoided(&config, "semantic_loop", "gpt-4o", None), 0.50);
+        assert_eq!(avoided(&config, "unknown", "gpt-4o", None), 0.0);
+        config.savings.method = SavingsMethod::None;
+        assert_eq!(avoided(&config, "semantic_loop", "gpt-4o", None), 0.0);
+    }
+}
@@ -47,18 +47,16 @@ impl Scorer {
     }
 }
 
-/// Calculates cosine similarity between two vectors.
-/// Assumes vectors are already normalized (standard for OpenAI embeddings).
+/// Calculates cosine similarity between two vectors, normalizing them so
+/// embeddings of any scale compare correctly. Mismatched or zero-length
+/// vectors score 0.
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return 0.0;
     }
-    
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    
-    // If they aren't normalized, we'd need: dot / (norm_a * norm_b)
-    // But OpenAI text-embedding-3-small/large are pre-normalized to 1.0.
-    dot_product
+    let dot = |x: &[f32], y: &[f32]| -> f32 { x.iter().zip(y).map(|(p, q)| p * q).sum() };
+    let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+    if norms == 0.0 { 0.0 } else { dot(a, b) / norms }
 }
 
 #[cfg(test)]
@@ -0,0 +1,187 @@
+use crate::config::ScriptPolicy;
+
+// --- SCRIPTED RULES ---
+// For checks too small to warrant a WASM plugin: built with `--features
+// scripting`, each line of `SENTINEL_SCRIPT_RULES` is `name: <expression>`
+// in Rhai, compiled at startup and on config reload, and evaluated per
+// request before loop detection, e.g.
+//
+//   gpt4o-budget: model == "gpt-4o" && prompt.contains("export all") && session_cost > 2.0
+//
+// The expression sees `model`, `provider`, `tenant` and `user` ("" when
+// absent), `prompt` (the latest prompt text), `prompt_tokens` (estimated),
+// `session_cost` and `session_interventions`. `true` blocks, a string blocks
+// with that string as the reason, anything else allows. The first rule that
+// blocks is logged as the `script` detector, whose mode and exemptions apply.
+//
+// Rules are expressions only (no statements, loops or `eval`), can't reach
+// files or the network, and are stopped after
+// `SENTINEL_SCRIPT_MAX_OPERATIONS` (100000); a rule that fails is logged
+// and skipped.
+
+pub const DETECTOR: &str = "script";
+
+/// What a rule sees of the request.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct Input<'a> {
+    pub model: &'a str,
+    pub provider: &'a str,
+    pub tenant: Option<&'a str>,
+    pub user: Option<&'a str>,
+    pub prompt: &'a str,
+    pub prompt_tokens: u64,
+    pub session_cost: f64,
+    pub session_interventions: u32,
+}
+
+/// A rule's result, stripped of the engine's types.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+enum Answer {
+    Bool(bool),
+    Text(String),
+    Other,
+}
+
+/// The block reason for `rule`'s answer, if it blocks.
+fn reason(rule: &str, answer: Answer) -> Option<String> {
+    match answer {
+        Answer::Bool(true) => Some(format!("Rule `{}`", rule)),
+        Answer::Text(text) if !text.is_empty() => Some(format!("Rule `{}`: {}", rule, text)),
+        Answer::Bool(false) | Answer::Text(_) | Answer::Other => None,
+    }
+}
+
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+struct Compiled {
+    name: String,
+    #[cfg(feature = "scripting")]
+    ast: rhai::AST,
+}
+
+#[derive(Default)]
+pub struct ScriptHost {
+    #[cfg(feature = "scripting")]
+    engine: Option<rhai::Engine>,
+    rules: Vec<Compiled>,
+}
+
+impl ScriptHost {
+    pub fn load(policy: &ScriptPolicy) -> Self {
+        if policy.rules.is_empty() {
+            return Self::default();
+        }
+        #[cfg(not(feature = "scripting"))]
+        {
+            tracing::warn!("{} SENTINEL_SCRIPT_RULES ignored: built without the `scripting` feature", policy.rules.len());
+            Self::default()
+        }
+        #[cfg(feature = "scripting")]
+        {
+            let engine = engine(policy);
+            let rules = policy.rules.iter().filter_map(|rule| match engine.compile_expression(&rule.source) {
+                Ok(ast) => Some(Compiled { name: rule.name.clone(), ast }),
+                Err(e) => {
+                    tracing::error!("Ignoring script rule `{}`: {}", rule.name, e);
+                    None
+                }
+            }).collect();
+            Self { engine: Some(engine), rules }
+        }
+    }
+
+    /// The reason the first blocking rule gives, if any.
+    pub fn check(&self, input: &Input) -> Option<String> {
+        self.rules.iter().find_map(|rule| match self.eval(rule, input) {
+            Ok(answer) => reason(&rule.name, answer),
+            Err(e) => {
+                tracing::warn!("Script rule `{}` skipped: {}", rule.name, e);
+                None
+            }
+        })
+    }
+
+    #[cfg(feature = "scripting")]
+    fn eval(&self, rule: &Compiled, input: &Input) -> Result<Answer, String> {
+        let Some(engine) = &self.engine else { return Ok(Answer::Other) };
+        let mut scope = rhai::Scope::new();
+        scope.push_constant("model", input.model.to_string());
+        scope.push_constant("provider", input.provider.to_string());
+        scope.push_constant("tenant", input.tenant.unwrap_or_default().to_string());
+        scope.push_constant("user", input.user.unwrap_or_default().to_string());
+        scope.push_constant("prompt", input.prompt.to_string());
+        scope.push_constant("prompt_tokens", input.prompt_tokens as i64);
+        scope.push_constant("session_cost", input.session_cost);
+        scope.push_constant("session_interventions", input.session_interventions as i64);
+        let result = engine.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &rule.ast).map_err(|e| e.to_string())?;
+        Ok(if let Ok(flag) = result.as_bool() {
+            Answer::Bool(flag)
+        } else if result.is_string() {
+            Answer::Text(result.into_string().unwrap_or_default())
+        } else {
+            Answer::Other
+        })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn eval(&self, _rule: &Compiled, _input: &Input) -> Result<Answer, String> {
+        Ok(Answer::Other)
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn engine(policy: &ScriptPolicy) -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine.set_max_operations(policy.max_operations);
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| tracing::info!("script: {}", text));
+    engine.on_debug(|text, _, _| tracing::debug!("script: {}", text));
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScriptRule;
+
+    #[test]
+    fn test_rules_parse_and_answers_map_to_blocks() {
+        let rule = ScriptRule::parse(r#"gpt4o-budget: model == "gpt-4o" && session_cost > 2.0"#).unwrap();
+        assert_eq!(rule.name, "gpt4o-budget");
+        assert_eq!(rule.source, r#"model == "gpt-4o" && session_cost > 2.0"#);
+        assert!(ScriptRule::parse("no expression:").is_err());
+        assert!(ScriptRule::parse("just text").is_err());
+
+        assert_eq!(reason("r", Answer::Bool(true)).as_deref(), Some("Rule `r`"));
+        assert_eq!(reason("r", Answer::Text("too costly".to_string())).as_deref(), Some("Rule `r`: too costly"));
+        assert_eq!(reason("r", Answer::Bool(false)), None);
+        assert_eq!(reason("r", Answer::Text(String::new())), None);
+        assert_eq!(reason("r", Answer::Other), None);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_rules_see_the_request() {
+        let policy = ScriptPolicy {
+            rules: vec![
+                ScriptRule::parse(r#"budget: model == "gpt-4o" && session_cost > 2.0"#).unwrap(),
+                ScriptRule::parse(r#"export: if prompt.contains("export all") { "bulk export" } else { false }"#).unwrap(),
+                ScriptRule::parse("broken: (").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let host = ScriptHost::load(&policy);
+        assert_eq!(host.rules.len(), 2);
+        let input = |prompt, session_cost| Input {
+            model: "gpt-4o", provider: "openai", tenant: None, user: None, prompt,
+            prompt_tokens: 5, session_cost, session_interventions: 0,
+        };
+        assert_eq!(host.check(&input("hello", 1.0)), None);
+        assert_eq!(host.check(&input("hello", 2.5)).as_deref(), Some("Rule `budget`"));
+        assert_eq!(host.check(&input("please export all rows", 0.0)).as_deref(), Some("Rule `export`: bulk export"));
+    }
+}
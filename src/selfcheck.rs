@@ -0,0 +1,101 @@
+use reqwest::Client;
+use std::time::Duration;
+
+use crate::config::{Config, ProviderConfig};
+
+// --- STARTUP SELF-CHECK ---
+// Runs once before the listener binds. Every problem is logged; in strict mode
+// (`SENTINEL_STARTUP_MODE=strict`) any problem aborts startup, otherwise
+// Sentinel starts degraded and reports the problems in `/api/stats`.
+// `SENTINEL_SELF_CHECK=off` skips the network probes.
+
+pub async fn run(client: &Client, config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let probe = std::env::var("SENTINEL_SELF_CHECK").map(|v| v != "off").unwrap_or(true);
+
+    let mut names: Vec<&String> = config.providers.keys().collect();
+    names.sort();
+    for name in names {
+        if name == crate::mock::PROVIDER {
+            continue;
+        }
+        let provider = &config.providers[name];
+        if provider.api_key == "none" || provider.api_key.contains("xxxx") {
+            problems.push(format!("provider '{}' has no API key; requests routed to it will fail", name));
+            continue;
+        }
+        if !probe { continue; }
+        if let Err(e) = probe_provider(client, provider).await {
+            problems.push(format!("provider '{}' {}", name, e));
+        }
+    }
+
+    if config.providers.get("openai").is_none_or(|p| p.api_key == "none") {
+        problems.push("no OPENAI_API_KEY: semantic loop detection is off, only fuzzy matching will run".to_string());
+    }
+
+    problems.extend(static_problems(config));
+    problems
+}
+
+/// Lists the provider's models with its key: `Err` says what went wrong.
+pub async fn probe_provider(client: &Client, provider: &ProviderConfig) -> Result<(), String> {
+    let res = client.get(provider.endpoint("models"))
+        .bearer_auth(&provider.api_key)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await;
+    match res {
+        Ok(r) if r.status().is_success() => Ok(()),
+        Ok(r) => Err(format!("rejected its API key ({})", r.status())),
+        Err(e) => Err(format!("is unreachable: {}", e)),
+    }
+}
+
+/// Checks that need no network: cross-references inside the configuration.
+pub fn static_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    for rule in &config.routing_rules {
+        if let Some(provider) = &rule.route().provider
+            && !config.providers.contains_key(provider) {
+            problems.push(format!("routing rule targets unknown provider '{}'", provider));
+        }
+        if let Some(pool) = &rule.route().budget_pool
+            && !config.budget_pools.contains_key(pool) {
+            problems.push(format!("routing rule uses budget pool '{}' with no configured budget", pool));
+        }
+    }
+    if config.oidc.enabled() && config.oidc.audience.is_none() {
+        problems.push("SENTINEL_OIDC_ISSUER is set without SENTINEL_OIDC_AUDIENCE; OIDC tokens will be refused".to_string());
+    }
+    for profile in &config.model_profiles {
+        for pattern in profile.patterns.iter().filter(|p| !p.contains('*')) {
+            if config.pricing.lookup(pattern).is_none() {
+                problems.push(format!("no pricing for model '{}'; costs will use the fallback rate", pattern));
+            }
+        }
+    }
+    problems
+}
+
+pub fn strict() -> bool {
+    std::env::var("SENTINEL_STARTUP_MODE").is_ok_and(|m| m == "strict")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelProfile;
+    use crate::routing::parse_rules;
+
+    #[test]
+    fn test_static_problems_flag_dangling_references() {
+        let config = Config {
+            routing_rules: parse_rules("if model == x then provider nowhere, budget ghost").0,
+            model_profiles: vec![ModelProfile::parse("my-private-model: budget=1").unwrap()],
+            ..Config::default()
+        };
+        let problems = static_problems(&config);
+        assert_eq!(problems.len(), 3);
+    }
+}
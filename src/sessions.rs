@@ -0,0 +1,443 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use crate::config::SessionPolicy;
+use crate::{AppState, SessionState, similarity, word_overlap_similarity};
+
+// --- SESSION ADMIN API ---
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct Page {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    /// Only sessions in this tenant's namespace.
+    tenant: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    id: String,
+    cumulative_cost: f64,
+    created_at: u64,
+    last_activity: u64,
+    interventions: u32,
+    history_len: usize,
+    blocked: bool,
+}
+
+/// `GET /api/sessions?offset=0&limit=50&tenant=`, most recently active first.
+pub async fn list_sessions(State(state): State<AppState>, Query(page): Query<Page>) -> impl IntoResponse {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let namespace = page.tenant.as_ref().map(|t| format!("{}/", t));
+
+    let mut sessions: Vec<SessionSummary> = state.sessions.iter()
+        .filter(|s| namespace.as_ref().is_none_or(|n| s.key().starts_with(n.as_str())))
+        .map(|s| SessionSummary {
+            id: s.key().clone(),
+            cumulative_cost: s.cumulative_cost,
+            created_at: s.created_at,
+            last_activity: s.last_activity,
+            interventions: s.interventions,
+            history_len: s.history_text.len().max(s.history.len()),
+            blocked: state.blocked.contains_key(s.key()),
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.last_activity.cmp(&a.last_activity).then_with(|| a.id.cmp(&b.id)));
+
+    let total = sessions.len();
+    let sessions: Vec<SessionSummary> = sessions.into_iter().skip(page.offset).take(limit).collect();
+    Json(serde_json::json!({
+        "total": total,
+        "offset": page.offset,
+        "limit": limit,
+        "sessions": sessions,
+    }))
+}
+
+/// `GET /api/sessions/{id}`: everything needed to explain a block.
+pub async fn get_session(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let mut detail = {
+        let Some(sess) = state.sessions.get(&id) else {
+            return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Session not found"}))).into_response();
+        };
+        // Global thresholds; model profiles may override them per request.
+        let loops = &state.config.loops;
+        serde_json::json!({
+            "id": id,
+            "created_at": sess.created_at,
+            "last_activity": sess.last_activity,
+            "cumulative_cost": sess.cumulative_cost,
+            "cost_trajectory": sess.cost_history,
+            "cost_baseline": { "mean": sess.cost_mean, "std_dev": sess.cost_var.sqrt(), "samples": sess.cost_samples },
+            "recent_prompts": sess.history_text,
+            // Similarity of each turn to the one before it (index i = turns i and i+1).
+            "semantic_similarity": sess.history.windows(2).map(|w| similarity(loops.metric, &w[0].to_f32(), &w[1].to_f32()).ok()).collect::<Vec<_>>(),
+            "fuzzy_similarity": sess.history_text.windows(2).map(|w| word_overlap_similarity(&w[0], &w[1])).collect::<Vec<_>>(),
+            "thresholds": {
+                "loop_sensitivity": sess.loop_sensitivity,
+                "semantic_baseline": sess.semantic_baseline,
+                "fuzzy_baseline": sess.fuzzy_baseline,
+                "semantic_default": sess.effective_threshold(loops.semantic_threshold, true, loops),
+                "fuzzy_default": sess.effective_threshold(loops.fuzzy_threshold, false, loops),
+            },
+            "interventions": sess.interventions,
+            "interventions_by_reason": sess.interventions_by_reason,
+            "blocked": state.blocked.get(&id).map(|b| b.clone()),
+        })
+    };
+
+    let logs = state.audit_logs.lock().await;
+    let history: Vec<_> = logs.iter().filter(|l| l.session_id == id).cloned().collect();
+    detail["intervention_log"] = serde_json::json!(history);
+    Json(detail).into_response()
+}
+
+/// `DELETE /api/sessions/{id}`: drops history and cost counters so a blocked
+/// agent starts fresh on its next request.
+pub async fn delete_session(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.sessions.remove(&id) {
+        Some(_) => {
+            tracing::info!("Session '{}' reset by operator", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Session not found"}))).into_response(),
+    }
+}
+
+// --- KILL SWITCH ---
+// Blocks live outside `SessionState` so they survive resets and eviction.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockEntry {
+    pub reason: String,
+    pub blocked_at: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BlockRequest {
+    reason: Option<String>,
+}
+
+/// `POST /api/sessions/{id}/block`: rejects all further traffic for the session.
+pub async fn block_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<BlockRequest>>,
+) -> impl IntoResponse {
+    let reason = body.and_then(|Json(b)| b.reason).unwrap_or_else(|| "Blocked by operator".to_string());
+    tracing::warn!("⛔ Session '{}' blocked: {}", id, reason);
+    let entry = BlockEntry { reason, blocked_at: crate::now_secs() };
+    state.blocked.insert(id.clone(), entry.clone());
+    Json(serde_json::json!({"session_id": id, "blocked": entry}))
+}
+
+/// `POST /api/sessions/{id}/unblock`.
+pub async fn unblock_session(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    match state.blocked.remove(&id) {
+        Some(_) => {
+            tracing::info!("Session '{}' unblocked", id);
+            Json(serde_json::json!({"session_id": id, "blocked": null})).into_response()
+        }
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "Session is not blocked"}))).into_response(),
+    }
+}
+
+// --- SNAPSHOTS ---
+// `GET /api/sessions/export` dumps the live sessions plus the blocks and
+// spend counters that budgets depend on; `POST /api/sessions/import` loads
+// such a dump into another instance, so an upgrade can hand over without
+// losing loop history mid-conversation. Imported entries replace local ones
+// with the same key; everything else is left alone.
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Snapshots carry embeddings, so they outgrow the default admin body limit.
+pub const MAX_IMPORT_BYTES: usize = 256 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    #[serde(default)]
+    exported_at: u64,
+    sessions: HashMap<String, SessionState>,
+    #[serde(default)]
+    blocked: HashMap<String, BlockEntry>,
+    #[serde(default)]
+    pool_spend: HashMap<String, f64>,
+    #[serde(default)]
+    tenant_spend: HashMap<String, f64>,
+    /// Per-day usage behind `/api/reports`.
+    #[serde(default)]
+    usage: Vec<crate::reports::DayUsage>,
+}
+
+pub fn snapshot(state: &AppState) -> Snapshot {
+    let copy = |map: &DashMap<String, f64>| map.iter().map(|e| (e.key().clone(), *e.value())).collect();
+    Snapshot {
+        version: SNAPSHOT_VERSION,
+        exported_at: crate::now_secs(),
+        sessions: state.sessions.iter().map(|s| (s.key().clone(), s.value().clone())).collect(),
+        blocked: state.blocked.iter().map(|b| (b.key().clone(), b.value().clone())).collect(),
+        pool_spend: copy(&state.pool_spend),
+        tenant_spend: copy(&state.tenant_spend),
+        usage: state.usage.days(),
+    }
+}
+
+/// Loads `snapshot` into `state`; returns how many sessions it held.
+pub fn import(state: &AppState, snapshot: Snapshot) -> Result<usize, String> {
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(format!("unsupported snapshot version {} (expected {})", snapshot.version, SNAPSHOT_VERSION));
+    }
+    let imported = snapshot.sessions.len();
+    for (id, session) in snapshot.sessions {
+        state.sessions.insert(id, session);
+    }
+    for (id, block) in snapshot.blocked {
+        state.blocked.insert(id, block);
+    }
+    for (pool, spent) in snapshot.pool_spend {
+        state.pool_spend.insert(pool, spent);
+    }
+    for (tenant, spent) in snapshot.tenant_spend {
+        state.tenant_spend.insert(tenant, spent);
+    }
+    state.usage.restore(snapshot.usage);
+    Ok(imported)
+}
+
+/// `GET /api/sessions/export`.
+pub async fn export_sessions(State(state): State<AppState>) -> impl IntoResponse {
+    Json(snapshot(&state))
+}
+
+/// `POST /api/sessions/import`.
+pub async fn import_sessions(State(state): State<AppState>, Json(body): Json<Snapshot>) -> impl IntoResponse {
+    match import(&state, body) {
+        Ok(imported) => {
+            tracing::info!("Imported {} sessions from a snapshot", imported);
+            persist(&state).await;
+            Json(serde_json::json!({"imported": imported, "sessions": state.sessions.len()})).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e}))).into_response(),
+    }
+}
+
+// --- EVICTION ---
+
+/// Drops sessions idle past the TTL, then trims the least recently active
+/// ones down to the cap. Returns `(expired, lru_evicted)`.
+pub fn evict(sessions: &DashMap<String, SessionState>, policy: &SessionPolicy, now: u64) -> (usize, usize) {
+    let before = sessions.len();
+    sessions.retain(|_, s| now.saturating_sub(s.last_activity) <= policy.ttl_secs);
+    let expired = before - sessions.len();
+
+    let excess = sessions.len().saturating_sub(policy.max_sessions);
+    if excess == 0 {
+        return (expired, 0);
+    }
+    let mut by_age: Vec<(u64, String)> = sessions.iter().map(|s| (s.last_activity, s.key().clone())).collect();
+    by_age.sort();
+    for (_, id) in by_age.into_iter().take(excess) {
+        sessions.remove(&id);
+    }
+    (expired, excess)
+}
+
+/// Forgets the prompt texts and embeddings of sessions quiet for longer than
+/// `history_ttl_secs`. Returns how many sessions were cleared.
+pub fn purge_history(sessions: &DashMap<String, SessionState>, policy: &SessionPolicy, now: u64) -> usize {
+    if policy.history_ttl_secs == 0 {
+        return 0;
+    }
+    let mut purged = 0;
+    for mut sess in sessions.iter_mut() {
+        let quiet = now.saturating_sub(sess.last_activity) > policy.history_ttl_secs;
+        if quiet && !(sess.history.is_empty() && sess.history_text.is_empty()) {
+            sess.history.clear();
+            sess.history_text.clear();
+            purged += 1;
+        }
+    }
+    purged
+}
+
+/// Loads the sessions and usage saved by the embedded store, if there is
+/// one; from Postgres also this instance's spend and provider keys.
+pub fn restore(state: &AppState) {
+    #[cfg(feature = "postgres")]
+    if let Some(pg) = state.audit.postgres() {
+        match pg.load_state(&state.config.storage.instance_id) {
+            Ok(saved) => {
+                tracing::info!("Restored {} sessions from Postgres", saved.sessions.len());
+                for (id, session) in saved.sessions {
+                    state.sessions.insert(id, session);
+                }
+                for (tenant, spent) in saved.tenant_spend {
+                    state.tenant_spend.insert(tenant, spent);
+                }
+                for (pool, spent) in saved.pool_spend {
+                    state.pool_spend.insert(pool, spent);
+                }
+                for (provider, ring) in saved.keys {
+                    state.keys.restore(provider, ring);
+                }
+                state.usage.restore_saved(saved.usage);
+            }
+            Err(e) => tracing::error!("Restoring state from Postgres failed: {}", e),
+        }
+        return;
+    }
+    let Some(store) = &state.embedded else { return };
+    match store.load_sessions() {
+        Ok(saved) => {
+            tracing::info!("Restored {} sessions", saved.len());
+            for (id, session) in saved {
+                state.sessions.insert(id, session);
+            }
+        }
+        Err(e) => tracing::error!("Restoring sessions failed: {}", e),
+    }
+    match store.load_usage() {
+        Ok(saved) => state.usage.restore_saved(saved),
+        Err(e) => tracing::error!("Restoring usage failed: {}", e),
+    }
+}
+
+/// Writes the current sessions and usage to the embedded store, if there
+/// is one; to Postgres also the spend and provider keys.
+pub async fn persist(state: &AppState) {
+    #[cfg(feature = "postgres")]
+    if state.audit.postgres().is_some() {
+        let copy = |map: &DashMap<String, f64>| map.iter().map(|e| (e.key().clone(), *e.value())).collect();
+        let saved = crate::postgres::SavedState {
+            sessions: state.sessions.iter().map(|s| (s.key().clone(), s.value().clone())).collect(),
+            tenant_spend: copy(&state.tenant_spend),
+            pool_spend: copy(&state.pool_spend),
+            keys: state.keys.snapshot().into_iter().collect(),
+            usage: state.usage.saved(),
+        };
+        let (store, instance) = (state.audit.clone(), state.config.storage.instance_id.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            store.postgres().expect("checked before spawning").save_state(&instance, &saved)
+        }).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Saving state to Postgres failed: {}", e),
+            Err(e) => tracing::error!("Saving state to Postgres failed: {}", e),
+        }
+        return;
+    }
+    let Some(store) = state.embedded.clone() else { return };
+    let (sessions, usage) = (state.sessions.clone(), state.usage.saved());
+    match tokio::task::spawn_blocking(move || store.save_sessions(&sessions, &usage)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => tracing::error!("Saving sessions failed: {}", e),
+        Err(e) => tracing::error!("Saving sessions failed: {}", e),
+    }
+}
+
+/// Background sweeper; runs for the lifetime of the process.
+pub fn spawn_evictor(state: AppState) {
+    tokio::spawn(async move {
+        let every = std::time::Duration::from_secs(state.config.sessions.sweep_interval_secs);
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            let config = state.current_config();
+            let (expired, lru) = evict(&state.sessions, &config.sessions, crate::now_secs());
+            crate::structured::evict(&state, config.sessions.ttl_secs, crate::now_secs());
+            state.user_fingerprints.sweep(crate::now_secs(), &config.user_loops);
+            state.ip_guard.sweep(&config.ip_limits, crate::now_secs());
+            let purged = purge_history(&state.sessions, &config.sessions, crate::now_secs());
+            if purged > 0 {
+                tracing::info!("Forgot the prompt history of {} quiet sessions", purged);
+            }
+            for log in state.audit_logs.lock().await.iter_mut() {
+                crate::audit::scrub(&config.audit, log, crate::now_secs());
+            }
+            if expired + lru > 0 {
+                state.sessions_expired.fetch_add(expired as u64, Ordering::Relaxed);
+                state.sessions_lru_evicted.fetch_add(lru as u64, Ordering::Relaxed);
+                tracing::info!("Evicted {} idle and {} over-cap sessions", expired, lru);
+            }
+            persist(&state).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_at(last_activity: u64) -> SessionState {
+        SessionState { last_activity, ..SessionState::new() }
+    }
+
+    #[test]
+    fn test_evict_ttl_then_lru() {
+        let sessions = DashMap::new();
+        sessions.insert("stale".to_string(), session_at(0));
+        sessions.insert("old".to_string(), session_at(9_000));
+        sessions.insert("mid".to_string(), session_at(9_500));
+        sessions.insert("new".to_string(), session_at(9_900));
+
+        let policy = SessionPolicy { ttl_secs: 3600, max_sessions: 2, sweep_interval_secs: 60, history_ttl_secs: 0, ..SessionPolicy::default() };
+        assert_eq!(evict(&sessions, &policy, 10_000), (1, 1));
+        assert!(sessions.contains_key("mid"));
+        assert!(sessions.contains_key("new"));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let old = AppState::for_tests(crate::config::Config::default());
+        let mut sess = session_at(9_000);
+        sess.history_text.push("list the files".to_string());
+        sess.cumulative_cost = 0.75;
+        old.sessions.insert("acme/bot".to_string(), sess);
+        old.blocked.insert("stuck".to_string(), BlockEntry { reason: "loop".to_string(), blocked_at: 1 });
+        old.tenant_spend.insert("acme".to_string(), 0.75);
+        let json = serde_json::to_string(&snapshot(&old)).unwrap();
+
+        let new = AppState::for_tests(crate::config::Config::default());
+        new.sessions.insert("local".to_string(), SessionState::new());
+        assert_eq!(import(&new, serde_json::from_str(&json).unwrap()), Ok(1));
+        assert_eq!(new.sessions.len(), 2);
+        assert_eq!(new.sessions.get("acme/bot").unwrap().history_text, vec!["list the files".to_string()]);
+        assert!(new.blocked.contains_key("stuck"));
+        assert_eq!(*new.tenant_spend.get("acme").unwrap(), 0.75);
+
+        let future = json.replacen("\"version\":1", "\"version\":2", 1);
+        assert!(import(&new, serde_json::from_str(&future).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_purge_history_keeps_spend() {
+        let sessions = DashMap::new();
+        for (id, at) in [("quiet", 9_000), ("busy", 9_950)] {
+            let mut sess = session_at(at);
+            sess.history_text.push("prompt".to_string());
+            sess.cumulative_cost = 1.5;
+            sessions.insert(id.to_string(), sess);
+        }
+        let policy = SessionPolicy { history_ttl_secs: 300, ..SessionPolicy::default() };
+        assert_eq!(purge_history(&sessions, &policy, 10_000), 1);
+        let quiet = sessions.get("quiet").unwrap();
+        assert!(quiet.history_text.is_empty());
+        assert_eq!(quiet.cumulative_cost, 1.5);
+        assert_eq!(sessions.get("busy").unwrap().history_text.len(), 1);
+        assert_eq!(purge_history(&sessions, &SessionPolicy::default(), 99_999), 0);
+    }
+}
@@ -0,0 +1,334 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::AppState;
+use crate::config::{AuditPolicy, SnippetMode};
+
+// --- SHADOW COMPARISON ---
+// Before routing traffic to a cheaper model, run it on the side. With
+// `SENTINEL_SHADOW_MODEL` and `SENTINEL_SHADOW_PERCENT`, that share of
+// non-streaming requests whose model matches `SENTINEL_SHADOW_MATCH` is sent
+// again, once the client has its answer, to the shadow model on
+// `SENTINEL_SHADOW_PROVIDER`. The shadow result is never returned; both
+// sides' status, latency, cost and text are kept (the newest
+// `SENTINEL_SHADOW_MAX_RECORDS`) and `GET /api/shadow` reports them per
+// model pair, with the word overlap of the two answers as a rough measure
+// of agreement.
+//
+// Sampling is by count, not chance: at 10 percent every tenth candidate is
+// mirrored. Shadow calls are not booked against sessions, pools or tenants;
+// instead mirroring pauses for the rest of the UTC day once their cost
+// reaches `SENTINEL_SHADOW_DAILY_BUDGET_USD` (10; 0 sets no cap). Stored
+// answers follow the audit snippet policy (`SENTINEL_SNIPPET_MODE` and
+// `SENTINEL_SNIPPET_RETENTION_DAYS`), and erasing a data subject drops the
+// subject's comparisons.
+
+/// Characters of each answer kept in a record.
+const MAX_TEXT_CHARS: usize = 2_000;
+
+/// One side of a comparison.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Side {
+    pub model: String,
+    /// Upstream HTTP status; `None` when the call itself failed.
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub cost_usd: f64,
+    pub completion_tokens: u64,
+    pub text: String,
+    /// SHA-256 of `text` once the snippet policy no longer keeps it raw.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_digest: Option<String>,
+}
+
+impl Side {
+    pub fn new(model: &str, status: Option<u16>, latency: std::time::Duration, cost_usd: f64, body: &Value) -> Self {
+        Self {
+            model: model.to_string(),
+            status,
+            latency_ms: latency.as_millis() as u64,
+            cost_usd,
+            completion_tokens: body["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+            text: crate::response_scan_text(body).chars().take(MAX_TEXT_CHARS).collect(),
+            text_digest: None,
+        }
+    }
+
+    fn ok(&self) -> bool {
+        self.status.is_some_and(|s| (200..300).contains(&s))
+    }
+
+    /// Reduces the text to what `mode` keeps of a snippet.
+    fn conceal(&mut self, mode: SnippetMode) {
+        match mode {
+            SnippetMode::Raw => {}
+            SnippetMode::Hash if !self.text.is_empty() => {
+                self.text_digest = Some(crate::audit::sha256(&self.text));
+                self.text.clear();
+            }
+            SnippetMode::Hash | SnippetMode::None => self.text.clear(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub timestamp: u64,
+    pub session_id: String,
+    pub tenant: Option<String>,
+    pub primary: Side,
+    pub shadow: Side,
+    /// Word overlap of the two answers, 0-1, when both succeeded.
+    pub similarity: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct ShadowRecords {
+    candidates: AtomicU64,
+    records: Mutex<VecDeque<Comparison>>,
+    /// The UTC day and what shadow calls have cost on it.
+    spent: Mutex<(u64, f64)>,
+}
+
+impl ShadowRecords {
+    /// Whether the next candidate is mirrored: exactly `percent` of them
+    /// over time, spread evenly.
+    pub fn sample(&self, percent: f64) -> bool {
+        let n = self.candidates.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * percent / 100.0).floor() > (n * percent / 100.0).floor()
+    }
+
+    /// Whether shadow calls have cost less than `budget` today; 0 sets no cap.
+    fn within_budget(&self, budget: f64, now: u64) -> bool {
+        let spent = self.spent.lock().unwrap();
+        budget <= 0.0 || spent.0 != now / 86_400 || spent.1 < budget
+    }
+
+    fn charge(&self, cost_usd: f64, now: u64) {
+        let mut spent = self.spent.lock().unwrap();
+        if spent.0 != now / 86_400 {
+            *spent = (now / 86_400, 0.0);
+        }
+        spent.1 += cost_usd;
+    }
+
+    /// Keeps `comparison`, with both answers reduced to what the snippet
+    /// policy allows; older answers past the snippet retention are hashed.
+    fn push(&self, mut comparison: Comparison, max: usize, policy: &AuditPolicy, now: u64) {
+        comparison.primary.conceal(policy.snippets);
+        comparison.shadow.conceal(policy.snippets);
+        let mut records = self.records.lock().unwrap();
+        records.push_back(comparison);
+        while records.len() > max {
+            records.pop_front();
+        }
+        if policy.snippet_retention_secs > 0 {
+            for record in records.iter_mut().filter(|r| now.saturating_sub(r.timestamp) > policy.snippet_retention_secs) {
+                record.primary.conceal(SnippetMode::Hash);
+                record.shadow.conceal(SnippetMode::Hash);
+            }
+        }
+    }
+
+    /// Drops the comparisons of sessions `matches` selects; how many.
+    pub fn forget_sessions(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut records = self.records.lock().unwrap();
+        let before = records.len();
+        records.retain(|r| !matches(&r.session_id));
+        before - records.len()
+    }
+}
+
+/// Mirrors `payload` to the shadow model in the background, if this
+/// request is sampled. `primary` is what the client got.
+pub fn mirror(state: &AppState, tenant: Option<&str>, session_id: &str, endpoint: &'static str, payload: &Value, primary: Side) {
+    let policy = &state.config.shadow;
+    if !policy.covers(&primary.model) || !state.shadow.within_budget(policy.daily_budget_usd, crate::now_secs())
+        || !state.shadow.sample(policy.percent)
+    {
+        return;
+    }
+    let Some(model) = policy.model.clone() else { return };
+    let Ok(upstream) = state.provider(tenant, &policy.provider) else {
+        tracing::warn!("Shadow provider `{}` is not configured", policy.provider);
+        return;
+    };
+    let mut request = payload.clone();
+    request["model"] = Value::String(model.clone());
+    if let Some(request) = request.as_object_mut() {
+        request.remove("stream");
+        request.remove("stream_options");
+    }
+    let (state, tenant, session_id) = (state.clone(), tenant.map(str::to_string), session_id.to_string());
+    tokio::spawn(async move {
+        let started = std::time::Instant::now();
+        let result = state.client
+            .post(upstream.endpoint(endpoint))
+            .header("Authorization", format!("Bearer {}", upstream.api_key))
+            .timeout(std::time::Duration::from_secs(state.config.shadow.timeout_secs))
+            .json(&request)
+            .send()
+            .await;
+        let shadow = match result {
+            Ok(res) => {
+                let status = res.status().as_u16();
+                let body: Value = res.json().await.unwrap_or_default();
+                let cost = crate::usage_cost(&state.config.pricing, &model, &body);
+                state.shadow.charge(cost, crate::now_secs());
+                Side::new(&model, Some(status), started.elapsed(), cost, &body)
+            }
+            Err(e) => {
+                tracing::debug!("Shadow call to {} failed: {}", model, e.without_url());
+                Side { model, latency_ms: started.elapsed().as_millis() as u64, ..Side::default() }
+            }
+        };
+        let similarity = (primary.ok() && shadow.ok()).then(|| crate::word_overlap_similarity(&primary.text, &shadow.text));
+        let now = crate::now_secs();
+        let comparison = Comparison { timestamp: now, session_id, tenant, primary, shadow, similarity };
+        state.shadow.push(comparison, state.config.shadow.max_records, &state.config.audit, now);
+    });
+}
+
+/// Averages over one side of a model pair.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SideSummary {
+    pub errors: usize,
+    pub avg_latency_ms: f64,
+    pub cost_usd: f64,
+    pub avg_completion_tokens: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PairSummary {
+    pub primary_model: String,
+    pub shadow_model: String,
+    pub compared: usize,
+    pub primary: SideSummary,
+    pub shadow: SideSummary,
+    /// Shadow cost as a share of primary cost (0.2 = 80% cheaper).
+    pub cost_ratio: Option<f64>,
+    pub avg_similarity: Option<f64>,
+}
+
+fn side_summary(sides: &[&Side]) -> SideSummary {
+    let n = sides.len().max(1) as f64;
+    SideSummary {
+        errors: sides.iter().filter(|s| !s.ok()).count(),
+        avg_latency_ms: sides.iter().map(|s| s.latency_ms as f64).sum::<f64>() / n,
+        cost_usd: sides.iter().map(|s| s.cost_usd).sum(),
+        avg_completion_tokens: sides.iter().map(|s| s.completion_tokens as f64).sum::<f64>() / n,
+    }
+}
+
+fn summarize(records: &[Comparison]) -> Vec<PairSummary> {
+    let mut pairs: BTreeMap<(&str, &str), Vec<&Comparison>> = BTreeMap::new();
+    for record in records {
+        pairs.entry((&record.primary.model, &record.shadow.model)).or_default().push(record);
+    }
+    pairs.into_iter().map(|((primary_model, shadow_model), records)| {
+        let primary = side_summary(&records.iter().map(|r| &r.primary).collect::<Vec<_>>());
+        let shadow = side_summary(&records.iter().map(|r| &r.shadow).collect::<Vec<_>>());
+        let similarities: Vec<f64> = records.iter().filter_map(|r| r.similarity).map(f64::from).collect();
+        PairSummary {
+            primary_model: primary_model.to_string(),
+            shadow_model: shadow_model.to_string(),
+            compared: records.len(),
+            cost_ratio: (primary.cost_usd > 0.0).then(|| shadow.cost_usd / primary.cost_usd),
+            avg_similarity: (!similarities.is_empty()).then(|| similarities.iter().sum::<f64>() / similarities.len() as f64),
+            primary,
+            shadow,
+        }
+    }).collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    /// Newest records to include next to the summary.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+/// `GET /api/shadow`: per model pair summary and the newest comparisons.
+pub async fn report(State(state): State<AppState>, Query(query): Query<ReportQuery>) -> impl IntoResponse {
+    let records: Vec<Comparison> = state.shadow.records.lock().unwrap().iter().cloned().collect();
+    let policy = &state.config.shadow;
+    Json(serde_json::json!({
+        "enabled": policy.percent > 0.0 && policy.model.is_some(),
+        "percent": policy.percent,
+        "shadow_model": policy.model,
+        "pairs": summarize(&records),
+        "recent": records.iter().rev().take(query.limit).collect::<Vec<_>>(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_and_pair_summary() {
+        let records = ShadowRecords::default();
+        assert_eq!((0..100).filter(|_| records.sample(10.0)).count(), 10);
+        assert_eq!((0..7).filter(|_| records.sample(100.0)).count(), 7);
+        assert!(!(0..50).any(|_| records.sample(0.0)));
+
+        let side = |model: &str, status: Option<u16>, latency_ms: u64, cost_usd: f64| Side {
+            model: model.to_string(), status, latency_ms, cost_usd, ..Side::default()
+        };
+        let comparison = |shadow: Side, similarity: Option<f32>| Comparison {
+            timestamp: 0, session_id: "s".to_string(), tenant: None,
+            primary: side("gpt-4o", Some(200), 1_000, 0.01), shadow, similarity,
+        };
+        let pairs = summarize(&[
+            comparison(side("gpt-4o-mini", Some(200), 400, 0.001), Some(0.5)),
+            comparison(side("gpt-4o-mini", None, 600, 0.0), None),
+        ]);
+        assert_eq!(pairs.len(), 1);
+        let pair = &pairs[0];
+        assert_eq!((pair.compared, pair.shadow.errors, pair.primary.errors), (2, 1, 0));
+        assert_eq!(pair.shadow.avg_latency_ms, 500.0);
+        assert!((pair.cost_ratio.unwrap() - 0.05).abs() < 1e-9);
+        assert_eq!(pair.avg_similarity, Some(0.5));
+
+        // A day's spend pauses mirroring until the next day.
+        let day = 20_000 * 86_400;
+        assert!(records.within_budget(0.05, day));
+        records.charge(0.03, day);
+        records.charge(0.03, day + 60);
+        assert!(!records.within_budget(0.05, day + 120));
+        assert!(records.within_budget(0.0, day + 120));
+        assert!(records.within_budget(0.05, day + 86_400));
+
+        // Answers follow the snippet policy; erasure drops a session's records.
+        let texts = |shadow: &str| Comparison {
+            timestamp: day, session_id: "acme/u1".to_string(), tenant: None,
+            primary: Side { text: "card 4111".to_string(), ..side("gpt-4o", Some(200), 1, 0.0) },
+            shadow: Side { text: shadow.to_string(), ..side("gpt-4o-mini", Some(200), 1, 0.0) },
+            similarity: None,
+        };
+        let hashed = AuditPolicy { snippets: SnippetMode::Hash, ..Default::default() };
+        records.push(texts("card 4111"), 10, &hashed, day);
+        let raw = AuditPolicy { snippet_retention_secs: 86_400, ..Default::default() };
+        records.push(texts(""), 10, &raw, day + 10);
+        {
+            let kept = records.records.lock().unwrap();
+            assert_eq!((kept[0].primary.text.as_str(), kept[0].primary.text_digest.as_deref()), ("", Some(crate::audit::sha256("card 4111").as_str())));
+            assert_eq!(kept[1].primary.text, "card 4111");
+        }
+        records.push(texts(""), 10, &raw, day + 2 * 86_400);
+        assert!(records.records.lock().unwrap().iter().take(2).all(|r| r.primary.text.is_empty() && r.primary.text_digest.is_some()));
+        assert_eq!(records.forget_sessions(|id| id == "acme/u1"), 3);
+    }
+}
@@ -0,0 +1,377 @@
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use std::time::{Duration, Instant};
+
+use crate::{Api, AppState};
+use crate::audit::LogContext;
+use crate::config::{CostPolicy, DetectorMode};
+use crate::events::{Event, RequestEvent};
+
+// --- STREAMING PASSTHROUGH ---
+// Upstream SSE bytes are forwarded untouched; a tap parses the events on the
+// side to time the first token and count generated tokens. Bytes are only
+// released once their line is complete and the text generated so far has been
+// scanned for leaks, so a leaking stream can be cut before the secret goes out.
+// A degenerating stream (repeated text, or a logprob stall) is cut once it
+// shows, saving the tokens the model would have spent on more of the same.
+
+/// Bytes of new text between degeneration scans of a stream.
+const REPETITION_SCAN_STEP: usize = 256;
+
+/// Incremental parser for `text/event-stream` bodies.
+#[derive(Debug, Default)]
+pub struct SseTap {
+    buf: Vec<u8>,
+    /// Complete lines parsed but not yet forwarded.
+    ready: Vec<u8>,
+}
+
+impl SseTap {
+    /// Feeds raw bytes and returns the JSON payload of every complete `data:` line.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<serde_json::Value> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            self.ready.extend_from_slice(&line);
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else { continue };
+            let data = data.trim();
+            if data == "[DONE]" { continue; }
+            if let Ok(event) = serde_json::from_str(data) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// The complete lines fed so far, to forward downstream.
+    pub fn take_ready(&mut self) -> Bytes {
+        Bytes::from(std::mem::take(&mut self.ready))
+    }
+
+    /// Whatever is left at the end of the stream, complete line or not.
+    pub fn take_rest(&mut self) -> Bytes {
+        let mut rest = std::mem::take(&mut self.ready);
+        rest.append(&mut self.buf);
+        Bytes::from(rest)
+    }
+}
+
+/// Accumulates generated text and reports the first leak marker, scanning
+/// only the new tail (plus enough overlap to catch a marker split across chunks),
+/// or the point where a protected system prompt has been reproduced.
+#[derive(Debug, Default)]
+pub struct LeakScanner {
+    text: String,
+    prompts: Option<crate::prompt_leak::Tracker>,
+    prompt_leaked: bool,
+}
+
+impl LeakScanner {
+    pub fn new(prompts: Option<crate::prompt_leak::Tracker>) -> Self {
+        Self { prompts, ..Self::default() }
+    }
+
+    pub fn push(&mut self, delta: &str) -> bool {
+        let overlap = crate::LEAK_MARKERS.iter().map(|m| m.len()).max().unwrap_or(0);
+        let mut start = self.text.len().saturating_sub(overlap);
+        while !self.text.is_char_boundary(start) { start -= 1; }
+        self.text.push_str(delta);
+        self.prompt_leaked |= self.prompts.as_mut().is_some_and(|p| p.push(delta));
+        crate::leaks_secret(&self.text[start..]) || self.prompt_leaked
+    }
+
+    /// What the hit was.
+    pub fn reason(&self) -> &'static str {
+        if self.prompt_leaked { crate::prompt_leak::REASON } else { crate::LEAK_REASON }
+    }
+}
+
+/// Final chunk sent in place of a stream cut by a detector. Response headers
+/// went out with the first byte, so the chunk names the detector itself.
+fn content_filter_chunk(api: Api, id: &serde_json::Value, model: &str, action: &str, detector: &str) -> Bytes {
+    let choice = match api {
+        Api::Chat => serde_json::json!({ "index": 0, "delta": {}, "finish_reason": "content_filter" }),
+        Api::Completions => serde_json::json!({ "index": 0, "text": "", "finish_reason": "content_filter" }),
+    };
+    let chunk = serde_json::json!({
+        "id": id,
+        "object": match api { Api::Chat => "chat.completion.chunk", Api::Completions => "text_completion" },
+        "created": crate::now_secs(),
+        "model": model,
+        "choices": [choice],
+        "sentinel": { "intervention": action, "detector": detector },
+    });
+    Bytes::from(format!("data: {}\n\ndata: [DONE]\n\n", chunk))
+}
+
+/// Text generated by a single chunk: content delta plus tool-call argument
+/// deltas (or the `text` of a legacy completions chunk).
+pub fn delta_text(event: &serde_json::Value) -> String {
+    let delta = &event["choices"][0]["delta"];
+    let mut text = event["choices"][0]["text"].as_str().unwrap_or_default().to_string();
+    text.push_str(delta["content"].as_str().unwrap_or_default());
+    if let Some(calls) = delta["tool_calls"].as_array() {
+        for call in calls {
+            text.push_str(call["function"]["arguments"].as_str().unwrap_or_default());
+        }
+    }
+    text
+}
+
+pub struct StreamContext {
+    pub session_id: String,
+    pub budget_pool: Option<String>,
+    pub tenant: Option<String>,
+    pub cost_policy: CostPolicy,
+    pub cost_exempt: bool,
+    pub cost_mode: DetectorMode,
+    pub leak_exempt: bool,
+    pub leak_mode: DetectorMode,
+    /// System prompts the completion must not reproduce.
+    pub leak_prompts: Option<crate::prompt_leak::Tracker>,
+    pub repetition_exempt: bool,
+    pub repetition_mode: DetectorMode,
+    pub stall_exempt: bool,
+    pub stall_mode: DetectorMode,
+    pub api: Api,
+    pub provider: String,
+    pub model: String,
+    /// When the upstream request was sent.
+    pub sent_at: Instant,
+    /// Time Sentinel spent on the request before forwarding it.
+    pub overhead: Duration,
+}
+
+pub fn proxy_stream(state: AppState, mut res: reqwest::Response, mut ctx: StreamContext) -> Response {
+    let status = res.status();
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(32);
+
+    tokio::spawn(async move {
+        let mut tap = SseTap::default();
+        let mut first_token: Option<Instant> = None;
+        let mut text_chunks = 0u64;
+        let mut usage: Option<serde_json::Value> = None;
+        let mut scanner = LeakScanner::new(ctx.leak_prompts.take());
+        let mut leaked = false;
+        // Set once a hit has been reported without cutting the stream.
+        let mut leak_settled = ctx.leak_mode == DetectorMode::Off;
+        let mut leak_scan_time = Duration::ZERO;
+        // Generated text so far, rescanned for degeneration every
+        // `REPETITION_SCAN_STEP` bytes until a hit or `max_scan_chars`.
+        let repetition = &state.config.repetition;
+        let mut generated = String::new();
+        let mut repetition_scanned = 0;
+        let mut repetition_settled = !repetition.enabled || ctx.repetition_mode == DetectorMode::Off;
+        let mut repetition_scan_time = Duration::ZERO;
+        let mut stall = crate::logprobs::StallTracker::default();
+        let mut stalled = false;
+        let mut stall_settled = !state.config.stall.enabled || ctx.stall_mode == DetectorMode::Off;
+        let mut stream_id = serde_json::Value::Null;
+        let log_ctx = LogContext::new(&ctx.session_id, &ctx.model).provider(&ctx.provider).tenant(ctx.tenant.as_deref());
+
+        loop {
+            match res.chunk().await {
+                Ok(Some(bytes)) => {
+                    for event in tap.feed(&bytes) {
+                        let delta = delta_text(&event);
+                        if !delta.is_empty() {
+                            first_token.get_or_insert_with(Instant::now);
+                            text_chunks += 1;
+                            if !leak_settled {
+                                let started = Instant::now();
+                                leaked |= scanner.push(&delta);
+                                leak_scan_time += started.elapsed();
+                            }
+                            if !repetition_settled {
+                                generated.push_str(&delta);
+                            }
+                        }
+                        let logprobs = &event["choices"][0]["logprobs"];
+                        if !stall_settled && !logprobs.is_null() {
+                            stalled |= stall.push(&crate::logprobs::token_entropies(logprobs), &state.config.stall);
+                        }
+                        if stream_id.is_null() {
+                            stream_id = event["id"].clone();
+                        }
+                        if event.get("usage").is_some_and(|u| !u.is_null()) {
+                            usage = Some(event);
+                        }
+                    }
+                    let leak_reason = scanner.reason();
+                    if leaked && ctx.leak_exempt {
+                        crate::audit::record_bypass(&state, &log_ctx, "leak", leak_reason).await;
+                    } else if leaked && ctx.leak_mode != DetectorMode::Block {
+                        // Headers are already out, so `warn` can only log here.
+                        crate::audit::record_dry_run(
+                            &state, &log_ctx, "leak", leak_reason,
+                            "[REDACTED SENSITIVE DATA]".to_string(),
+                        ).await;
+                    }
+                    if leaked && (ctx.leak_exempt || ctx.leak_mode != DetectorMode::Block) {
+                        // Report once, then stop scanning.
+                        leak_settled = true;
+                        leaked = false;
+                    }
+                    if leaked {
+                        // Drop the lines carrying the marker and end the stream
+                        // the way a provider-side filter would.
+                        let _ = tx.send(Ok(content_filter_chunk(ctx.api, &stream_id, &ctx.model, "redacted", "leak"))).await;
+                        crate::audit::record_intervention(
+                            &state, &log_ctx, "leak", leak_reason,
+                            "[REDACTED SENSITIVE DATA]".to_string(),
+                            crate::savings::avoided(&state.config, "leak", &ctx.model, None),
+                        ).await;
+                        break;
+                    }
+                    if stalled && !stall_settled {
+                        let reason = "Logprob Stall (Low-Entropy Output)";
+                        let snippet = format!("{} tokens", stall.tokens);
+                        stall_settled = true;
+                        if ctx.stall_exempt {
+                            crate::audit::record_bypass(&state, &log_ctx, "logprob_stall", reason).await;
+                        } else if ctx.stall_mode != DetectorMode::Block {
+                            crate::audit::record_dry_run(&state, &log_ctx, "logprob_stall", reason, snippet).await;
+                        } else {
+                            let _ = tx.send(Ok(content_filter_chunk(ctx.api, &stream_id, &ctx.model, "truncated", "logprob_stall"))).await;
+                            crate::audit::record_intervention(
+                                &state, &log_ctx, "logprob_stall", reason, snippet,
+                                crate::savings::avoided(&state.config, "logprob_stall", &ctx.model, None),
+                            ).await;
+                            break;
+                        }
+                    }
+                    if !repetition_settled && generated.len() >= repetition_scanned + REPETITION_SCAN_STEP {
+                        repetition_scanned = generated.len();
+                        let started = Instant::now();
+                        let hit = crate::repetition::scan(&generated, repetition);
+                        repetition_scan_time += started.elapsed();
+                        repetition_settled = hit.is_some() || generated.len() >= repetition.max_scan_chars;
+                        if let Some(hit) = hit {
+                            let snippet = generated[hit.cut_at..].chars().take(50).collect::<String>() + "...";
+                            if ctx.repetition_exempt {
+                                crate::audit::record_bypass(&state, &log_ctx, "repetition", &hit.reason).await;
+                            } else if ctx.repetition_mode != DetectorMode::Block {
+                                crate::audit::record_dry_run(&state, &log_ctx, "repetition", &hit.reason, snippet).await;
+                            } else {
+                                // What was already sent stays; the rest is never generated.
+                                let _ = tx.send(Ok(content_filter_chunk(ctx.api, &stream_id, &ctx.model, "truncated", "repetition"))).await;
+                                crate::audit::record_intervention(
+                                    &state, &log_ctx, "repetition", &hit.reason, snippet,
+                                    crate::savings::avoided(&state.config, "repetition", &ctx.model, None),
+                                ).await;
+                                break;
+                            }
+                        }
+                    }
+                    // Client hung up: stop pulling from upstream.
+                    let ready = tap.take_ready();
+                    if !ready.is_empty() && tx.send(Ok(ready)).await.is_err() { break; }
+                }
+                Ok(None) => {
+                    let rest = tap.take_rest();
+                    if !rest.is_empty() { let _ = tx.send(Ok(rest)).await; }
+                    break;
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(std::io::Error::other(e))).await;
+                    break;
+                }
+            }
+        }
+
+        // One evaluation per stream, however many chunks it took.
+        if ctx.leak_mode != DetectorMode::Off {
+            state.detectors.observe_eval("leak", leak_scan_time);
+        }
+        if repetition_scanned > 0 {
+            state.detectors.observe_eval("repetition", repetition_scan_time);
+        }
+        let finished = Instant::now();
+        // Providers emit roughly one token per chunk; prefer exact usage when
+        // the client asked for it via `stream_options.include_usage`.
+        let tokens = usage.as_ref()
+            .and_then(|u| u["usage"]["completion_tokens"].as_u64())
+            .unwrap_or(text_chunks);
+        state.latency.observe(
+            &ctx.provider,
+            &ctx.model,
+            first_token.map(|t| t - ctx.sent_at),
+            tokens,
+            first_token.map(|t| finished - t).unwrap_or_default(),
+            ctx.overhead,
+        );
+
+        let cost = usage.as_ref().map_or(0.0, |u| crate::usage_cost(&state.config.pricing, &ctx.model, u));
+        let request_event = RequestEvent::new(&ctx.session_id, ctx.tenant.as_deref(), &ctx.provider, ctx.api.path(), &ctx.model)
+            .with_usage(usage.as_ref().unwrap_or(&serde_json::Value::Null));
+        crate::events::emit(&state, Event::Request(RequestEvent {
+            streamed: true,
+            completion_tokens: Some(tokens),
+            cost_usd: cost,
+            latency_ms: Some((finished - ctx.sent_at).as_millis() as u64),
+            ..request_event
+        }));
+
+        if let Some(usage) = usage {
+            let started = Instant::now();
+            let throttled = crate::book_cost(&state, &ctx.session_id, ctx.budget_pool.as_deref(), ctx.tenant.as_deref(), cost, &ctx.cost_policy)
+                && ctx.cost_mode != DetectorMode::Off;
+            state.detectors.observe_eval("cost_spike", started.elapsed());
+            let log_ctx = log_ctx.with_usage(&usage);
+            if throttled && ctx.cost_exempt {
+                crate::audit::record_bypass(&state, &log_ctx, "cost_spike", "Economic Throttling (Cost Spike)").await;
+            } else if throttled && ctx.cost_mode != DetectorMode::Block {
+                crate::audit::record_dry_run(
+                    &state, &log_ctx, "cost_spike", "Economic Throttling (Cost Spike)", format!("Cost: ${:.4}", cost),
+                ).await;
+            } else if throttled {
+                crate::audit::record_intervention(
+                    &state, &log_ctx, "cost_spike", "Economic Throttling (Cost Spike)",
+                    format!("Cost: ${:.4}", cost),
+                    crate::savings::avoided(&state.config, "cost_spike", &ctx.model, None),
+                ).await;
+            }
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    });
+    (
+        status,
+        [(header::CONTENT_TYPE, "text/event-stream"), (header::CACHE_CONTROL, "no-cache")],
+        Body::from_stream(stream),
+    ).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_handles_split_lines() {
+        let mut tap = SseTap::default();
+        assert!(tap.feed(b"data: {\"choices\":[{\"delta\":{\"con").is_empty());
+        let events = tap.feed(b"tent\":\"hi\"}}]}\n\ndata: [DONE]\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(delta_text(&events[0]), "hi");
+    }
+
+    #[test]
+    fn test_holds_partial_lines_and_catches_split_markers() {
+        let mut tap = SseTap::default();
+        tap.feed(b"data: {}\n\ndata: {\"par");
+        assert_eq!(&tap.take_ready()[..], b"data: {}\n\n");
+        assert_eq!(&tap.take_rest()[..], b"data: {\"par");
+
+        let mut scanner = LeakScanner::default();
+        assert!(!scanner.push("Sure, the config is API_"));
+        assert!(scanner.push("KEY=sk-123"));
+        assert!(!LeakScanner::default().push("nothing to see"));
+    }
+}
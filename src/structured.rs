@@ -0,0 +1,352 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::system_prompt::AGENT_HEADER;
+
+// --- STRUCTURED OUTPUT ---
+// An agent that expects JSON and gets prose, a truncated object or a field of
+// the wrong type usually just asks again, and again. Sentinel can hold the
+// schema instead and check non-streamed completions against it. A schema is
+// registered for a session with `PUT /api/sessions/{id}/schema`, for an agent
+// (`x-sentinel-agent`) as `<agent>.json` in `SENTINEL_SCHEMAS_DIR`, or taken
+// from the request's own `response_format` (`json_schema`) unless
+// `SENTINEL_SCHEMA_FROM_REQUEST=false`; the first one found applies.
+//
+// Validation covers the common keywords (`type`, `properties`, `required`,
+// `additionalProperties`, `items`, `enum`, `const`, `anyOf` / `oneOf` /
+// `allOf`, lengths, bounds and local `$ref`s); others are ignored. In block
+// mode, a chat completion that fails is re-prompted with the errors up to
+// `SENTINEL_SCHEMA_RETRIES` (1) times, each retry audit-logged as a rewrite
+// and its tokens booked with the reply; dry runs never re-prompt. What still
+// fails is a `schema` hit: blocked, warned about or logged as the detector's
+// mode says. A registered schema goes with its session when the session is
+// swept or erased.
+
+pub const DETECTOR: &str = "schema";
+pub const REASON: &str = "Structured Output Invalid";
+/// Errors reported per completion; the rest are usually consequences.
+const MAX_ERRORS: usize = 5;
+/// Schema applications per completion. Request schemas come from clients,
+/// and `$ref`s can make a small one fan out without end.
+const MAX_STEPS: usize = 10_000;
+
+/// `schema`, or the definition its local `$ref` points to.
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str().and_then(|r| r.strip_prefix("#/")) {
+        Some(pointer) => pointer.split('/').fold(root, |v, key| &v[key]),
+        None if schema["$ref"] == "#" => root,
+        None => schema,
+    }
+}
+
+fn is_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0),
+        _ => true,
+    }
+}
+
+/// Applies schemas, all within one root, to a completion's values.
+struct Validator<'a> {
+    root: &'a Value,
+    /// `(schema, value)` pairs being applied, innermost last.
+    visiting: Vec<(*const Value, *const Value)>,
+    steps: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn validate(&mut self, schema: &'a Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+        let schema = resolve(self.root, schema);
+        // Back at a schema already being applied to this value: a `$ref` cycle,
+        // which adds nothing the outer application doesn't check.
+        let key = (schema as *const Value, value as *const Value);
+        if self.visiting.contains(&key) {
+            return;
+        }
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return;
+        }
+        self.visiting.push(key);
+        self.apply(schema, value, path, errors);
+        self.visiting.pop();
+    }
+
+    fn apply(&mut self, schema: &'a Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(ty) => vec![ty.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|ty| is_type(value, ty)) {
+            errors.push(format!("{}: expected {}", path, types.join(" or ")));
+            return;
+        }
+        if let Some(options) = schema["enum"].as_array()
+            && !options.contains(value) {
+            errors.push(format!("{}: not one of {}", path, Value::from(options.clone())));
+        }
+        if let Some(expected) = schema.get("const")
+            && expected != value {
+            errors.push(format!("{}: expected {}", path, expected));
+        }
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(options) = schema[keyword].as_array()
+                && !options.iter().any(|option| {
+                    let mut ignored = Vec::new();
+                    self.validate(option, value, path, &mut ignored);
+                    ignored.is_empty()
+                }) {
+                errors.push(format!("{}: matches none of the `{}` schemas", path, keyword));
+            }
+        }
+        for part in schema["allOf"].as_array().into_iter().flatten() {
+            self.validate(part, value, path, errors);
+        }
+        let bound = |keyword: &str| schema[keyword].as_f64();
+        match value {
+            Value::Object(map) => {
+                for key in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        errors.push(format!("{}: missing required `{}`", path, key));
+                    }
+                }
+                for (key, field) in map {
+                    let child = format!("{}.{}", path, key);
+                    match schema["properties"].get(key) {
+                        Some(property) => self.validate(property, field, &child, errors),
+                        None => match &schema["additionalProperties"] {
+                            Value::Bool(false) => errors.push(format!("{}: not allowed", child)),
+                            extra @ Value::Object(_) => self.validate(extra, field, &child, errors),
+                            _ => {}
+                        },
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if bound("minItems").is_some_and(|min| (items.len() as f64) < min) {
+                    errors.push(format!("{}: fewer than {} items", path, schema["minItems"]));
+                }
+                if bound("maxItems").is_some_and(|max| items.len() as f64 > max) {
+                    errors.push(format!("{}: more than {} items", path, schema["maxItems"]));
+                }
+                if schema["items"].is_object() {
+                    for (i, item) in items.iter().enumerate() {
+                        self.validate(&schema["items"], item, &format!("{}[{}]", path, i), errors);
+                    }
+                }
+            }
+            Value::String(text) => {
+                let chars = text.chars().count() as f64;
+                if bound("minLength").is_some_and(|min| chars < min) {
+                    errors.push(format!("{}: shorter than {} characters", path, schema["minLength"]));
+                }
+                if bound("maxLength").is_some_and(|max| chars > max) {
+                    errors.push(format!("{}: longer than {} characters", path, schema["maxLength"]));
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                if bound("minimum").is_some_and(|min| n < min) || bound("exclusiveMinimum").is_some_and(|min| n <= min) {
+                    errors.push(format!("{}: below the minimum", path));
+                }
+                if bound("maximum").is_some_and(|max| n > max) || bound("exclusiveMaximum").is_some_and(|max| n >= max) {
+                    errors.push(format!("{}: above the maximum", path));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What is wrong with `text` as an instance of `schema`; empty when valid.
+pub fn check(schema: &Value, text: &str) -> Vec<String> {
+    match serde_json::from_str::<Value>(text.trim()) {
+        Ok(value) => {
+            let mut errors = Vec::new();
+            let mut validator = Validator { root: schema, visiting: Vec::new(), steps: 0 };
+            validator.validate(schema, &value, "$", &mut errors);
+            if validator.steps > MAX_STEPS {
+                // What was found before giving up may be wrong either way.
+                return vec!["$: schema too complex to check".to_string()];
+            }
+            errors.truncate(MAX_ERRORS);
+            errors
+        }
+        Err(e) => vec![format!("not valid JSON ({})", e)],
+    }
+}
+
+/// A schema set through `PUT /api/sessions/{id}/schema`.
+#[derive(Debug, Clone)]
+pub struct Registered {
+    pub schema: Value,
+    pub registered_at: u64,
+}
+
+/// Drops the schemas of sessions that are gone. One registered before its
+/// session's first request is kept for a session lifetime. Returns how many
+/// were dropped.
+pub fn evict(state: &AppState, ttl_secs: u64, now: u64) -> usize {
+    let before = state.schemas.len();
+    state.schemas.retain(|id, r| state.sessions.contains_key(id) || now.saturating_sub(r.registered_at) <= ttl_secs);
+    before - state.schemas.len()
+}
+
+/// Drops the schemas of the sessions `matches` picks; returns how many.
+pub fn forget_sessions(state: &AppState, matches: impl Fn(&str) -> bool) -> usize {
+    let before = state.schemas.len();
+    state.schemas.retain(|id, _| !matches(id));
+    before - state.schemas.len()
+}
+
+/// The schema the completion of this request must match, if any.
+pub fn schema_for(state: &AppState, session_id: &str, headers: &HeaderMap, payload: &Value) -> Option<Value> {
+    if let Some(registered) = state.schemas.get(session_id) {
+        return Some(registered.schema.clone());
+    }
+    let agent = headers.get(AGENT_HEADER).and_then(|h| h.to_str().ok());
+    if let Some(schema) = agent.and_then(|agent| state.config.structured.agents.get(agent)) {
+        return Some(schema.clone());
+    }
+    let format = &payload["response_format"];
+    Some(format["json_schema"]["schema"].clone())
+        .filter(|schema| state.config.structured.from_request && format["type"] == "json_schema" && schema.is_object())
+}
+
+/// `payload` continued with the rejected reply and what is wrong with it;
+/// `None` for legacy completions, which have no conversation to continue.
+pub fn repair_request(payload: &Value, completion: &str, errors: &[String]) -> Option<Value> {
+    let mut retry = payload.clone();
+    let messages = retry.get_mut("messages")?.as_array_mut()?;
+    messages.push(json!({ "role": "assistant", "content": completion }));
+    messages.push(json!({
+        "role": "user",
+        "content": format!(
+            "Your reply does not match the required JSON schema:\n- {}\nReply again with only the corrected JSON.",
+            errors.join("\n- "),
+        ),
+    }));
+    Some(retry)
+}
+
+/// Adds the token counts of a rejected reply to the one replacing it, so
+/// the retries are billed.
+pub fn add_usage(next: &mut Value, rejected: &Value) {
+    for key in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+        if let Some(spent) = rejected["usage"][key].as_u64() {
+            next["usage"][key] = json!(next["usage"][key].as_u64().unwrap_or(0) + spent);
+        }
+    }
+}
+
+/// `PUT /api/sessions/{id}/schema`: the JSON schema the session's
+/// completions must match from now on.
+pub async fn register(State(state): State<AppState>, Path(id): Path<String>, Json(schema): Json<Value>) -> Response {
+    if !schema.is_object() {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "Schema must be a JSON object"}))).into_response();
+    }
+    tracing::info!("Session '{}' now expects structured output", id);
+    state.schemas.insert(id.clone(), Registered { schema: schema.clone(), registered_at: crate::now_secs() });
+    Json(json!({"session_id": id, "schema": schema})).into_response()
+}
+
+/// `DELETE /api/sessions/{id}/schema`.
+pub async fn unregister(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.schemas.remove(&id) {
+        Some(_) => Json(json!({"session_id": id, "schema": null})).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(json!({"error": "Session has no schema"}))).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions_are_checked_against_schemas() {
+        let schema = json!({
+            "type": "object",
+            "required": ["status", "items"],
+            "additionalProperties": false,
+            "properties": {
+                "status": { "enum": ["ok", "partial"] },
+                "items": { "type": "array", "minItems": 1, "items": { "$ref": "#/$defs/item" } },
+                "note": { "type": ["string", "null"], "maxLength": 20 },
+            },
+            "$defs": {
+                "item": {
+                    "type": "object",
+                    "required": ["sku", "qty"],
+                    "properties": { "sku": { "type": "string" }, "qty": { "type": "integer", "minimum": 1 } },
+                },
+            },
+        });
+        assert!(check(&schema, r#" {"status": "ok", "items": [{"sku": "A-1", "qty": 2}], "note": null} "#).is_empty());
+        let mut errors = check(&schema, r#"{"status": "done", "items": [{"sku": 7, "qty": 0}], "extra": 1}"#);
+        errors.sort();
+        assert_eq!(errors, [
+            "$.extra: not allowed",
+            "$.items[0].qty: below the minimum",
+            "$.items[0].sku: expected string",
+            r#"$.status: not one of ["ok","partial"]"#,
+        ]);
+        assert!(check(&schema, "```json\n{\"status\": \"ok\"}\n```")[0].starts_with("not valid JSON"));
+
+        let payload = json!({ "model": "gpt-4o", "messages": [{ "role": "user", "content": "List the order." }] });
+        let errors = check(&schema, r#"{"status": "ok"}"#);
+        assert_eq!(errors, ["$: missing required `items`"]);
+        let retry = repair_request(&payload, r#"{"status": "ok"}"#, &errors).unwrap();
+        assert_eq!(retry["messages"][1]["role"], "assistant");
+        assert!(retry["messages"][2]["content"].as_str().unwrap().contains("- $: missing required `items`"));
+        assert!(repair_request(&json!({ "prompt": "List the order." }), "", &errors).is_none());
+
+        let mut next = json!({ "usage": { "prompt_tokens": 40, "completion_tokens": 12, "total_tokens": 52 } });
+        add_usage(&mut next, &json!({ "usage": { "prompt_tokens": 20, "completion_tokens": 9, "total_tokens": 29 } }));
+        assert_eq!(next["usage"], json!({ "prompt_tokens": 60, "completion_tokens": 21, "total_tokens": 81 }));
+    }
+
+    #[test]
+    fn test_recursive_schemas_terminate() {
+        let cyclic = json!({ "allOf": [{ "$ref": "#" }], "anyOf": [{ "$ref": "#" }], "type": "object", "required": ["a"] });
+        assert!(check(&cyclic, r#"{"a": 1}"#).is_empty());
+        assert_eq!(check(&cyclic, "{}"), ["$: missing required `a`"]);
+
+        // Each level applies the next twice: 2^40 paths without a budget.
+        let mut defs = serde_json::Map::new();
+        for level in 0..40 {
+            let next = json!({ "$ref": format!("#/$defs/d{}", level + 1) });
+            defs.insert(format!("d{}", level), json!({ "allOf": [next.clone(), { "anyOf": [next] }] }));
+        }
+        defs.insert("d40".to_string(), json!({ "type": "string" }));
+        let fan_out = json!({ "$defs": defs, "$ref": "#/$defs/d0" });
+        assert_eq!(check(&fan_out, "1"), ["$: schema too complex to check"]);
+    }
+
+    #[test]
+    fn test_schemas_go_with_their_sessions() {
+        let state = AppState::for_tests(crate::config::Config::default());
+        let registered = |at| Registered { schema: json!({ "type": "object" }), registered_at: at };
+        state.sessions.insert("live".to_string(), crate::SessionState::new());
+        state.schemas.insert("live".to_string(), registered(0));
+        state.schemas.insert("gone".to_string(), registered(0));
+        state.schemas.insert("upcoming".to_string(), registered(9_000));
+
+        assert_eq!(evict(&state, 3600, 10_000), 1);
+        assert!(!state.schemas.contains_key("gone"));
+        assert_eq!(forget_sessions(&state, |id| id == "live"), 1);
+        assert_eq!(state.schemas.len(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use axum::http::HeaderMap;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{Layer, filter::filter_fn, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::audit::AUDIT_TARGET;
+use crate::config::LogFilePolicy;
+use crate::logfile::RotatingFile;
+
+// --- TRACING / OPENTELEMETRY ---
+// Spans always carry W3C trace context, so an incoming `traceparent` is
+// continued and passed on to the upstream provider. Export is opt-in: set
+// `OTEL_EXPORTER_OTLP_ENDPOINT` (OTLP/HTTP) and optionally `OTEL_SERVICE_NAME`;
+// the other standard `OTEL_*` variables (sampler, headers) are honoured too.
+//
+// Besides stdout, logs can go to a rotating file (`SENTINEL_LOG_FILE`), with
+// intervention records split out into `SENTINEL_LOG_AUDIT_FILE`.
+
+/// Installs the global subscriber. Keep the returned provider alive for the
+/// lifetime of the process; dropping it flushes pending spans.
+pub fn init() -> SdkTracerProvider {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "sentinel".to_string());
+    let mut builder = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(service_name).build());
+
+    // Nothing can be logged until the subscriber exists, so collect problems.
+    let mut problems = Vec::new();
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|e| !e.is_empty());
+    if let Some(endpoint) = &endpoint {
+        match opentelemetry_otlp::SpanExporter::builder().with_http().build() {
+            Ok(exporter) => builder = builder.with_batch_exporter(exporter),
+            Err(e) => problems.push(format!("OTLP exporter for {} failed to start: {}", endpoint, e)),
+        }
+    }
+    let provider = builder.build();
+
+    let files = LogFilePolicy::from_env();
+    let mut open = |path: &Option<String>| {
+        let path = path.as_deref()?;
+        RotatingFile::open(path, &files)
+            .inspect_err(|e| problems.push(format!("Cannot open log file {}: {}", path, e)))
+            .ok()
+    };
+    let file_layer = open(&files.path).map(|f| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(f))
+            .with_filter(filter_fn(|meta| meta.target() != AUDIT_TARGET))
+    });
+    let audit_layer = open(&files.audit_path).map(|f| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(std::sync::Mutex::new(f))
+            .with_filter(filter_fn(|meta| meta.target() == AUDIT_TARGET))
+    });
+
+    tracing_subscriber::registry()
+        .with(files.stdout.then(tracing_subscriber::fmt::layer))
+        .with(file_layer)
+        .with(audit_layer)
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("sentinel")))
+        .init();
+
+    for problem in problems {
+        tracing::error!("{}", problem);
+    }
+    if let Some(endpoint) = endpoint {
+        tracing::info!("Exporting traces to {}", endpoint);
+    }
+    provider
+}
+
+/// Makes `span` a child of the caller's trace, if it sent `traceparent`.
+pub fn continue_trace(span: &tracing::Span, headers: &HeaderMap) {
+    let parent = opentelemetry::global::get_text_map_propagator(|p| {
+        p.extract(&opentelemetry_http::HeaderExtractor(headers))
+    });
+    let _ = span.set_parent(parent);
+}
+
+/// `traceparent` / `tracestate` headers identifying `span`, for outgoing calls.
+pub fn trace_headers(span: &tracing::Span) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let cx = span.context();
+    opentelemetry::global::get_text_map_propagator(|p| {
+        p.inject_context(&cx, &mut opentelemetry_http::HeaderInjector(&mut headers))
+    });
+    headers
+}
@@ -0,0 +1,158 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+
+use crate::AppState;
+use crate::config::Config;
+
+// --- TENANTS ---
+// One deployment can serve several teams (`SENTINEL_TENANTS`). A proxy
+// request is attributed to a tenant by its client API key, or by
+// `x-sentinel-tenant` for tenants that list no keys. The tenant's sessions
+// live under `<tenant>/`, it calls providers with its own keys, spends
+// against its own budget, runs its own detector profile, and its
+// interventions carry its name in the audit log.
+//
+// A session id or `user` a client sends may not contain `/` (400), so only
+// the tenant itself reaches its namespace. `/mcp` is attributed the same
+// way as the proxy routes; set `SENTINEL_TENANT_REQUIRED` to refuse traffic
+// that belongs to no tenant.
+
+/// Set by `identify` to the resolved tenant (a client-sent value is replaced)
+/// and read back with `of`.
+pub const TENANT_HEADER: &str = "x-sentinel-tenant";
+
+/// The tenant a request belongs to, if any.
+pub fn resolve(config: &Config, headers: &HeaderMap) -> Result<Option<String>, String> {
+    if config.tenants.is_empty() {
+        return Ok(None);
+    }
+    if let Some(key) = crate::client_api_key(headers)
+        && let Some(tenant) = config.tenants.iter().find(|t| t.keys.iter().any(|k| k == key)) {
+        return Ok(Some(tenant.name.clone()));
+    }
+    match headers.get(TENANT_HEADER).and_then(|h| h.to_str().ok()) {
+        Some(name) => match config.tenant(name) {
+            Some(tenant) if tenant.keys.is_empty() => Ok(Some(tenant.name.clone())),
+            Some(_) => Err(format!("tenant `{}` requires one of its API keys", name)),
+            None => Err(format!("unknown tenant `{}`", name)),
+        },
+        None if config.tenant_required => Err("no tenant: send a tenant API key or `x-sentinel-tenant`".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// The tenant `identify` resolved for this request.
+pub fn of(headers: &HeaderMap) -> Option<&str> {
+    headers.get(TENANT_HEADER).and_then(|h| h.to_str().ok())
+}
+
+/// The 400 for a caller-chosen session id (`x-sentinel-session` or a
+/// request's `user`) containing `/`, which could name another tenant's
+/// session.
+pub fn qualified_session(id: Option<&str>) -> Option<Response> {
+    id.filter(|id| id.contains('/'))
+        .map(|_| rejection(StatusCode::BAD_REQUEST, "invalid_session", "session ids may not contain `/`".to_string()))
+}
+
+/// Middleware on the proxy routes and `/mcp`: resolves the tenant into `TENANT_HEADER`,
+/// or refuses the request (403 for an unknown or unproven tenant, 429 once
+/// the tenant's budget is spent).
+pub async fn identify(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    if let Some(rejected) = qualified_session(request.headers().get("x-sentinel-session").and_then(|h| h.to_str().ok())) {
+        return rejected;
+    }
+    let tenant = match resolve(&state.config, request.headers()) {
+        Ok(tenant) => tenant,
+        Err(reason) => {
+            tracing::warn!("Tenant check failed: {}", reason);
+            return rejection(StatusCode::FORBIDDEN, "tenant_rejected", reason);
+        }
+    };
+    request.headers_mut().remove(TENANT_HEADER);
+    if let Some(tenant) = tenant {
+        let budget = state.config.tenant(&tenant).and_then(|t| t.budget_usd);
+        let spent = state.tenant_spend.get(&tenant).map_or(0.0, |s| *s);
+        if let Some(budget) = budget.filter(|b| spent > *b) {
+            return rejection(StatusCode::TOO_MANY_REQUESTS, "tenant_budget_exhausted", format!("tenant `{}` has exhausted its ${:.2} budget", tenant, budget));
+        }
+        if let Ok(value) = HeaderValue::from_str(&tenant) {
+            request.headers_mut().insert(TENANT_HEADER, value);
+        }
+    }
+    next.run(request).await
+}
+
+fn rejection(status: StatusCode, code: &str, reason: String) -> Response {
+    (status, Json(serde_json::json!({
+        "error": {
+            "message": format!("Sentinel: {}", reason),
+            "type": "sentinel_tenant",
+            "param": null,
+            "code": code
+        }
+    }))).into_response()
+}
+
+/// `GET /api/tenants`: spend, budget and live-session totals per tenant.
+pub async fn list(State(state): State<AppState>) -> impl IntoResponse {
+    let mut live: HashMap<&str, (usize, u64)> = HashMap::new();
+    let sessions: Vec<(String, u32)> = state.sessions.iter().map(|s| (s.key().clone(), s.interventions)).collect();
+    for (id, interventions) in &sessions {
+        if let Some((tenant, _)) = id.split_once('/') {
+            let entry = live.entry(tenant).or_default();
+            entry.0 += 1;
+            entry.1 += *interventions as u64;
+        }
+    }
+    let tenants: Vec<serde_json::Value> = state.config.tenants.iter().map(|t| {
+        let (sessions, interventions) = live.get(t.name.as_str()).copied().unwrap_or_default();
+        serde_json::json!({
+            "name": t.name,
+            "spent_usd": state.tenant_spend.get(&t.name).map_or(0.0, |s| *s),
+            "budget_usd": t.budget_usd,
+            "active_sessions": sessions,
+            "interventions": interventions,
+        })
+    }).collect();
+    Json(tenants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Tenant;
+
+    #[test]
+    fn test_resolve_by_key_then_header() {
+        let mut config = Config {
+            tenants: vec![
+                Tenant::parse("acme: keys=sk-acme").unwrap(),
+                Tenant::parse("lab: budget=10").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let headers = |pairs: &[(&'static str, &str)]| -> HeaderMap {
+            pairs.iter().map(|(k, v)| (axum::http::HeaderName::from_static(k), v.parse().unwrap())).collect()
+        };
+        assert_eq!(resolve(&config, &headers(&[("authorization", "Bearer sk-acme")])), Ok(Some("acme".to_string())));
+        assert_eq!(resolve(&config, &headers(&[(TENANT_HEADER, "lab")])), Ok(Some("lab".to_string())));
+        assert!(resolve(&config, &headers(&[(TENANT_HEADER, "acme")])).is_err());
+        assert!(resolve(&config, &headers(&[(TENANT_HEADER, "nobody")])).is_err());
+        assert_eq!(resolve(&config, &HeaderMap::new()), Ok(None));
+        config.tenant_required = true;
+        assert!(resolve(&config, &HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_session_ids_cannot_name_a_tenant_namespace() {
+        assert!(qualified_session(None).is_none());
+        assert!(qualified_session(Some("agent-1")).is_none());
+        assert_eq!(qualified_session(Some("acme/foo")).unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+// --- TIME-SERIES STATS ---
+// Counters are kept in hourly buckets for the last 30 days; coarser views
+// (daily) are folded from them on read. Quiet hours have no bucket and are
+// filled with zeroes when a series is rendered.
+
+pub const HOUR: u64 = 3600;
+pub const DAY: u64 = 24 * HOUR;
+const RETAINED_HOURS: usize = 30 * 24;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Bucket {
+    /// Unix seconds at the start of the bucket.
+    pub start: u64,
+    pub requests: u64,
+    /// Keyed by detector.
+    pub interventions: BTreeMap<String, u64>,
+    pub cost_usd: f64,
+    pub saved_usd: f64,
+}
+
+impl Bucket {
+    fn merge(&mut self, other: &Bucket) {
+        self.requests += other.requests;
+        for (detector, n) in &other.interventions {
+            *self.interventions.entry(detector.clone()).or_default() += n;
+        }
+        self.cost_usd += other.cost_usd;
+        self.saved_usd += other.saved_usd;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TimeSeries {
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl TimeSeries {
+    fn with_bucket(&self, now: u64, f: impl FnOnce(&mut Bucket)) {
+        let start = now - now % HOUR;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.back().is_none_or(|b| b.start < start) {
+            buckets.push_back(Bucket { start, ..Default::default() });
+            while buckets.len() > RETAINED_HOURS { buckets.pop_front(); }
+        }
+        // A clock that stepped backwards books into the newest bucket.
+        if let Some(bucket) = buckets.back_mut() {
+            f(bucket);
+        }
+    }
+
+    pub fn record_request(&self, now: u64) {
+        self.with_bucket(now, |b| b.requests += 1);
+    }
+
+    pub fn record_intervention(&self, now: u64, detector: &str) {
+        self.with_bucket(now, |b| *b.interventions.entry(detector.to_string()).or_default() += 1);
+    }
+
+    pub fn record_cost(&self, now: u64, usd: f64) {
+        self.with_bucket(now, |b| b.cost_usd += usd);
+    }
+
+    pub fn record_savings(&self, now: u64, usd: f64) {
+        self.with_bucket(now, |b| b.saved_usd += usd);
+    }
+
+    /// `step`-sized buckets covering the last `window` seconds up to and
+    /// including the current one, oldest first. `step` must be a whole
+    /// number of hours.
+    pub fn series(&self, now: u64, window: u64, step: u64) -> Vec<Bucket> {
+        let end = now - now % step + step;
+        let count = window.div_ceil(step).max(1);
+        let first = end.saturating_sub(count * step);
+        let mut out: Vec<Bucket> = (0..count)
+            .map(|i| Bucket { start: first + i * step, ..Default::default() })
+            .collect();
+        let buckets = self.buckets.lock().unwrap();
+        for b in buckets.iter().filter(|b| b.start >= first && b.start < end) {
+            out[((b.start - first) / step) as usize].merge(b);
+        }
+        out
+    }
+}
+
+/// Parses `24h` / `7d` style durations into seconds.
+pub fn parse_window(src: &str) -> Option<u64> {
+    let src = src.trim();
+    let (n, unit) = src.split_at(src.len() - src.chars().last()?.len_utf8());
+    let n: u64 = n.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "h" => Some(n * HOUR),
+        "d" => Some(n * DAY),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_fills_gaps_and_folds_days() {
+        let ts = TimeSeries::default();
+        let now = 10 * DAY + 5 * HOUR + 120;
+        ts.record_request(now - 2 * HOUR);
+        ts.record_intervention(now - 2 * HOUR, "fuzzy_loop");
+        ts.record_request(now);
+        ts.record_cost(now, 0.25);
+        ts.record_savings(now, 0.5);
+
+        let hourly = ts.series(now, 3 * HOUR, HOUR);
+        assert_eq!(hourly.len(), 3);
+        assert_eq!(hourly.iter().map(|b| b.requests).collect::<Vec<_>>(), vec![1, 0, 1]);
+        assert_eq!(hourly[0].interventions["fuzzy_loop"], 1);
+        assert_eq!(hourly[2].start, now - 120);
+
+        let daily = ts.series(now, 2 * DAY, DAY);
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[1].requests, 2);
+        assert_eq!(daily[1].saved_usd, 0.5);
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("24h"), Some(DAY));
+        assert_eq!(parse_window("7d"), Some(7 * DAY));
+        assert_eq!(parse_window("0h"), None);
+        assert_eq!(parse_window("15m"), None);
+        assert_eq!(parse_window(""), None);
+    }
+}
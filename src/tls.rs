@@ -0,0 +1,61 @@
+use axum::Router;
+use std::path::Path;
+use tokio::net::TcpListener;
+
+// --- TLS ---
+// With `SENTINEL_TLS_CERT` and `SENTINEL_TLS_KEY` set, the main listener
+// speaks HTTPS through rustls. Both files are polled every `reload_secs` and
+// re-read when either changes, so a rotated certificate is picked up by new
+// connections without dropping sessions. A build without the `tls` feature
+// refuses to start rather than fall back to cleartext.
+
+/// Serves `app` over HTTPS on every listener until one of them fails.
+#[cfg(feature = "tls")]
+pub async fn serve(listeners: Vec<TcpListener>, app: Router, cert: &Path, key: &Path, reload_secs: u64) -> std::io::Result<()> {
+    use axum_server::tls_rustls::RustlsConfig;
+
+    let tls = RustlsConfig::from_pem_file(cert, key).await?;
+    if reload_secs > 0 {
+        spawn_rotation(tls.clone(), cert.to_path_buf(), key.to_path_buf(), reload_secs);
+    }
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tracing::info!("🔒 Sentinel SaaS active on https://{}", listener.local_addr()?);
+        let listener = listener.into_std()?;
+        servers.spawn(axum_server::from_tcp_rustls(listener, tls.clone()).serve(app.clone().into_make_service_with_connect_info::<std::net::SocketAddr>()));
+    }
+    while let Some(result) = servers.join_next().await {
+        result.map_err(std::io::Error::other)??;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tls"))]
+pub async fn serve(_: Vec<TcpListener>, _: Router, _: &Path, _: &Path, _: u64) -> std::io::Result<()> {
+    Err(std::io::Error::other("SENTINEL_TLS_CERT is set but this build lacks the `tls` feature; refusing to serve cleartext"))
+}
+
+#[cfg(feature = "tls")]
+fn spawn_rotation(tls: axum_server::tls_rustls::RustlsConfig, cert: std::path::PathBuf, key: std::path::PathBuf, secs: u64) {
+    tokio::spawn(async move {
+        let mut seen = (modified(&cert), modified(&key));
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(secs));
+        loop {
+            tick.tick().await;
+            let now = (modified(&cert), modified(&key));
+            if now == seen { continue; }
+            // Remember the attempt either way: a half-written pair changes
+            // again once the second file lands.
+            seen = now;
+            match tls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => tracing::info!("🔒 TLS certificate reloaded from {}", cert.display()),
+                Err(e) => tracing::error!("TLS certificate reload failed, keeping the previous one: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(feature = "tls")]
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
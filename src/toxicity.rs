@@ -0,0 +1,151 @@
+use serde_json::{Value, json};
+use std::time::Duration;
+
+use crate::AppState;
+use crate::config::ToxicityPolicy;
+
+// --- TOXICITY ---
+// A customer-facing bot that swears back at a user is an incident even when
+// nothing leaked. With `SENTINEL_TOXICITY=true`, non-streamed completions are
+// scored from 0 to 1: each word on the list (built in, plus
+// `SENTINEL_TOXICITY_WORDS` and one word per line of
+// `SENTINEL_TOXICITY_WORDS_FILE`; a trailing `*` matches any ending) halves
+// the distance to 1, so one hit scores 0.5 and three 0.875. Words that begin
+// innocent ones ("prickly", "shiitake", "bastardize") are listed with each
+// inflection instead of a `*`. Streamed completions are not scored: they reach
+// the client as they arrive, before there is a whole text to score or mask. When
+// `SENTINEL_TOXICITY_CLASSIFIER_URL` is set, the completion is also posted
+// there as `{"input": "..."}` and the `score` (or `toxicity`) it answers
+// with counts if higher; a classifier that fails or times out is skipped.
+//
+// A completion at `SENTINEL_TOXICITY_THRESHOLD` (0.5) or above is a
+// `toxicity` hit. In block mode, `SENTINEL_TOXICITY_ACTION=redact` (the
+// default) masks the listed words, or withholds the whole text when only the
+// classifier objected; `block` refuses the completion like a leak. Tenants
+// set their own `toxicity=<threshold>` and `toxicity_action=<action>`.
+
+pub const DETECTOR: &str = "toxicity";
+pub const REASON: &str = "Toxic Output";
+/// Stands in for a completion the classifier flagged without naming words.
+const WITHHELD: &str = "[Response withheld: toxic content]";
+const CLASSIFIER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Built-in list; matched against lowercased words.
+pub const WORDS: &[&str] = &[
+    "asshole*", "bastard", "bastards", "bitch*", "bollocks", "bullshit*", "cocksucker*", "cunt*", "dickhead*",
+    "dumbass*", "fuck*", "jackass*", "motherfuck*", "piss off", "prick", "pricks", "shit", "shits", "shitty",
+    "shitting", "shithead", "shitheads", "shitshow", "twat", "twats", "wanker*",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Score {
+    pub score: f32,
+    /// Byte spans of the listed words found.
+    pub spans: Vec<(usize, usize)>,
+}
+
+fn listed(word: &str, entry: &str) -> bool {
+    match entry.strip_suffix('*') {
+        Some(stem) => word.starts_with(stem),
+        None => word == entry,
+    }
+}
+
+/// Scores `text` against the word list. Multi-word entries match
+/// consecutive words.
+pub fn wordlist(text: &str, words: &[String]) -> Score {
+    let tokens: Vec<(usize, usize, String)> = text.char_indices()
+        .filter(|(i, c)| c.is_alphanumeric() && !text[..*i].chars().next_back().is_some_and(char::is_alphanumeric))
+        .map(|(start, _)| {
+            let end = text[start..].find(|c: char| !c.is_alphanumeric()).map_or(text.len(), |n| start + n);
+            (start, end, text[start..end].to_lowercase())
+        })
+        .collect();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let hit = words.iter().find_map(|entry| {
+            let parts: Vec<&str> = entry.split_whitespace().collect();
+            let window = tokens.get(i..i + parts.len())?;
+            window.iter().zip(&parts).all(|((_, _, word), part)| listed(word, part)).then_some(parts.len())
+        });
+        match hit {
+            Some(n) if n > 0 => {
+                spans.push((tokens[i].0, tokens[i + n - 1].1));
+                i += n;
+            }
+            _ => i += 1,
+        }
+    }
+    let score = 1.0 - 0.5f32.powi(spans.len() as i32);
+    Score { score, spans }
+}
+
+/// Asks the classifier for a score; `None` when it can't answer.
+async fn classify(state: &AppState, url: &str, text: &str) -> Option<f32> {
+    let response = state.client.post(url).json(&json!({ "input": text })).timeout(CLASSIFIER_TIMEOUT).send().await
+        .and_then(|r| r.error_for_status());
+    let body: Value = match response {
+        Ok(response) => response.json().await.ok()?,
+        Err(e) => {
+            tracing::warn!("Toxicity classifier failed: {}", e.without_url());
+            return None;
+        }
+    };
+    body.get("score").or_else(|| body.get("toxicity")).and_then(Value::as_f64).map(|s| s as f32)
+}
+
+/// The word-list score, raised to the classifier's when that is higher.
+pub async fn score(state: &AppState, policy: &ToxicityPolicy, text: &str) -> Score {
+    let mut scored = wordlist(text, &policy.words);
+    if let Some(url) = &policy.classifier_url
+        && let Some(score) = classify(state, url, text).await {
+        scored.score = scored.score.max(score.clamp(0.0, 1.0));
+    }
+    scored
+}
+
+/// `text` with the listed words masked but for their first letter
+/// (`f******`), or withheld when nothing on the list accounts for the score.
+pub fn redact(text: &str, scored: &Score) -> String {
+    if scored.spans.is_empty() {
+        return WITHHELD.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for &(start, end) in &scored.spans {
+        out.push_str(&text[copied..start]);
+        let mut word_start = true;
+        for c in text[start..end].chars() {
+            out.push(if word_start || !c.is_alphanumeric() { c } else { '*' });
+            word_start = !c.is_alphanumeric();
+        }
+        copied = end;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listed_words_score_and_redact() {
+        let words: Vec<String> = WORDS.iter().map(|w| w.to_string()).collect();
+        let clean = wordlist("Your order ships Tuesday; the Scunthorpe depot confirmed it.", &words);
+        assert_eq!(clean, Score { score: 0.0, spans: Vec::new() });
+        let innocent = "A prickly pear, shitake and shiitake risotto, and a twattle about bastardized recipes.";
+        assert_eq!(wordlist(innocent, &words).spans, Vec::new());
+        assert_eq!(wordlist("What a prick, this shitty printer.", &words).spans.len(), 2);
+
+        let text = "That's bullshit. Piss off and fix your own FUCKING printer.";
+        let scored = wordlist(text, &words);
+        assert_eq!(scored.spans.len(), 3);
+        assert_eq!(scored.score, 0.875);
+        assert_eq!(redact(text, &scored), "That's b*******. P*** o** and fix your own F****** printer.");
+
+        // A classifier-only score leaves nothing to mask.
+        assert_eq!(redact(text, &Score { score: 0.9, spans: Vec::new() }), WITHHELD);
+    }
+}
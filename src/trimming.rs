@@ -0,0 +1,263 @@
+use serde_json::{Value, json};
+use std::sync::atomic::Ordering;
+
+use crate::AppState;
+use crate::config::ContextPolicy;
+use crate::savings::estimate_message_tokens;
+
+// --- CONTEXT TRIMMING ---
+// Agents that never prune their history resend it on every turn, and pay for
+// it every time. With `SENTINEL_CONTEXT_TRIM=true`, a chat request whose
+// estimated prompt (about four characters per token) exceeds its budget
+// loses its oldest non-system turns before it is forwarded. The budget is
+// the model's context window less the completion budget, capped by
+// `SENTINEL_CONTEXT_MAX_TOKENS` when set.
+//
+// A turn is a message plus the `tool` results that answer it, so a tool call
+// never loses its result or the other way around. System and developer
+// messages stay, as do the newest `SENTINEL_CONTEXT_KEEP_LAST` turns; a
+// request that still doesn't fit goes out as trimmed as it can be.
+//
+// With `SENTINEL_CONTEXT_SUMMARY_MODEL` the dropped turns are instead
+// summarized by that (cheap) model and replaced by one system message in
+// their place. The summary call is booked as the session's spend like any
+// other call (and its pool's and tenant's), and what it saves on the
+// forwarded prompt is added to `total_saved_usd`; the log line gives the net,
+// which is negative when the summary cost more than it saved. If the summary
+// call fails, the turns are dropped as usual.
+
+/// Instruction given to the summary model.
+const SUMMARY_PROMPT: &str = "Summarize the following conversation between a user, an assistant and its tools \
+so the assistant can continue the task without it. Keep facts, decisions, file names, identifiers, open questions \
+and results of tool calls; leave out pleasantries. Reply with the summary only.";
+
+/// Transcript characters sent for summarizing; older ones are cut first.
+const MAX_TRANSCRIPT_CHARS: usize = 200_000;
+
+/// What trimming did to one request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trimmed {
+    pub messages: usize,
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+    /// Set when the turns were summarized: USD saved net of the summary
+    /// call, negative when the summary cost more.
+    pub saved_usd: Option<f64>,
+    /// What the summary call cost, to book against the session.
+    pub summary_cost_usd: f64,
+}
+
+/// Messages to drop to fit the budget, and the estimate once they are gone.
+struct Plan {
+    drop: Vec<bool>,
+    trimmed: Trimmed,
+}
+
+/// Prompt tokens `model` may receive: its window minus the completion
+/// budget, capped by `max_tokens`. `None` when neither is known.
+fn budget(policy: &ContextPolicy, model: &str, body: &Value) -> Option<u64> {
+    let completion = ["max_completion_tokens", "max_tokens"].iter().find_map(|k| body[k].as_u64()).unwrap_or(0);
+    let window = policy.window(model).map(|w| w.saturating_sub(completion));
+    let cap = (policy.max_tokens > 0).then_some(policy.max_tokens);
+    match (window, cap) {
+        (Some(w), Some(c)) => Some(w.min(c)),
+        (w, c) => w.or(c),
+    }
+}
+
+fn is_pinned(message: &Value) -> bool {
+    matches!(message["role"].as_str(), Some("system" | "developer"))
+}
+
+/// Drops the oldest turns of `body["messages"]` until it fits the budget.
+/// Returns `None` when nothing was dropped.
+pub fn trim(policy: &ContextPolicy, model: &str, body: &mut Value) -> Option<Trimmed> {
+    let plan = plan(policy, model, body)?;
+    remove(body, &plan.drop);
+    Some(plan.trimmed)
+}
+
+/// Like `trim`, but summarizes the dropped turns when a summary model is set.
+pub async fn compress(state: &AppState, tenant: Option<&str>, model: &str, body: &mut Value) -> Option<Trimmed> {
+    let policy = &state.config.context;
+    let Some(summary_model) = &policy.summary_model else {
+        return trim(policy, model, body);
+    };
+    let plan = plan(policy, model, body)?;
+    let dropped: Vec<&Value> = body["messages"].as_array()?.iter().zip(&plan.drop).filter(|(_, d)| **d).map(|(m, _)| m).collect();
+    let count = dropped.len();
+    match summarize(state, tenant, summary_model, &transcript(&dropped)).await {
+        Ok((summary, cost)) => {
+            let message = json!({"role": "system", "content": format!("Summary of {} earlier messages: {}", count, summary)});
+            let tokens_after = plan.trimmed.tokens_after + estimate_message_tokens(&message);
+            let saved_tokens = plan.trimmed.tokens_before.saturating_sub(tokens_after);
+            let prompt_saved = state.config.pricing.cost(model, saved_tokens, 0);
+            let at = plan.drop.iter().position(|d| *d).unwrap_or(0);
+            remove(body, &plan.drop);
+            if let Some(messages) = body["messages"].as_array_mut() {
+                messages.insert(at, message);
+            }
+            state.saved_micro_usd.fetch_add((prompt_saved * 1_000_000.0).round() as u64, Ordering::Relaxed);
+            Some(Trimmed { tokens_after, saved_usd: Some(prompt_saved - cost), summary_cost_usd: cost, ..plan.trimmed })
+        }
+        Err(e) => {
+            tracing::warn!("Context summary with {} failed, dropping the turns instead: {}", summary_model, e);
+            remove(body, &plan.drop);
+            Some(plan.trimmed)
+        }
+    }
+}
+
+fn remove(body: &mut Value, drop: &[bool]) {
+    if let Some(messages) = body["messages"].as_array_mut() {
+        let mut keep = drop.iter().map(|d| !d);
+        messages.retain(|_| keep.next().unwrap_or(true));
+    }
+}
+
+/// `role: text` lines, with tool calls spelled out.
+fn transcript(messages: &[&Value]) -> String {
+    let mut out = String::new();
+    for message in messages {
+        let role = message["role"].as_str().unwrap_or("user");
+        let text = match &message["content"] {
+            Value::String(text) => text.clone(),
+            Value::Array(parts) => parts.iter().filter_map(|p| p["text"].as_str()).collect::<Vec<_>>().join("\n"),
+            _ => String::new(),
+        };
+        if !text.is_empty() {
+            out.push_str(&format!("{}: {}\n", role, text));
+        }
+        for call in message["tool_calls"].as_array().into_iter().flatten() {
+            let function = &call["function"];
+            out.push_str(&format!("{} called {}({})\n", role, function["name"].as_str().unwrap_or_default(), function["arguments"].as_str().unwrap_or_default()));
+        }
+    }
+    let excess = out.chars().count().saturating_sub(MAX_TRANSCRIPT_CHARS);
+    if excess > 0 {
+        out = out.chars().skip(excess).collect();
+    }
+    out
+}
+
+/// Asks `model` for a summary of `transcript`; returns it and what it cost.
+async fn summarize(state: &AppState, tenant: Option<&str>, model: &str, transcript: &str) -> Result<(String, f64), String> {
+    let policy = &state.config.context;
+    let upstream = state.provider(tenant, &policy.summary_provider)?;
+    let request = json!({
+        "model": model,
+        "messages": [
+            {"role": "system", "content": SUMMARY_PROMPT},
+            {"role": "user", "content": transcript},
+        ],
+        "max_tokens": policy.summary_max_tokens,
+    });
+    let res = state.client
+        .post(upstream.endpoint("chat/completions"))
+        .header("Authorization", format!("Bearer {}", upstream.api_key))
+        .timeout(std::time::Duration::from_secs(policy.summary_timeout_secs))
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| e.without_url().to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("HTTP {}", res.status()));
+    }
+    let body: Value = res.json().await.map_err(|e| e.to_string())?;
+    let summary = body["choices"][0]["message"]["content"].as_str().map(str::trim).unwrap_or_default();
+    if summary.is_empty() {
+        return Err("empty summary".to_string());
+    }
+    Ok((summary.to_string(), crate::usage_cost(&state.config.pricing, model, &body)))
+}
+
+fn plan(policy: &ContextPolicy, model: &str, body: &Value) -> Option<Plan> {
+    if !policy.trim {
+        return None;
+    }
+    let budget = budget(policy, model, body)?;
+    let messages = body["messages"].as_array()?;
+    let tokens_before: u64 = messages.iter().map(estimate_message_tokens).sum();
+    if tokens_before <= budget {
+        return None;
+    }
+
+    // Turn boundaries: indices of non-system messages that don't answer a tool call.
+    let turns: Vec<usize> = (0..messages.len())
+        .filter(|&i| !is_pinned(&messages[i]) && messages[i]["role"] != "tool")
+        .collect();
+    let droppable = turns.len().saturating_sub(policy.keep_last);
+    let mut drop = vec![false; messages.len()];
+    let mut tokens = tokens_before;
+    for (n, &start) in turns.iter().take(droppable).enumerate() {
+        if tokens <= budget {
+            break;
+        }
+        let end = turns.get(n + 1).copied().unwrap_or(messages.len());
+        for (i, message) in messages.iter().enumerate().take(end).skip(start) {
+            if !is_pinned(message) {
+                drop[i] = true;
+                tokens -= estimate_message_tokens(message);
+            }
+        }
+    }
+    // Tool results ahead of the first turn have no call left to answer.
+    let first_turn = turns.first().copied().unwrap_or(messages.len());
+    for (i, message) in messages.iter().enumerate().take(first_turn) {
+        if message["role"] == "tool" && !drop[i] {
+            drop[i] = true;
+            tokens -= estimate_message_tokens(message);
+        }
+    }
+
+    let dropped = drop.iter().filter(|d| **d).count();
+    if dropped == 0 {
+        return None;
+    }
+    Some(Plan { drop, trimmed: Trimmed { messages: dropped, tokens_before, tokens_after: tokens, saved_usd: None, summary_cost_usd: 0.0 } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_trims_oldest_turns_with_their_tool_results() {
+        let policy = ContextPolicy { trim: true, max_tokens: 30, keep_last: 1, ..ContextPolicy::default() };
+        let filler = "x".repeat(80);
+        let mut body = json!({"model": "gpt-4o", "messages": [
+            {"role": "system", "content": "You are an agent."},
+            {"role": "user", "content": filler},
+            {"role": "assistant", "content": null, "tool_calls": [{"id": "c1", "type": "function", "function": {"name": "ls", "arguments": "{}"}}]},
+            {"role": "tool", "tool_call_id": "c1", "content": filler},
+            {"role": "assistant", "content": "Done."},
+            {"role": "user", "content": "And now?"},
+        ]});
+        let trimmed = trim(&policy, "gpt-4o", &mut body).unwrap();
+        let roles: Vec<&str> = body["messages"].as_array().unwrap().iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["system", "assistant", "user"]);
+        assert_eq!(trimmed.messages, 3);
+        assert_eq!((trimmed.tokens_before, trimmed.tokens_after), (74, 21));
+
+        // Under budget, or switched off: untouched.
+        assert_eq!(trim(&policy, "gpt-4o", &mut body), None);
+        let mut long = json!({"messages": [{"role": "user", "content": filler}, {"role": "user", "content": filler}]});
+        assert_eq!(trim(&ContextPolicy { trim: false, ..policy.clone() }, "gpt-4o", &mut long), None);
+        // gpt-4's 8k window less a 8k completion budget leaves nothing: trims down to keep_last.
+        let mut big = json!({"max_tokens": 8_192, "messages": [{"role": "user", "content": "a"}, {"role": "user", "content": "b"}]});
+        let policy = ContextPolicy { max_tokens: 0, ..policy };
+        assert_eq!(trim(&policy, "gpt-4", &mut big).map(|t| t.messages), Some(1));
+    }
+
+    #[test]
+    fn test_transcript_spells_out_tool_calls() {
+        let messages = [
+            json!({"role": "user", "content": "list files"}),
+            json!({"role": "assistant", "content": null, "tool_calls": [{"function": {"name": "ls", "arguments": "{\"path\":\".\"}"}}]}),
+            json!({"role": "tool", "content": [{"type": "text", "text": "a.rs"}]}),
+        ];
+        let refs: Vec<&Value> = messages.iter().collect();
+        assert_eq!(transcript(&refs), "user: list files\nassistant called ls({\"path\":\".\"})\ntool: a.rs\n");
+    }
+}
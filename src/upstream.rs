@@ -0,0 +1,56 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+
+use crate::AppState;
+use crate::events::{Event, RequestEvent};
+
+// --- DEFERRED UPSTREAM CALLS ---
+
+/// A chat request exactly as it would have been forwarded, kept so it can be
+/// sent later (quarantine approval, intervention replay).
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredRequest {
+    pub session_id: String,
+    /// Path below the provider's `/v1` root, e.g. `chat/completions`.
+    pub endpoint: String,
+    pub provider: String,
+    pub model: String,
+    pub budget_pool: Option<String>,
+    pub tenant: Option<String>,
+    pub payload: serde_json::Value,
+}
+
+/// Sends a stored request upstream without running detectors and books its
+/// cost. Always non-streaming, since the result is stored rather than piped.
+pub async fn forward(state: &AppState, req: &StoredRequest) -> Result<(StatusCode, serde_json::Value), String> {
+    let upstream = state.provider(req.tenant.as_deref(), &req.provider)?;
+    let mut payload = req.payload.clone();
+    if let Some(obj) = payload.as_object_mut() {
+        obj.remove("stream");
+        obj.remove("stream_options");
+    }
+
+    let res = state.client
+        .post(upstream.endpoint(&req.endpoint))
+        .header("Authorization", format!("Bearer {}", upstream.api_key))
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let body: serde_json::Value = match res.json().await {
+        Err(e) if e.is_timeout() => return Err(e.to_string()),
+        body => body.unwrap_or_default(),
+    };
+
+    if status.is_success() {
+        let (cost_policy, _) = state.config.tenant_policies(req.tenant.as_deref(), &req.model);
+        let cost = crate::usage_cost(&state.config.pricing, &req.model, &body);
+        crate::book_cost(state, &req.session_id, req.budget_pool.as_deref(), req.tenant.as_deref(), cost, &cost_policy);
+        crate::events::emit(state, Event::Request(RequestEvent {
+            cost_usd: cost,
+            ..RequestEvent::new(&req.session_id, req.tenant.as_deref(), &req.provider, &req.endpoint, &req.model).with_usage(&body)
+        }));
+    }
+    Ok((status, body))
+}
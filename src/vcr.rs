@@ -0,0 +1,162 @@
+use axum::http::{HeaderValue, StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::config::{VcrMode, VcrPolicy};
+
+// --- RECORD AND REPLAY ---
+// Integration tests of an agent behind Sentinel shouldn't depend on a
+// provider being up, or pay for it. With `SENTINEL_VCR_MODE=record` every
+// upstream chat / completions exchange is written to `SENTINEL_VCR_DIR` as
+// one JSON "cassette", named by a hash of the endpoint and the request body
+// as forwarded. With `SENTINEL_VCR_MODE=replay` the cassette is served
+// instead and no provider is called; a request without one gets a 502
+// `cassette_missing`, so a test never silently falls through to a real call.
+//
+// Recording buffers the whole upstream body, so streams reach the client
+// at once rather than chunk by chunk; replayed streams arrive the same way.
+// Identical requests share a cassette and the last recording wins. Only the
+// proxied generation call is covered: embeddings for loop detection,
+// context summaries and shadow calls still go out unless their keys are
+// left unset.
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cassette {
+    pub endpoint: String,
+    pub request: Value,
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Body as received: JSON, or the raw server-sent events of a stream.
+    pub body: String,
+}
+
+/// Cassette name for `payload` sent to `endpoint`.
+pub fn key(endpoint: &str, payload: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(endpoint.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(serde_json::to_string(payload).unwrap_or_default().as_bytes());
+    crate::audit::hex(&hasher.finalize())
+}
+
+fn path(dir: &str, endpoint: &str, payload: &Value) -> PathBuf {
+    Path::new(dir).join(format!("{}.json", key(endpoint, payload)))
+}
+
+fn response(status: u16, content_type: Option<&str>, body: String) -> reqwest::Response {
+    let mut response = axum::http::Response::new(body);
+    *response.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+    if let Some(value) = content_type.and_then(|t| HeaderValue::from_str(t).ok()) {
+        response.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    reqwest::Response::from(response)
+}
+
+/// Sends `request` upstream, or records or replays the exchange per `policy`.
+pub async fn send(policy: &VcrPolicy, request: reqwest::RequestBuilder, endpoint: &str, payload: &Value) -> reqwest::Result<reqwest::Response> {
+    match policy.mode {
+        VcrMode::Off => request.send().await,
+        VcrMode::Replay => Ok(replay(&path(&policy.dir, endpoint, payload)).await),
+        VcrMode::Record => {
+            let res = request.send().await?;
+            let status = res.status().as_u16();
+            let content_type = res.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+            let body = res.text().await?;
+            let cassette = Cassette { endpoint: endpoint.to_string(), request: payload.clone(), status, content_type, body };
+            let file = path(&policy.dir, endpoint, payload);
+            if let Err(e) = write(&file, &cassette).await {
+                tracing::warn!("Recording {} failed: {}", file.display(), e);
+            }
+            Ok(response(cassette.status, cassette.content_type.as_deref(), cassette.body))
+        }
+    }
+}
+
+async fn write(file: &Path, cassette: &Cassette) -> std::io::Result<()> {
+    if let Some(dir) = file.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let json = serde_json::to_string_pretty(cassette).map_err(std::io::Error::other)?;
+    tokio::fs::write(file, json).await
+}
+
+async fn replay(file: &Path) -> reqwest::Response {
+    let cassette = tokio::fs::read_to_string(file).await
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str::<Cassette>(&json).map_err(|e| e.to_string()));
+    match cassette {
+        Ok(cassette) => response(cassette.status, cassette.content_type.as_deref(), cassette.body),
+        Err(e) => {
+            tracing::warn!("No cassette to replay at {}: {}", file.display(), e);
+            let error = serde_json::json!({
+                "error": {
+                    "message": format!("Sentinel replay: no recorded response ({})", file.display()),
+                    "type": "sentinel_replay",
+                    "param": null,
+                    "code": "cassette_missing"
+                }
+            });
+            response(502, Some("application/json"), error.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replays_what_was_recorded() {
+        let dir = std::env::temp_dir().join(format!("sentinel-vcr-{}", std::process::id()));
+        let policy = VcrPolicy { mode: VcrMode::Replay, dir: dir.to_string_lossy().into_owned() };
+        let payload = serde_json::json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]});
+        let client = reqwest::Client::new();
+        // Replay never sends, so the unroutable URL is never dialed.
+        let request = || client.post("http://127.0.0.1:9/v1/chat/completions");
+
+        let missing = send(&policy, request(), "chat/completions", &payload).await.unwrap();
+        assert_eq!(missing.status(), 502);
+
+        let cassette = Cassette {
+            endpoint: "chat/completions".to_string(),
+            request: payload.clone(),
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: r#"{"choices":[{"message":{"content":"hello"}}]}"#.to_string(),
+        };
+        write(&path(&policy.dir, "chat/completions", &payload), &cassette).await.unwrap();
+        let replayed = send(&policy, request(), "chat/completions", &payload).await.unwrap();
+        assert_eq!(replayed.status(), 200);
+        let body: Value = replayed.json().await.unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "hello");
+        assert_ne!(key("chat/completions", &payload), key("completions", &payload));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_reprompts_replay_from_cassettes() {
+        let dir = std::env::temp_dir().join(format!("sentinel-vcr-reprompt-{}", std::process::id()));
+        let vcr = VcrPolicy { mode: VcrMode::Replay, dir: dir.to_string_lossy().into_owned() };
+        let state = crate::AppState::for_tests(crate::config::Config { vcr, ..Default::default() });
+        let retry = serde_json::json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Fix the JSON."}]});
+        let cassette = Cassette {
+            endpoint: "chat/completions".to_string(),
+            request: retry.clone(),
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: r#"{"choices":[{"message":{"content":"{\"status\":\"ok\"}"}}]}"#.to_string(),
+        };
+        write(&path(&state.config.vcr.dir, "chat/completions", &retry), &cassette).await.unwrap();
+        let (url, headers) = ("http://127.0.0.1:9/v1/chat/completions", reqwest::header::HeaderMap::new());
+        let resend = |payload| crate::resend(&state, "openai", "gpt-4o", url, "sk-test", &headers, crate::Api::Chat, payload);
+
+        let body = resend(&retry).await.unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], r#"{"status":"ok"}"#);
+        // Without a cassette the re-prompt fails rather than going out.
+        assert!(resend(&serde_json::json!({"model": "gpt-4o", "messages": []})).await.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}